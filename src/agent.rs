@@ -0,0 +1,80 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # Pairing Agent
+//!
+//! The [PairingAgent] is implemented by the application to take part in pairing. It declares
+//! what the device is able to display and to input, which decides on the association model, and
+//! it interacts with the user whenever pairing needs the user. The same agent can be set with
+//! [crate::smp::Smp::set_pairing_agent] for LE pairing and with
+//! [crate::hci::Hci::set_pairing_agent] for BR/EDR Secure Simple Pairing.
+//!
+//! The agent is called while the security manager or the ``Hci`` is locked, so it must not call
+//! back into them. A request the agent can not answer at once is answered with
+//! [AgentReply::Later], the answer is passed later with the function named in the request.
+//!
+
+use crate::alloc::boxed::Box;
+use crate::alloc::sync::Arc;
+use crate::hci::connection::ConnectionTransport;
+use crate::hci::BD_ADDRESS_SIZE;
+use crate::lock::*;
+use crate::smp::IoCapability;
+
+/// The peer a request of the agent relates to
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PairingPeer {
+    pub transport: ConnectionTransport,
+    /// the handle of the connection to the peer, ``None`` if the BT host pairs with a BR/EDR
+    /// peer before the connection is known
+    pub handle: Option<u16>,
+    pub address: [u8; BD_ADDRESS_SIZE],
+}
+
+/// The answer of the agent to a request
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AgentReply<T> {
+    /// the request is answered with the value given
+    Now(T),
+    /// the request is answered later
+    Later,
+}
+
+/// The interaction of pairing with the user
+pub trait PairingAgent: Send {
+    /// The input and output capabilities of this device
+    fn io_capability(&self) -> IoCapability;
+
+    /// Display the passkey the user enters on the peer
+    fn display_passkey(&mut self, peer: PairingPeer, passkey: u32);
+
+    /// The user need to enter the passkey displayed by the peer, ``None`` cancels the pairing.
+    /// The answer is passed later with [crate::smp::Smp::enter_passkey] or
+    /// [crate::hci::Hci::reply_user_passkey].
+    fn request_passkey(&mut self, peer: PairingPeer) -> AgentReply<Option<u32>>;
+
+    /// Display the six digit value the user confirms to match the one displayed by the peer.
+    /// The answer is passed later with [crate::smp::Smp::confirm_value] or
+    /// [crate::hci::Hci::reply_user_confirmation].
+    fn confirm_value(&mut self, peer: PairingPeer, value: u32) -> AgentReply<bool>;
+
+    /// The peer requests to pair without authentication, which can not involve the user
+    /// otherwise. The answer is passed later with [crate::smp::Smp::authorize] or
+    /// [crate::hci::Hci::reply_user_confirmation].
+    fn authorize(&mut self, peer: PairingPeer) -> AgentReply<bool>;
+}
+
+/// The pairing agent shared by the security manager and the ``Hci``
+pub type SharedPairingAgent = Arc<DataLock<Box<dyn PairingAgent>>>;
+
+/// Share the given agent to pass it to the security manager and the ``Hci``
+pub fn shared<A>(agent: A) -> SharedPairingAgent
+where
+    A: PairingAgent + 'static,
+{
+    Arc::new(DataLock::new(Box::new(agent)))
+}
@@ -0,0 +1,86 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # ATT Errors
+//!
+
+use crate::error::Error;
+
+/// The attribute handle given is not valid on this server
+pub const ATT_ERROR_INVALID_HANDLE: u8 = 0x01;
+/// The attribute can not be read
+pub const ATT_ERROR_READ_NOT_PERMITTED: u8 = 0x02;
+/// The attribute can not be written
+pub const ATT_ERROR_WRITE_NOT_PERMITTED: u8 = 0x03;
+/// The attribute PDU was invalid
+pub const ATT_ERROR_INVALID_PDU: u8 = 0x04;
+/// The attribute requires authentication before it can be read or written
+pub const ATT_ERROR_INSUFFICIENT_AUTHENTICATION: u8 = 0x05;
+/// The attribute server does not support the request received
+pub const ATT_ERROR_REQUEST_NOT_SUPPORTED: u8 = 0x06;
+/// The offset specified was past the end of the attribute
+pub const ATT_ERROR_INVALID_OFFSET: u8 = 0x07;
+/// The attribute requires authorization before it can be read or written
+pub const ATT_ERROR_INSUFFICIENT_AUTHORIZATION: u8 = 0x08;
+/// Too many prepare writes have been queued
+pub const ATT_ERROR_PREPARE_QUEUE_FULL: u8 = 0x09;
+/// No attribute found within the given handle range
+pub const ATT_ERROR_ATTRIBUTE_NOT_FOUND: u8 = 0x0A;
+/// The attribute can not be read using the Read Blob Request
+pub const ATT_ERROR_ATTRIBUTE_NOT_LONG: u8 = 0x0B;
+/// The encryption key size used for encrypting this link is insufficient
+pub const ATT_ERROR_INSUFFICIENT_ENCRYPTION_KEY_SIZE: u8 = 0x0C;
+/// The attribute value length is invalid for the operation
+pub const ATT_ERROR_INVALID_ATTRIBUTE_VALUE_LENGTH: u8 = 0x0D;
+/// The request has encountered an unlikely error and could not be completed
+pub const ATT_ERROR_UNLIKELY_ERROR: u8 = 0x0E;
+/// The attribute requires encryption before it can be read or written
+pub const ATT_ERROR_INSUFFICIENT_ENCRYPTION: u8 = 0x0F;
+/// The attribute type is not a supported grouping attribute
+pub const ATT_ERROR_UNSUPPORTED_GROUP_TYPE: u8 = 0x10;
+/// Insufficient resources to complete the request
+pub const ATT_ERROR_INSUFFICIENT_RESOURCES: u8 = 0x11;
+
+/// Error of an attribute request. This is either received from the peer with an Error Response
+/// or raised locally if a PDU could not be decoded and is answered with an Error Response.
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub struct AttError {
+    /// opcode of the request that caused the error
+    pub request: u8,
+    /// attribute handle that caused the error, 0 if the error is not related to a handle
+    pub handle: u16,
+    /// one of the ``ATT_ERROR_*`` codes
+    pub code: u8,
+}
+
+impl AttError {
+    pub fn new(request: u8, handle: u16, code: u8) -> Self {
+        Self {
+            request,
+            handle,
+            code,
+        }
+    }
+}
+
+impl Error for AttError {}
+
+impl core::fmt::Display for AttError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "ATT error {:#X} for request {:#X} on handle {:#X}",
+            self.code, self.request, self.handle
+        )
+    }
+}
+
+impl core::fmt::Debug for AttError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        <AttError as core::fmt::Display>::fmt(self, f)
+    }
+}
@@ -0,0 +1,244 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # Attribute Protocol
+//!
+//! The attribute protocol runs on the L2CAP fixed channel 0x0004 of each connection. It allows a
+//! client to discover, read and write the attributes exposed by a server and a server to notify
+//! or indicate attribute values to a client. Both roles share the channel, the PDU's received
+//! are handed to the role they are addressed to.
+//!
+
+pub mod errors;
+pub use errors::*;
+pub mod pdu;
+pub use pdu::*;
+
+use crate::alloc::collections::{BTreeMap, VecDeque};
+use crate::alloc::sync::Arc;
+use crate::brain::{waker::*, *};
+use crate::error::BoxError;
+use crate::hci::acl::SendAclThinkable;
+use crate::hci::connection::Connection;
+use crate::hci::BD_ADDRESS_SIZE;
+use crate::hctl::HcTransportLayer;
+use crate::l2cap::{FixedChannel, L2cap, RecvFixedChannelThinkable, CID_ATT};
+use crate::lock::*;
+use crate::pin::Pin;
+
+/// The default and minimum ATT_MTU of LE connections
+pub const ATT_DEFAULT_MTU_LE: u16 = 23;
+/// The minimum ATT_MTU of BR/EDR connections
+pub const ATT_MIN_MTU_BREDR: u16 = 48;
+/// The maximum length of an attribute value
+pub const ATT_MAX_VALUE_LENGTH: usize = 512;
+/// Time in milliseconds a request or indication waits for its response before the transaction
+/// has failed
+pub const ATT_TRANSACTION_TIMEOUT: u64 = 30_000;
+/// Maximum number of PDU's kept per role until they are taken. If there are more PDU's received
+/// the oldest ones are dropped.
+const MAX_PENDING_PDUS: usize = 16;
+
+/// The role a PDU is addressed to
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AttRole {
+    /// requests, commands and confirmations
+    Server,
+    /// responses, notifications and indications
+    Client,
+}
+
+impl AttRole {
+    /// The role the PDU received is addressed to. PDU's that could not be decoded are addressed
+    /// to the server if they are requests or commands to let the server answer them.
+    pub fn of(pdu: &Result<AttPdu, AttError>) -> Self {
+        let opcode = match pdu {
+            Ok(pdu) => pdu.opcode(),
+            Err(error) => match AttOpcode::from(error.request) {
+                AttOpcode::Unknown => return AttRole::Server,
+                opcode => opcode,
+            },
+        };
+        match opcode {
+            AttOpcode::WriteCommand
+            | AttOpcode::SignedWriteCommand
+            | AttOpcode::HandleValueConfirmation => AttRole::Server,
+            opcode if opcode.is_request() => AttRole::Server,
+            _ => AttRole::Client,
+        }
+    }
+}
+
+/// The PDU's received for each role and the ATT_MTU of the connections
+struct AttShared {
+    server_pdus: VecDeque<(u16, Result<AttPdu, AttError>)>,
+    server_waker: Option<Waker>,
+    client_pdus: VecDeque<(u16, Result<AttPdu, AttError>)>,
+    client_waker: Option<Waker>,
+    /// the ATT_MTU by connection handle together with the peer it was agreed with
+    mtus: BTreeMap<u16, ([u8; BD_ADDRESS_SIZE], u16)>,
+}
+
+impl AttShared {
+    fn pdus(&mut self, role: AttRole) -> &mut VecDeque<(u16, Result<AttPdu, AttError>)> {
+        match role {
+            AttRole::Server => &mut self.server_pdus,
+            AttRole::Client => &mut self.client_pdus,
+        }
+    }
+
+    fn waker(&mut self, role: AttRole) -> &mut Option<Waker> {
+        match role {
+            AttRole::Server => &mut self.server_waker,
+            AttRole::Client => &mut self.client_waker,
+        }
+    }
+}
+
+/// The attribute bearer sending and receiving ATT PDU's on the fixed channel of all connections.
+/// It is cloned to be shared between the GATT server and client.
+pub struct Att<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    channel: Arc<FixedChannel<T>>,
+    shared: Arc<DataLock<AttShared>>,
+}
+
+impl<T> Clone for Att<T>
+where
+    T: HcTransportLayer,
+{
+    fn clone(&self) -> Self {
+        Self {
+            channel: self.channel.clone(),
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Att<T>
+where
+    T: HcTransportLayer,
+{
+    /// Register the attribute protocol channel with L2CAP. This can only be done once.
+    pub fn new(l2cap: Arc<DataLock<L2cap<T>>>) -> Result<Self, BoxError> {
+        let channel = L2cap::register_fixed_channel(l2cap, CID_ATT)?;
+        Ok(Self {
+            channel: Arc::new(channel),
+            shared: Arc::new(DataLock::new(AttShared {
+                server_pdus: VecDeque::new(),
+                server_waker: None,
+                client_pdus: VecDeque::new(),
+                client_waker: None,
+                mtus: BTreeMap::new(),
+            })),
+        })
+    }
+
+    /// Send the PDU to the peer of the connection with the given handle
+    pub fn send(&self, handle: u16, pdu: &AttPdu) -> SendAclThinkable<T> {
+        self.channel.send(handle, &pdu.to_bytes())
+    }
+
+    /// Queue the PDU to the peer of the connection with the given handle without waiting for it
+    /// to be send. PDU's queued are send in order.
+    pub fn queue(&self, handle: u16, pdu: &AttPdu) {
+        self.channel.queue(handle, &pdu.to_bytes());
+    }
+
+    /// The connection with the given handle if it is established
+    pub fn connection(&self, handle: u16) -> Option<Connection<T>> {
+        self.channel.connection(handle)
+    }
+
+    /// The ATT_MTU of the connection with the given handle
+    pub fn mtu(&self, handle: u16) -> u16 {
+        let peer_address = match self.connection(handle) {
+            Some(connection) => connection.peer_address(),
+            None => return ATT_DEFAULT_MTU_LE,
+        };
+        match self.shared.read().mtus.get(&handle) {
+            // the handle may have been reused for a connection to another peer
+            Some((peer, mtu)) if *peer == peer_address => *mtu,
+            _ => ATT_DEFAULT_MTU_LE,
+        }
+    }
+
+    /// Set the ATT_MTU agreed with the peer of the connection with the given handle
+    pub fn set_mtu(&self, handle: u16, mtu: u16) {
+        if let Some(connection) = self.connection(handle) {
+            let mtu = mtu.max(ATT_DEFAULT_MTU_LE);
+            self.shared
+                .lock()
+                .mtus
+                .insert(handle, (connection.peer_address(), mtu));
+        }
+    }
+
+    /// Returns a ``Thinkable`` that concludes with the next PDU addressed to the given role
+    /// received from any connection together with the connection handle. If the PDU could not be
+    /// decoded the error is concluded instead, that is answered with an Error Response if the PDU
+    /// was a request.
+    pub fn recv(&self, role: AttRole) -> RecvAttThinkable<T> {
+        RecvAttThinkable {
+            shared: self.shared.clone(),
+            recv: self.channel.recv(),
+            role,
+        }
+    }
+}
+
+/// This ``Thinkable`` concludes with the next ATT PDU received for a role. PDU's addressed to the
+/// other role are handed over to it.
+pub struct RecvAttThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    shared: Arc<DataLock<AttShared>>,
+    recv: RecvFixedChannelThinkable<T>,
+    role: AttRole,
+}
+
+impl<T> Thinkable for RecvAttThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = (u16, Result<AttPdu, AttError>);
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        let this = self.get_mut();
+        // the shared state stays locked to not miss a PDU handed over by the other role
+        let mut shared = this.shared.lock();
+        if let Some(pdu) = shared.pdus(this.role).pop_front() {
+            return Conclusion::Ready(pdu);
+        }
+        loop {
+            match Pin::new(&mut this.recv).think(cx) {
+                Conclusion::Pending => {
+                    shared.waker(this.role).replace(cx.waker().clone());
+                    return Conclusion::Pending;
+                }
+                Conclusion::Ready((handle, pdu)) => {
+                    let pdu = AttPdu::parse(&pdu);
+                    let role = AttRole::of(&pdu);
+                    if role == this.role {
+                        return Conclusion::Ready((handle, pdu));
+                    }
+                    let pdus = shared.pdus(role);
+                    if pdus.len() >= MAX_PENDING_PDUS {
+                        pdus.pop_front();
+                    }
+                    pdus.push_back((handle, pdu));
+                    if let Some(waker) = shared.waker(role).take() {
+                        waker.wake();
+                    }
+                }
+            }
+        }
+    }
+}
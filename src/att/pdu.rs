@@ -0,0 +1,844 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # ATT PDU's
+//!
+//! Encoding and decoding of the attribute protocol PDU's. Each PDU starts with the opcode
+//! followed by the parameters in little endian order.
+//!
+
+use super::errors::*;
+use crate::alloc::vec::Vec;
+use crate::l2cap::signaling::{get_u16, put_u16};
+use crate::uuid::Uuid;
+
+/// Bit of the opcode marking a command that is not answered by the server
+pub const ATT_COMMAND_FLAG: u8 = 0x40;
+/// Bit of the opcode marking a PDU carrying an authentication signature
+pub const ATT_SIGNATURE_FLAG: u8 = 0x80;
+/// Size of the authentication signature of a Signed Write Command
+pub const ATT_SIGNATURE_SIZE: usize = 12;
+/// Execute Write Request flag cancelling all prepared writes
+pub const ATT_EXECUTE_WRITE_CANCEL: u8 = 0x00;
+/// Execute Write Request flag writing all prepared values
+pub const ATT_EXECUTE_WRITE_COMMIT: u8 = 0x01;
+
+/// Find Information Response format of 16 bit UUID's
+const FORMAT_UUID16: u8 = 0x01;
+/// Find Information Response format of 128 bit UUID's
+const FORMAT_UUID128: u8 = 0x02;
+
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AttOpcode {
+    ErrorResponse = 0x01,
+    ExchangeMtuRequest = 0x02,
+    ExchangeMtuResponse = 0x03,
+    FindInformationRequest = 0x04,
+    FindInformationResponse = 0x05,
+    FindByTypeValueRequest = 0x06,
+    FindByTypeValueResponse = 0x07,
+    ReadByTypeRequest = 0x08,
+    ReadByTypeResponse = 0x09,
+    ReadRequest = 0x0A,
+    ReadResponse = 0x0B,
+    ReadBlobRequest = 0x0C,
+    ReadBlobResponse = 0x0D,
+    ReadMultipleRequest = 0x0E,
+    ReadMultipleResponse = 0x0F,
+    ReadByGroupTypeRequest = 0x10,
+    ReadByGroupTypeResponse = 0x11,
+    WriteRequest = 0x12,
+    WriteResponse = 0x13,
+    PrepareWriteRequest = 0x16,
+    PrepareWriteResponse = 0x17,
+    ExecuteWriteRequest = 0x18,
+    ExecuteWriteResponse = 0x19,
+    HandleValueNotification = 0x1B,
+    HandleValueIndication = 0x1D,
+    HandleValueConfirmation = 0x1E,
+    WriteCommand = 0x52,
+    SignedWriteCommand = 0xD2,
+    Unknown,
+}
+
+impl From<u8> for AttOpcode {
+    fn from(orig: u8) -> Self {
+        match orig {
+            0x01 => AttOpcode::ErrorResponse,
+            0x02 => AttOpcode::ExchangeMtuRequest,
+            0x03 => AttOpcode::ExchangeMtuResponse,
+            0x04 => AttOpcode::FindInformationRequest,
+            0x05 => AttOpcode::FindInformationResponse,
+            0x06 => AttOpcode::FindByTypeValueRequest,
+            0x07 => AttOpcode::FindByTypeValueResponse,
+            0x08 => AttOpcode::ReadByTypeRequest,
+            0x09 => AttOpcode::ReadByTypeResponse,
+            0x0A => AttOpcode::ReadRequest,
+            0x0B => AttOpcode::ReadResponse,
+            0x0C => AttOpcode::ReadBlobRequest,
+            0x0D => AttOpcode::ReadBlobResponse,
+            0x0E => AttOpcode::ReadMultipleRequest,
+            0x0F => AttOpcode::ReadMultipleResponse,
+            0x10 => AttOpcode::ReadByGroupTypeRequest,
+            0x11 => AttOpcode::ReadByGroupTypeResponse,
+            0x12 => AttOpcode::WriteRequest,
+            0x13 => AttOpcode::WriteResponse,
+            0x16 => AttOpcode::PrepareWriteRequest,
+            0x17 => AttOpcode::PrepareWriteResponse,
+            0x18 => AttOpcode::ExecuteWriteRequest,
+            0x19 => AttOpcode::ExecuteWriteResponse,
+            0x1B => AttOpcode::HandleValueNotification,
+            0x1D => AttOpcode::HandleValueIndication,
+            0x1E => AttOpcode::HandleValueConfirmation,
+            0x52 => AttOpcode::WriteCommand,
+            0xD2 => AttOpcode::SignedWriteCommand,
+            _ => AttOpcode::Unknown,
+        }
+    }
+}
+
+impl AttOpcode {
+    /// Whether the PDU with this opcode is a request the server need to answer
+    pub fn is_request(self) -> bool {
+        match self {
+            AttOpcode::ExchangeMtuRequest
+            | AttOpcode::FindInformationRequest
+            | AttOpcode::FindByTypeValueRequest
+            | AttOpcode::ReadByTypeRequest
+            | AttOpcode::ReadRequest
+            | AttOpcode::ReadBlobRequest
+            | AttOpcode::ReadMultipleRequest
+            | AttOpcode::ReadByGroupTypeRequest
+            | AttOpcode::WriteRequest
+            | AttOpcode::PrepareWriteRequest
+            | AttOpcode::ExecuteWriteRequest => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the PDU with this opcode is a response to a request send by this device
+    pub fn is_response(self) -> bool {
+        match self {
+            AttOpcode::ErrorResponse
+            | AttOpcode::ExchangeMtuResponse
+            | AttOpcode::FindInformationResponse
+            | AttOpcode::FindByTypeValueResponse
+            | AttOpcode::ReadByTypeResponse
+            | AttOpcode::ReadResponse
+            | AttOpcode::ReadBlobResponse
+            | AttOpcode::ReadMultipleResponse
+            | AttOpcode::ReadByGroupTypeResponse
+            | AttOpcode::WriteResponse
+            | AttOpcode::PrepareWriteResponse
+            | AttOpcode::ExecuteWriteResponse => true,
+            _ => false,
+        }
+    }
+}
+
+/// Decode the UUID of an attribute type from the given data which need to be either 2 or 16
+/// bytes long
+pub fn parse_uuid(data: &[u8]) -> Option<Uuid> {
+    match data.len() {
+        2 | 16 => Uuid::from_le_bytes(data),
+        _ => None,
+    }
+}
+
+/// The number of bytes of the UUID in ATT PDU's. UUID's without a 16 bit short form are
+/// transferred in the full 128 bit form.
+pub fn uuid_size(uuid: &Uuid) -> usize {
+    if uuid.as_u16().is_some() {
+        2
+    } else {
+        16
+    }
+}
+
+/// Append the little endian encoded UUID to the data
+pub fn put_uuid(data: &mut Vec<u8>, uuid: &Uuid) {
+    match uuid.as_u16() {
+        Some(uuid) => put_u16(data, uuid),
+        None => data.extend_from_slice(&uuid.to_le_bytes_full()),
+    }
+}
+
+/// One attribute protocol PDU
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum AttPdu {
+    ErrorResponse(AttError),
+    ExchangeMtuRequest {
+        mtu: u16,
+    },
+    ExchangeMtuResponse {
+        mtu: u16,
+    },
+    FindInformationRequest {
+        start: u16,
+        end: u16,
+    },
+    /// All UUID's need to have the same size. Encoding stops at the first UUID of a different
+    /// size than the first one.
+    FindInformationResponse {
+        information: Vec<(u16, Uuid)>,
+    },
+    FindByTypeValueRequest {
+        start: u16,
+        end: u16,
+        attribute_type: u16,
+        value: Vec<u8>,
+    },
+    /// The found attribute handles together with the end of their group
+    FindByTypeValueResponse {
+        handles: Vec<(u16, u16)>,
+    },
+    ReadByTypeRequest {
+        start: u16,
+        end: u16,
+        attribute_type: Uuid,
+    },
+    /// All values need to have the same length. Encoding stops at the first value of a
+    /// different length than the first one.
+    ReadByTypeResponse {
+        values: Vec<(u16, Vec<u8>)>,
+    },
+    ReadRequest {
+        handle: u16,
+    },
+    ReadResponse {
+        value: Vec<u8>,
+    },
+    ReadBlobRequest {
+        handle: u16,
+        offset: u16,
+    },
+    ReadBlobResponse {
+        value: Vec<u8>,
+    },
+    ReadMultipleRequest {
+        handles: Vec<u16>,
+    },
+    /// The concatenated values of the attributes requested
+    ReadMultipleResponse {
+        values: Vec<u8>,
+    },
+    ReadByGroupTypeRequest {
+        start: u16,
+        end: u16,
+        group_type: Uuid,
+    },
+    /// The attribute handle, end group handle and value of each group found. All values need to
+    /// have the same length. Encoding stops at the first value of a different length than the
+    /// first one.
+    ReadByGroupTypeResponse {
+        groups: Vec<(u16, u16, Vec<u8>)>,
+    },
+    WriteRequest {
+        handle: u16,
+        value: Vec<u8>,
+    },
+    WriteResponse,
+    WriteCommand {
+        handle: u16,
+        value: Vec<u8>,
+    },
+    SignedWriteCommand {
+        handle: u16,
+        value: Vec<u8>,
+        signature: [u8; ATT_SIGNATURE_SIZE],
+    },
+    PrepareWriteRequest {
+        handle: u16,
+        offset: u16,
+        value: Vec<u8>,
+    },
+    PrepareWriteResponse {
+        handle: u16,
+        offset: u16,
+        value: Vec<u8>,
+    },
+    /// The flags are either [ATT_EXECUTE_WRITE_CANCEL] or [ATT_EXECUTE_WRITE_COMMIT]
+    ExecuteWriteRequest {
+        flags: u8,
+    },
+    ExecuteWriteResponse,
+    HandleValueNotification {
+        handle: u16,
+        value: Vec<u8>,
+    },
+    HandleValueIndication {
+        handle: u16,
+        value: Vec<u8>,
+    },
+    HandleValueConfirmation,
+}
+
+impl AttPdu {
+    pub fn opcode(&self) -> AttOpcode {
+        match self {
+            AttPdu::ErrorResponse(_) => AttOpcode::ErrorResponse,
+            AttPdu::ExchangeMtuRequest { .. } => AttOpcode::ExchangeMtuRequest,
+            AttPdu::ExchangeMtuResponse { .. } => AttOpcode::ExchangeMtuResponse,
+            AttPdu::FindInformationRequest { .. } => AttOpcode::FindInformationRequest,
+            AttPdu::FindInformationResponse { .. } => AttOpcode::FindInformationResponse,
+            AttPdu::FindByTypeValueRequest { .. } => AttOpcode::FindByTypeValueRequest,
+            AttPdu::FindByTypeValueResponse { .. } => AttOpcode::FindByTypeValueResponse,
+            AttPdu::ReadByTypeRequest { .. } => AttOpcode::ReadByTypeRequest,
+            AttPdu::ReadByTypeResponse { .. } => AttOpcode::ReadByTypeResponse,
+            AttPdu::ReadRequest { .. } => AttOpcode::ReadRequest,
+            AttPdu::ReadResponse { .. } => AttOpcode::ReadResponse,
+            AttPdu::ReadBlobRequest { .. } => AttOpcode::ReadBlobRequest,
+            AttPdu::ReadBlobResponse { .. } => AttOpcode::ReadBlobResponse,
+            AttPdu::ReadMultipleRequest { .. } => AttOpcode::ReadMultipleRequest,
+            AttPdu::ReadMultipleResponse { .. } => AttOpcode::ReadMultipleResponse,
+            AttPdu::ReadByGroupTypeRequest { .. } => AttOpcode::ReadByGroupTypeRequest,
+            AttPdu::ReadByGroupTypeResponse { .. } => AttOpcode::ReadByGroupTypeResponse,
+            AttPdu::WriteRequest { .. } => AttOpcode::WriteRequest,
+            AttPdu::WriteResponse => AttOpcode::WriteResponse,
+            AttPdu::WriteCommand { .. } => AttOpcode::WriteCommand,
+            AttPdu::SignedWriteCommand { .. } => AttOpcode::SignedWriteCommand,
+            AttPdu::PrepareWriteRequest { .. } => AttOpcode::PrepareWriteRequest,
+            AttPdu::PrepareWriteResponse { .. } => AttOpcode::PrepareWriteResponse,
+            AttPdu::ExecuteWriteRequest { .. } => AttOpcode::ExecuteWriteRequest,
+            AttPdu::ExecuteWriteResponse => AttOpcode::ExecuteWriteResponse,
+            AttPdu::HandleValueNotification { .. } => AttOpcode::HandleValueNotification,
+            AttPdu::HandleValueIndication { .. } => AttOpcode::HandleValueIndication,
+            AttPdu::HandleValueConfirmation => AttOpcode::HandleValueConfirmation,
+        }
+    }
+
+    /// Decode an ATT PDU. If it could not be decoded the error contains the code the server
+    /// answers the PDU with.
+    pub fn parse(pdu: &[u8]) -> Result<Self, AttError> {
+        let raw_opcode = match pdu.first() {
+            Some(opcode) => *opcode,
+            None => return Err(AttError::new(0, 0, ATT_ERROR_INVALID_PDU)),
+        };
+        let invalid = AttError::new(raw_opcode, 0, ATT_ERROR_INVALID_PDU);
+        let params = &pdu[1..];
+        // ensure the parameters have at least the given length
+        let expect = |length: usize| {
+            if params.len() < length {
+                Err(invalid)
+            } else {
+                Ok(())
+            }
+        };
+        let u16_at = |offset: usize| get_u16(params, offset);
+
+        let decoded = match AttOpcode::from(raw_opcode) {
+            AttOpcode::ErrorResponse => {
+                expect(4)?;
+                AttPdu::ErrorResponse(AttError::new(params[0], u16_at(1), params[3]))
+            }
+            AttOpcode::ExchangeMtuRequest => {
+                expect(2)?;
+                AttPdu::ExchangeMtuRequest { mtu: u16_at(0) }
+            }
+            AttOpcode::ExchangeMtuResponse => {
+                expect(2)?;
+                AttPdu::ExchangeMtuResponse { mtu: u16_at(0) }
+            }
+            AttOpcode::FindInformationRequest => {
+                expect(4)?;
+                AttPdu::FindInformationRequest {
+                    start: u16_at(0),
+                    end: u16_at(2),
+                }
+            }
+            AttOpcode::FindInformationResponse => {
+                expect(1)?;
+                let uuid_size = match params[0] {
+                    FORMAT_UUID16 => 2,
+                    FORMAT_UUID128 => 16,
+                    _ => return Err(invalid),
+                };
+                let information = params[1..]
+                    .chunks_exact(2 + uuid_size)
+                    .filter_map(|entry| {
+                        parse_uuid(&entry[2..]).map(|uuid| (get_u16(entry, 0), uuid))
+                    })
+                    .collect();
+                AttPdu::FindInformationResponse { information }
+            }
+            AttOpcode::FindByTypeValueRequest => {
+                expect(6)?;
+                AttPdu::FindByTypeValueRequest {
+                    start: u16_at(0),
+                    end: u16_at(2),
+                    attribute_type: u16_at(4),
+                    value: params[6..].to_vec(),
+                }
+            }
+            AttOpcode::FindByTypeValueResponse => AttPdu::FindByTypeValueResponse {
+                handles: params
+                    .chunks_exact(4)
+                    .map(|entry| (get_u16(entry, 0), get_u16(entry, 2)))
+                    .collect(),
+            },
+            AttOpcode::ReadByTypeRequest => {
+                expect(6)?;
+                AttPdu::ReadByTypeRequest {
+                    start: u16_at(0),
+                    end: u16_at(2),
+                    attribute_type: parse_uuid(&params[4..]).ok_or(invalid)?,
+                }
+            }
+            AttOpcode::ReadByTypeResponse => {
+                expect(1)?;
+                let length = params[0] as usize;
+                if length < 2 {
+                    return Err(invalid);
+                }
+                AttPdu::ReadByTypeResponse {
+                    values: params[1..]
+                        .chunks_exact(length)
+                        .map(|entry| (get_u16(entry, 0), entry[2..].to_vec()))
+                        .collect(),
+                }
+            }
+            AttOpcode::ReadRequest => {
+                expect(2)?;
+                AttPdu::ReadRequest { handle: u16_at(0) }
+            }
+            AttOpcode::ReadResponse => AttPdu::ReadResponse {
+                value: params.to_vec(),
+            },
+            AttOpcode::ReadBlobRequest => {
+                expect(4)?;
+                AttPdu::ReadBlobRequest {
+                    handle: u16_at(0),
+                    offset: u16_at(2),
+                }
+            }
+            AttOpcode::ReadBlobResponse => AttPdu::ReadBlobResponse {
+                value: params.to_vec(),
+            },
+            AttOpcode::ReadMultipleRequest => {
+                // at least two handles are requested
+                expect(4)?;
+                AttPdu::ReadMultipleRequest {
+                    handles: params
+                        .chunks_exact(2)
+                        .map(|handle| get_u16(handle, 0))
+                        .collect(),
+                }
+            }
+            AttOpcode::ReadMultipleResponse => AttPdu::ReadMultipleResponse {
+                values: params.to_vec(),
+            },
+            AttOpcode::ReadByGroupTypeRequest => {
+                expect(6)?;
+                AttPdu::ReadByGroupTypeRequest {
+                    start: u16_at(0),
+                    end: u16_at(2),
+                    group_type: parse_uuid(&params[4..]).ok_or(invalid)?,
+                }
+            }
+            AttOpcode::ReadByGroupTypeResponse => {
+                expect(1)?;
+                let length = params[0] as usize;
+                if length < 4 {
+                    return Err(invalid);
+                }
+                AttPdu::ReadByGroupTypeResponse {
+                    groups: params[1..]
+                        .chunks_exact(length)
+                        .map(|entry| (get_u16(entry, 0), get_u16(entry, 2), entry[4..].to_vec()))
+                        .collect(),
+                }
+            }
+            AttOpcode::WriteRequest => {
+                expect(2)?;
+                AttPdu::WriteRequest {
+                    handle: u16_at(0),
+                    value: params[2..].to_vec(),
+                }
+            }
+            AttOpcode::WriteResponse => AttPdu::WriteResponse,
+            AttOpcode::WriteCommand => {
+                expect(2)?;
+                AttPdu::WriteCommand {
+                    handle: u16_at(0),
+                    value: params[2..].to_vec(),
+                }
+            }
+            AttOpcode::SignedWriteCommand => {
+                expect(2 + ATT_SIGNATURE_SIZE)?;
+                let value_end = params.len() - ATT_SIGNATURE_SIZE;
+                let mut signature = [0; ATT_SIGNATURE_SIZE];
+                signature.copy_from_slice(&params[value_end..]);
+                AttPdu::SignedWriteCommand {
+                    handle: u16_at(0),
+                    value: params[2..value_end].to_vec(),
+                    signature,
+                }
+            }
+            AttOpcode::PrepareWriteRequest => {
+                expect(4)?;
+                AttPdu::PrepareWriteRequest {
+                    handle: u16_at(0),
+                    offset: u16_at(2),
+                    value: params[4..].to_vec(),
+                }
+            }
+            AttOpcode::PrepareWriteResponse => {
+                expect(4)?;
+                AttPdu::PrepareWriteResponse {
+                    handle: u16_at(0),
+                    offset: u16_at(2),
+                    value: params[4..].to_vec(),
+                }
+            }
+            AttOpcode::ExecuteWriteRequest => {
+                expect(1)?;
+                if params[0] > ATT_EXECUTE_WRITE_COMMIT {
+                    return Err(invalid);
+                }
+                AttPdu::ExecuteWriteRequest { flags: params[0] }
+            }
+            AttOpcode::ExecuteWriteResponse => AttPdu::ExecuteWriteResponse,
+            AttOpcode::HandleValueNotification => {
+                expect(2)?;
+                AttPdu::HandleValueNotification {
+                    handle: u16_at(0),
+                    value: params[2..].to_vec(),
+                }
+            }
+            AttOpcode::HandleValueIndication => {
+                expect(2)?;
+                AttPdu::HandleValueIndication {
+                    handle: u16_at(0),
+                    value: params[2..].to_vec(),
+                }
+            }
+            AttOpcode::HandleValueConfirmation => AttPdu::HandleValueConfirmation,
+            AttOpcode::Unknown => {
+                return Err(AttError::new(
+                    raw_opcode,
+                    0,
+                    ATT_ERROR_REQUEST_NOT_SUPPORTED,
+                ))
+            }
+        };
+        Ok(decoded)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.push(self.opcode() as u8);
+        match self {
+            AttPdu::ErrorResponse(error) => {
+                bytes.push(error.request);
+                put_u16(&mut bytes, error.handle);
+                bytes.push(error.code);
+            }
+            AttPdu::ExchangeMtuRequest { mtu } | AttPdu::ExchangeMtuResponse { mtu } => {
+                put_u16(&mut bytes, *mtu)
+            }
+            AttPdu::FindInformationRequest { start, end } => {
+                put_u16(&mut bytes, *start);
+                put_u16(&mut bytes, *end);
+            }
+            AttPdu::FindInformationResponse { information } => {
+                let size = information.first().map_or(2, |(_, uuid)| uuid_size(uuid));
+                bytes.push(if size == 2 {
+                    FORMAT_UUID16
+                } else {
+                    FORMAT_UUID128
+                });
+                for (handle, uuid) in information
+                    .iter()
+                    .take_while(|(_, uuid)| uuid_size(uuid) == size)
+                {
+                    put_u16(&mut bytes, *handle);
+                    put_uuid(&mut bytes, uuid);
+                }
+            }
+            AttPdu::FindByTypeValueRequest {
+                start,
+                end,
+                attribute_type,
+                value,
+            } => {
+                put_u16(&mut bytes, *start);
+                put_u16(&mut bytes, *end);
+                put_u16(&mut bytes, *attribute_type);
+                bytes.extend_from_slice(value);
+            }
+            AttPdu::FindByTypeValueResponse { handles } => {
+                for (handle, group_end) in handles {
+                    put_u16(&mut bytes, *handle);
+                    put_u16(&mut bytes, *group_end);
+                }
+            }
+            AttPdu::ReadByTypeRequest {
+                start,
+                end,
+                attribute_type: uuid,
+            }
+            | AttPdu::ReadByGroupTypeRequest {
+                start,
+                end,
+                group_type: uuid,
+            } => {
+                put_u16(&mut bytes, *start);
+                put_u16(&mut bytes, *end);
+                put_uuid(&mut bytes, uuid);
+            }
+            AttPdu::ReadByTypeResponse { values } => {
+                let length = values.first().map_or(0, |(_, value)| value.len());
+                bytes.push((2 + length) as u8);
+                for (handle, value) in values.iter().take_while(|(_, value)| value.len() == length)
+                {
+                    put_u16(&mut bytes, *handle);
+                    bytes.extend_from_slice(value);
+                }
+            }
+            AttPdu::ReadRequest { handle } => put_u16(&mut bytes, *handle),
+            AttPdu::ReadResponse { value }
+            | AttPdu::ReadBlobResponse { value }
+            | AttPdu::ReadMultipleResponse { values: value } => bytes.extend_from_slice(value),
+            AttPdu::ReadBlobRequest { handle, offset } => {
+                put_u16(&mut bytes, *handle);
+                put_u16(&mut bytes, *offset);
+            }
+            AttPdu::ReadMultipleRequest { handles } => {
+                for handle in handles {
+                    put_u16(&mut bytes, *handle);
+                }
+            }
+            AttPdu::ReadByGroupTypeResponse { groups } => {
+                let length = groups.first().map_or(0, |(_, _, value)| value.len());
+                bytes.push((4 + length) as u8);
+                for (handle, group_end, value) in groups
+                    .iter()
+                    .take_while(|(_, _, value)| value.len() == length)
+                {
+                    put_u16(&mut bytes, *handle);
+                    put_u16(&mut bytes, *group_end);
+                    bytes.extend_from_slice(value);
+                }
+            }
+            AttPdu::WriteRequest { handle, value }
+            | AttPdu::WriteCommand { handle, value }
+            | AttPdu::HandleValueNotification { handle, value }
+            | AttPdu::HandleValueIndication { handle, value } => {
+                put_u16(&mut bytes, *handle);
+                bytes.extend_from_slice(value);
+            }
+            AttPdu::SignedWriteCommand {
+                handle,
+                value,
+                signature,
+            } => {
+                put_u16(&mut bytes, *handle);
+                bytes.extend_from_slice(value);
+                bytes.extend_from_slice(signature);
+            }
+            AttPdu::PrepareWriteRequest {
+                handle,
+                offset,
+                value,
+            }
+            | AttPdu::PrepareWriteResponse {
+                handle,
+                offset,
+                value,
+            } => {
+                put_u16(&mut bytes, *handle);
+                put_u16(&mut bytes, *offset);
+                bytes.extend_from_slice(value);
+            }
+            AttPdu::ExecuteWriteRequest { flags } => bytes.push(*flags),
+            AttPdu::WriteResponse
+            | AttPdu::ExecuteWriteResponse
+            | AttPdu::HandleValueConfirmation => (),
+        }
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alloc::vec;
+
+    fn round_trip(pdu: AttPdu) {
+        let bytes = pdu.to_bytes();
+        assert_eq!(bytes[0], pdu.opcode() as u8);
+        assert_eq!(AttPdu::parse(&bytes), Ok(pdu));
+    }
+
+    #[test]
+    fn round_trip_all_pdus() {
+        let uuid128 = Uuid::from_u128(0x6E40_0001_B5A3_F393_E0A9_E50E_24DC_CA9E);
+        round_trip(AttPdu::ErrorResponse(AttError::new(
+            AttOpcode::ReadRequest as u8,
+            0x0012,
+            ATT_ERROR_INVALID_OFFSET,
+        )));
+        round_trip(AttPdu::ExchangeMtuRequest { mtu: 517 });
+        round_trip(AttPdu::ExchangeMtuResponse { mtu: 23 });
+        round_trip(AttPdu::FindInformationRequest {
+            start: 0x0001,
+            end: 0xFFFF,
+        });
+        round_trip(AttPdu::FindInformationResponse {
+            information: vec![
+                (0x0001, Uuid::from_u16(0x2800)),
+                (0x0002, Uuid::from_u16(0x2803)),
+            ],
+        });
+        round_trip(AttPdu::FindInformationResponse {
+            information: vec![(0x0010, uuid128)],
+        });
+        round_trip(AttPdu::FindByTypeValueRequest {
+            start: 0x0001,
+            end: 0xFFFF,
+            attribute_type: 0x2800,
+            value: vec![0x0F, 0x18],
+        });
+        round_trip(AttPdu::FindByTypeValueResponse {
+            handles: vec![(0x0001, 0x0005), (0x0010, 0x0014)],
+        });
+        round_trip(AttPdu::ReadByTypeRequest {
+            start: 0x0001,
+            end: 0x0005,
+            attribute_type: Uuid::from_u16(0x2803),
+        });
+        round_trip(AttPdu::ReadByTypeRequest {
+            start: 0x0001,
+            end: 0x0005,
+            attribute_type: uuid128,
+        });
+        round_trip(AttPdu::ReadByTypeResponse {
+            values: vec![
+                (0x0002, vec![0x02, 0x03, 0x00]),
+                (0x0004, vec![0x10, 0x05, 0x00]),
+            ],
+        });
+        round_trip(AttPdu::ReadRequest { handle: 0x0003 });
+        round_trip(AttPdu::ReadResponse {
+            value: vec![0x42, 0x54, 0x4C, 0x45],
+        });
+        round_trip(AttPdu::ReadBlobRequest {
+            handle: 0x0003,
+            offset: 22,
+        });
+        round_trip(AttPdu::ReadBlobResponse { value: vec![0x01] });
+        round_trip(AttPdu::ReadMultipleRequest {
+            handles: vec![0x0003, 0x0005, 0x0007],
+        });
+        round_trip(AttPdu::ReadMultipleResponse {
+            values: vec![0x01, 0x02, 0x03],
+        });
+        round_trip(AttPdu::ReadByGroupTypeRequest {
+            start: 0x0001,
+            end: 0xFFFF,
+            group_type: Uuid::from_u16(0x2800),
+        });
+        round_trip(AttPdu::ReadByGroupTypeResponse {
+            groups: vec![
+                (0x0001, 0x0005, vec![0x00, 0x18]),
+                (0x0006, 0x0009, vec![0x01, 0x18]),
+            ],
+        });
+        round_trip(AttPdu::WriteRequest {
+            handle: 0x000A,
+            value: vec![0x01, 0x00],
+        });
+        round_trip(AttPdu::WriteResponse);
+        round_trip(AttPdu::WriteCommand {
+            handle: 0x000C,
+            value: vec![0xAA; 20],
+        });
+        round_trip(AttPdu::SignedWriteCommand {
+            handle: 0x000C,
+            value: vec![0x55, 0x66],
+            signature: [0x5A; ATT_SIGNATURE_SIZE],
+        });
+        round_trip(AttPdu::PrepareWriteRequest {
+            handle: 0x000E,
+            offset: 18,
+            value: vec![0x01, 0x02, 0x03],
+        });
+        round_trip(AttPdu::PrepareWriteResponse {
+            handle: 0x000E,
+            offset: 18,
+            value: vec![0x01, 0x02, 0x03],
+        });
+        round_trip(AttPdu::ExecuteWriteRequest {
+            flags: ATT_EXECUTE_WRITE_COMMIT,
+        });
+        round_trip(AttPdu::ExecuteWriteResponse);
+        round_trip(AttPdu::HandleValueNotification {
+            handle: 0x0010,
+            value: vec![0x64],
+        });
+        round_trip(AttPdu::HandleValueIndication {
+            handle: 0x0012,
+            value: vec![0x01, 0x02],
+        });
+        round_trip(AttPdu::HandleValueConfirmation);
+    }
+
+    #[test]
+    fn reject_empty_pdu() {
+        assert_eq!(
+            AttPdu::parse(&[]),
+            Err(AttError::new(0, 0, ATT_ERROR_INVALID_PDU))
+        );
+    }
+
+    #[test]
+    fn reject_short_error_response() {
+        assert_eq!(
+            AttPdu::parse(&[0x01, 0x0A, 0x03, 0x00]),
+            Err(AttError::new(0x01, 0, ATT_ERROR_INVALID_PDU))
+        );
+    }
+
+    #[test]
+    fn reject_unknown_opcode() {
+        assert_eq!(
+            AttPdu::parse(&[0x1F, 0x01, 0x02]),
+            Err(AttError::new(0x1F, 0, ATT_ERROR_REQUEST_NOT_SUPPORTED))
+        );
+    }
+
+    #[test]
+    fn reject_bad_read_by_type_length() {
+        // each entry need to hold at least the attribute handle
+        for length in 0..2 {
+            assert_eq!(
+                AttPdu::parse(&[0x09, length, 0x02, 0x00]),
+                Err(AttError::new(0x09, 0, ATT_ERROR_INVALID_PDU))
+            );
+        }
+        assert_eq!(
+            AttPdu::parse(&[0x09]),
+            Err(AttError::new(0x09, 0, ATT_ERROR_INVALID_PDU))
+        );
+    }
+
+    #[test]
+    fn reject_bad_read_by_group_type_length() {
+        // each entry need to hold at least the attribute handle and the end group handle
+        for length in 0..4 {
+            assert_eq!(
+                AttPdu::parse(&[0x11, length, 0x01, 0x00, 0x05, 0x00]),
+                Err(AttError::new(0x11, 0, ATT_ERROR_INVALID_PDU))
+            );
+        }
+        assert_eq!(
+            AttPdu::parse(&[0x11]),
+            Err(AttError::new(0x11, 0, ATT_ERROR_INVALID_PDU))
+        );
+    }
+}
@@ -0,0 +1,104 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # Bond Storage
+//!
+//! The keys created while pairing with a peer and the Client Characteristic Configurations the
+//! peer has written are kept as one [BondRecord] per peer in a [BondStore]. The security
+//! manager, the link key requests of the BT host and the GATT server consult the store they are
+//! given, so sharing one store keeps the bonds of all of them together. Each [BondRecord] can be
+//! converted into bytes, so a store can persist the bonds between reboots. Peers using
+//! resolvable private addresses are found with [BondStore::resolve].
+//!
+
+mod record;
+pub use record::*;
+
+use crate::alloc::boxed::Box;
+use crate::alloc::sync::Arc;
+use crate::alloc::vec::Vec;
+use crate::hci::privacy::resolve_address;
+use crate::hci::BD_ADDRESS_SIZE;
+use crate::lock::*;
+
+/// The storage of the bonds with the peers. The store is locked by its users on its own, so an
+/// implementation must not access the security manager, the ``Hci`` or the GATT server.
+pub trait BondStore: Send {
+    /// The record of the peer with the given address. This is either its identity address or
+    /// the address it used while pairing.
+    fn load(&self, address: &[u8; BD_ADDRESS_SIZE]) -> Option<BondRecord>;
+
+    /// Store the record, replacing the one stored with the same address
+    fn store(&mut self, record: BondRecord);
+
+    /// Remove the record of the peer with the given address
+    fn remove(&mut self, address: &[u8; BD_ADDRESS_SIZE]);
+
+    /// All records stored
+    fn records(&self) -> Vec<BondRecord>;
+
+    /// The record of the peer with the given address like [BondStore::load]. A resolvable
+    /// private address not known to the store is resolved against the identity resolving keys
+    /// of the peers.
+    fn resolve(&self, address: &[u8; BD_ADDRESS_SIZE]) -> Option<BondRecord> {
+        self.load(address).or_else(|| {
+            self.records().into_iter().find(|record| {
+                record
+                    .le
+                    .and_then(|bond| bond.peer_identity)
+                    .map_or(false, |identity| resolve_address(&identity.irk, address))
+            })
+        })
+    }
+}
+
+/// The bond store shared by the security manager, the ``Hci`` and the GATT server
+pub type SharedBondStore = Arc<DataLock<Box<dyn BondStore>>>;
+
+/// Share the given store to pass it to the security manager, the ``Hci`` and the GATT server
+pub fn shared<S>(store: S) -> SharedBondStore
+where
+    S: BondStore + 'static,
+{
+    Arc::new(DataLock::new(Box::new(store)))
+}
+
+/// The bond store keeping the records in memory only, they are lost on reboot
+#[derive(Debug, Default)]
+pub struct MemoryBondStore {
+    records: Vec<BondRecord>,
+}
+
+impl MemoryBondStore {
+    pub fn new() -> Self {
+        Self {
+            records: Vec::new(),
+        }
+    }
+}
+
+impl BondStore for MemoryBondStore {
+    fn load(&self, address: &[u8; BD_ADDRESS_SIZE]) -> Option<BondRecord> {
+        self.records
+            .iter()
+            .find(|record| record.is_peer(address))
+            .cloned()
+    }
+
+    fn store(&mut self, record: BondRecord) {
+        self.records.retain(|known| known.address != record.address);
+        self.records.push(record);
+    }
+
+    fn remove(&mut self, address: &[u8; BD_ADDRESS_SIZE]) {
+        self.records.retain(|record| !record.is_peer(address));
+    }
+
+    fn records(&self) -> Vec<BondRecord> {
+        self.records.clone()
+    }
+}
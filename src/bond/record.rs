@@ -0,0 +1,369 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # Bond Record
+//!
+//! The record of the bond with one peer and its format as bytes. All values are little endian,
+//! the record starts with the version of the format:
+//!
+//! | field                 | size | content                                                 |
+//! |-----------------------|------|---------------------------------------------------------|
+//! | version               | 1    | [BOND_RECORD_VERSION]                                   |
+//! | address type, address | 7    | the identity of the peer                                |
+//! | content               | 1    | 0x01 the LE bond follows, 0x02 the link key follows     |
+//! | LE bond               | var  | peer address type and address, a byte with the keys     |
+//! |                       |      | present and these keys in the order of [Bond]           |
+//! | link key              | 17   | the key and its type                                    |
+//! | configurations        | var  | their number (2 bytes), each handle and value (2 each)  |
+//!
+
+use crate::alloc::collections::BTreeMap;
+use crate::alloc::vec::Vec;
+use crate::hci::BD_ADDRESS_SIZE;
+use crate::l2cap::signaling::{get_u16, put_u16};
+use crate::smp::{Bond, IdentityKey, LongTermKey};
+
+/// The version of the format of a record as bytes
+pub const BOND_RECORD_VERSION: u8 = 0x01;
+
+const CONTENT_LE: u8 = 0x01;
+const CONTENT_LINK_KEY: u8 = 0x02;
+
+const KEY_LOCAL_LTK: u8 = 0x01;
+const KEY_PEER_LTK: u8 = 0x02;
+const KEY_PEER_IDENTITY: u8 = 0x04;
+const KEY_LOCAL_CSRK: u8 = 0x08;
+const KEY_PEER_CSRK: u8 = 0x10;
+
+const LTK_AUTHENTICATED: u8 = 0x01;
+const LTK_SECURE_CONNECTIONS: u8 = 0x02;
+
+/// The link key used to authenticate BR/EDR connections
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct LinkKey {
+    pub key: [u8; 16],
+    /// the ``LINK_KEY_TYPE_*`` of the key
+    pub key_type: u8,
+}
+
+/// The bond with one peer
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BondRecord {
+    /// 0x00 public, 0x01 random
+    pub address_type: u8,
+    /// the identity address of the peer, or the address it paired with if it has not
+    /// distributed its identity
+    pub address: [u8; BD_ADDRESS_SIZE],
+    /// the keys of LE pairing
+    pub le: Option<Bond>,
+    /// the link key of BR/EDR pairing
+    pub link_key: Option<LinkKey>,
+    /// the Client Characteristic Configurations the peer has written to the GATT server by
+    /// their attribute handle
+    pub client_configurations: BTreeMap<u16, u16>,
+}
+
+impl BondRecord {
+    pub fn new(address_type: u8, address: [u8; BD_ADDRESS_SIZE]) -> Self {
+        Self {
+            address_type,
+            address,
+            le: None,
+            link_key: None,
+            client_configurations: BTreeMap::new(),
+        }
+    }
+
+    /// Whether the record is the one of the peer with the given address, which is either its
+    /// identity address or the address it paired with
+    pub fn is_peer(&self, address: &[u8; BD_ADDRESS_SIZE]) -> bool {
+        self.address == *address || self.le.map_or(false, |bond| bond.is_peer(address))
+    }
+
+    /// The record as bytes to be persisted
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(128);
+        bytes.push(BOND_RECORD_VERSION);
+        bytes.push(self.address_type);
+        bytes.extend_from_slice(&self.address);
+        let mut content = 0;
+        if self.le.is_some() {
+            content |= CONTENT_LE;
+        }
+        if self.link_key.is_some() {
+            content |= CONTENT_LINK_KEY;
+        }
+        bytes.push(content);
+
+        if let Some(bond) = self.le {
+            bytes.push(bond.peer_address_type);
+            bytes.extend_from_slice(&bond.peer_address);
+            let mut keys = 0;
+            if bond.local_ltk.is_some() {
+                keys |= KEY_LOCAL_LTK;
+            }
+            if bond.peer_ltk.is_some() {
+                keys |= KEY_PEER_LTK;
+            }
+            if bond.peer_identity.is_some() {
+                keys |= KEY_PEER_IDENTITY;
+            }
+            if bond.local_csrk.is_some() {
+                keys |= KEY_LOCAL_CSRK;
+            }
+            if bond.peer_csrk.is_some() {
+                keys |= KEY_PEER_CSRK;
+            }
+            bytes.push(keys);
+            for key in bond.local_ltk.iter().chain(bond.peer_ltk.iter()) {
+                bytes.extend_from_slice(&key.ltk);
+                put_u16(&mut bytes, key.ediv);
+                bytes.extend_from_slice(&key.random_number);
+                bytes.push(key.key_size);
+                let mut properties = 0;
+                if key.authenticated {
+                    properties |= LTK_AUTHENTICATED;
+                }
+                if key.secure_connections {
+                    properties |= LTK_SECURE_CONNECTIONS;
+                }
+                bytes.push(properties);
+            }
+            if let Some(identity) = bond.peer_identity {
+                bytes.extend_from_slice(&identity.irk);
+                bytes.push(identity.address_type);
+                bytes.extend_from_slice(&identity.address);
+            }
+            for csrk in bond.local_csrk.iter().chain(bond.peer_csrk.iter()) {
+                bytes.extend_from_slice(csrk);
+            }
+        }
+
+        if let Some(link_key) = self.link_key {
+            bytes.extend_from_slice(&link_key.key);
+            bytes.push(link_key.key_type);
+        }
+
+        put_u16(&mut bytes, self.client_configurations.len() as u16);
+        for (handle, configuration) in self.client_configurations.iter() {
+            put_u16(&mut bytes, *handle);
+            put_u16(&mut bytes, *configuration);
+        }
+        bytes
+    }
+
+    /// Decode a record from the bytes created with [BondRecord::to_bytes]. This returns ``None``
+    /// if the bytes are not a record of the current version.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let mut reader = Reader { data, offset: 0 };
+        if reader.byte()? != BOND_RECORD_VERSION {
+            return None;
+        }
+        let mut record = BondRecord::new(reader.byte()?, reader.address()?);
+        let content = reader.byte()?;
+
+        if content & CONTENT_LE != 0 {
+            let mut bond = Bond::new(reader.byte()?, reader.address()?);
+            let keys = reader.byte()?;
+            if keys & KEY_LOCAL_LTK != 0 {
+                bond.local_ltk = Some(reader.long_term_key()?);
+            }
+            if keys & KEY_PEER_LTK != 0 {
+                bond.peer_ltk = Some(reader.long_term_key()?);
+            }
+            if keys & KEY_PEER_IDENTITY != 0 {
+                bond.peer_identity = Some(IdentityKey {
+                    irk: reader.key()?,
+                    address_type: reader.byte()?,
+                    address: reader.address()?,
+                });
+            }
+            if keys & KEY_LOCAL_CSRK != 0 {
+                bond.local_csrk = Some(reader.key()?);
+            }
+            if keys & KEY_PEER_CSRK != 0 {
+                bond.peer_csrk = Some(reader.key()?);
+            }
+            record.le = Some(bond);
+        }
+
+        if content & CONTENT_LINK_KEY != 0 {
+            record.link_key = Some(LinkKey {
+                key: reader.key()?,
+                key_type: reader.byte()?,
+            });
+        }
+
+        let count = reader.u16()?;
+        for _ in 0..count {
+            let handle = reader.u16()?;
+            let configuration = reader.u16()?;
+            record.client_configurations.insert(handle, configuration);
+        }
+        // the record need to use all bytes given
+        if reader.offset != data.len() {
+            return None;
+        }
+        Some(record)
+    }
+}
+
+/// Read the values of a record from its bytes one after another
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, length: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.offset..self.offset + length)?;
+        self.offset += length;
+        Some(bytes)
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        self.bytes(1).map(|bytes| bytes[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes(2).map(|bytes| get_u16(bytes, 0))
+    }
+
+    fn address(&mut self) -> Option<[u8; BD_ADDRESS_SIZE]> {
+        let mut address = [0; BD_ADDRESS_SIZE];
+        address.copy_from_slice(self.bytes(BD_ADDRESS_SIZE)?);
+        Some(address)
+    }
+
+    fn key(&mut self) -> Option<[u8; 16]> {
+        let mut key = [0; 16];
+        key.copy_from_slice(self.bytes(16)?);
+        Some(key)
+    }
+
+    fn long_term_key(&mut self) -> Option<LongTermKey> {
+        let ltk = self.key()?;
+        let ediv = self.u16()?;
+        let mut random_number = [0; 8];
+        random_number.copy_from_slice(self.bytes(8)?);
+        let key_size = self.byte()?;
+        let properties = self.byte()?;
+        Some(LongTermKey {
+            ltk,
+            ediv,
+            random_number,
+            key_size,
+            authenticated: properties & LTK_AUTHENTICATED != 0,
+            secure_connections: properties & LTK_SECURE_CONNECTIONS != 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn long_term_key(seed: u8, secure_connections: bool) -> LongTermKey {
+        LongTermKey {
+            ltk: [seed; 16],
+            ediv: 0x1234,
+            random_number: [seed ^ 0xFF; 8],
+            key_size: 16,
+            authenticated: true,
+            secure_connections,
+        }
+    }
+
+    /// A record with the LE bond keys selected by the bits of ``le_keys``, and the link key and
+    /// configurations if requested
+    fn record(le_keys: Option<u8>, link_key: bool, configurations: bool) -> BondRecord {
+        let mut record = BondRecord::new(0x01, [0xC1, 0xC2, 0xC3, 0xC4, 0xC5, 0xC6]);
+        if let Some(keys) = le_keys {
+            let mut bond = Bond::new(0x01, [0x11, 0x12, 0x13, 0x14, 0x15, 0x56]);
+            if keys & KEY_LOCAL_LTK != 0 {
+                bond.local_ltk = Some(long_term_key(0xA1, false));
+            }
+            if keys & KEY_PEER_LTK != 0 {
+                bond.peer_ltk = Some(long_term_key(0xA2, true));
+            }
+            if keys & KEY_PEER_IDENTITY != 0 {
+                bond.peer_identity = Some(IdentityKey {
+                    irk: [0xA3; 16],
+                    address_type: 0x01,
+                    address: [0xC1, 0xC2, 0xC3, 0xC4, 0xC5, 0xC6],
+                });
+            }
+            if keys & KEY_LOCAL_CSRK != 0 {
+                bond.local_csrk = Some([0xA4; 16]);
+            }
+            if keys & KEY_PEER_CSRK != 0 {
+                bond.peer_csrk = Some([0xA5; 16]);
+            }
+            record.le = Some(bond);
+        }
+        if link_key {
+            record.link_key = Some(LinkKey {
+                key: [0xB1; 16],
+                // authenticated combination key P-256
+                key_type: 0x08,
+            });
+        }
+        if configurations {
+            record.client_configurations.insert(0x0012, 0x0001);
+            record.client_configurations.insert(0x0020, 0x0002);
+        }
+        record
+    }
+
+    #[test]
+    fn round_trip_all_keys() {
+        let all_keys =
+            KEY_LOCAL_LTK | KEY_PEER_LTK | KEY_PEER_IDENTITY | KEY_LOCAL_CSRK | KEY_PEER_CSRK;
+        let record = record(Some(all_keys), true, true);
+        assert_eq!(BondRecord::parse(&record.to_bytes()), Some(record));
+    }
+
+    #[test]
+    fn round_trip_without_keys() {
+        let record = record(None, false, false);
+        let bytes = record.to_bytes();
+        assert_eq!(bytes.len(), 1 + 7 + 1 + 2);
+        assert_eq!(BondRecord::parse(&bytes), Some(record));
+    }
+
+    #[test]
+    fn round_trip_each_optional_key() {
+        for keys in 0..0x20 {
+            for link_key in [false, true].iter() {
+                let record = record(Some(keys), *link_key, keys & 0x01 != 0);
+                assert_eq!(BondRecord::parse(&record.to_bytes()), Some(record));
+            }
+        }
+        let record = record(None, true, true);
+        assert_eq!(BondRecord::parse(&record.to_bytes()), Some(record));
+    }
+
+    #[test]
+    fn reject_truncated_records() {
+        let all_keys =
+            KEY_LOCAL_LTK | KEY_PEER_LTK | KEY_PEER_IDENTITY | KEY_LOCAL_CSRK | KEY_PEER_CSRK;
+        let bytes = record(Some(all_keys), true, true).to_bytes();
+        for length in 0..bytes.len() {
+            assert_eq!(BondRecord::parse(&bytes[..length]), None);
+        }
+    }
+
+    #[test]
+    fn reject_other_versions_and_trailing_bytes() {
+        let mut bytes = record(Some(KEY_PEER_LTK), false, true).to_bytes();
+        bytes.push(0x00);
+        assert_eq!(BondRecord::parse(&bytes), None);
+        bytes.pop();
+        bytes[0] = BOND_RECORD_VERSION + 1;
+        assert_eq!(BondRecord::parse(&bytes), None);
+    }
+}
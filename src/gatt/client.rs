@@ -0,0 +1,1098 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # GATT Client
+//!
+//! The GATT client discovers the services, characteristics and descriptors of the peers and
+//! reads and writes their values. Each GATT procedure is a sequence of ATT requests, only one
+//! request is outstanding per connection at any time so procedures on the same connection are
+//! run one after the other. Notifications and indications received are queued until taken with
+//! [GattClient::notification].
+//!
+
+use super::*;
+use crate::alloc::boxed::Box;
+use crate::alloc::collections::{BTreeMap, VecDeque};
+use crate::alloc::sync::Arc;
+use crate::alloc::vec::Vec;
+use crate::att::*;
+use crate::brain::{waker::*, *};
+use crate::error::BoxError;
+use crate::hci::acl::SendAclThinkable;
+use crate::hci::BD_ADDRESS_SIZE;
+use crate::hctl::HcTransportLayer;
+use crate::l2cap::signaling::get_u16;
+use crate::lock::*;
+use crate::pin::Pin;
+use crate::uuid::Uuid;
+use crate::warn;
+
+/// Maximum number of notifications and indications kept until they are taken. If there are more
+/// received the oldest ones are dropped.
+const MAX_PENDING_NOTIFICATIONS: usize = 32;
+
+/// A service discovered on the server
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Service {
+    /// handle of the service declaration
+    pub handle: u16,
+    /// last handle of the service
+    pub end: u16,
+    pub uuid: Uuid,
+}
+
+/// A service included by another service
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct IncludedService {
+    /// handle of the include declaration
+    pub handle: u16,
+    pub service: Service,
+}
+
+/// A characteristic discovered on the server
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Characteristic {
+    /// handle of the characteristic declaration
+    pub handle: u16,
+    /// the ``PROPERTY_*`` bits of the characteristic
+    pub properties: u8,
+    pub value_handle: u16,
+    /// last handle of the characteristic including its descriptors
+    pub end: u16,
+    pub uuid: Uuid,
+}
+
+/// A characteristic descriptor discovered on the server
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Descriptor {
+    pub handle: u16,
+    pub uuid: Uuid,
+}
+
+/// A value notified or indicated by the peer of a connection
+#[derive(Debug, Clone)]
+pub struct Notification {
+    /// handle of the connection
+    pub connection: u16,
+    /// handle of the characteristic value
+    pub handle: u16,
+    pub value: Vec<u8>,
+    /// whether the value has been indicated, the indication is already confirmed
+    pub indication: bool,
+}
+
+/// The state the client keeps for each connection
+struct ClientConnection {
+    /// the peer of the connection, to detect a handle being reused for a new connection
+    peer_address: [u8; BD_ADDRESS_SIZE],
+    /// a request awaits its response
+    busy: bool,
+    /// increased with each request send, to relate the response and timeout to the request
+    sequence: u32,
+    response: Option<Result<AttPdu, AttError>>,
+    response_waker: Option<Waker>,
+    /// procedures waiting to send their next request
+    waiting: Vec<Waker>,
+    /// a request has not been answered in time, no further requests are send to the peer
+    timed_out: bool,
+}
+
+impl ClientConnection {
+    fn release(&mut self) {
+        self.busy = false;
+        for waker in self.waiting.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+/// The GATT client accessing the attributes of the peers
+pub struct GattClient<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    att: Att<T>,
+    connections: BTreeMap<u16, ClientConnection>,
+    notifications: VecDeque<Notification>,
+    notification_waker: Option<Waker>,
+}
+
+impl<T> GattClient<T>
+where
+    T: HcTransportLayer,
+{
+    /// Create the client using the attribute bearer
+    pub fn new(att: Att<T>) -> Arc<DataLock<Self>> {
+        Arc::new(DataLock::new(Self {
+            att,
+            connections: BTreeMap::new(),
+            notifications: VecDeque::new(),
+            notification_waker: None,
+        }))
+    }
+
+    /// This returns the ``Thinkable`` receiving the responses, notifications and indications of
+    /// the peers. This should be spawned to the Brain once the client is created.
+    #[must_use]
+    pub fn serve(this: Arc<DataLock<Self>>) -> ServeGattClientThinkable<T> {
+        let recv = this.read().att.recv(AttRole::Client);
+        ServeGattClientThinkable { client: this, recv }
+    }
+
+    /// Exchange the ATT_MTU with the peer of the connection with the given handle. This
+    /// concludes with the ATT_MTU agreed.
+    pub fn exchange_mtu(
+        this: Arc<DataLock<Self>>,
+        handle: u16,
+    ) -> impl Thinkable<Output = Result<u16, BoxError>> {
+        let att = this.read().att.clone();
+        ProcedureThinkable::new(
+            this,
+            handle,
+            ExchangeMtu {
+                sent: false,
+                mtu: None,
+            },
+        )
+        .map(move |result| {
+            if let Ok(mtu) = result {
+                att.set_mtu(handle, mtu);
+            }
+            result
+        })
+    }
+
+    /// Discover all primary services of the peer of the connection with the given handle
+    pub fn discover_services(
+        this: Arc<DataLock<Self>>,
+        handle: u16,
+    ) -> impl Thinkable<Output = Result<Vec<Service>, BoxError>> {
+        ProcedureThinkable::new(this, handle, DiscoverServices::new(None))
+    }
+
+    /// Discover the primary services with the given UUID of the peer of the connection with the
+    /// given handle
+    pub fn discover_services_by_uuid(
+        this: Arc<DataLock<Self>>,
+        handle: u16,
+        uuid: Uuid,
+    ) -> impl Thinkable<Output = Result<Vec<Service>, BoxError>> {
+        ProcedureThinkable::new(this, handle, DiscoverServices::new(Some(uuid)))
+    }
+
+    /// Find the services included by a service of the peer of the connection with the given
+    /// handle
+    pub fn find_included_services(
+        this: Arc<DataLock<Self>>,
+        handle: u16,
+        service: &Service,
+    ) -> impl Thinkable<Output = Result<Vec<IncludedService>, BoxError>> {
+        ProcedureThinkable::new(
+            this,
+            handle,
+            FindIncludedServices {
+                end: service.end,
+                start: service.handle,
+                includes: Vec::new(),
+                unresolved: Vec::new(),
+                reading: None,
+                done: false,
+            },
+        )
+    }
+
+    /// Discover all characteristics of a service of the peer of the connection with the given
+    /// handle
+    pub fn discover_characteristics(
+        this: Arc<DataLock<Self>>,
+        handle: u16,
+        service: &Service,
+    ) -> impl Thinkable<Output = Result<Vec<Characteristic>, BoxError>> {
+        ProcedureThinkable::new(
+            this,
+            handle,
+            DiscoverCharacteristics {
+                end: service.end,
+                start: service.handle,
+                characteristics: Vec::new(),
+                done: false,
+            },
+        )
+    }
+
+    /// Discover all descriptors of a characteristic of the peer of the connection with the given
+    /// handle
+    pub fn discover_descriptors(
+        this: Arc<DataLock<Self>>,
+        handle: u16,
+        characteristic: &Characteristic,
+    ) -> impl Thinkable<Output = Result<Vec<Descriptor>, BoxError>> {
+        ProcedureThinkable::new(
+            this,
+            handle,
+            DiscoverDescriptors {
+                end: characteristic.end,
+                // a start of 0 ends the discovery without any descriptor
+                start: characteristic.value_handle.checked_add(1).unwrap_or(0),
+                descriptors: Vec::new(),
+            },
+        )
+    }
+
+    /// Read the value of an attribute of the peer of the connection with the given handle.
+    /// Values that do not fit into one response are read with Read Blob Requests.
+    pub fn read(
+        this: Arc<DataLock<Self>>,
+        handle: u16,
+        attribute: u16,
+    ) -> impl Thinkable<Output = Result<Vec<u8>, BoxError>> {
+        let mtu = this.read().att.mtu(handle) as usize;
+        ProcedureThinkable::new(
+            this,
+            handle,
+            ReadValue {
+                handle: attribute,
+                mtu,
+                value: Vec::new(),
+                started: false,
+                done: false,
+            },
+        )
+    }
+
+    /// Write the value of an attribute of the peer of the connection with the given handle.
+    /// Values that do not fit into one request are written with Prepare and Execute Write
+    /// Requests.
+    pub fn write(
+        this: Arc<DataLock<Self>>,
+        handle: u16,
+        attribute: u16,
+        value: Vec<u8>,
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        let mtu = this.read().att.mtu(handle) as usize;
+        ProcedureThinkable::new(this, handle, WriteValue::new(attribute, value, mtu))
+    }
+
+    /// Write the value of an attribute of the peer of the connection with the given handle
+    /// without a response. The value is truncated to fit into one PDU.
+    pub fn write_without_response(
+        this: Arc<DataLock<Self>>,
+        handle: u16,
+        attribute: u16,
+        mut value: Vec<u8>,
+    ) -> SendAclThinkable<T> {
+        let client = this.read();
+        value.truncate(client.att.mtu(handle) as usize - 3);
+        client.att.send(
+            handle,
+            &AttPdu::WriteCommand {
+                handle: attribute,
+                value,
+            },
+        )
+    }
+
+    /// Write the Client Characteristic Configuration descriptor with the given handle of the
+    /// peer of the connection with the given handle. The configuration is a combination of
+    /// [CLIENT_CONFIGURATION_NOTIFY] and [CLIENT_CONFIGURATION_INDICATE], 0 unsubscribes.
+    pub fn subscribe(
+        this: Arc<DataLock<Self>>,
+        handle: u16,
+        descriptor: u16,
+        configuration: u16,
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        let value = Vec::from(configuration.to_le_bytes());
+        GattClient::write(this, handle, descriptor, value)
+    }
+
+    /// Returns a ``Thinkable`` that concludes with the next value notified or indicated by the
+    /// peer of any connection
+    pub fn notification(this: Arc<DataLock<Self>>) -> RecvNotificationThinkable<T> {
+        RecvNotificationThinkable { client: this }
+    }
+
+    /// Ensure the state of the connection with the given handle is known. If the connection is
+    /// closed this returns ``None``.
+    fn refresh_connection(&mut self, handle: u16) -> Option<&mut ClientConnection> {
+        let peer_address = match self.att.connection(handle) {
+            Some(connection) => connection.peer_address(),
+            None => {
+                self.connections.remove(&handle);
+                return None;
+            }
+        };
+        let known = self
+            .connections
+            .get(&handle)
+            .map_or(false, |connection| connection.peer_address == peer_address);
+        if !known {
+            self.connections.insert(
+                handle,
+                ClientConnection {
+                    peer_address,
+                    busy: false,
+                    sequence: 0,
+                    response: None,
+                    response_waker: None,
+                    waiting: Vec::new(),
+                    timed_out: false,
+                },
+            );
+        }
+        self.connections.get_mut(&handle)
+    }
+
+    /// Reserve the connection to send the next request of a procedure. If another request awaits
+    /// its response this returns ``false`` and the procedure is woken once the connection can be
+    /// used.
+    fn acquire(&mut self, handle: u16, waker: &Waker) -> Result<bool, BoxError> {
+        let connection = match self.refresh_connection(handle) {
+            Some(connection) => connection,
+            None => return Err(Box::new(GattError::new("connection closed"))),
+        };
+        if connection.timed_out {
+            return Err(Box::new(GattError::new("ATT bearer timed out")));
+        }
+        if connection.busy {
+            connection.waiting.push(waker.clone());
+            return Ok(false);
+        }
+        connection.busy = true;
+        Ok(true)
+    }
+
+    fn release(&mut self, handle: u16) {
+        if let Some(connection) = self.connections.get_mut(&handle) {
+            connection.release();
+        }
+    }
+
+    /// Send the request on the connection reserved and return its sequence number
+    fn send_request(
+        this: &Arc<DataLock<Self>>,
+        client: &mut Self,
+        handle: u16,
+        request: AttPdu,
+    ) -> u32 {
+        let sequence = match client.connections.get_mut(&handle) {
+            Some(connection) => {
+                connection.sequence += 1;
+                connection.response = None;
+                connection.sequence
+            }
+            None => 0,
+        };
+        client.att.queue(handle, &request);
+        let client = this.clone();
+        spawn(
+            wait(Mseconds(ATT_TRANSACTION_TIMEOUT), ())
+                .map(move |_| client.lock().request_timeout(handle, sequence)),
+        );
+        sequence
+    }
+
+    /// Take the response to the request with the given sequence number
+    fn take_response(
+        &mut self,
+        handle: u16,
+        sequence: u32,
+        waker: &Waker,
+    ) -> Conclusion<Result<Result<AttPdu, AttError>, BoxError>> {
+        let connection = match self.connections.get_mut(&handle) {
+            Some(connection) if connection.sequence == sequence => connection,
+            _ => return Conclusion::Ready(Err(Box::new(GattError::new("connection closed")))),
+        };
+        if let Some(response) = connection.response.take() {
+            connection.release();
+            return Conclusion::Ready(Ok(response));
+        }
+        if connection.timed_out {
+            return Conclusion::Ready(Err(Box::new(GattError::new("request timed out"))));
+        }
+        connection.response_waker.replace(waker.clone());
+        Conclusion::Pending
+    }
+
+    /// A request has not been answered in time. The ATT bearer of the connection can not be used
+    /// any longer.
+    fn request_timeout(&mut self, handle: u16, sequence: u32) {
+        if let Some(connection) = self.connections.get_mut(&handle) {
+            if connection.busy && connection.sequence == sequence && connection.response.is_none() {
+                warn!("ATT request on connection {} not answered", handle);
+                connection.timed_out = true;
+                if let Some(waker) = connection.response_waker.take() {
+                    waker.wake();
+                }
+                connection.release();
+            }
+        }
+    }
+
+    /// Process a PDU received from the peer of the connection with the given handle
+    fn received(&mut self, handle: u16, pdu: Result<AttPdu, AttError>) {
+        let pdu = match pdu {
+            Ok(pdu) => pdu,
+            Err(error) => {
+                warn!("invalid ATT PDU received: {:?}", error);
+                return;
+            }
+        };
+        match pdu {
+            AttPdu::HandleValueNotification {
+                handle: attribute,
+                value,
+            } => self.notified(handle, attribute, value, false),
+            AttPdu::HandleValueIndication {
+                handle: attribute,
+                value,
+            } => {
+                self.att.queue(handle, &AttPdu::HandleValueConfirmation);
+                self.notified(handle, attribute, value, true);
+            }
+            response => {
+                let response = match response {
+                    AttPdu::ErrorResponse(error) => Err(error),
+                    response => Ok(response),
+                };
+                match self.connections.get_mut(&handle) {
+                    Some(connection) if connection.busy && connection.response.is_none() => {
+                        connection.response.replace(response);
+                        if let Some(waker) = connection.response_waker.take() {
+                            waker.wake();
+                        }
+                    }
+                    _ => warn!("unexpected ATT response on connection {}", handle),
+                }
+            }
+        }
+    }
+
+    fn notified(&mut self, handle: u16, attribute: u16, value: Vec<u8>, indication: bool) {
+        if self.notifications.len() >= MAX_PENDING_NOTIFICATIONS {
+            self.notifications.pop_front();
+        }
+        self.notifications.push_back(Notification {
+            connection: handle,
+            handle: attribute,
+            value,
+            indication,
+        });
+        if let Some(waker) = self.notification_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// This ``Thinkable`` receives the responses, notifications and indications for the GATT client
+pub struct ServeGattClientThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    client: Arc<DataLock<GattClient<T>>>,
+    recv: RecvAttThinkable<T>,
+}
+
+impl<T> Thinkable for ServeGattClientThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = ();
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        let this = self.get_mut();
+        loop {
+            match Pin::new(&mut this.recv).think(cx) {
+                Conclusion::Pending => return Conclusion::Pending,
+                Conclusion::Ready((handle, pdu)) => this.client.lock().received(handle, pdu),
+            }
+        }
+    }
+}
+
+/// This ``Thinkable`` concludes with the next value notified or indicated
+pub struct RecvNotificationThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    client: Arc<DataLock<GattClient<T>>>,
+}
+
+impl<T> Thinkable for RecvNotificationThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = Notification;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        let mut client = self.client.lock();
+        match client.notifications.pop_front() {
+            Some(notification) => Conclusion::Ready(notification),
+            None => {
+                client.notification_waker.replace(cx.waker().clone());
+                Conclusion::Pending
+            }
+        }
+    }
+}
+
+/// A GATT procedure sending one request after the other until it is complete
+trait Procedure {
+    type Output;
+
+    /// The next request of the procedure, ``None`` once it is complete
+    fn request(&mut self) -> Option<AttPdu>;
+
+    /// Process the response to the request send last
+    fn response(&mut self, response: Result<AttPdu, AttError>) -> Result<(), BoxError>;
+
+    /// The result of the completed procedure
+    fn conclude(&mut self) -> Self::Output;
+}
+
+/// This ``Thinkable`` runs a GATT procedure on a connection
+struct ProcedureThinkable<T, P>
+where
+    T: HcTransportLayer + 'static,
+{
+    client: Arc<DataLock<GattClient<T>>>,
+    handle: u16,
+    procedure: P,
+    /// the sequence number of the request awaiting its response
+    sequence: Option<u32>,
+}
+
+impl<T, P> ProcedureThinkable<T, P>
+where
+    T: HcTransportLayer,
+{
+    fn new(client: Arc<DataLock<GattClient<T>>>, handle: u16, procedure: P) -> Self {
+        Self {
+            client,
+            handle,
+            procedure,
+            sequence: None,
+        }
+    }
+}
+
+impl<T, P> Thinkable for ProcedureThinkable<T, P>
+where
+    T: HcTransportLayer,
+    P: Procedure + Unpin,
+{
+    type Output = Result<P::Output, BoxError>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        let this = self.get_mut();
+        let mut client = this.client.lock();
+        loop {
+            if let Some(sequence) = this.sequence {
+                let response = match client.take_response(this.handle, sequence, cx.waker()) {
+                    Conclusion::Pending => return Conclusion::Pending,
+                    Conclusion::Ready(Err(error)) => return Conclusion::Ready(Err(error)),
+                    Conclusion::Ready(Ok(response)) => response,
+                };
+                this.sequence = None;
+                if let Err(error) = this.procedure.response(response) {
+                    return Conclusion::Ready(Err(error));
+                }
+            }
+            match client.acquire(this.handle, cx.waker()) {
+                Err(error) => return Conclusion::Ready(Err(error)),
+                Ok(false) => return Conclusion::Pending,
+                Ok(true) => (),
+            }
+            match this.procedure.request() {
+                Some(request) => {
+                    let sequence =
+                        GattClient::send_request(&this.client, &mut client, this.handle, request);
+                    this.sequence = Some(sequence);
+                }
+                None => {
+                    client.release(this.handle);
+                    return Conclusion::Ready(Ok(this.procedure.conclude()));
+                }
+            }
+        }
+    }
+}
+
+fn unexpected_response() -> BoxError {
+    Box::new(GattError::new("unexpected response"))
+}
+
+/// Whether the error response ends a discovery as there are no further attributes
+fn not_found(error: &AttError) -> bool {
+    error.code == ATT_ERROR_ATTRIBUTE_NOT_FOUND
+}
+
+struct ExchangeMtu {
+    sent: bool,
+    mtu: Option<u16>,
+}
+
+impl Procedure for ExchangeMtu {
+    type Output = u16;
+
+    fn request(&mut self) -> Option<AttPdu> {
+        if self.sent {
+            return None;
+        }
+        self.sent = true;
+        Some(AttPdu::ExchangeMtuRequest { mtu: GATT_MAX_MTU })
+    }
+
+    fn response(&mut self, response: Result<AttPdu, AttError>) -> Result<(), BoxError> {
+        match response {
+            Ok(AttPdu::ExchangeMtuResponse { mtu }) => {
+                self.mtu = Some(mtu.min(GATT_MAX_MTU));
+                Ok(())
+            }
+            // a server not supporting the exchange keeps the default ATT_MTU
+            Err(error) if error.code == ATT_ERROR_REQUEST_NOT_SUPPORTED => Ok(()),
+            Err(error) => Err(Box::new(error)),
+            Ok(_) => Err(unexpected_response()),
+        }
+    }
+
+    fn conclude(&mut self) -> u16 {
+        self.mtu
+            .unwrap_or(ATT_DEFAULT_MTU_LE)
+            .max(ATT_DEFAULT_MTU_LE)
+    }
+}
+
+struct DiscoverServices {
+    uuid: Option<Uuid>,
+    start: u16,
+    services: Vec<Service>,
+    done: bool,
+}
+
+impl DiscoverServices {
+    fn new(uuid: Option<Uuid>) -> Self {
+        Self {
+            uuid,
+            start: 0x0001,
+            services: Vec::new(),
+            done: false,
+        }
+    }
+}
+
+impl Procedure for DiscoverServices {
+    type Output = Vec<Service>;
+
+    fn request(&mut self) -> Option<AttPdu> {
+        if self.done {
+            return None;
+        }
+        Some(match self.uuid {
+            None => AttPdu::ReadByGroupTypeRequest {
+                start: self.start,
+                end: 0xFFFF,
+                group_type: Uuid::from_u16(GATT_PRIMARY_SERVICE),
+            },
+            Some(uuid) => {
+                let mut value = Vec::with_capacity(uuid_size(&uuid));
+                put_uuid(&mut value, &uuid);
+                AttPdu::FindByTypeValueRequest {
+                    start: self.start,
+                    end: 0xFFFF,
+                    attribute_type: GATT_PRIMARY_SERVICE,
+                    value,
+                }
+            }
+        })
+    }
+
+    fn response(&mut self, response: Result<AttPdu, AttError>) -> Result<(), BoxError> {
+        let found: Vec<Service> = match (response, self.uuid) {
+            (Ok(AttPdu::ReadByGroupTypeResponse { groups }), None) => groups
+                .into_iter()
+                .filter_map(|(handle, end, value)| {
+                    parse_uuid(&value).map(|uuid| Service { handle, end, uuid })
+                })
+                .collect(),
+            (Ok(AttPdu::FindByTypeValueResponse { handles }), Some(uuid)) => handles
+                .into_iter()
+                .map(|(handle, end)| Service { handle, end, uuid })
+                .collect(),
+            (Err(error), _) if not_found(&error) => Vec::new(),
+            (Err(error), _) => return Err(Box::new(error)),
+            _ => return Err(unexpected_response()),
+        };
+        match found.last() {
+            Some(last) if last.end != 0xFFFF && last.end >= self.start => self.start = last.end + 1,
+            _ => self.done = true,
+        }
+        self.services.extend(found);
+        Ok(())
+    }
+
+    fn conclude(&mut self) -> Vec<Service> {
+        core::mem::take(&mut self.services)
+    }
+}
+
+struct FindIncludedServices {
+    end: u16,
+    start: u16,
+    includes: Vec<IncludedService>,
+    /// includes of services with a 128 bit UUID, which need to be read separately
+    unresolved: Vec<(u16, u16, u16)>,
+    reading: Option<(u16, u16, u16)>,
+    done: bool,
+}
+
+impl Procedure for FindIncludedServices {
+    type Output = Vec<IncludedService>;
+
+    fn request(&mut self) -> Option<AttPdu> {
+        if let Some(unresolved) = self.unresolved.pop() {
+            self.reading = Some(unresolved);
+            return Some(AttPdu::ReadRequest {
+                handle: unresolved.1,
+            });
+        }
+        if self.done {
+            return None;
+        }
+        Some(AttPdu::ReadByTypeRequest {
+            start: self.start,
+            end: self.end,
+            attribute_type: Uuid::from_u16(GATT_INCLUDE),
+        })
+    }
+
+    fn response(&mut self, response: Result<AttPdu, AttError>) -> Result<(), BoxError> {
+        if let Some((handle, start, end)) = self.reading.take() {
+            return match response {
+                Ok(AttPdu::ReadResponse { value }) => match parse_uuid(&value) {
+                    Some(uuid) => {
+                        self.includes.push(IncludedService {
+                            handle,
+                            service: Service {
+                                handle: start,
+                                end,
+                                uuid,
+                            },
+                        });
+                        Ok(())
+                    }
+                    None => Err(unexpected_response()),
+                },
+                Err(error) => Err(Box::new(error)),
+                Ok(_) => Err(unexpected_response()),
+            };
+        }
+        let values = match response {
+            Ok(AttPdu::ReadByTypeResponse { values }) => values,
+            Err(error) if not_found(&error) => Vec::new(),
+            Err(error) => return Err(Box::new(error)),
+            Ok(_) => return Err(unexpected_response()),
+        };
+        match values.last() {
+            Some((handle, _)) if *handle < self.end && *handle >= self.start => {
+                self.start = handle + 1
+            }
+            _ => self.done = true,
+        }
+        for (handle, value) in values {
+            let start = get_u16(&value, 0);
+            let end = get_u16(&value, 2);
+            match value.len() {
+                6 => self.includes.push(IncludedService {
+                    handle,
+                    service: Service {
+                        handle: start,
+                        end,
+                        uuid: Uuid::from_u16(get_u16(&value, 4)),
+                    },
+                }),
+                4 => self.unresolved.push((handle, start, end)),
+                _ => return Err(unexpected_response()),
+            }
+        }
+        Ok(())
+    }
+
+    fn conclude(&mut self) -> Vec<IncludedService> {
+        self.includes.sort_by_key(|include| include.handle);
+        core::mem::take(&mut self.includes)
+    }
+}
+
+struct DiscoverCharacteristics {
+    end: u16,
+    start: u16,
+    characteristics: Vec<Characteristic>,
+    done: bool,
+}
+
+impl Procedure for DiscoverCharacteristics {
+    type Output = Vec<Characteristic>;
+
+    fn request(&mut self) -> Option<AttPdu> {
+        if self.done {
+            return None;
+        }
+        Some(AttPdu::ReadByTypeRequest {
+            start: self.start,
+            end: self.end,
+            attribute_type: Uuid::from_u16(GATT_CHARACTERISTIC),
+        })
+    }
+
+    fn response(&mut self, response: Result<AttPdu, AttError>) -> Result<(), BoxError> {
+        let values = match response {
+            Ok(AttPdu::ReadByTypeResponse { values }) => values,
+            Err(error) if not_found(&error) => Vec::new(),
+            Err(error) => return Err(Box::new(error)),
+            Ok(_) => return Err(unexpected_response()),
+        };
+        let mut found = false;
+        for (handle, value) in values {
+            // declarations outside the range requested or not in ascending order are dropped
+            if self.done || handle < self.start || handle > self.end {
+                continue;
+            }
+            let uuid = match value.get(3..).and_then(parse_uuid) {
+                Some(uuid) => uuid,
+                None => return Err(unexpected_response()),
+            };
+            self.characteristics.push(Characteristic {
+                handle,
+                properties: value[0],
+                value_handle: get_u16(&value, 1),
+                end: self.end,
+                uuid,
+            });
+            found = true;
+            if handle < self.end {
+                self.start = handle + 1;
+            } else {
+                self.done = true;
+            }
+        }
+        if !found {
+            self.done = true;
+        }
+        Ok(())
+    }
+
+    fn conclude(&mut self) -> Vec<Characteristic> {
+        // each characteristic ends before the declaration of the next one
+        let mut characteristics = core::mem::take(&mut self.characteristics);
+        for index in 1..characteristics.len() {
+            characteristics[index - 1].end = characteristics[index].handle.saturating_sub(1);
+        }
+        characteristics
+    }
+}
+
+struct DiscoverDescriptors {
+    end: u16,
+    start: u16,
+    descriptors: Vec<Descriptor>,
+}
+
+impl Procedure for DiscoverDescriptors {
+    type Output = Vec<Descriptor>;
+
+    fn request(&mut self) -> Option<AttPdu> {
+        if self.start == 0 || self.start > self.end {
+            return None;
+        }
+        Some(AttPdu::FindInformationRequest {
+            start: self.start,
+            end: self.end,
+        })
+    }
+
+    fn response(&mut self, response: Result<AttPdu, AttError>) -> Result<(), BoxError> {
+        let information = match response {
+            Ok(AttPdu::FindInformationResponse { information }) => information,
+            Err(error) if not_found(&error) => Vec::new(),
+            Err(error) => return Err(Box::new(error)),
+            Ok(_) => return Err(unexpected_response()),
+        };
+        // a start of 0 ends the discovery
+        self.start = match information.last() {
+            Some((handle, _)) if *handle >= self.start => handle.wrapping_add(1),
+            _ => 0,
+        };
+        self.descriptors.extend(
+            information
+                .into_iter()
+                .map(|(handle, uuid)| Descriptor { handle, uuid }),
+        );
+        Ok(())
+    }
+
+    fn conclude(&mut self) -> Vec<Descriptor> {
+        core::mem::take(&mut self.descriptors)
+    }
+}
+
+struct ReadValue {
+    handle: u16,
+    mtu: usize,
+    value: Vec<u8>,
+    started: bool,
+    done: bool,
+}
+
+impl Procedure for ReadValue {
+    type Output = Vec<u8>;
+
+    fn request(&mut self) -> Option<AttPdu> {
+        if self.done {
+            None
+        } else if !self.started {
+            self.started = true;
+            Some(AttPdu::ReadRequest {
+                handle: self.handle,
+            })
+        } else {
+            Some(AttPdu::ReadBlobRequest {
+                handle: self.handle,
+                offset: self.value.len() as u16,
+            })
+        }
+    }
+
+    fn response(&mut self, response: Result<AttPdu, AttError>) -> Result<(), BoxError> {
+        match response {
+            Ok(AttPdu::ReadResponse { value }) | Ok(AttPdu::ReadBlobResponse { value }) => {
+                // a response shorter than possible ends the value
+                self.done = value.len() < self.mtu - 1
+                    || self.value.len() + value.len() >= ATT_MAX_VALUE_LENGTH;
+                self.value.extend_from_slice(&value);
+                Ok(())
+            }
+            Err(error)
+                if !self.value.is_empty()
+                    && (error.code == ATT_ERROR_ATTRIBUTE_NOT_LONG
+                        || error.code == ATT_ERROR_INVALID_OFFSET) =>
+            {
+                self.done = true;
+                Ok(())
+            }
+            Err(error) => Err(Box::new(error)),
+            Ok(_) => Err(unexpected_response()),
+        }
+    }
+
+    fn conclude(&mut self) -> Vec<u8> {
+        core::mem::take(&mut self.value)
+    }
+}
+
+/// The next request of a write procedure
+#[derive(Copy, Clone)]
+enum WriteStep {
+    Write,
+    Prepare,
+    Execute(u8),
+    Done,
+}
+
+struct WriteValue {
+    handle: u16,
+    value: Vec<u8>,
+    /// the size of the parts of a long value
+    part_size: usize,
+    /// offset of the part prepared last
+    offset: usize,
+    step: WriteStep,
+    /// the error a long write has been cancelled with
+    failure: Option<BoxError>,
+}
+
+impl WriteValue {
+    fn new(handle: u16, value: Vec<u8>, mtu: usize) -> Self {
+        let step = if value.len() <= mtu - 3 {
+            WriteStep::Write
+        } else {
+            WriteStep::Prepare
+        };
+        Self {
+            handle,
+            value,
+            part_size: mtu - 5,
+            offset: 0,
+            step,
+            failure: None,
+        }
+    }
+
+    fn part(&self) -> &[u8] {
+        let end = self.value.len().min(self.offset + self.part_size);
+        &self.value[self.offset..end]
+    }
+}
+
+impl Procedure for WriteValue {
+    type Output = ();
+
+    fn request(&mut self) -> Option<AttPdu> {
+        match self.step {
+            WriteStep::Write => Some(AttPdu::WriteRequest {
+                handle: self.handle,
+                value: self.value.clone(),
+            }),
+            WriteStep::Prepare => Some(AttPdu::PrepareWriteRequest {
+                handle: self.handle,
+                offset: self.offset as u16,
+                value: self.part().to_vec(),
+            }),
+            WriteStep::Execute(flags) => Some(AttPdu::ExecuteWriteRequest { flags }),
+            WriteStep::Done => None,
+        }
+    }
+
+    fn response(&mut self, response: Result<AttPdu, AttError>) -> Result<(), BoxError> {
+        match (self.step, response) {
+            (WriteStep::Write, Ok(AttPdu::WriteResponse)) => self.step = WriteStep::Done,
+            (
+                WriteStep::Prepare,
+                Ok(AttPdu::PrepareWriteResponse {
+                    handle,
+                    offset,
+                    value,
+                }),
+            ) => {
+                // the server echoes the part, a mismatch cancels the write
+                if handle != self.handle || offset as usize != self.offset || value != self.part() {
+                    self.failure = Some(Box::new(GattError::new("prepared write corrupted")));
+                    self.step = WriteStep::Execute(ATT_EXECUTE_WRITE_CANCEL);
+                    return Ok(());
+                }
+                self.offset += value.len();
+                if self.offset >= self.value.len() {
+                    self.step = WriteStep::Execute(ATT_EXECUTE_WRITE_COMMIT);
+                }
+            }
+            (WriteStep::Prepare, Err(error)) => {
+                self.failure = Some(Box::new(error));
+                self.step = WriteStep::Execute(ATT_EXECUTE_WRITE_CANCEL);
+            }
+            (WriteStep::Execute(_), Ok(AttPdu::ExecuteWriteResponse)) => {
+                self.step = WriteStep::Done;
+                if let Some(failure) = self.failure.take() {
+                    return Err(failure);
+                }
+            }
+            (_, Err(error)) => return Err(Box::new(error)),
+            _ => return Err(unexpected_response()),
+        }
+        Ok(())
+    }
+
+    fn conclude(&mut self) {}
+}
@@ -0,0 +1,312 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # GATT Attribute Database
+//!
+//! The attribute table exposed by the GATT server. Services, characteristics and descriptors are
+//! declared in the order they appear in the table and get the next free handle assigned. A
+//! characteristic belongs to the service declared last and a descriptor to the characteristic
+//! declared last.
+//!
+
+use super::errors::*;
+use super::*;
+use crate::alloc::boxed::Box;
+use crate::alloc::vec;
+use crate::alloc::vec::Vec;
+use crate::att::{parse_uuid, put_uuid, uuid_size, ATT_MAX_VALUE_LENGTH};
+use crate::error::BoxError;
+use crate::l2cap::signaling::put_u16;
+use crate::uuid::Uuid;
+
+/// Callback providing the value of an attribute when it is read by the peer of the connection
+/// with the given handle. An error is answered with the ATT error code returned.
+pub type ReadCallback = Box<dyn FnMut(u16) -> Result<Vec<u8>, u8> + Send>;
+
+/// Callback receiving the value written to an attribute by the peer of the connection with the
+/// given handle. An error is answered with the ATT error code returned and the value is not
+/// stored.
+pub type WriteCallback = Box<dyn FnMut(u16, &[u8]) -> Result<(), u8> + Send>;
+
+/// One entry of the attribute table
+pub(crate) struct Attribute {
+    pub(crate) attribute_type: Uuid,
+    pub(crate) permissions: u8,
+    pub(crate) value: Vec<u8>,
+    pub(crate) read: Option<ReadCallback>,
+    pub(crate) write: Option<WriteCallback>,
+}
+
+/// The static attribute table of the GATT server
+pub struct GattDatabase {
+    attributes: Vec<Attribute>,
+    /// the handle of the service and characteristic declared last
+    current_service: Option<u16>,
+    current_characteristic: Option<u16>,
+}
+
+impl Default for GattDatabase {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GattDatabase {
+    pub fn new() -> Self {
+        Self {
+            attributes: Vec::new(),
+            current_service: None,
+            current_characteristic: None,
+        }
+    }
+
+    /// Declare a primary service and return its handle
+    pub fn add_primary_service(&mut self, uuid: Uuid) -> u16 {
+        self.add_service(GATT_PRIMARY_SERVICE, uuid)
+    }
+
+    /// Declare a secondary service that is only referenced by other services and return its
+    /// handle
+    pub fn add_secondary_service(&mut self, uuid: Uuid) -> u16 {
+        self.add_service(GATT_SECONDARY_SERVICE, uuid)
+    }
+
+    fn add_service(&mut self, service_type: u16, uuid: Uuid) -> u16 {
+        let mut value = Vec::with_capacity(uuid_size(&uuid));
+        put_uuid(&mut value, &uuid);
+        let handle = self.push(Uuid::from_u16(service_type), PERMISSION_READ, value);
+        self.current_service = Some(handle);
+        self.current_characteristic = None;
+        handle
+    }
+
+    /// Include the service with the given handle in the service declared last and return the
+    /// handle of the include declaration. Includes need to be declared before the
+    /// characteristics of the service.
+    pub fn add_included_service(&mut self, service: u16) -> Result<u16, BoxError> {
+        if self.current_service.is_none() || self.current_characteristic.is_some() {
+            return Err(Box::new(GattError::new(
+                "include outside of a service declaration",
+            )));
+        }
+        if self.current_service == Some(service) {
+            return Err(Box::new(GattError::new("service can not include itself")));
+        }
+        let uuid = match self.service_uuid(service) {
+            Some(uuid) => uuid,
+            None => return Err(Box::new(GattError::new("included service does not exist"))),
+        };
+        let mut value = Vec::with_capacity(6);
+        put_u16(&mut value, service);
+        put_u16(&mut value, self.group_end(service));
+        // 128 bit UUID's are not part of the include declaration and need to be read separately
+        if uuid.as_u16().is_some() {
+            put_uuid(&mut value, &uuid);
+        }
+        Ok(self.push(Uuid::from_u16(GATT_INCLUDE), PERMISSION_READ, value))
+    }
+
+    /// Declare a characteristic of the service declared last with its properties
+    /// (``PROPERTY_*``), the permissions of its value (``PERMISSION_*``) and the initial value.
+    /// This returns the handle of the characteristic value. A characteristic that can be
+    /// notified or indicated gets its Client Characteristic Configuration descriptor added.
+    pub fn add_characteristic(
+        &mut self,
+        uuid: Uuid,
+        properties: u8,
+        permissions: u8,
+        value: Vec<u8>,
+    ) -> Result<u16, BoxError> {
+        if self.current_service.is_none() {
+            return Err(Box::new(GattError::new(
+                "characteristic outside of a service",
+            )));
+        }
+        if value.len() > ATT_MAX_VALUE_LENGTH {
+            return Err(Box::new(GattError::new("characteristic value too long")));
+        }
+        let value_handle = self.next_handle() + 1;
+        let mut declaration = Vec::with_capacity(3 + uuid_size(&uuid));
+        declaration.push(properties);
+        put_u16(&mut declaration, value_handle);
+        put_uuid(&mut declaration, &uuid);
+        let handle = self.push(
+            Uuid::from_u16(GATT_CHARACTERISTIC),
+            PERMISSION_READ,
+            declaration,
+        );
+        self.current_characteristic = Some(handle);
+        let value_handle = self.push(uuid, permissions, value);
+        if properties & (PROPERTY_NOTIFY | PROPERTY_INDICATE) != 0 {
+            // the value of the configuration is kept per connection by the server
+            self.push(
+                Uuid::from_u16(GATT_CLIENT_CHARACTERISTIC_CONFIGURATION),
+                PERMISSION_READ | PERMISSION_WRITE,
+                vec![0, 0],
+            );
+        }
+        Ok(value_handle)
+    }
+
+    /// Declare a descriptor of the characteristic declared last and return its handle
+    pub fn add_descriptor(
+        &mut self,
+        uuid: Uuid,
+        permissions: u8,
+        value: Vec<u8>,
+    ) -> Result<u16, BoxError> {
+        if self.current_characteristic.is_none() {
+            return Err(Box::new(GattError::new(
+                "descriptor outside of a characteristic",
+            )));
+        }
+        if value.len() > ATT_MAX_VALUE_LENGTH {
+            return Err(Box::new(GattError::new("descriptor value too long")));
+        }
+        Ok(self.push(uuid, permissions, value))
+    }
+
+    /// Set the callback providing the value of the attribute with the given handle whenever it
+    /// is read. Without a callback the stored value is read.
+    pub fn set_read_callback(
+        &mut self,
+        handle: u16,
+        callback: ReadCallback,
+    ) -> Result<(), BoxError> {
+        match self.get_mut(handle) {
+            Some(attribute) => {
+                attribute.read.replace(callback);
+                Ok(())
+            }
+            None => Err(Box::new(GattError::new("attribute does not exist"))),
+        }
+    }
+
+    /// Set the callback receiving the values written to the attribute with the given handle
+    pub fn set_write_callback(
+        &mut self,
+        handle: u16,
+        callback: WriteCallback,
+    ) -> Result<(), BoxError> {
+        match self.get_mut(handle) {
+            Some(attribute) => {
+                attribute.write.replace(callback);
+                Ok(())
+            }
+            None => Err(Box::new(GattError::new("attribute does not exist"))),
+        }
+    }
+
+    /// The value stored for the attribute with the given handle
+    pub fn value(&self, handle: u16) -> Option<&[u8]> {
+        self.get(handle).map(|attribute| attribute.value.as_slice())
+    }
+
+    /// Replace the value stored for the attribute with the given handle
+    pub fn set_value(&mut self, handle: u16, value: Vec<u8>) -> Result<(), BoxError> {
+        if value.len() > ATT_MAX_VALUE_LENGTH {
+            return Err(Box::new(GattError::new("attribute value too long")));
+        }
+        match self.get_mut(handle) {
+            Some(attribute) => {
+                attribute.value = value;
+                Ok(())
+            }
+            None => Err(Box::new(GattError::new("attribute does not exist"))),
+        }
+    }
+
+    /// The handle of the Client Characteristic Configuration descriptor of the characteristic
+    /// with the given value handle
+    pub fn client_configuration(&self, value_handle: u16) -> Option<u16> {
+        let mut handle = value_handle + 1;
+        while let Some(attribute) = self.get(handle) {
+            if attribute.attribute_type == Uuid::from_u16(GATT_CLIENT_CHARACTERISTIC_CONFIGURATION)
+            {
+                return Some(handle);
+            }
+            if attribute.attribute_type == Uuid::from_u16(GATT_CHARACTERISTIC)
+                || is_service_type(&attribute.attribute_type)
+            {
+                break;
+            }
+            handle += 1;
+        }
+        None
+    }
+
+    /// The highest handle used in the table
+    pub fn last_handle(&self) -> u16 {
+        self.attributes.len() as u16
+    }
+
+    pub(crate) fn get(&self, handle: u16) -> Option<&Attribute> {
+        if handle == 0 {
+            return None;
+        }
+        self.attributes.get(handle as usize - 1)
+    }
+
+    pub(crate) fn get_mut(&mut self, handle: u16) -> Option<&mut Attribute> {
+        if handle == 0 {
+            return None;
+        }
+        self.attributes.get_mut(handle as usize - 1)
+    }
+
+    /// The handles and attributes within the given range of handles
+    pub(crate) fn range(&self, start: u16, end: u16) -> impl Iterator<Item = (u16, &Attribute)> {
+        let last = end.min(self.last_handle());
+        (start.max(1)..=last).filter_map(move |handle| self.get(handle).map(|a| (handle, a)))
+    }
+
+    /// Whether the attribute with the given handle is a primary or secondary service declaration
+    pub(crate) fn is_service(&self, handle: u16) -> bool {
+        match self.get(handle) {
+            Some(attribute) => is_service_type(&attribute.attribute_type),
+            None => false,
+        }
+    }
+
+    /// The last handle of the group started by the service declaration with the given handle
+    pub(crate) fn group_end(&self, service: u16) -> u16 {
+        let mut end = service;
+        while end < self.last_handle() && !self.is_service(end + 1) {
+            end += 1;
+        }
+        end
+    }
+
+    fn service_uuid(&self, service: u16) -> Option<Uuid> {
+        if !self.is_service(service) {
+            return None;
+        }
+        self.get(service)
+            .and_then(|attribute| parse_uuid(&attribute.value))
+    }
+
+    fn next_handle(&self) -> u16 {
+        self.last_handle() + 1
+    }
+
+    fn push(&mut self, attribute_type: Uuid, permissions: u8, value: Vec<u8>) -> u16 {
+        self.attributes.push(Attribute {
+            attribute_type,
+            permissions,
+            value,
+            read: None,
+            write: None,
+        });
+        self.last_handle()
+    }
+}
+
+/// Whether the attribute type is a primary or secondary service declaration
+pub(crate) fn is_service_type(attribute_type: &Uuid) -> bool {
+    *attribute_type == Uuid::from_u16(GATT_PRIMARY_SERVICE)
+        || *attribute_type == Uuid::from_u16(GATT_SECONDARY_SERVICE)
+}
@@ -0,0 +1,36 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # GATT Errors
+//!
+
+use crate::error::Error;
+
+/// Error raised if a GATT operation can not be processed
+pub struct GattError {
+    reason: &'static str,
+}
+
+impl GattError {
+    pub fn new(reason: &'static str) -> Self {
+        Self { reason }
+    }
+}
+
+impl Error for GattError {}
+
+impl core::fmt::Display for GattError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "GATT error: {}", self.reason)
+    }
+}
+
+impl core::fmt::Debug for GattError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        <GattError as core::fmt::Display>::fmt(self, f)
+    }
+}
@@ -0,0 +1,74 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # Generic Attribute Profile
+//!
+//! GATT structures the attributes exposed with the attribute protocol into services,
+//! characteristics and descriptors. The [GattServer] exposes a [GattDatabase] declared by the
+//! application to the peers of all connections, while the [GattClient] discovers and accesses
+//! the attributes of the peers. Both share the [crate::att::Att] bearer.
+//!
+
+pub mod client;
+pub use client::*;
+pub mod database;
+pub use database::*;
+pub mod errors;
+pub use errors::*;
+pub mod server;
+pub use server::*;
+
+/// The ATT_MTU supported by the GATT server and client
+pub const GATT_MAX_MTU: u16 = 247;
+
+/// Attribute type of a primary service declaration
+pub const GATT_PRIMARY_SERVICE: u16 = 0x2800;
+/// Attribute type of a secondary service declaration
+pub const GATT_SECONDARY_SERVICE: u16 = 0x2801;
+/// Attribute type of an include declaration
+pub const GATT_INCLUDE: u16 = 0x2802;
+/// Attribute type of a characteristic declaration
+pub const GATT_CHARACTERISTIC: u16 = 0x2803;
+/// Attribute type of the Characteristic Extended Properties descriptor
+pub const GATT_CHARACTERISTIC_EXTENDED_PROPERTIES: u16 = 0x2900;
+/// Attribute type of the Characteristic User Description descriptor
+pub const GATT_CHARACTERISTIC_USER_DESCRIPTION: u16 = 0x2901;
+/// Attribute type of the Client Characteristic Configuration descriptor
+pub const GATT_CLIENT_CHARACTERISTIC_CONFIGURATION: u16 = 0x2902;
+/// Attribute type of the Characteristic Presentation Format descriptor
+pub const GATT_CHARACTERISTIC_PRESENTATION_FORMAT: u16 = 0x2904;
+
+/// Characteristic property: the value may be broadcast in advertising data
+pub const PROPERTY_BROADCAST: u8 = 0x01;
+/// Characteristic property: the value may be read
+pub const PROPERTY_READ: u8 = 0x02;
+/// Characteristic property: the value may be written with a Write Command
+pub const PROPERTY_WRITE_WITHOUT_RESPONSE: u8 = 0x04;
+/// Characteristic property: the value may be written with a Write Request
+pub const PROPERTY_WRITE: u8 = 0x08;
+/// Characteristic property: the value may be notified
+pub const PROPERTY_NOTIFY: u8 = 0x10;
+/// Characteristic property: the value may be indicated
+pub const PROPERTY_INDICATE: u8 = 0x20;
+/// Characteristic property: the value may be written with a Signed Write Command
+pub const PROPERTY_AUTHENTICATED_SIGNED_WRITES: u8 = 0x40;
+/// Characteristic property: further properties are given by the extended properties descriptor
+pub const PROPERTY_EXTENDED_PROPERTIES: u8 = 0x80;
+
+/// Attribute permission: the value may be read
+pub const PERMISSION_READ: u8 = 0x01;
+/// Attribute permission: the value may be written
+pub const PERMISSION_WRITE: u8 = 0x02;
+/// Attribute permission: the value may only be read on an encrypted link
+pub const PERMISSION_READ_ENCRYPTED: u8 = 0x04;
+/// Attribute permission: the value may only be written on an encrypted link
+pub const PERMISSION_WRITE_ENCRYPTED: u8 = 0x08;
+
+/// Client Characteristic Configuration bit enabling notifications
+pub const CLIENT_CONFIGURATION_NOTIFY: u16 = 0x0001;
+/// Client Characteristic Configuration bit enabling indications
+pub const CLIENT_CONFIGURATION_INDICATE: u16 = 0x0002;
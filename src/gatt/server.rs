@@ -0,0 +1,1008 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # GATT Server
+//!
+//! The GATT server answers the ATT requests of the peers of all connections from its
+//! [GattDatabase]. The read and write callbacks of the database are called while the server is
+//! locked, so they must not call back into the server. The Client Characteristic Configurations
+//! of peers with a record in the [crate::bond::BondStore] set with [GattServer::set_bond_store]
+//! are kept in this record between their connections.
+//!
+
+use super::*;
+use crate::alloc::boxed::Box;
+use crate::alloc::collections::BTreeMap;
+use crate::alloc::sync::Arc;
+use crate::alloc::vec::Vec;
+use crate::att::*;
+use crate::bond::{self, BondRecord, MemoryBondStore, SharedBondStore};
+use crate::brain::{waker::*, *};
+use crate::error::BoxError;
+use crate::hci::acl::SendAclThinkable;
+use crate::hci::BD_ADDRESS_SIZE;
+use crate::hctl::HcTransportLayer;
+use crate::l2cap::signaling::{get_u16, put_u16};
+use crate::lock::*;
+use crate::pin::Pin;
+use crate::uuid::Uuid;
+use crate::warn;
+
+/// Maximum number of prepared writes queued per connection
+const MAX_PREPARED_WRITES: usize = 32;
+
+/// A write queued with a Prepare Write Request until it is executed
+struct PreparedWrite {
+    handle: u16,
+    offset: u16,
+    value: Vec<u8>,
+}
+
+/// The state the server keeps for each connection
+struct ServerConnection {
+    /// identifies the state of this connection once the handle is reused for a new connection
+    id: u32,
+    /// the peer of the connection, to detect a handle being reused for a new connection
+    peer_address: [u8; BD_ADDRESS_SIZE],
+    prepared: Vec<PreparedWrite>,
+    /// the Client Characteristic Configuration of the peer by descriptor handle
+    configurations: BTreeMap<u16, u16>,
+    /// the sequence number of the indication send last and whether it awaits its confirmation
+    indication_sequence: u32,
+    indication_pending: bool,
+    /// the sequence number of the indication confirmed last
+    confirmed_sequence: u32,
+    /// an indication has not been confirmed in time, no further PDU's are send to the peer
+    timed_out: bool,
+    /// indications waiting to be send or confirmed
+    indication_wakers: Vec<Waker>,
+}
+
+impl ServerConnection {
+    fn new(
+        id: u32,
+        peer_address: [u8; BD_ADDRESS_SIZE],
+        configurations: BTreeMap<u16, u16>,
+    ) -> Self {
+        Self {
+            id,
+            peer_address,
+            prepared: Vec::new(),
+            configurations,
+            indication_sequence: 0,
+            indication_pending: false,
+            confirmed_sequence: 0,
+            timed_out: false,
+            indication_wakers: Vec::new(),
+        }
+    }
+
+    /// Whether the peer has enabled the given configuration bit for the descriptor
+    fn subscribed(&self, descriptor: u16, bit: u16) -> bool {
+        !self.timed_out
+            && self
+                .configurations
+                .get(&descriptor)
+                .map_or(false, |configuration| configuration & bit != 0)
+    }
+
+    fn wake_indications(&mut self) {
+        for waker in self.indication_wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+/// The GATT server exposing a [GattDatabase]
+pub struct GattServer<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    att: Att<T>,
+    database: GattDatabase,
+    connections: BTreeMap<u16, ServerConnection>,
+    /// the id given to the state of the next connection
+    next_connection_id: u32,
+    /// the records of bonded peers keeping their Client Characteristic Configurations between
+    /// connections
+    store: SharedBondStore,
+}
+
+impl<T> GattServer<T>
+where
+    T: HcTransportLayer,
+{
+    /// Create the server exposing the given database on the attribute bearer
+    pub fn new(att: Att<T>, database: GattDatabase) -> Arc<DataLock<Self>> {
+        Arc::new(DataLock::new(Self {
+            att,
+            database,
+            connections: BTreeMap::new(),
+            next_connection_id: 0,
+            store: bond::shared(MemoryBondStore::new()),
+        }))
+    }
+
+    /// This returns the ``Thinkable`` answering the ATT requests received. This should be spawned
+    /// to the Brain once the server is created.
+    #[must_use]
+    pub fn serve(this: Arc<DataLock<Self>>) -> ServeGattThinkable<T> {
+        let recv = this.read().att.recv(AttRole::Server);
+        ServeGattThinkable { server: this, recv }
+    }
+
+    /// The value stored for the attribute with the given handle
+    pub fn value(this: Arc<DataLock<Self>>, handle: u16) -> Option<Vec<u8>> {
+        this.read()
+            .database
+            .value(handle)
+            .map(|value| value.to_vec())
+    }
+
+    /// Replace the value stored for the attribute with the given handle
+    pub fn set_value(
+        this: Arc<DataLock<Self>>,
+        handle: u16,
+        value: Vec<u8>,
+    ) -> Result<(), BoxError> {
+        this.lock().database.set_value(handle, value)
+    }
+
+    /// The ATT_MTU agreed with the peer of the connection with the given handle
+    pub fn mtu(this: Arc<DataLock<Self>>, handle: u16) -> u16 {
+        this.read().att.mtu(handle)
+    }
+
+    /// Set the store the Client Characteristic Configurations of bonded peers are kept in.
+    /// Sharing the store of the security manager keeps the configurations of all peers that
+    /// bonded with it. Without a store set only the peers passed to
+    /// [GattServer::add_bonded_peer] are kept in memory.
+    pub fn set_bond_store(this: Arc<DataLock<Self>>, store: SharedBondStore) {
+        this.lock().store = store;
+    }
+
+    /// Keep the Client Characteristic Configurations of the peer with the given identity
+    /// address between its connections. The configurations of a current connection to the peer
+    /// are taken over. If the store has no record of the peer a record for its public address
+    /// is created.
+    pub fn add_bonded_peer(this: Arc<DataLock<Self>>, peer_address: [u8; BD_ADDRESS_SIZE]) {
+        let server = this.lock();
+        let configurations = server
+            .connections
+            .values()
+            .find(|connection| connection.peer_address == peer_address)
+            .map(|connection| connection.configurations.clone())
+            .unwrap_or_default();
+        let mut store = server.store.lock();
+        let mut record = store
+            .load(&peer_address)
+            .unwrap_or_else(|| BondRecord::new(0x00, peer_address));
+        record.client_configurations.extend(configurations);
+        store.store(record);
+    }
+
+    /// Forget the Client Characteristic Configurations kept for a peer that is no longer bonded
+    pub fn remove_bonded_peer(this: Arc<DataLock<Self>>, peer_address: [u8; BD_ADDRESS_SIZE]) {
+        let server = this.lock();
+        let mut store = server.store.lock();
+        if let Some(mut record) = store.load(&peer_address) {
+            record.client_configurations.clear();
+            store.store(record);
+        }
+    }
+
+    /// Notify the value of the characteristic with the given value handle to all peers that
+    /// have enabled notifications. The value is stored as the value of the characteristic as
+    /// well. The ``Thinkable`` returned concludes once the notifications are passed to the BT
+    /// host, which is throttled by the ACL flow control.
+    pub fn notify(
+        this: Arc<DataLock<Self>>,
+        handle: u16,
+        value: Vec<u8>,
+    ) -> Result<NotifyThinkable<T>, BoxError> {
+        let mut server = this.lock();
+        let descriptor = server.subscription_descriptor(handle, PROPERTY_NOTIFY)?;
+        server.database.set_value(handle, value.clone())?;
+        let subscribers =
+            GattServer::subscribers(&this, &mut server, descriptor, CLIENT_CONFIGURATION_NOTIFY);
+        let sends = subscribers
+            .into_iter()
+            .map(|connection_handle| {
+                let mut value = value.clone();
+                value.truncate(server.att.mtu(connection_handle) as usize - 3);
+                server.att.send(
+                    connection_handle,
+                    &AttPdu::HandleValueNotification { handle, value },
+                )
+            })
+            .collect();
+        Ok(NotifyThinkable { sends, error: None })
+    }
+
+    /// Indicate the value of the characteristic with the given value handle to all peers that
+    /// have enabled indications. The value is stored as the value of the characteristic as
+    /// well. The ``Thinkable`` returned concludes once all peers confirmed the indication, which
+    /// fails if any of them did not confirm it in time.
+    pub fn indicate(
+        this: Arc<DataLock<Self>>,
+        handle: u16,
+        value: Vec<u8>,
+    ) -> Result<IndicateThinkable<T>, BoxError> {
+        let mut server = this.lock();
+        let descriptor = server.subscription_descriptor(handle, PROPERTY_INDICATE)?;
+        server.database.set_value(handle, value.clone())?;
+        let subscribers = GattServer::subscribers(
+            &this,
+            &mut server,
+            descriptor,
+            CLIENT_CONFIGURATION_INDICATE,
+        );
+        let targets = subscribers
+            .into_iter()
+            .map(|connection_handle| (connection_handle, IndicationStep::Queued))
+            .collect();
+        drop(server);
+        Ok(IndicateThinkable {
+            server: this,
+            handle,
+            value,
+            targets,
+            failed: false,
+        })
+    }
+
+    /// The handles of the connections whose peer enabled the given configuration bit for the
+    /// descriptor. The state of connections closed meanwhile is dropped.
+    fn subscribers(
+        this: &Arc<DataLock<Self>>,
+        server: &mut Self,
+        descriptor: u16,
+        bit: u16,
+    ) -> Vec<u16> {
+        let handles: Vec<u16> = server.connections.keys().copied().collect();
+        handles
+            .into_iter()
+            .filter(|handle| {
+                GattServer::refresh_connection(this, server, *handle).is_some()
+                    && server.connections[handle].subscribed(descriptor, bit)
+            })
+            .collect()
+    }
+
+    /// The Client Characteristic Configuration descriptor of the characteristic with the given
+    /// value handle, if the characteristic has the given property
+    fn subscription_descriptor(&self, handle: u16, property: u8) -> Result<u16, BoxError> {
+        let declaration = self
+            .database
+            .get(handle.saturating_sub(1))
+            .filter(|attribute| attribute.attribute_type == Uuid::from_u16(GATT_CHARACTERISTIC));
+        match declaration {
+            Some(declaration) if declaration.value[0] & property != 0 => {
+                match self.database.client_configuration(handle) {
+                    Some(descriptor) => Ok(descriptor),
+                    None => Err(Box::new(GattError::new(
+                        "characteristic has no configuration",
+                    ))),
+                }
+            }
+            Some(_) => Err(Box::new(GattError::new(
+                "characteristic does not support the operation",
+            ))),
+            None => Err(Box::new(GattError::new(
+                "handle is not a characteristic value",
+            ))),
+        }
+    }
+
+    /// Send the indication to the peer of the connection with the given handle if no other
+    /// indication awaits its confirmation. This returns the sequence number of the indication
+    /// send, or ``None`` if it need to wait. An error is returned if the indication can not be
+    /// send to the peer.
+    fn send_indication(
+        this: &Arc<DataLock<Self>>,
+        server: &mut Self,
+        connection_handle: u16,
+        handle: u16,
+        value: &[u8],
+        waker: &Waker,
+    ) -> Result<Option<u32>, ()> {
+        let connection = match server.connections.get_mut(&connection_handle) {
+            Some(connection) if !connection.timed_out => connection,
+            _ => return Err(()),
+        };
+        if connection.indication_pending {
+            connection.indication_wakers.push(waker.clone());
+            return Ok(None);
+        }
+        connection.indication_pending = true;
+        connection.indication_sequence += 1;
+        let sequence = connection.indication_sequence;
+        let mut value = value.to_vec();
+        value.truncate(server.att.mtu(connection_handle) as usize - 3);
+        server.att.queue(
+            connection_handle,
+            &AttPdu::HandleValueIndication { handle, value },
+        );
+        let server = this.clone();
+        spawn(wait(Mseconds(ATT_TRANSACTION_TIMEOUT), ()).map(move |_| {
+            server
+                .lock()
+                .indication_timeout(connection_handle, sequence)
+        }));
+        Ok(Some(sequence))
+    }
+
+    /// An indication has not been confirmed in time. The ATT bearer of the connection can not be
+    /// used any longer.
+    fn indication_timeout(&mut self, handle: u16, sequence: u32) {
+        if let Some(connection) = self.connections.get_mut(&handle) {
+            if connection.indication_pending && connection.indication_sequence == sequence {
+                warn!("indication to connection {} not confirmed", handle);
+                connection.indication_pending = false;
+                connection.timed_out = true;
+                connection.wake_indications();
+            }
+        }
+    }
+
+    /// The connection with the given handle has been closed. Its state is dropped unless the
+    /// handle has been reused for a new connection already.
+    fn disconnected(&mut self, handle: u16, id: u32) {
+        if self
+            .connections
+            .get(&handle)
+            .map_or(false, |connection| connection.id == id)
+        {
+            if let Some(mut connection) = self.connections.remove(&handle) {
+                connection.wake_indications();
+            }
+        }
+    }
+
+    /// Process a PDU received from the peer of the connection with the given handle
+    fn received(
+        this: &Arc<DataLock<Self>>,
+        server: &mut Self,
+        handle: u16,
+        pdu: Result<AttPdu, AttError>,
+    ) {
+        let encrypted = match GattServer::refresh_connection(this, server, handle) {
+            Some(encrypted) => encrypted,
+            None => return,
+        };
+        let response = match pdu {
+            Ok(pdu) if pdu.opcode().is_request() => Some(
+                server
+                    .process_request(handle, encrypted, pdu)
+                    .unwrap_or_else(AttPdu::ErrorResponse),
+            ),
+            Ok(pdu) => {
+                server.process_command(handle, encrypted, pdu);
+                None
+            }
+            // commands that can not be decoded are dropped without a response
+            Err(error) if error.request & ATT_COMMAND_FLAG != 0 => None,
+            Err(error) => match AttOpcode::from(error.request) {
+                opcode if opcode.is_request() || opcode == AttOpcode::Unknown => {
+                    Some(AttPdu::ErrorResponse(error))
+                }
+                _ => None,
+            },
+        };
+        if let Some(response) = response {
+            server.att.queue(handle, &response);
+        }
+    }
+
+    /// Ensure the state of the connection with the given handle is known and return whether
+    /// the connection is encrypted. If the connection is closed this returns ``None``. The
+    /// state of a new connection starts with the Client Characteristic Configurations kept for
+    /// a bonded peer, all others start unsubscribed. It is dropped once the connection closes.
+    fn refresh_connection(
+        this: &Arc<DataLock<Self>>,
+        server: &mut Self,
+        handle: u16,
+    ) -> Option<bool> {
+        let connection = match server.att.connection(handle) {
+            Some(connection) if connection.is_connected() => connection,
+            _ => {
+                server.connections.remove(&handle);
+                return None;
+            }
+        };
+        let info = connection.info();
+        let known = server.connections.get(&handle).map_or(false, |connection| {
+            connection.peer_address == info.peer_address
+        });
+        if !known {
+            let configurations = server
+                .store
+                .read()
+                .resolve(&info.peer_address)
+                .map(|record| record.client_configurations)
+                .unwrap_or_default();
+            let id = server.next_connection_id;
+            server.next_connection_id = id.wrapping_add(1);
+            server.connections.insert(
+                handle,
+                ServerConnection::new(id, info.peer_address, configurations),
+            );
+            let this = this.clone();
+            spawn(
+                connection
+                    .disconnected()
+                    .map(move |_| this.lock().disconnected(handle, id)),
+            );
+        }
+        Some(info.encrypted)
+    }
+
+    /// Process an ATT request and return the response
+    fn process_request(
+        &mut self,
+        handle: u16,
+        encrypted: bool,
+        request: AttPdu,
+    ) -> Result<AttPdu, AttError> {
+        let opcode = request.opcode() as u8;
+        let mtu = self.att.mtu(handle) as usize;
+        match request {
+            AttPdu::ExchangeMtuRequest { mtu: client_mtu } => {
+                self.att.set_mtu(handle, client_mtu.min(GATT_MAX_MTU));
+                Ok(AttPdu::ExchangeMtuResponse { mtu: GATT_MAX_MTU })
+            }
+            AttPdu::FindInformationRequest { start, end } => {
+                check_range(opcode, start, end)?;
+                self.find_information(start, end, mtu)
+                    .ok_or_else(|| AttError::new(opcode, start, ATT_ERROR_ATTRIBUTE_NOT_FOUND))
+            }
+            AttPdu::FindByTypeValueRequest {
+                start,
+                end,
+                attribute_type,
+                value,
+            } => {
+                check_range(opcode, start, end)?;
+                let handles: Vec<(u16, u16)> = self
+                    .database
+                    .range(start, end)
+                    .filter(|(_, attribute)| {
+                        attribute.attribute_type == Uuid::from_u16(attribute_type)
+                            && attribute.value == value
+                    })
+                    .map(|(found, _)| {
+                        if self.database.is_service(found) {
+                            (found, self.database.group_end(found))
+                        } else {
+                            (found, found)
+                        }
+                    })
+                    .take((mtu - 1) / 4)
+                    .collect();
+                if handles.is_empty() {
+                    return Err(AttError::new(opcode, start, ATT_ERROR_ATTRIBUTE_NOT_FOUND));
+                }
+                Ok(AttPdu::FindByTypeValueResponse { handles })
+            }
+            AttPdu::ReadByTypeRequest {
+                start,
+                end,
+                attribute_type,
+            } => {
+                check_range(opcode, start, end)?;
+                self.read_by_type(handle, encrypted, opcode, start, end, attribute_type, mtu)
+            }
+            AttPdu::ReadRequest { handle: attribute } => {
+                let mut value = self
+                    .read_value(handle, encrypted, attribute)
+                    .map_err(|code| AttError::new(opcode, attribute, code))?;
+                value.truncate(mtu - 1);
+                Ok(AttPdu::ReadResponse { value })
+            }
+            AttPdu::ReadBlobRequest {
+                handle: attribute,
+                offset,
+            } => {
+                let value = self
+                    .read_value(handle, encrypted, attribute)
+                    .map_err(|code| AttError::new(opcode, attribute, code))?;
+                let offset = offset as usize;
+                if offset > value.len() {
+                    return Err(AttError::new(opcode, attribute, ATT_ERROR_INVALID_OFFSET));
+                }
+                let end = value.len().min(offset + mtu - 1);
+                Ok(AttPdu::ReadBlobResponse {
+                    value: value[offset..end].to_vec(),
+                })
+            }
+            AttPdu::ReadMultipleRequest { handles } => {
+                let mut values = Vec::new();
+                for attribute in handles {
+                    let value = self
+                        .read_value(handle, encrypted, attribute)
+                        .map_err(|code| AttError::new(opcode, attribute, code))?;
+                    values.extend_from_slice(&value);
+                }
+                values.truncate(mtu - 1);
+                Ok(AttPdu::ReadMultipleResponse { values })
+            }
+            AttPdu::ReadByGroupTypeRequest {
+                start,
+                end,
+                group_type,
+            } => {
+                check_range(opcode, start, end)?;
+                if !is_service_type(&group_type) {
+                    return Err(AttError::new(
+                        opcode,
+                        start,
+                        ATT_ERROR_UNSUPPORTED_GROUP_TYPE,
+                    ));
+                }
+                self.read_by_group_type(start, end, group_type, mtu)
+                    .ok_or_else(|| AttError::new(opcode, start, ATT_ERROR_ATTRIBUTE_NOT_FOUND))
+            }
+            AttPdu::WriteRequest {
+                handle: attribute,
+                value,
+            } => {
+                self.write_value(handle, encrypted, attribute, &value)
+                    .map_err(|code| AttError::new(opcode, attribute, code))?;
+                Ok(AttPdu::WriteResponse)
+            }
+            AttPdu::PrepareWriteRequest {
+                handle: attribute,
+                offset,
+                value,
+            } => {
+                match self.database.get(attribute) {
+                    Some(found) => check_write(found.permissions, encrypted),
+                    None => Err(ATT_ERROR_INVALID_HANDLE),
+                }
+                .map_err(|code| AttError::new(opcode, attribute, code))?;
+                let connection = match self.connections.get_mut(&handle) {
+                    Some(connection) => connection,
+                    None => return Err(AttError::new(opcode, attribute, ATT_ERROR_UNLIKELY_ERROR)),
+                };
+                if connection.prepared.len() >= MAX_PREPARED_WRITES {
+                    return Err(AttError::new(
+                        opcode,
+                        attribute,
+                        ATT_ERROR_PREPARE_QUEUE_FULL,
+                    ));
+                }
+                connection.prepared.push(PreparedWrite {
+                    handle: attribute,
+                    offset,
+                    value: value.clone(),
+                });
+                Ok(AttPdu::PrepareWriteResponse {
+                    handle: attribute,
+                    offset,
+                    value,
+                })
+            }
+            AttPdu::ExecuteWriteRequest { flags } => {
+                let prepared = match self.connections.get_mut(&handle) {
+                    Some(connection) => core::mem::take(&mut connection.prepared),
+                    None => Vec::new(),
+                };
+                if flags == ATT_EXECUTE_WRITE_COMMIT {
+                    self.execute_writes(handle, encrypted, prepared)
+                        .map_err(|(attribute, code)| AttError::new(opcode, attribute, code))?;
+                }
+                Ok(AttPdu::ExecuteWriteResponse)
+            }
+            _ => Err(AttError::new(opcode, 0, ATT_ERROR_REQUEST_NOT_SUPPORTED)),
+        }
+    }
+
+    /// Process an ATT PDU that is not answered
+    fn process_command(&mut self, handle: u16, encrypted: bool, command: AttPdu) {
+        match command {
+            AttPdu::WriteCommand {
+                handle: attribute,
+                value,
+            } => {
+                if let Err(code) = self.write_value(handle, encrypted, attribute, &value) {
+                    warn!("write command to {:#X} failed with {:#X}", attribute, code);
+                }
+            }
+            AttPdu::SignedWriteCommand {
+                handle: attribute, ..
+            } => warn!(
+                "signed write to {:#X} dropped, signing is not supported",
+                attribute
+            ),
+            AttPdu::HandleValueConfirmation => {
+                if let Some(connection) = self.connections.get_mut(&handle) {
+                    if connection.indication_pending {
+                        connection.indication_pending = false;
+                        connection.confirmed_sequence = connection.indication_sequence;
+                        connection.wake_indications();
+                    }
+                }
+            }
+            // responses, notifications and indications are for a GATT client
+            _ => (),
+        }
+    }
+
+    fn find_information(&self, start: u16, end: u16, mtu: usize) -> Option<AttPdu> {
+        let mut information: Vec<(u16, Uuid)> = Vec::new();
+        for (found, attribute) in self.database.range(start, end) {
+            let size = uuid_size(&attribute.attribute_type);
+            if let Some((_, first)) = information.first() {
+                if uuid_size(first) != size || 2 + (information.len() + 1) * (2 + size) > mtu {
+                    break;
+                }
+            }
+            information.push((found, attribute.attribute_type));
+        }
+        if information.is_empty() {
+            None
+        } else {
+            Some(AttPdu::FindInformationResponse { information })
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn read_by_type(
+        &mut self,
+        handle: u16,
+        encrypted: bool,
+        opcode: u8,
+        start: u16,
+        end: u16,
+        attribute_type: Uuid,
+        mtu: usize,
+    ) -> Result<AttPdu, AttError> {
+        let candidates: Vec<u16> = self
+            .database
+            .range(start, end)
+            .filter(|(_, attribute)| attribute.attribute_type == attribute_type)
+            .map(|(found, _)| found)
+            .collect();
+        // each value is at most the MTU minus the opcode, length and handle and fits the length
+        let max_length = (mtu - 4).min(253);
+        let mut values: Vec<(u16, Vec<u8>)> = Vec::new();
+        for found in candidates {
+            let mut value = match self.read_value(handle, encrypted, found) {
+                Ok(value) => value,
+                // only an error of the first attribute is reported
+                Err(code) if values.is_empty() => return Err(AttError::new(opcode, found, code)),
+                Err(_) => break,
+            };
+            value.truncate(max_length);
+            if let Some((_, first)) = values.first() {
+                if first.len() != value.len() || 2 + (values.len() + 1) * (2 + value.len()) > mtu {
+                    break;
+                }
+            }
+            values.push((found, value));
+        }
+        if values.is_empty() {
+            return Err(AttError::new(opcode, start, ATT_ERROR_ATTRIBUTE_NOT_FOUND));
+        }
+        Ok(AttPdu::ReadByTypeResponse { values })
+    }
+
+    fn read_by_group_type(
+        &self,
+        start: u16,
+        end: u16,
+        group_type: Uuid,
+        mtu: usize,
+    ) -> Option<AttPdu> {
+        let max_length = (mtu - 6).min(251);
+        let mut groups: Vec<(u16, u16, Vec<u8>)> = Vec::new();
+        for (found, attribute) in self.database.range(start, end) {
+            if attribute.attribute_type != group_type {
+                continue;
+            }
+            let mut value = attribute.value.clone();
+            value.truncate(max_length);
+            if let Some((_, _, first)) = groups.first() {
+                if first.len() != value.len() || 2 + (groups.len() + 1) * (4 + value.len()) > mtu {
+                    break;
+                }
+            }
+            groups.push((found, self.database.group_end(found), value));
+        }
+        if groups.is_empty() {
+            None
+        } else {
+            Some(AttPdu::ReadByGroupTypeResponse { groups })
+        }
+    }
+
+    /// Read the value of an attribute for the peer of the connection with the given handle
+    fn read_value(&mut self, handle: u16, encrypted: bool, attribute: u16) -> Result<Vec<u8>, u8> {
+        let attribute_handle = attribute;
+        let attribute = match self.database.get_mut(attribute) {
+            Some(attribute) => attribute,
+            None => return Err(ATT_ERROR_INVALID_HANDLE),
+        };
+        check_read(attribute.permissions, encrypted)?;
+        if is_client_configuration(&attribute.attribute_type) {
+            let configuration = self
+                .connections
+                .get(&handle)
+                .and_then(|connection| connection.configurations.get(&attribute_handle))
+                .copied()
+                .unwrap_or(0);
+            let mut value = Vec::with_capacity(2);
+            put_u16(&mut value, configuration);
+            return Ok(value);
+        }
+        match attribute.read {
+            Some(ref mut read) => read(handle),
+            None => Ok(attribute.value.clone()),
+        }
+    }
+
+    /// Write the value of an attribute for the peer of the connection with the given handle
+    fn write_value(
+        &mut self,
+        handle: u16,
+        encrypted: bool,
+        attribute: u16,
+        value: &[u8],
+    ) -> Result<(), u8> {
+        let attribute_handle = attribute;
+        let attribute = match self.database.get_mut(attribute) {
+            Some(attribute) => attribute,
+            None => return Err(ATT_ERROR_INVALID_HANDLE),
+        };
+        check_write(attribute.permissions, encrypted)?;
+        if value.len() > ATT_MAX_VALUE_LENGTH {
+            return Err(ATT_ERROR_INVALID_ATTRIBUTE_VALUE_LENGTH);
+        }
+        if is_client_configuration(&attribute.attribute_type) {
+            if value.len() != 2 {
+                return Err(ATT_ERROR_INVALID_ATTRIBUTE_VALUE_LENGTH);
+            }
+            let configuration = get_u16(value, 0);
+            if let Some(connection) = self.connections.get_mut(&handle) {
+                connection
+                    .configurations
+                    .insert(attribute_handle, configuration);
+                let mut store = self.store.lock();
+                if let Some(mut record) = store.resolve(&connection.peer_address) {
+                    record
+                        .client_configurations
+                        .insert(attribute_handle, configuration);
+                    store.store(record);
+                }
+            }
+            return Ok(());
+        }
+        if let Some(ref mut write) = attribute.write {
+            write(handle, value)?;
+        }
+        attribute.value = value.to_vec();
+        Ok(())
+    }
+
+    /// Assemble the values of the prepared writes and write them. If this fails the handle of
+    /// the attribute and the error code is returned.
+    fn execute_writes(
+        &mut self,
+        handle: u16,
+        encrypted: bool,
+        prepared: Vec<PreparedWrite>,
+    ) -> Result<(), (u16, u8)> {
+        let mut values: Vec<(u16, Vec<u8>)> = Vec::new();
+        for write in prepared {
+            let index = match values.iter().position(|(found, _)| *found == write.handle) {
+                Some(index) => index,
+                None => {
+                    let current = self.database.value(write.handle).unwrap_or(&[]).to_vec();
+                    values.push((write.handle, current));
+                    values.len() - 1
+                }
+            };
+            let value = &mut values[index].1;
+            let offset = write.offset as usize;
+            if offset > value.len() {
+                return Err((write.handle, ATT_ERROR_INVALID_OFFSET));
+            }
+            value.truncate(offset);
+            value.extend_from_slice(&write.value);
+            if value.len() > ATT_MAX_VALUE_LENGTH {
+                return Err((write.handle, ATT_ERROR_INVALID_ATTRIBUTE_VALUE_LENGTH));
+            }
+        }
+        for (attribute, value) in values {
+            self.write_value(handle, encrypted, attribute, &value)
+                .map_err(|code| (attribute, code))?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether the attribute type is a Client Characteristic Configuration descriptor
+fn is_client_configuration(attribute_type: &Uuid) -> bool {
+    *attribute_type == Uuid::from_u16(GATT_CLIENT_CHARACTERISTIC_CONFIGURATION)
+}
+
+/// Check the handle range of a request
+fn check_range(opcode: u8, start: u16, end: u16) -> Result<(), AttError> {
+    if start == 0 || start > end {
+        Err(AttError::new(opcode, start, ATT_ERROR_INVALID_HANDLE))
+    } else {
+        Ok(())
+    }
+}
+
+/// Check whether an attribute with the given permissions can be read
+fn check_read(permissions: u8, encrypted: bool) -> Result<(), u8> {
+    if permissions & (PERMISSION_READ | PERMISSION_READ_ENCRYPTED) == 0 {
+        Err(ATT_ERROR_READ_NOT_PERMITTED)
+    } else if permissions & PERMISSION_READ_ENCRYPTED != 0 && !encrypted {
+        // without a bond the peer need to pair first, which is requested by this error
+        Err(ATT_ERROR_INSUFFICIENT_AUTHENTICATION)
+    } else {
+        Ok(())
+    }
+}
+
+/// Check whether an attribute with the given permissions can be written
+fn check_write(permissions: u8, encrypted: bool) -> Result<(), u8> {
+    if permissions & (PERMISSION_WRITE | PERMISSION_WRITE_ENCRYPTED) == 0 {
+        Err(ATT_ERROR_WRITE_NOT_PERMITTED)
+    } else if permissions & PERMISSION_WRITE_ENCRYPTED != 0 && !encrypted {
+        Err(ATT_ERROR_INSUFFICIENT_AUTHENTICATION)
+    } else {
+        Ok(())
+    }
+}
+
+/// This ``Thinkable`` answers the ATT requests received by the GATT server
+pub struct ServeGattThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    server: Arc<DataLock<GattServer<T>>>,
+    recv: RecvAttThinkable<T>,
+}
+
+impl<T> Thinkable for ServeGattThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = ();
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        let this = self.get_mut();
+        loop {
+            match Pin::new(&mut this.recv).think(cx) {
+                Conclusion::Pending => return Conclusion::Pending,
+                Conclusion::Ready((handle, pdu)) => {
+                    GattServer::received(&this.server, &mut this.server.lock(), handle, pdu)
+                }
+            }
+        }
+    }
+}
+
+/// This ``Thinkable`` concludes once the notifications are passed to the BT host
+pub struct NotifyThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    sends: Vec<SendAclThinkable<T>>,
+    error: Option<BoxError>,
+}
+
+impl<T> Thinkable for NotifyThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = Result<(), BoxError>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        let this = self.get_mut();
+        let mut index = 0;
+        while index < this.sends.len() {
+            match Pin::new(&mut this.sends[index]).think(cx) {
+                Conclusion::Pending => index += 1,
+                Conclusion::Ready(result) => {
+                    if let Err(error) = result {
+                        this.error.replace(error);
+                    }
+                    this.sends.remove(index);
+                }
+            }
+        }
+        if !this.sends.is_empty() {
+            return Conclusion::Pending;
+        }
+        match this.error.take() {
+            Some(error) => Conclusion::Ready(Err(error)),
+            None => Conclusion::Ready(Ok(())),
+        }
+    }
+}
+
+/// The progress of an indication to one peer
+#[derive(Copy, Clone)]
+enum IndicationStep {
+    /// waiting for a previous indication to be confirmed
+    Queued,
+    /// send with the given sequence number and waiting for the confirmation
+    Sent(u32),
+    Done,
+}
+
+/// This ``Thinkable`` concludes once all peers confirmed an indication
+pub struct IndicateThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    server: Arc<DataLock<GattServer<T>>>,
+    handle: u16,
+    value: Vec<u8>,
+    targets: Vec<(u16, IndicationStep)>,
+    failed: bool,
+}
+
+impl<T> Thinkable for IndicateThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = Result<(), BoxError>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        let this = self.get_mut();
+        let mut server = this.server.lock();
+        for (connection_handle, step) in this.targets.iter_mut() {
+            if let IndicationStep::Queued = step {
+                match GattServer::send_indication(
+                    &this.server,
+                    &mut server,
+                    *connection_handle,
+                    this.handle,
+                    &this.value,
+                    cx.waker(),
+                ) {
+                    Ok(Some(sequence)) => *step = IndicationStep::Sent(sequence),
+                    Ok(None) => (),
+                    Err(_) => {
+                        this.failed = true;
+                        *step = IndicationStep::Done;
+                    }
+                }
+            }
+            if let IndicationStep::Sent(sequence) = step {
+                match server.connections.get_mut(connection_handle) {
+                    Some(connection) if connection.confirmed_sequence >= *sequence => {
+                        *step = IndicationStep::Done
+                    }
+                    Some(connection) if !connection.timed_out => {
+                        connection.indication_wakers.push(cx.waker().clone())
+                    }
+                    _ => {
+                        this.failed = true;
+                        *step = IndicationStep::Done;
+                    }
+                }
+            }
+        }
+        let done = this.targets.iter().all(|(_, step)| match step {
+            IndicationStep::Done => true,
+            _ => false,
+        });
+        if !done {
+            Conclusion::Pending
+        } else if this.failed {
+            Conclusion::Ready(Err(Box::new(GattError::new("indication not confirmed"))))
+        } else {
+            Conclusion::Ready(Ok(()))
+        }
+    }
+}
@@ -0,0 +1,746 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # ACL Data
+//!
+//! ACL data packets carry the upper layer PDU's (L2CAP) of a connection. PDU's send are fragmented
+//! to the size of the ACL buffers of the BT host. PDU's received are reassembled from their
+//! fragments based on the length given in the L2CAP header and queued per connection until they
+//! are taken.
+//!
+
+use super::*;
+use crate::alloc::collections::VecDeque;
+use crate::hci::commands::{HciCommandLeReadBufferSize, HciCommandReadBufferSize};
+use crate::hci::connection::{ConnectionTransport, SharedConnectionState, CONNECTION_HANDLE_MASK};
+use crate::hci::errors::*;
+use crate::hci::events::HciEventNumberOfCompletedPackets;
+
+/// Size of the header of an ACL data packet
+pub const ACL_HEADER_SIZE: usize = 4;
+/// Size of the L2CAP basic header containing the length of the PDU
+const L2CAP_HEADER_SIZE: usize = 4;
+/// The length of ACL data every BT host supports. This is used until the buffer size has been
+/// read from the BT host
+const DEFAULT_ACL_DATA_LENGTH: u16 = 27;
+/// The number of ACL data packets the BT host is assumed to hold until the buffer size has been
+/// read from the BT host
+const DEFAULT_ACL_PACKETS: u16 = 1;
+/// Maximum number of PDU's kept per connection until they are taken. If there are more PDU's
+/// received the oldest ones are dropped.
+const MAX_PENDING_PDUS: usize = 16;
+
+/// The packet boundary flag of an ACL data packet
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PacketBoundary {
+    /// first fragment of a PDU that is not automatically flushable, used for LE
+    FirstNonFlushable = 0b00,
+    /// any further fragment of a PDU
+    Continuing = 0b01,
+    /// first fragment of a PDU that is automatically flushable
+    FirstFlushable = 0b10,
+    /// complete PDU, not used any more
+    Complete = 0b11,
+}
+
+impl From<u8> for PacketBoundary {
+    fn from(orig: u8) -> Self {
+        match orig & 0b11 {
+            0b00 => PacketBoundary::FirstNonFlushable,
+            0b01 => PacketBoundary::Continuing,
+            0b10 => PacketBoundary::FirstFlushable,
+            _ => PacketBoundary::Complete,
+        }
+    }
+}
+
+/// The broadcast flag of an ACL data packet
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BroadcastFlag {
+    PointToPoint = 0b00,
+    ActivePeripheralBroadcast = 0b01,
+    Unknown,
+}
+
+impl From<u8> for BroadcastFlag {
+    fn from(orig: u8) -> Self {
+        match orig & 0b11 {
+            0b00 => BroadcastFlag::PointToPoint,
+            0b01 => BroadcastFlag::ActivePeripheralBroadcast,
+            _ => BroadcastFlag::Unknown,
+        }
+    }
+}
+
+/// The header of an ACL data packet
+#[derive(Debug, Copy, Clone)]
+pub struct AclHeader {
+    /// connection handle, only the least 12Bit's are used
+    pub handle: u16,
+    pub packet_boundary: PacketBoundary,
+    pub broadcast: BroadcastFlag,
+    /// length of the data following the header
+    pub length: u16,
+}
+
+impl AclHeader {
+    pub fn new(handle: u16, packet_boundary: PacketBoundary, length: u16) -> Self {
+        Self {
+            handle: handle & CONNECTION_HANDLE_MASK,
+            packet_boundary,
+            broadcast: BroadcastFlag::PointToPoint,
+            length,
+        }
+    }
+
+    /// Parse the header from the first 4 bytes of the raw ACL data packet (without packet type)
+    pub fn parse(raw: &[u8]) -> Self {
+        let handle_flags = raw[0] as u16 | (raw[1] as u16) << 8;
+        Self {
+            handle: handle_flags & CONNECTION_HANDLE_MASK,
+            packet_boundary: ((handle_flags >> 12) as u8).into(),
+            broadcast: ((handle_flags >> 14) as u8).into(),
+            length: raw[2] as u16 | (raw[3] as u16) << 8,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; ACL_HEADER_SIZE] {
+        let handle_flags = self.handle & CONNECTION_HANDLE_MASK
+            | (self.packet_boundary as u16) << 12
+            | (self.broadcast as u16) << 14;
+        [
+            handle_flags as u8,
+            (handle_flags >> 8) as u8,
+            self.length as u8,
+            (self.length >> 8) as u8,
+        ]
+    }
+}
+
+/// The ACL buffers provided by the BT host
+#[derive(Debug, Copy, Clone)]
+pub struct AclBufferSize {
+    /// maximum length of the data of one ACL data packet
+    pub max_data_length: u16,
+    /// number of ACL data packets the BT host is able to hold
+    pub num_packets: u16,
+}
+
+/// The ACL data received for a connection
+struct AclInbound {
+    /// fragments of a PDU waiting for the remaining fragments
+    partial: Vec<u8>,
+    /// number of ACL data packets the fragments has been received with
+    partial_packets: u16,
+    /// the PDU's received together with the number of ACL data packets they were received with
+    pdus: VecDeque<(Vec<u8>, u16)>,
+    waker: Option<Waker>,
+}
+
+impl AclInbound {
+    fn new() -> Self {
+        Self {
+            partial: Vec::new(),
+            partial_packets: 0,
+            pdus: VecDeque::new(),
+            waker: None,
+        }
+    }
+}
+
+/// A PDU waiting to be send
+struct OutboundPdu {
+    id: u32,
+    /// the ACL data packets of this PDU not yet send
+    packets: VecDeque<Vec<u8>>,
+    /// whether a Thinkable waits for the PDU to be send
+    awaited: bool,
+    waker: Option<Waker>,
+}
+
+/// The ACL data to be send on a connection
+struct AclOutbound {
+    transport: ConnectionTransport,
+    pdus: VecDeque<OutboundPdu>,
+    /// number of ACL data packets send that the BT host has not yet completed
+    outstanding: u16,
+}
+
+/// The state of the ACL data path of all connections
+pub(crate) struct AclState {
+    bredr_buffer: Option<AclBufferSize>,
+    le_buffer: Option<AclBufferSize>,
+    /// number of ACL data packets that can be send to the BT host's BR/EDR buffers
+    bredr_credits: u16,
+    /// number of ACL data packets that can be send to the BT host's LE buffers
+    le_credits: u16,
+    inbound: BTreeMap<u16, AclInbound>,
+    outbound: BTreeMap<u16, AclOutbound>,
+    /// the connection served last, the next packet is send from the connection following it
+    last_served: Option<u16>,
+    next_pdu_id: u32,
+    /// the PDU's no longer queued and whether they have been send completely
+    finished: BTreeMap<u32, bool>,
+    /// the buffers this host provides for ACL data send from the BT host, if flow control of
+    /// this data is enabled
+    host_buffer: Option<AclBufferSize>,
+}
+
+impl AclState {
+    pub(crate) fn new() -> Self {
+        Self {
+            bredr_buffer: None,
+            le_buffer: None,
+            bredr_credits: DEFAULT_ACL_PACKETS,
+            le_credits: DEFAULT_ACL_PACKETS,
+            inbound: BTreeMap::new(),
+            outbound: BTreeMap::new(),
+            last_served: None,
+            next_pdu_id: 0,
+            finished: BTreeMap::new(),
+            host_buffer: None,
+        }
+    }
+
+    pub(crate) fn set_host_buffer(&mut self, buffer: Option<AclBufferSize>) {
+        info!("host ACL buffer {:?}", buffer);
+        self.host_buffer = buffer;
+    }
+
+    pub(crate) fn set_bredr_buffer(&mut self, buffer: AclBufferSize) {
+        info!("BR/EDR ACL buffer {:?}", buffer);
+        self.bredr_buffer.replace(buffer);
+        self.bredr_credits = buffer.num_packets.saturating_sub(self.outstanding(false));
+    }
+
+    /// Set the LE buffer size. If the BT host reports no dedicated LE buffers they are shared
+    /// with BR/EDR.
+    pub(crate) fn set_le_buffer(&mut self, buffer: AclBufferSize) {
+        info!("LE ACL buffer {:?}", buffer);
+        if buffer.max_data_length == 0 || buffer.num_packets == 0 {
+            self.le_buffer = None;
+        } else {
+            self.le_buffer.replace(buffer);
+            self.le_credits = buffer.num_packets.saturating_sub(self.outstanding(true));
+        }
+    }
+
+    /// Whether the LE connections use dedicated buffers of the BT host
+    fn has_le_buffers(&self) -> bool {
+        self.le_buffer.is_some()
+    }
+
+    /// The number of packets outstanding in the LE or the BR/EDR buffers of the BT host
+    fn outstanding(&self, le_buffers: bool) -> u16 {
+        let has_le_buffers = self.has_le_buffers();
+        self.outbound
+            .values()
+            .filter(|outbound| {
+                (outbound.transport == ConnectionTransport::Le && has_le_buffers) == le_buffers
+            })
+            .map(|outbound| outbound.outstanding)
+            .sum()
+    }
+
+    /// The credits of the buffers of the BT host used for the given transport
+    fn credits(&mut self, transport: ConnectionTransport) -> &mut u16 {
+        if transport == ConnectionTransport::Le && self.has_le_buffers() {
+            &mut self.le_credits
+        } else {
+            &mut self.bredr_credits
+        }
+    }
+
+    /// Queue the ACL data packets of a PDU to be send. This returns the id of the PDU to check
+    /// whether it has been send if a waker is given.
+    fn enqueue(
+        &mut self,
+        handle: u16,
+        transport: ConnectionTransport,
+        packets: Vec<Vec<u8>>,
+        waker: Option<Waker>,
+    ) -> u32 {
+        let id = self.next_pdu_id;
+        self.next_pdu_id = self.next_pdu_id.wrapping_add(1);
+        self.outbound
+            .entry(handle)
+            .or_insert_with(|| AclOutbound {
+                transport,
+                pdus: VecDeque::new(),
+                outstanding: 0,
+            })
+            .pdus
+            .push_back(OutboundPdu {
+                id,
+                packets: packets.into_iter().collect(),
+                awaited: waker.is_some(),
+                waker,
+            });
+        id
+    }
+
+    /// Take the next packet to be send. The connections with data to send are served round robin
+    /// one packet at a time as long as the buffers of the BT host have free space.
+    fn next_packet(&mut self) -> Option<Vec<u8>> {
+        let after = self.last_served;
+        let mut candidates: Vec<u16> = self
+            .outbound
+            .iter()
+            .filter(|(_, outbound)| !outbound.pdus.is_empty())
+            .map(|(handle, _)| *handle)
+            .collect();
+        // start with the connection following the one served last
+        if let Some(after) = after {
+            let split = candidates
+                .iter()
+                .position(|handle| *handle > after)
+                .unwrap_or(candidates.len());
+            candidates.rotate_left(split);
+        }
+
+        for handle in candidates {
+            let transport = self.outbound[&handle].transport;
+            let credits = self.credits(transport);
+            if *credits == 0 {
+                continue;
+            }
+            *credits -= 1;
+            self.last_served = Some(handle);
+            let outbound = self.outbound.get_mut(&handle).unwrap();
+            outbound.outstanding += 1;
+            let pdu = outbound.pdus.front_mut().unwrap();
+            let packet = pdu.packets.pop_front();
+            if pdu.packets.is_empty() {
+                let mut pdu = outbound.pdus.pop_front().unwrap();
+                if pdu.awaited {
+                    self.finished.insert(pdu.id, true);
+                }
+                if let Some(waker) = pdu.waker.take() {
+                    waker.wake();
+                }
+            }
+            return packet;
+        }
+        None
+    }
+
+    /// The Thinkable waiting for the PDU with the given id has been dropped. A PDU still queued is
+    /// send anyway, but its outcome is no longer kept.
+    fn abandon(&mut self, handle: u16, id: u32) {
+        if self.finished.remove(&id).is_some() {
+            return;
+        }
+        if let Some(outbound) = self.outbound.get_mut(&handle) {
+            if let Some(pdu) = outbound.pdus.iter_mut().find(|pdu| pdu.id == id) {
+                pdu.awaited = false;
+                pdu.waker.take();
+            }
+        }
+    }
+
+    /// The BT host has completed ACL data packets, so there is free buffer space again
+    pub(crate) fn completed(&mut self, event: &HciEventNumberOfCompletedPackets) {
+        for completed in &event.completed {
+            let handle = completed.handle & CONNECTION_HANDLE_MASK;
+            let transport = match self.outbound.get_mut(&handle) {
+                Some(outbound) => {
+                    outbound.outstanding =
+                        outbound.outstanding.saturating_sub(completed.num_completed);
+                    outbound.transport
+                }
+                None => continue,
+            };
+            let credits = self.credits(transport);
+            *credits = credits.saturating_add(completed.num_completed);
+        }
+    }
+
+    /// The maximum length of the data of one ACL data packet send on the given transport
+    pub(crate) fn max_data_length(&self, transport: ConnectionTransport) -> u16 {
+        let buffer = match transport {
+            ConnectionTransport::Le => self.le_buffer.or(self.bredr_buffer),
+            ConnectionTransport::BrEdr => self.bredr_buffer,
+        };
+        buffer.map_or(DEFAULT_ACL_DATA_LENGTH, |buffer| buffer.max_data_length)
+    }
+
+    /// An ACL data packet has been received for an established connection, the packet data
+    /// starts with the packet type followed by the complete header. Once a PDU has been
+    /// completely received it is queued for its connection. This returns the number of packets
+    /// dropped, as their buffers are free again.
+    fn received(&mut self, packet_data: &[u8]) -> u16 {
+        let header = AclHeader::parse(&packet_data[1..]);
+        let data = &packet_data[ACL_HEADER_SIZE + 1..];
+        let inbound = self
+            .inbound
+            .entry(header.handle)
+            .or_insert_with(AclInbound::new);
+        let mut dropped = 0;
+
+        match header.packet_boundary {
+            PacketBoundary::Continuing => {
+                if inbound.partial.is_empty() {
+                    warn!("unexpected ACL continuation fragment for {}", header.handle);
+                    return 1;
+                }
+                inbound.partial.extend_from_slice(data);
+                inbound.partial_packets += 1;
+            }
+            _ => {
+                if !inbound.partial.is_empty() {
+                    warn!("incomplete ACL PDU for {} dropped", header.handle);
+                    dropped += inbound.partial_packets;
+                }
+                inbound.partial = data.to_vec();
+                inbound.partial_packets = 1;
+            }
+        }
+
+        if inbound.partial.len() < L2CAP_HEADER_SIZE {
+            return dropped;
+        }
+        let expected = L2CAP_HEADER_SIZE
+            + (inbound.partial[0] as usize | (inbound.partial[1] as usize) << 8);
+        if inbound.partial.len() < expected {
+            return dropped;
+        }
+        if inbound.partial.len() > expected {
+            warn!("ACL PDU for {} exceeds its length, truncated", header.handle);
+            inbound.partial.truncate(expected);
+        }
+
+        let pdu = core::mem::replace(&mut inbound.partial, Vec::new());
+        let packets = core::mem::replace(&mut inbound.partial_packets, 0);
+        if inbound.pdus.len() >= MAX_PENDING_PDUS {
+            warn!("ACL PDU queue for {} full, drop oldest PDU", header.handle);
+            if let Some((_, packets)) = inbound.pdus.pop_front() {
+                dropped += packets;
+            }
+        }
+        inbound.pdus.push_back((pdu, packets));
+        if let Some(waker) = inbound.waker.take() {
+            waker.wake();
+        }
+        dropped
+    }
+
+    /// The connection has been closed, drop its data and wake the Thinkables waiting for data to
+    /// be received or send. The packets still outstanding are flushed by the BT host, so their
+    /// buffers are free again.
+    pub(crate) fn closed(&mut self, handle: u16) {
+        let handle = handle & CONNECTION_HANDLE_MASK;
+        if let Some(inbound) = self.inbound.remove(&handle) {
+            if let Some(waker) = inbound.waker {
+                waker.wake();
+            }
+        }
+        if let Some(outbound) = self.outbound.remove(&handle) {
+            let credits = self.credits(outbound.transport);
+            *credits = credits.saturating_add(outbound.outstanding);
+            for mut pdu in outbound.pdus {
+                if pdu.awaited {
+                    self.finished.insert(pdu.id, false);
+                }
+                if let Some(waker) = pdu.waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+}
+
+impl<T> Hci<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    /// Process an ACL data packet received from the BT host. Packets that are dropped are
+    /// released for the connection handle of their header, even if the header is incomplete.
+    pub(crate) fn receive_acl_packet(&mut self, packet_data: &[u8]) {
+        let handle = match packet_data.get(1..3) {
+            Some(raw) => (raw[0] as u16 | (raw[1] as u16) << 8) & CONNECTION_HANDLE_MASK,
+            None => {
+                warn!("ACL data packet without connection handle dropped");
+                return;
+            }
+        };
+        let dropped = if packet_data.len() < ACL_HEADER_SIZE + 1 {
+            warn!("ACL data packet for {} too short", handle);
+            1
+        } else if self.connections.get(handle).is_none() {
+            warn!("ACL data packet for unknown connection {} dropped", handle);
+            1
+        } else {
+            self.acl.received(packet_data)
+        };
+        self.release_acl_packets(handle, dropped);
+    }
+
+    /// Release the buffers of ACL data packets received that has been processed. The BT host is
+    /// informed about the free buffers if flow control of the data send by the BT host is enabled.
+    /// The command is send without waiting for any response as the BT host does not answer it.
+    pub(crate) fn release_acl_packets(&mut self, handle: u16, num_completed: u16) {
+        if num_completed == 0 || self.acl.host_buffer.is_none() {
+            return;
+        }
+        let packet = packet::HciPacket::from(
+            commands::HciCommandHostNumberOfCompletedPackets::new(handle, num_completed),
+        );
+        if let Some(ref mut transport) = self.transport_layer {
+            if transport
+                .send_packet(unsafe { packet.as_array_ref() })
+                .is_err()
+            {
+                warn!("releasing ACL buffers of {} failed", handle);
+            }
+        }
+    }
+
+    /// Queue a PDU to be send to the peer of a connection without waiting for it. The PDU's are
+    /// send in the order they are queued, so protocols sending several PDU's at once use this
+    /// instead of a ``SendAclThinkable`` per PDU.
+    pub(crate) fn queue_acl_pdu(&mut self, handle: u16, pdu: &[u8]) {
+        let transport = match self.connections.get(handle) {
+            Some(state) => state.read().info.transport,
+            None => return,
+        };
+        let max_data_length = self.acl.max_data_length(transport);
+        let packets = fragment(handle, transport, pdu, max_data_length);
+        self.acl.enqueue(handle, transport, packets, None);
+        self.send_acl_packets();
+    }
+
+    /// Send the queued ACL data packets as long as the BT host has free buffers for them
+    pub(crate) fn send_acl_packets(&mut self) {
+        if let Some(ref mut transport) = self.transport_layer {
+            while let Some(packet) = self.acl.next_packet() {
+                if transport.send_packet(&packet).is_err() {
+                    warn!("sending ACL data packet failed");
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn read_bredr_buffer_size<T>(
+    hci: Arc<DataLock<Hci<T>>>,
+) -> impl Thinkable<Output = Result<(), BoxError>>
+where
+    T: HcTransportLayer + 'static,
+{
+    let hci_clone = hci.clone();
+    Hci::send_command_with_result(hci, HciCommandReadBufferSize::new()).map(move |result| {
+        let parameters = result?;
+        if parameters.len() < 7 {
+            return Err(Box::new(HciError {}) as BoxError);
+        }
+        hci_clone.lock().acl.set_bredr_buffer(AclBufferSize {
+            max_data_length: parameters[0] as u16 | (parameters[1] as u16) << 8,
+            num_packets: parameters[3] as u16 | (parameters[4] as u16) << 8,
+        });
+        Ok(())
+    })
+}
+
+pub(crate) fn read_le_buffer_size<T>(
+    hci: Arc<DataLock<Hci<T>>>,
+) -> impl Thinkable<Output = Result<(), BoxError>>
+where
+    T: HcTransportLayer + 'static,
+{
+    let hci_clone = hci.clone();
+    Hci::send_command_with_result(hci, HciCommandLeReadBufferSize::new()).map(move |result| {
+        let parameters = result?;
+        if parameters.len() < 3 {
+            return Err(Box::new(HciError {}) as BoxError);
+        }
+        hci_clone.lock().acl.set_le_buffer(AclBufferSize {
+            max_data_length: parameters[0] as u16 | (parameters[1] as u16) << 8,
+            num_packets: parameters[2] as u16,
+        });
+        Ok(())
+    })
+}
+
+/// Fragment a PDU into ACL data packets (including the packet type) with the given maximum data
+/// length
+pub(crate) fn fragment(
+    handle: u16,
+    transport: ConnectionTransport,
+    pdu: &[u8],
+    max_data_length: u16,
+) -> Vec<Vec<u8>> {
+    let first_boundary = match transport {
+        ConnectionTransport::Le => PacketBoundary::FirstNonFlushable,
+        ConnectionTransport::BrEdr => PacketBoundary::FirstFlushable,
+    };
+    pdu.chunks(max_data_length.max(1) as usize)
+        .enumerate()
+        .map(|(index, chunk)| {
+            let boundary = if index == 0 {
+                first_boundary
+            } else {
+                PacketBoundary::Continuing
+            };
+            let header = AclHeader::new(handle, boundary, chunk.len() as u16);
+            let mut packet = Vec::with_capacity(1 + ACL_HEADER_SIZE + chunk.len());
+            packet.push(packet::HciPacketType::AclData as u8);
+            packet.extend_from_slice(&header.to_bytes());
+            packet.extend_from_slice(chunk);
+            packet
+        })
+        .collect()
+}
+
+/// This ``Thinkable`` sends a PDU to the peer of a connection. It concludes once all packets of
+/// the PDU have been passed to the BT host, which might need to wait for free buffers of the
+/// BT host.
+pub struct SendAclThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    hci: Arc<DataLock<Hci<T>>>,
+    handle: u16,
+    pdu: Option<Vec<u8>>,
+    /// the id of the queued PDU
+    id: Option<u32>,
+}
+
+impl<T> SendAclThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    pub(crate) fn new(hci: Arc<DataLock<Hci<T>>>, handle: u16, pdu: Vec<u8>) -> Self {
+        Self {
+            hci,
+            handle,
+            pdu: Some(pdu),
+            id: None,
+        }
+    }
+}
+
+impl<T> Thinkable for SendAclThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = Result<(), BoxError>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        // none of the fields is structurally pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        let mut hci = this.hci.lock();
+        if let Some(id) = this.id {
+            return match hci.acl.finished.remove(&id) {
+                Some(true) => {
+                    this.id.take();
+                    Conclusion::Ready(Ok(()))
+                }
+                Some(false) => {
+                    this.id.take();
+                    Conclusion::Ready(Err(Box::new(HciParameterError::new("connection closed"))))
+                }
+                None => {
+                    // still waiting for free buffers, update the waker of the PDU
+                    if let Some(outbound) = hci.acl.outbound.get_mut(&this.handle) {
+                        if let Some(pdu) = outbound.pdus.iter_mut().find(|pdu| pdu.id == id) {
+                            pdu.waker.replace(cx.waker().clone());
+                        }
+                    }
+                    Conclusion::Pending
+                }
+            };
+        }
+
+        let transport = match hci.connections.get(this.handle) {
+            Some(state) => state.read().info.transport,
+            None => {
+                return Conclusion::Ready(Err(Box::new(HciParameterError::new(
+                    "unknown connection",
+                ))))
+            }
+        };
+        let pdu = this.pdu.take().unwrap_or_default();
+        let max_data_length = hci.acl.max_data_length(transport);
+        let packets = fragment(this.handle, transport, &pdu, max_data_length);
+        let id = hci
+            .acl
+            .enqueue(this.handle, transport, packets, Some(cx.waker().clone()));
+        hci.send_acl_packets();
+        match hci.acl.finished.remove(&id) {
+            Some(true) => Conclusion::Ready(Ok(())),
+            Some(false) => Conclusion::Ready(Err(Box::new(HciParameterError::new(
+                "connection closed",
+            )))),
+            None => {
+                this.id.replace(id);
+                Conclusion::Pending
+            }
+        }
+    }
+}
+
+impl<T> Drop for SendAclThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    fn drop(&mut self) {
+        // the outcome of a PDU still awaited is dropped once known
+        if let Some(id) = self.id {
+            self.hci.lock().acl.abandon(self.handle, id);
+        }
+    }
+}
+
+/// This ``Thinkable`` concludes with the next PDU received on a connection or with ``None`` once
+/// the connection has been closed
+pub struct RecvAclThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    hci: Arc<DataLock<Hci<T>>>,
+    state: SharedConnectionState,
+}
+
+impl<T> RecvAclThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    pub(crate) fn new(hci: Arc<DataLock<Hci<T>>>, state: SharedConnectionState) -> Self {
+        Self { hci, state }
+    }
+}
+
+impl<T> Thinkable for RecvAclThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = Option<Vec<u8>>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        let mut hci = self.hci.lock();
+        let state = self.state.read();
+        // the data of a closed connection has been dropped already
+        if state.disconnect_reason.is_some() {
+            return Conclusion::Ready(None);
+        }
+        let handle = state.info.handle;
+        let inbound = hci.acl.inbound.entry(handle).or_insert_with(AclInbound::new);
+        match inbound.pdus.pop_front() {
+            Some((pdu, packets)) => {
+                // the PDU has been taken, so the buffers it has been received with are free
+                hci.release_acl_packets(handle, packets);
+                Conclusion::Ready(Some(pdu))
+            }
+            None => {
+                inbound.waker.replace(cx.waker().clone());
+                Conclusion::Pending
+            }
+        }
+    }
+}
@@ -0,0 +1,407 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # LE Extended Advertising
+//!
+//! Bluetooth 5 capable BT hosts are able to run several advertising sets at the same time. Each
+//! advertising set has its own parameters, advertising data and scan response data. This allows
+//! for example to broadcast a beacon while advertising a connectable GATT service.
+//!
+
+use super::*;
+use crate::hci::commands::*;
+use crate::hci::errors::*;
+use crate::hci::events::HciEventLeAdvertisingSetTerminated;
+use crate::hctl::HcTransportLayer;
+
+/// Handle of an advertising set
+pub type AdvertisingHandle = u8;
+
+/// The highest advertising handle allowed
+const MAX_ADVERTISING_HANDLE: AdvertisingHandle = 0xEF;
+/// Maximum advertising or scan response data length of advertising sets using legacy PDU's
+const LEGACY_ADV_DATA_LENGTH: usize = 31;
+/// Maximum advertising or scan response data length the specification allows for advertising sets
+/// using extended advertising PDU's
+const EXT_ADV_DATA_LENGTH: usize = 1650;
+
+/// The state of an advertising set known to the BT host
+struct AdvertisingSet {
+    parameters: AdvertisingParameters,
+    /// the TX power the BT host has choosen for this set
+    selected_tx_power: Option<i8>,
+    enabled: bool,
+}
+
+/// The advertising sets created at the BT host. The limits of the BT host are only known once
+/// they have been read from the BT host.
+pub(crate) struct AdvertisingSets {
+    sets: BTreeMap<AdvertisingHandle, AdvertisingSet>,
+    max_data_length: Option<u16>,
+    num_supported_sets: Option<u8>,
+}
+
+impl AdvertisingSets {
+    pub(crate) fn new() -> Self {
+        Self {
+            sets: BTreeMap::new(),
+            max_data_length: None,
+            num_supported_sets: None,
+        }
+    }
+
+    /// Returns true if the advertising set is currently advertising
+    pub(crate) fn is_enabled(&self, handle: AdvertisingHandle) -> bool {
+        self.sets.get(&handle).map_or(false, |set| set.enabled)
+    }
+
+    /// Returns the TX power the BT host uses for the advertising set
+    pub(crate) fn selected_tx_power(&self, handle: AdvertisingHandle) -> Option<i8> {
+        self.sets.get(&handle).and_then(|set| set.selected_tx_power)
+    }
+
+    /// Reserve the next free advertising handle for a new advertising set
+    fn allocate(
+        &mut self,
+        parameters: AdvertisingParameters,
+    ) -> Result<AdvertisingHandle, BoxError> {
+        if let Some(num_supported_sets) = self.num_supported_sets {
+            if self.sets.len() >= num_supported_sets as usize {
+                return Err(Box::new(HciParameterError::new(
+                    "no more advertising sets supported",
+                )));
+            }
+        }
+
+        let handle = (0..=MAX_ADVERTISING_HANDLE)
+            .find(|handle| !self.sets.contains_key(handle))
+            .ok_or_else(|| -> BoxError {
+                Box::new(HciParameterError::new("no free advertising handle"))
+            })?;
+
+        self.sets.insert(
+            handle,
+            AdvertisingSet {
+                parameters,
+                selected_tx_power: None,
+                enabled: false,
+            },
+        );
+
+        Ok(handle)
+    }
+
+    fn release(&mut self, handle: AdvertisingHandle) {
+        self.sets.remove(&handle);
+    }
+
+    fn set_enabled(&mut self, handle: AdvertisingHandle, enabled: bool) {
+        if let Some(set) = self.sets.get_mut(&handle) {
+            set.enabled = enabled;
+        }
+    }
+
+    /// Update the state of an advertising set that has been terminated by the BT host
+    pub(crate) fn terminated(&mut self, event: &HciEventLeAdvertisingSetTerminated) {
+        let handle = event.handle;
+        info!("advertising set {} terminated with status {}", handle, event.status);
+        self.set_enabled(handle, false);
+    }
+
+    /// Verify the advertising or scan response data could be passed to the advertising set
+    fn validate_data(
+        &self,
+        parameters: &AdvertisingParameters,
+        data: &[u8],
+        scan_response: bool,
+    ) -> Result<(), BoxError> {
+        if parameters.is_legacy() {
+            if data.len() > LEGACY_ADV_DATA_LENGTH {
+                return Err(Box::new(HciParameterError::new(
+                    "data exceeds 31 bytes of legacy advertising",
+                )));
+            }
+        } else {
+            let max_length = self
+                .max_data_length
+                .map_or(EXT_ADV_DATA_LENGTH, |length| length as usize);
+            if data.len() > max_length {
+                return Err(Box::new(HciParameterError::new(
+                    "data exceeds maximum advertising data length",
+                )));
+            }
+        }
+
+        if scan_response && !data.is_empty() && parameters.event_properties & ADV_PROP_SCANNABLE == 0 {
+            return Err(Box::new(HciParameterError::new(
+                "scan response data requires scannable advertising",
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Split the advertising or scan response data into the commands passing the data fragments to
+/// the BT host
+fn fragment_data(
+    handle: AdvertisingHandle,
+    data: &[u8],
+    scan_response: bool,
+) -> Vec<HciCommandLeSetExtendedAdvertisingData> {
+    let build: fn(u8, AdvertisingDataOperation, &[u8]) -> HciCommandLeSetExtendedAdvertisingData =
+        if scan_response {
+            HciCommandLeSetExtendedAdvertisingData::scan_response
+        } else {
+            HciCommandLeSetExtendedAdvertisingData::new
+        };
+
+    if data.len() <= EXT_ADV_DATA_FRAGMENT_SIZE {
+        return vec![build(handle, AdvertisingDataOperation::Complete, data)];
+    }
+
+    let last = (data.len() - 1) / EXT_ADV_DATA_FRAGMENT_SIZE;
+    data.chunks(EXT_ADV_DATA_FRAGMENT_SIZE)
+        .enumerate()
+        .map(|(idx, fragment)| {
+            let operation = match idx {
+                0 => AdvertisingDataOperation::FirstFragment,
+                _ if idx == last => AdvertisingDataOperation::LastFragment,
+                _ => AdvertisingDataOperation::IntermediateFragment,
+            };
+            build(handle, operation, fragment)
+        })
+        .collect()
+}
+
+pub(crate) fn read_max_advertising_data_length<T>(
+    hci: Arc<DataLock<Hci<T>>>,
+) -> impl Thinkable<Output = Result<u16, BoxError>>
+where
+    T: HcTransportLayer + 'static,
+{
+    let hci_clone = hci.clone();
+    Hci::send_command_with_result(hci, HciCommandLeReadMaximumAdvertisingDataLength::new()).map(
+        move |result| {
+            let parameters = result?;
+            if parameters.len() < 2 {
+                return Err(Box::new(HciError {}) as BoxError);
+            }
+            let length = parameters[0] as u16 | (parameters[1] as u16) << 8;
+            hci_clone.lock().advertising_sets.max_data_length.replace(length);
+            Ok(length)
+        },
+    )
+}
+
+pub(crate) fn read_number_of_supported_advertising_sets<T>(
+    hci: Arc<DataLock<Hci<T>>>,
+) -> impl Thinkable<Output = Result<u8, BoxError>>
+where
+    T: HcTransportLayer + 'static,
+{
+    let hci_clone = hci.clone();
+    Hci::send_command_with_result(hci, HciCommandLeReadNumberOfSupportedAdvertisingSets::new()).map(
+        move |result| {
+            let parameters = result?;
+            let num_sets = *parameters.first().ok_or_else(|| -> BoxError { Box::new(HciError {}) })?;
+            hci_clone.lock().advertising_sets.num_supported_sets.replace(num_sets);
+            Ok(num_sets)
+        },
+    )
+}
+
+pub(crate) fn create_advertising_set<T>(
+    hci: Arc<DataLock<Hci<T>>>,
+    parameters: AdvertisingParameters,
+    advertising_data: Vec<u8>,
+    scan_response_data: Vec<u8>,
+) -> impl Thinkable<Output = Result<AdvertisingHandle, BoxError>>
+where
+    T: HcTransportLayer + 'static,
+{
+    let allocation = {
+        let mut hci = hci.lock();
+        hci.advertising_sets
+            .validate_data(&parameters, &advertising_data, false)
+            .and_then(|_| {
+                hci.advertising_sets
+                    .validate_data(&parameters, &scan_response_data, true)
+            })
+            .and_then(|_| hci.advertising_sets.allocate(parameters))
+    };
+
+    let (handle, chain): (Option<AdvertisingHandle>, Vec<CommandThinkable>) = match allocation {
+        Ok(handle) => {
+            let hci_clone = hci.clone();
+            let mut chain: Vec<CommandThinkable> = vec![Box::pin(
+                Hci::send_command_with_result(
+                    hci.clone(),
+                    HciCommandLeSetExtendedAdvertisingParameters::new(handle, &parameters),
+                )
+                .map(move |result| {
+                    // the BT host returns the TX power it has selected for the advertising set
+                    let parameters = result?;
+                    if let Some(tx_power) = parameters.first() {
+                        if let Some(set) = hci_clone.lock().advertising_sets.sets.get_mut(&handle) {
+                            set.selected_tx_power.replace(*tx_power as i8);
+                        }
+                    }
+                    Ok(())
+                }),
+            )];
+            if !advertising_data.is_empty() {
+                chain.push(Box::pin(send_commands(
+                    fragment_data(handle, &advertising_data, false),
+                    hci.clone(),
+                )));
+            }
+            if !scan_response_data.is_empty() {
+                chain.push(Box::pin(send_commands(
+                    fragment_data(handle, &scan_response_data, true),
+                    hci.clone(),
+                )));
+            }
+            (Some(handle), chain)
+        }
+        Err(e) => (None, vec![Box::pin(ReadyThinkable::new(Err(e)))]),
+    };
+
+    CommandChainThinkable::new(chain).map(move |result| match (result, handle) {
+        (Ok(_), Some(handle)) => Ok(handle),
+        (Ok(_), None) => Err(Box::new(HciError {}) as BoxError),
+        (Err(e), handle) => {
+            // the advertising set could not be created, so release the reserved handle
+            if let Some(handle) = handle {
+                hci.lock().advertising_sets.release(handle);
+            }
+            Err(e)
+        }
+    })
+}
+
+pub(crate) fn set_advertising_data<T>(
+    hci: Arc<DataLock<Hci<T>>>,
+    handle: AdvertisingHandle,
+    data: Vec<u8>,
+    scan_response: bool,
+) -> impl Thinkable<Output = Result<(), BoxError>>
+where
+    T: HcTransportLayer + 'static,
+{
+    let validation = {
+        let hci = hci.lock();
+        match hci.advertising_sets.sets.get(&handle) {
+            Some(set) => {
+                if !set.parameters.is_legacy()
+                    && set.enabled
+                    && data.len() > EXT_ADV_DATA_FRAGMENT_SIZE
+                {
+                    // fragmented data could only be passed while the advertising set is disabled
+                    Err(Box::new(HciParameterError::new(
+                        "fragmented data requires disabled advertising set",
+                    )) as BoxError)
+                } else {
+                    hci.advertising_sets
+                        .validate_data(&set.parameters, &data, scan_response)
+                }
+            }
+            None => Err(Box::new(HciParameterError::new("unknown advertising set")) as BoxError),
+        }
+    };
+
+    let link: CommandThinkable = match validation {
+        Ok(_) => Box::pin(send_commands(fragment_data(handle, &data, scan_response), hci)),
+        Err(e) => Box::pin(ReadyThinkable::new(Err(e))),
+    };
+    CommandChainThinkable::new(vec![link])
+}
+
+pub(crate) fn enable_advertising_sets<T>(
+    hci: Arc<DataLock<Hci<T>>>,
+    sets: Vec<AdvertisingSetEnable>,
+) -> impl Thinkable<Output = Result<(), BoxError>>
+where
+    T: HcTransportLayer + 'static,
+{
+    let hci_clone = hci.clone();
+    let handles: Vec<AdvertisingHandle> = sets.iter().map(|set| set.handle()).collect();
+    Hci::send_command(hci, HciCommandLeSetExtendedAdvertisingEnable::new(true, &sets)).map(
+        move |result| {
+            if result.is_ok() {
+                let mut hci = hci_clone.lock();
+                for handle in handles {
+                    hci.advertising_sets.set_enabled(handle, true);
+                }
+            }
+            result
+        },
+    )
+}
+
+pub(crate) fn disable_advertising_sets<T>(
+    hci: Arc<DataLock<Hci<T>>>,
+    handles: Vec<AdvertisingHandle>,
+) -> impl Thinkable<Output = Result<(), BoxError>>
+where
+    T: HcTransportLayer + 'static,
+{
+    let hci_clone = hci.clone();
+    let sets: Vec<AdvertisingSetEnable> = handles
+        .iter()
+        .map(|handle| AdvertisingSetEnable::new(*handle, 0, 0))
+        .collect();
+    Hci::send_command(hci, HciCommandLeSetExtendedAdvertisingEnable::new(false, &sets)).map(
+        move |result| {
+            if result.is_ok() {
+                let mut hci = hci_clone.lock();
+                if handles.is_empty() {
+                    // disabling without any set given disables all sets
+                    for set in hci.advertising_sets.sets.values_mut() {
+                        set.enabled = false;
+                    }
+                } else {
+                    for handle in handles {
+                        hci.advertising_sets.set_enabled(handle, false);
+                    }
+                }
+            }
+            result
+        },
+    )
+}
+
+pub(crate) fn remove_advertising_set<T>(
+    hci: Arc<DataLock<Hci<T>>>,
+    handle: AdvertisingHandle,
+) -> impl Thinkable<Output = Result<(), BoxError>>
+where
+    T: HcTransportLayer + 'static,
+{
+    let hci_clone = hci.clone();
+    Hci::send_command(hci, HciCommandLeRemoveAdvertisingSet::new(handle)).map(move |result| {
+        if result.is_ok() {
+            hci_clone.lock().advertising_sets.release(handle);
+        }
+        result
+    })
+}
+
+pub(crate) fn clear_advertising_sets<T>(
+    hci: Arc<DataLock<Hci<T>>>,
+) -> impl Thinkable<Output = Result<(), BoxError>>
+where
+    T: HcTransportLayer + 'static,
+{
+    let hci_clone = hci.clone();
+    Hci::send_command(hci, HciCommandLeClearAdvertisingSets::new()).map(move |result| {
+        if result.is_ok() {
+            hci_clone.lock().advertising_sets.sets.clear();
+        }
+        result
+    })
+}
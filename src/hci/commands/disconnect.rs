@@ -0,0 +1,37 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+//! # HCI Disconnect Command
+//!
+
+use super::{get_command_size, HciCommand, HciCommandHeader, IsHciCommand};
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+pub struct HciCommandDisconnect {
+    header: HciCommandHeader,
+    handle: u16,
+    reason: u8,
+}
+
+impl HciCommandDisconnect {
+    pub fn new(handle: u16, reason: u8) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::Disconnect,
+                param_length: get_command_size::<Self>(),
+            },
+            handle,
+            reason,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandDisconnect {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
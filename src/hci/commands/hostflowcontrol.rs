@@ -0,0 +1,104 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+//! # HCI Host Flow Control Commands
+//! Those commands enable the flow control of the data the BT host sends to this host. The BT host
+//! only sends as many ACL data packets as this host has announced buffers for. The buffers are
+//! released with the HostNumberOfCompletedPackets command once the data has been processed.
+
+use super::{get_command_size, HciCommand, HciCommandHeader, IsHciCommand};
+
+/// Flow control of the data send from the BT host is disabled
+pub const HOST_FLOW_CONTROL_OFF: u8 = 0x00;
+/// Flow control of the ACL data send from the BT host is enabled
+pub const HOST_FLOW_CONTROL_ACL: u8 = 0x01;
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandSetControllerToHostFlowControl {
+    header: HciCommandHeader,
+    flow_control_enable: u8,
+}
+
+impl HciCommandSetControllerToHostFlowControl {
+    pub fn new(flow_control_enable: u8) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::SetControllerToHostFlowControl,
+                param_length: get_command_size::<Self>(),
+            },
+            flow_control_enable,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandSetControllerToHostFlowControl {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandHostBufferSize {
+    header: HciCommandHeader,
+    acl_data_length: u16,
+    sync_data_length: u8,
+    total_acl_packets: u16,
+    total_sync_packets: u16,
+}
+
+impl HciCommandHostBufferSize {
+    pub fn new(acl_data_length: u16, total_acl_packets: u16) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::HostBufferSize,
+                param_length: get_command_size::<Self>(),
+            },
+            acl_data_length,
+            sync_data_length: 0,
+            total_acl_packets,
+            total_sync_packets: 0,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandHostBufferSize {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+/// This command releases the buffers of one connection. It is not answered by the BT host unless
+/// it contains invalid parameters, so it's not send like any other command.
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandHostNumberOfCompletedPackets {
+    header: HciCommandHeader,
+    num_handles: u8,
+    handle: u16,
+    num_completed: u16,
+}
+
+impl HciCommandHostNumberOfCompletedPackets {
+    pub fn new(handle: u16, num_completed: u16) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::HostNumberOfCompletedPackets,
+                param_length: get_command_size::<Self>(),
+            },
+            num_handles: 1,
+            handle,
+            num_completed,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandHostNumberOfCompletedPackets {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
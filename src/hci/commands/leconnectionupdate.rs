@@ -0,0 +1,147 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+//! # HCI LE Connection Update Commands
+//! Those commands change the timing of an existing LE connection. Either the BT host requests
+//! the update on its own or it replies to the connection parameters requested by the peer.
+
+use super::{get_command_size, HciCommand, HciCommandHeader, IsHciCommand};
+
+/// Reason passed with the negative reply to a connection parameter request of a peer
+pub const REASON_UNACCEPTABLE_CONNECTION_PARAMETERS: u8 = 0x3B;
+
+/// The timing parameters of an existing LE connection to be requested
+#[derive(Debug, Copy, Clone)]
+pub struct LeConnectionUpdateParameters {
+    /// minimum connection interval in units of 1.25ms
+    pub interval_min: u16,
+    /// maximum connection interval in units of 1.25ms
+    pub interval_max: u16,
+    /// number of connection events the peripheral may skip
+    pub latency: u16,
+    /// supervision timeout in units of 10ms
+    pub supervision_timeout: u16,
+    /// minimum length of a connection event in units of 0.625ms
+    pub min_ce_length: u16,
+    /// maximum length of a connection event in units of 0.625ms
+    pub max_ce_length: u16,
+}
+
+impl LeConnectionUpdateParameters {
+    pub fn new(interval_min: u16, interval_max: u16, latency: u16, supervision_timeout: u16) -> Self {
+        Self {
+            interval_min,
+            interval_max,
+            latency,
+            supervision_timeout,
+            min_ce_length: 0,
+            max_ce_length: 0,
+        }
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeConnectionUpdate {
+    header: HciCommandHeader,
+    handle: u16,
+    interval_min: u16,
+    interval_max: u16,
+    latency: u16,
+    supervision_timeout: u16,
+    min_ce_length: u16,
+    max_ce_length: u16,
+}
+
+impl HciCommandLeConnectionUpdate {
+    pub fn new(handle: u16, parameters: &LeConnectionUpdateParameters) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeConnectionUpdate,
+                param_length: get_command_size::<Self>(),
+            },
+            handle,
+            interval_min: parameters.interval_min,
+            interval_max: parameters.interval_max,
+            latency: parameters.latency,
+            supervision_timeout: parameters.supervision_timeout,
+            min_ce_length: parameters.min_ce_length,
+            max_ce_length: parameters.max_ce_length,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeConnectionUpdate {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+/// Accept the connection parameters requested by the peer
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeRemoteConnectionParameterRequestReply {
+    header: HciCommandHeader,
+    handle: u16,
+    interval_min: u16,
+    interval_max: u16,
+    latency: u16,
+    supervision_timeout: u16,
+    min_ce_length: u16,
+    max_ce_length: u16,
+}
+
+impl HciCommandLeRemoteConnectionParameterRequestReply {
+    pub fn new(handle: u16, parameters: &LeConnectionUpdateParameters) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeRemoteConnectionParameterRequestReply,
+                param_length: get_command_size::<Self>(),
+            },
+            handle,
+            interval_min: parameters.interval_min,
+            interval_max: parameters.interval_max,
+            latency: parameters.latency,
+            supervision_timeout: parameters.supervision_timeout,
+            min_ce_length: parameters.min_ce_length,
+            max_ce_length: parameters.max_ce_length,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeRemoteConnectionParameterRequestReply {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+/// Reject the connection parameters requested by the peer
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeRemoteConnectionParameterRequestNegativeReply {
+    header: HciCommandHeader,
+    handle: u16,
+    reason: u8,
+}
+
+impl HciCommandLeRemoteConnectionParameterRequestNegativeReply {
+    pub fn new(handle: u16, reason: u8) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeRemoteConnectionParameterRequestNegativeReply,
+                param_length: get_command_size::<Self>(),
+            },
+            handle,
+            reason,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeRemoteConnectionParameterRequestNegativeReply {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
@@ -0,0 +1,197 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+//! # HCI LE Create Connection Commands
+//! Those commands are used to initiate a connection to an advertising LE device. The BT host
+//! takes the central (master) role of the connection.
+
+use super::{get_command_size, HciCommand, HciCommandHeader, IsHciCommand};
+use super::{OwnAddressType, PeerAddressType};
+use crate::hci::BD_ADDRESS_SIZE;
+
+/// Initiating PHY bit for the LE 1M PHY
+const INITIATING_PHY_LE_1M: u8 = 0x01;
+
+/// Policy which advertiser the BT host connects to
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum InitiatorFilterPolicy {
+    /// connect to the peer address given with the command
+    PeerAddress = 0x00,
+    /// connect to any device in the filter accept list, the peer address is ignored
+    FilterAcceptList = 0x01,
+}
+
+/// The parameters of an LE connection to be created
+#[derive(Debug, Copy, Clone)]
+pub struct LeConnectionParameters {
+    pub own_address_type: OwnAddressType,
+    pub filter_policy: InitiatorFilterPolicy,
+    /// scan interval while initiating in units of 0.625ms
+    pub scan_interval: u16,
+    /// scan window while initiating in units of 0.625ms
+    pub scan_window: u16,
+    /// minimum connection interval in units of 1.25ms
+    pub interval_min: u16,
+    /// maximum connection interval in units of 1.25ms
+    pub interval_max: u16,
+    /// number of connection events the peripheral may skip
+    pub latency: u16,
+    /// supervision timeout in units of 10ms
+    pub supervision_timeout: u16,
+    /// minimum length of a connection event in units of 0.625ms
+    pub min_ce_length: u16,
+    /// maximum length of a connection event in units of 0.625ms
+    pub max_ce_length: u16,
+}
+
+impl LeConnectionParameters {
+    /// Create connection parameters with a connection interval between 30ms and 50ms, no
+    /// peripheral latency and a supervision timeout of 4s using the public address
+    pub fn new() -> Self {
+        Self {
+            own_address_type: OwnAddressType::Public,
+            filter_policy: InitiatorFilterPolicy::PeerAddress,
+            scan_interval: 0x60,
+            scan_window: 0x60,
+            interval_min: 0x18,
+            interval_max: 0x28,
+            latency: 0,
+            supervision_timeout: 0x190,
+            min_ce_length: 0,
+            max_ce_length: 0,
+        }
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeCreateConnection {
+    header: HciCommandHeader,
+    scan_interval: u16,
+    scan_window: u16,
+    filter_policy: InitiatorFilterPolicy,
+    peer_address_type: PeerAddressType,
+    peer_address: [u8; BD_ADDRESS_SIZE],
+    own_address_type: OwnAddressType,
+    interval_min: u16,
+    interval_max: u16,
+    latency: u16,
+    supervision_timeout: u16,
+    min_ce_length: u16,
+    max_ce_length: u16,
+}
+
+impl HciCommandLeCreateConnection {
+    pub fn new(
+        peer_address_type: PeerAddressType,
+        peer_address: [u8; BD_ADDRESS_SIZE],
+        parameters: &LeConnectionParameters,
+    ) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeCreateConnection,
+                param_length: get_command_size::<Self>(),
+            },
+            scan_interval: parameters.scan_interval,
+            scan_window: parameters.scan_window,
+            filter_policy: parameters.filter_policy,
+            peer_address_type,
+            peer_address,
+            own_address_type: parameters.own_address_type,
+            interval_min: parameters.interval_min,
+            interval_max: parameters.interval_max,
+            latency: parameters.latency,
+            supervision_timeout: parameters.supervision_timeout,
+            min_ce_length: parameters.min_ce_length,
+            max_ce_length: parameters.max_ce_length,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeCreateConnection {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+/// The extended create connection command is used if the BT host supports extended advertising.
+/// The connection is initiated on the LE 1M PHY.
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeExtendedCreateConnection {
+    header: HciCommandHeader,
+    filter_policy: InitiatorFilterPolicy,
+    own_address_type: OwnAddressType,
+    peer_address_type: PeerAddressType,
+    peer_address: [u8; BD_ADDRESS_SIZE],
+    initiating_phys: u8,
+    scan_interval: u16,
+    scan_window: u16,
+    interval_min: u16,
+    interval_max: u16,
+    latency: u16,
+    supervision_timeout: u16,
+    min_ce_length: u16,
+    max_ce_length: u16,
+}
+
+impl HciCommandLeExtendedCreateConnection {
+    pub fn new(
+        peer_address_type: PeerAddressType,
+        peer_address: [u8; BD_ADDRESS_SIZE],
+        parameters: &LeConnectionParameters,
+    ) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeExtendedCreateConnection,
+                param_length: get_command_size::<Self>(),
+            },
+            filter_policy: parameters.filter_policy,
+            own_address_type: parameters.own_address_type,
+            peer_address_type,
+            peer_address,
+            initiating_phys: INITIATING_PHY_LE_1M,
+            scan_interval: parameters.scan_interval,
+            scan_window: parameters.scan_window,
+            interval_min: parameters.interval_min,
+            interval_max: parameters.interval_max,
+            latency: parameters.latency,
+            supervision_timeout: parameters.supervision_timeout,
+            min_ce_length: parameters.min_ce_length,
+            max_ce_length: parameters.max_ce_length,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeExtendedCreateConnection {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeCreateConnectionCancel {
+    header: HciCommandHeader,
+}
+
+impl HciCommandLeCreateConnectionCancel {
+    pub fn new() -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeCreateConnectionCancel,
+                param_length: get_command_size::<Self>(),
+            },
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeCreateConnectionCancel {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
@@ -0,0 +1,152 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+//! # HCI LE Encryption Commands
+//! Those commands give access to the AES-128 block cipher and random number generator of the BT
+//! host and start the encryption of LE connections. Keys and data are passed in little endian
+//! order like any other parameter.
+
+use super::{get_command_size, HciCommand, HciCommandHeader, IsHciCommand};
+
+/// Encrypt 16 bytes of plaintext with the given key using AES-128. The command returns the
+/// encrypted data.
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeEncrypt {
+    header: HciCommandHeader,
+    key: [u8; 16],
+    plaintext: [u8; 16],
+}
+
+impl HciCommandLeEncrypt {
+    pub fn new(key: [u8; 16], plaintext: [u8; 16]) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeEncrypt,
+                param_length: get_command_size::<Self>(),
+            },
+            key,
+            plaintext,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeEncrypt {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+/// Request 8 random bytes from the BT host
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeRand {
+    header: HciCommandHeader,
+}
+
+impl HciCommandLeRand {
+    pub fn new() -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeRand,
+                param_length: get_command_size::<Self>(),
+            },
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeRand {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+/// Start or refresh the encryption of an LE connection in the central role. The key is
+/// identified by the random number and the encrypted diversifier towards the peripheral.
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeEnableEncryption {
+    header: HciCommandHeader,
+    handle: u16,
+    random_number: [u8; 8],
+    ediv: u16,
+    ltk: [u8; 16],
+}
+
+impl HciCommandLeEnableEncryption {
+    pub fn new(handle: u16, random_number: [u8; 8], ediv: u16, ltk: [u8; 16]) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeEnableEncryption,
+                param_length: get_command_size::<Self>(),
+            },
+            handle,
+            random_number,
+            ediv,
+            ltk,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeEnableEncryption {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+/// Provide the long term key requested by the BT host in the peripheral role
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeLongTermKeyRequestReply {
+    header: HciCommandHeader,
+    handle: u16,
+    ltk: [u8; 16],
+}
+
+impl HciCommandLeLongTermKeyRequestReply {
+    pub fn new(handle: u16, ltk: [u8; 16]) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeLongTermKeyRequestReply,
+                param_length: get_command_size::<Self>(),
+            },
+            handle,
+            ltk,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeLongTermKeyRequestReply {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+/// Tell the BT host there is no long term key for the connection, the encryption is not started
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeLongTermKeyRequestNegativeReply {
+    header: HciCommandHeader,
+    handle: u16,
+}
+
+impl HciCommandLeLongTermKeyRequestNegativeReply {
+    pub fn new(handle: u16) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeLongTermKeyRequestNegativeReply,
+                param_length: get_command_size::<Self>(),
+            },
+            handle,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeLongTermKeyRequestNegativeReply {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
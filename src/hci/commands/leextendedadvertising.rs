@@ -0,0 +1,471 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+//! # HCI LE Extended Advertising Commands
+//! Those commands are available with Bluetooth 5 capable BT hosts (Raspberry Pi 3B+ and 4) and
+//! allow to run several independent advertising sets at the same time.
+
+use super::{get_command_size, HciCommand, HciCommandHeader, IsHciCommand};
+use super::{OwnAddressType, PeerAddressType};
+use crate::hci::BD_ADDRESS_SIZE;
+
+/// Maximum number of advertising or scan response data bytes that could be passed with one command
+pub const EXT_ADV_DATA_FRAGMENT_SIZE: usize = 251;
+/// Maximum number of advertising sets that could be enabled/disabled with one command
+pub const EXT_ADV_MAX_SETS: usize = 63;
+
+/// Advertising event properties - the advertising is connectable
+pub const ADV_PROP_CONNECTABLE: u16 = 0x0001;
+/// Advertising event properties - the advertising is scannable
+pub const ADV_PROP_SCANNABLE: u16 = 0x0002;
+/// Advertising event properties - the advertising is directed to a specific peer
+pub const ADV_PROP_DIRECTED: u16 = 0x0004;
+/// Advertising event properties - use high duty cycle directed connectable advertising
+pub const ADV_PROP_HIGH_DUTY_CYCLE: u16 = 0x0008;
+/// Advertising event properties - use legacy advertising PDU's
+pub const ADV_PROP_LEGACY: u16 = 0x0010;
+/// Advertising event properties - omit the advertiser's address from all PDU's
+pub const ADV_PROP_ANONYMOUS: u16 = 0x0020;
+/// Advertising event properties - include the TX power in the extended header
+pub const ADV_PROP_INCLUDE_TX_POWER: u16 = 0x0040;
+
+/// Advertising channel map with all three primary advertising channels enabled
+pub const ADV_CHANNEL_ALL: u8 = 0x07;
+/// TX power value indicating that the host has no preference
+pub const ADV_TX_POWER_NO_PREFERENCE: i8 = 0x7F;
+
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AdvertisingPhy {
+    Le1M = 0x01,
+    Le2M = 0x02,
+    LeCoded = 0x03,
+}
+
+/// Policy which scan and connection requests are processed by an advertising set
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AdvertisingFilterPolicy {
+    /// process scan and connection requests from all devices
+    ProcessAll = 0x00,
+    /// process connection requests from all and scan requests only from devices in the filter
+    /// accept list
+    FilterScanRequests = 0x01,
+    /// process scan requests from all and connection requests only from devices in the filter
+    /// accept list
+    FilterConnectionRequests = 0x02,
+    /// process scan and connection requests only from devices in the filter accept list
+    FilterAll = 0x03,
+}
+
+/// The operation tells the BT host which part of the advertising data is passed
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AdvertisingDataOperation {
+    IntermediateFragment = 0x00,
+    FirstFragment = 0x01,
+    LastFragment = 0x02,
+    Complete = 0x03,
+    Unchanged = 0x04,
+}
+
+/// The parameters of an advertising set
+#[derive(Debug, Copy, Clone)]
+pub struct AdvertisingParameters {
+    /// combination of the ``ADV_PROP_*`` values
+    pub event_properties: u16,
+    /// minimum advertising interval in units of 0.625ms (0x20 to 0xFFFFFF)
+    pub interval_min: u32,
+    /// maximum advertising interval in units of 0.625ms (0x20 to 0xFFFFFF)
+    pub interval_max: u32,
+    pub channel_map: u8,
+    pub own_address_type: OwnAddressType,
+    /// type of the peer address for directed advertising
+    pub peer_address_type: PeerAddressType,
+    /// peer address for directed advertising
+    pub peer_address: [u8; BD_ADDRESS_SIZE],
+    pub filter_policy: AdvertisingFilterPolicy,
+    /// requested TX power in dBm
+    pub tx_power: i8,
+    pub primary_phy: AdvertisingPhy,
+    /// number of advertising events that can be skipped before the AUX_ADV_IND is send
+    pub secondary_max_skip: u8,
+    pub secondary_phy: AdvertisingPhy,
+    /// advertising set identifier (0x00 to 0x0F) passed in the advertising PDU's
+    pub sid: u8,
+    /// request the ScanRequestReceived event for any scan request received for this set
+    pub scan_request_notification: bool,
+}
+
+impl AdvertisingParameters {
+    /// Create the advertising parameters with the given event properties and an advertising
+    /// interval of 100ms on all primary advertising channels using the public address
+    pub fn new(event_properties: u16) -> Self {
+        Self {
+            event_properties,
+            interval_min: 0xA0,
+            interval_max: 0xA0,
+            channel_map: ADV_CHANNEL_ALL,
+            own_address_type: OwnAddressType::Public,
+            peer_address_type: PeerAddressType::Public,
+            peer_address: [0; BD_ADDRESS_SIZE],
+            filter_policy: AdvertisingFilterPolicy::ProcessAll,
+            tx_power: ADV_TX_POWER_NO_PREFERENCE,
+            primary_phy: AdvertisingPhy::Le1M,
+            secondary_max_skip: 0,
+            secondary_phy: AdvertisingPhy::Le1M,
+            sid: 0,
+            scan_request_notification: false,
+        }
+    }
+
+    /// Parameters for connectable and scannable advertising using legacy advertising PDU's that
+    /// are also understood by Bluetooth 4 devices
+    pub fn legacy_connectable() -> Self {
+        Self::new(ADV_PROP_LEGACY | ADV_PROP_CONNECTABLE | ADV_PROP_SCANNABLE)
+    }
+
+    /// Parameters for non-connectable and non-scannable advertising using legacy advertising
+    /// PDU's, for example to broadcast a beacon
+    pub fn legacy_non_connectable() -> Self {
+        Self::new(ADV_PROP_LEGACY)
+    }
+
+    /// Returns true if the advertising uses legacy advertising PDU's
+    pub fn is_legacy(&self) -> bool {
+        self.event_properties & ADV_PROP_LEGACY != 0
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeSetAdvertisingSetRandomAddress {
+    header: HciCommandHeader,
+    handle: u8,
+    address: [u8; BD_ADDRESS_SIZE],
+}
+
+impl HciCommandLeSetAdvertisingSetRandomAddress {
+    pub fn new(handle: u8, address: [u8; BD_ADDRESS_SIZE]) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeSetAdvertisingSetRandomAddress,
+                param_length: get_command_size::<Self>(),
+            },
+            handle,
+            address,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeSetAdvertisingSetRandomAddress {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeSetExtendedAdvertisingParameters {
+    header: HciCommandHeader,
+    handle: u8,
+    event_properties: u16,
+    /// minimum advertising interval in units of 0.625ms (3 octets)
+    interval_min: [u8; 3],
+    /// maximum advertising interval in units of 0.625ms (3 octets)
+    interval_max: [u8; 3],
+    channel_map: u8,
+    own_address_type: OwnAddressType,
+    peer_address_type: PeerAddressType,
+    peer_address: [u8; BD_ADDRESS_SIZE],
+    filter_policy: AdvertisingFilterPolicy,
+    tx_power: i8,
+    primary_phy: AdvertisingPhy,
+    secondary_max_skip: u8,
+    secondary_phy: AdvertisingPhy,
+    sid: u8,
+    scan_request_notification: u8,
+}
+
+impl HciCommandLeSetExtendedAdvertisingParameters {
+    pub fn new(handle: u8, parameters: &AdvertisingParameters) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeSetExtendedAdvertisingParameters,
+                param_length: get_command_size::<Self>(),
+            },
+            handle,
+            event_properties: parameters.event_properties,
+            interval_min: [
+                parameters.interval_min as u8,
+                (parameters.interval_min >> 8) as u8,
+                (parameters.interval_min >> 16) as u8,
+            ],
+            interval_max: [
+                parameters.interval_max as u8,
+                (parameters.interval_max >> 8) as u8,
+                (parameters.interval_max >> 16) as u8,
+            ],
+            channel_map: parameters.channel_map,
+            own_address_type: parameters.own_address_type,
+            peer_address_type: parameters.peer_address_type,
+            peer_address: parameters.peer_address,
+            filter_policy: parameters.filter_policy,
+            tx_power: parameters.tx_power,
+            primary_phy: parameters.primary_phy,
+            secondary_max_skip: parameters.secondary_max_skip,
+            secondary_phy: parameters.secondary_phy,
+            sid: parameters.sid,
+            scan_request_notification: parameters.scan_request_notification as u8,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeSetExtendedAdvertisingParameters {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+/// The advertising data and the scan response data commands share the same layout, only the
+/// op code differs
+#[repr(C, packed)]
+pub struct HciCommandLeSetExtendedAdvertisingData {
+    header: HciCommandHeader,
+    handle: u8,
+    operation: AdvertisingDataOperation,
+    fragment_preference: u8,
+    data_length: u8,
+    data: [u8; EXT_ADV_DATA_FRAGMENT_SIZE],
+}
+
+impl HciCommandLeSetExtendedAdvertisingData {
+    /// Create the command to pass (a fragment of) the advertising data of an advertising set
+    pub fn new(handle: u8, operation: AdvertisingDataOperation, data: &[u8]) -> Self {
+        Self::with_op_code(HciCommand::LeSetExtendedAdvertisingData, handle, operation, data)
+    }
+
+    /// Create the command to pass (a fragment of) the scan response data of an advertising set
+    pub fn scan_response(handle: u8, operation: AdvertisingDataOperation, data: &[u8]) -> Self {
+        Self::with_op_code(HciCommand::LeSetExtendedScanResponseData, handle, operation, data)
+    }
+
+    fn with_op_code(
+        op_code: HciCommand,
+        handle: u8,
+        operation: AdvertisingDataOperation,
+        data: &[u8],
+    ) -> Self {
+        let mut command = Self {
+            header: HciCommandHeader {
+                op_code,
+                param_length: 4 + data.len() as u8,
+            },
+            handle,
+            operation,
+            // the data is already fragmented by us, the BT host should not fragment it further
+            fragment_preference: 0x01,
+            data_length: data.len() as u8,
+            data: [0; EXT_ADV_DATA_FRAGMENT_SIZE],
+        };
+
+        command.data[..data.len()].copy_from_slice(data);
+        command
+    }
+}
+
+impl IsHciCommand for HciCommandLeSetExtendedAdvertisingData {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+
+    fn size(&self) -> usize {
+        core::mem::size_of::<HciCommandHeader>() + self.header.param_length as usize
+    }
+}
+
+impl core::fmt::Debug for HciCommandLeSetExtendedAdvertisingData {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "HciCommandLeSetExtendedAdvertisingData {{ header: {{ {:?} }}, handle: {}, operation: {:?}, data: {{ size: {} }} }}",
+            self.header,
+            self.handle,
+            self.operation,
+            self.data_length
+        )
+    }
+}
+
+/// Enable parameters of one advertising set
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct AdvertisingSetEnable {
+    handle: u8,
+    /// duration in units of 10ms, 0 means advertising until disabled
+    duration: u16,
+    /// maximum number of extended advertising events, 0 means no maximum
+    max_events: u8,
+}
+
+impl AdvertisingSetEnable {
+    pub fn new(handle: u8, duration: u16, max_events: u8) -> Self {
+        Self {
+            handle,
+            duration,
+            max_events,
+        }
+    }
+
+    pub fn handle(&self) -> u8 {
+        self.handle
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub struct HciCommandLeSetExtendedAdvertisingEnable {
+    header: HciCommandHeader,
+    enable: u8,
+    num_sets: u8,
+    sets: [AdvertisingSetEnable; EXT_ADV_MAX_SETS],
+}
+
+impl HciCommandLeSetExtendedAdvertisingEnable {
+    /// Create the command to enable or disable the given advertising sets. Disabling with an empty
+    /// list of sets disables all advertising sets.
+    pub fn new(enable: bool, sets: &[AdvertisingSetEnable]) -> Self {
+        let num_sets = sets.len().min(EXT_ADV_MAX_SETS);
+        let mut command = Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeSetExtendedAdvertisingEnable,
+                param_length: (2 + num_sets * core::mem::size_of::<AdvertisingSetEnable>()) as u8,
+            },
+            enable: enable as u8,
+            num_sets: num_sets as u8,
+            sets: [AdvertisingSetEnable::new(0, 0, 0); EXT_ADV_MAX_SETS],
+        };
+
+        command.sets[..num_sets].copy_from_slice(&sets[..num_sets]);
+        command
+    }
+}
+
+impl IsHciCommand for HciCommandLeSetExtendedAdvertisingEnable {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+
+    fn size(&self) -> usize {
+        core::mem::size_of::<HciCommandHeader>() + self.header.param_length as usize
+    }
+}
+
+impl core::fmt::Debug for HciCommandLeSetExtendedAdvertisingEnable {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "HciCommandLeSetExtendedAdvertisingEnable {{ header: {{ {:?} }}, enable: {}, num_sets: {} }}",
+            self.header,
+            self.enable,
+            self.num_sets
+        )
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeReadMaximumAdvertisingDataLength {
+    header: HciCommandHeader,
+}
+
+impl HciCommandLeReadMaximumAdvertisingDataLength {
+    pub fn new() -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeReadMaximumAdvertisingDataLength,
+                param_length: get_command_size::<Self>(),
+            },
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeReadMaximumAdvertisingDataLength {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeReadNumberOfSupportedAdvertisingSets {
+    header: HciCommandHeader,
+}
+
+impl HciCommandLeReadNumberOfSupportedAdvertisingSets {
+    pub fn new() -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeReadNumberOfSupportedAdvertisingSets,
+                param_length: get_command_size::<Self>(),
+            },
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeReadNumberOfSupportedAdvertisingSets {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeRemoveAdvertisingSet {
+    header: HciCommandHeader,
+    handle: u8,
+}
+
+impl HciCommandLeRemoveAdvertisingSet {
+    pub fn new(handle: u8) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeRemoveAdvertisingSet,
+                param_length: get_command_size::<Self>(),
+            },
+            handle,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeRemoveAdvertisingSet {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeClearAdvertisingSets {
+    header: HciCommandHeader,
+}
+
+impl HciCommandLeClearAdvertisingSets {
+    pub fn new() -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeClearAdvertisingSets,
+                param_length: get_command_size::<Self>(),
+            },
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeClearAdvertisingSets {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
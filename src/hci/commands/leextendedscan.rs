@@ -0,0 +1,187 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+//! # HCI LE Extended Scan Commands
+//! Those commands configure and start scanning for extended and legacy advertising on Bluetooth 5
+//! capable BT hosts.
+
+use super::OwnAddressType;
+use super::{get_command_size, HciCommand, HciCommandHeader, IsHciCommand};
+
+/// Scanning PHY bit for the LE 1M PHY
+const SCAN_PHY_LE_1M: u8 = 0x01;
+/// Scanning PHY bit for the LE Coded PHY
+const SCAN_PHY_LE_CODED: u8 = 0x04;
+
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ScanType {
+    /// only listen for advertising, no scan requests are send
+    Passive = 0x00,
+    /// send scan requests to scannable advertisers to receive their scan response data
+    Active = 0x01,
+}
+
+/// Policy which advertising is reported while scanning
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ScanningFilterPolicy {
+    /// report all advertising except directed advertising not addressed to this device
+    AcceptAll = 0x00,
+    /// report only advertising from devices in the filter accept list
+    FilterAcceptList = 0x01,
+    /// like ``AcceptAll`` but also report directed advertising using a resolvable private
+    /// address as target address
+    AcceptAllResolvable = 0x02,
+    /// like ``FilterAcceptList`` but also report directed advertising using a resolvable private
+    /// address as target address
+    FilterAcceptListResolvable = 0x03,
+}
+
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ScanFilterDuplicates {
+    Disabled = 0x00,
+    Enabled = 0x01,
+    /// filter duplicates, but reset the filtering at the beginning of each scan period
+    EnabledResetEachPeriod = 0x02,
+}
+
+/// Scan parameters for one of the PHY's to scan on
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct ScanPhyParameters {
+    scan_type: ScanType,
+    /// scan interval in units of 0.625ms
+    interval: u16,
+    /// scan window in units of 0.625ms, shall not exceed the scan interval
+    window: u16,
+}
+
+impl ScanPhyParameters {
+    pub fn new(scan_type: ScanType, interval: u16, window: u16) -> Self {
+        Self {
+            scan_type,
+            interval,
+            window,
+        }
+    }
+}
+
+/// The parameters used for extended scanning
+#[derive(Debug, Copy, Clone)]
+pub struct ExtendedScanParameters {
+    pub own_address_type: OwnAddressType,
+    pub filter_policy: ScanningFilterPolicy,
+    /// scan on the LE 1M PHY with those parameters if given
+    pub le_1m: Option<ScanPhyParameters>,
+    /// scan on the LE Coded PHY with those parameters if given
+    pub le_coded: Option<ScanPhyParameters>,
+}
+
+impl ExtendedScanParameters {
+    /// Create scan parameters to scan on the LE 1M PHY with a scan interval and window of 10ms
+    /// using the public address and reporting all advertising.
+    pub fn new(scan_type: ScanType) -> Self {
+        Self {
+            own_address_type: OwnAddressType::Public,
+            filter_policy: ScanningFilterPolicy::AcceptAll,
+            le_1m: Some(ScanPhyParameters::new(scan_type, 0x10, 0x10)),
+            le_coded: None,
+        }
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeSetExtendedScanParameters {
+    header: HciCommandHeader,
+    own_address_type: OwnAddressType,
+    filter_policy: ScanningFilterPolicy,
+    scanning_phys: u8,
+    phys: [ScanPhyParameters; 2],
+}
+
+impl HciCommandLeSetExtendedScanParameters {
+    pub fn new(parameters: &ExtendedScanParameters) -> Self {
+        let unused = ScanPhyParameters::new(ScanType::Passive, 0, 0);
+        let mut command = Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeSetExtendedScanParameters,
+                param_length: 3,
+            },
+            own_address_type: parameters.own_address_type,
+            filter_policy: parameters.filter_policy,
+            scanning_phys: 0,
+            phys: [unused; 2],
+        };
+
+        // the parameters for each PHY are passed in the order of the bits set in the scanning
+        // PHY's value
+        let mut num_phys = 0;
+        if let Some(le_1m) = parameters.le_1m {
+            command.scanning_phys |= SCAN_PHY_LE_1M;
+            command.phys[num_phys] = le_1m;
+            num_phys += 1;
+        }
+        if let Some(le_coded) = parameters.le_coded {
+            command.scanning_phys |= SCAN_PHY_LE_CODED;
+            command.phys[num_phys] = le_coded;
+            num_phys += 1;
+        }
+        command.header.param_length += (num_phys * core::mem::size_of::<ScanPhyParameters>()) as u8;
+        command
+    }
+}
+
+impl IsHciCommand for HciCommandLeSetExtendedScanParameters {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+
+    fn size(&self) -> usize {
+        core::mem::size_of::<HciCommandHeader>() + self.header.param_length as usize
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeSetExtendedScanEnable {
+    header: HciCommandHeader,
+    enable: u8,
+    filter_duplicates: ScanFilterDuplicates,
+    /// scan duration in units of 10ms, 0 means scanning until disabled
+    duration: u16,
+    /// time interval from when the BT host started its last scan duration until it begins the
+    /// subsequent scan duration in units of 1.28s, 0 means scanning continuously
+    period: u16,
+}
+
+impl HciCommandLeSetExtendedScanEnable {
+    pub fn new(
+        enable: bool,
+        filter_duplicates: ScanFilterDuplicates,
+        duration: u16,
+        period: u16,
+    ) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeSetExtendedScanEnable,
+                param_length: get_command_size::<Self>(),
+            },
+            enable: enable as u8,
+            filter_duplicates,
+            duration,
+            period,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeSetExtendedScanEnable {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
@@ -0,0 +1,123 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+//! # HCI LE Filter Accept List Commands
+//! Those commands maintain the list of devices the BT host accepts if the filter policy of
+//! advertising, scanning or initiating is configured to use the filter accept list. The list
+//! shall not be changed while any of those operations uses it.
+
+use super::{get_command_size, HciCommand, HciCommandHeader, IsHciCommand};
+use crate::hci::BD_ADDRESS_SIZE;
+
+/// The type of address of a device in the filter accept list
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FilterAcceptListAddressType {
+    Public = 0x00,
+    Random = 0x01,
+    /// devices sending advertising without an address, the address is ignored
+    Anonymous = 0xFF,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeReadFilterAcceptListSize {
+    header: HciCommandHeader,
+}
+
+impl HciCommandLeReadFilterAcceptListSize {
+    pub fn new() -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeReadFilterAcceptListSize,
+                param_length: get_command_size::<Self>(),
+            },
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeReadFilterAcceptListSize {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeClearFilterAcceptList {
+    header: HciCommandHeader,
+}
+
+impl HciCommandLeClearFilterAcceptList {
+    pub fn new() -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeClearFilterAcceptList,
+                param_length: get_command_size::<Self>(),
+            },
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeClearFilterAcceptList {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeAddDeviceToFilterAcceptList {
+    header: HciCommandHeader,
+    address_type: FilterAcceptListAddressType,
+    address: [u8; BD_ADDRESS_SIZE],
+}
+
+impl HciCommandLeAddDeviceToFilterAcceptList {
+    pub fn new(address_type: FilterAcceptListAddressType, address: [u8; BD_ADDRESS_SIZE]) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeAddDeviceToFilterAcceptList,
+                param_length: get_command_size::<Self>(),
+            },
+            address_type,
+            address,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeAddDeviceToFilterAcceptList {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeRemoveDeviceFromFilterAcceptList {
+    header: HciCommandHeader,
+    address_type: FilterAcceptListAddressType,
+    address: [u8; BD_ADDRESS_SIZE],
+}
+
+impl HciCommandLeRemoveDeviceFromFilterAcceptList {
+    pub fn new(address_type: FilterAcceptListAddressType, address: [u8; BD_ADDRESS_SIZE]) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeRemoveDeviceFromFilterAcceptList,
+                param_length: get_command_size::<Self>(),
+            },
+            address_type,
+            address,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeRemoveDeviceFromFilterAcceptList {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
@@ -0,0 +1,71 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+//! # HCI LE P-256 Commands
+//! Those commands give access to the elliptic curve Diffie-Hellman key agreement on the P-256
+//! curve of the BT host used by LE Secure Connections. Both commands are answered with a command
+//! status, the result is passed with an LE event once calculated. Keys are passed in little
+//! endian order, the X coordinate of a public key first.
+
+use super::{get_command_size, HciCommand, HciCommandHeader, IsHciCommand};
+
+/// Read the local P-256 public key. The BT host creates a new key pair and passes the public key
+/// with the LE Read Local P-256 Public Key Complete event.
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeReadLocalP256PublicKey {
+    header: HciCommandHeader,
+}
+
+impl HciCommandLeReadLocalP256PublicKey {
+    pub fn new() -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeReadLocalP256PublicKey,
+                param_length: get_command_size::<Self>(),
+            },
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeReadLocalP256PublicKey {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+/// Calculate the Diffie-Hellman key from the remote public key and the local private key. The
+/// key is passed with the LE Generate DHKey Complete event.
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub struct HciCommandLeGenerateDhKey {
+    header: HciCommandHeader,
+    remote_public_key: [u8; 64],
+}
+
+impl HciCommandLeGenerateDhKey {
+    pub fn new(remote_public_key: [u8; 64]) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeGenerateDhKey,
+                param_length: get_command_size::<Self>(),
+            },
+            remote_public_key,
+        }
+    }
+}
+
+impl core::fmt::Debug for HciCommandLeGenerateDhKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("HciCommandLeGenerateDhKey").finish()
+    }
+}
+
+impl IsHciCommand for HciCommandLeGenerateDhKey {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
@@ -0,0 +1,113 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+//! # HCI LE Periodic Advertising Sync Commands
+//! Those commands are used to synchronize to the periodic advertising of an advertiser found
+//! while scanning.
+
+use super::PeerAddressType;
+use super::{get_command_size, HciCommand, HciCommandHeader, IsHciCommand};
+use crate::hci::BD_ADDRESS_SIZE;
+
+/// Create sync option - use the periodic advertiser list instead of the given advertiser
+pub const SYNC_OPTION_USE_ADVERTISER_LIST: u8 = 0x01;
+/// Create sync option - the reporting of periodic advertising is initially disabled
+pub const SYNC_OPTION_REPORTING_DISABLED: u8 = 0x02;
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLePeriodicAdvertisingCreateSync {
+    header: HciCommandHeader,
+    options: u8,
+    sid: u8,
+    address_type: PeerAddressType,
+    address: [u8; BD_ADDRESS_SIZE],
+    /// number of periodic advertising packets that can be skipped after a successful receive
+    skip: u16,
+    /// synchronization timeout in units of 10ms
+    sync_timeout: u16,
+    /// constant tone extension types the sync shall not be created with, 0 means no restriction
+    sync_cte_type: u8,
+}
+
+impl HciCommandLePeriodicAdvertisingCreateSync {
+    pub fn new(
+        options: u8,
+        sid: u8,
+        address_type: PeerAddressType,
+        address: [u8; BD_ADDRESS_SIZE],
+        skip: u16,
+        sync_timeout: u16,
+    ) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LePeriodicAdvertisingCreateSync,
+                param_length: get_command_size::<Self>(),
+            },
+            options,
+            sid,
+            address_type,
+            address,
+            skip,
+            sync_timeout,
+            sync_cte_type: 0,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLePeriodicAdvertisingCreateSync {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLePeriodicAdvertisingCreateSyncCancel {
+    header: HciCommandHeader,
+}
+
+impl HciCommandLePeriodicAdvertisingCreateSyncCancel {
+    pub fn new() -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LePeriodicAdvertisingCreateSyncCancel,
+                param_length: get_command_size::<Self>(),
+            },
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLePeriodicAdvertisingCreateSyncCancel {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLePeriodicAdvertisingTerminateSync {
+    header: HciCommandHeader,
+    sync_handle: u16,
+}
+
+impl HciCommandLePeriodicAdvertisingTerminateSync {
+    pub fn new(sync_handle: u16) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LePeriodicAdvertisingTerminateSync,
+                param_length: get_command_size::<Self>(),
+            },
+            sync_handle,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLePeriodicAdvertisingTerminateSync {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
@@ -0,0 +1,252 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+//! # HCI LE Privacy Commands
+//! Those commands set the random address of the BT host and maintain the resolving list. With
+//! address resolution enabled the BT host resolves the resolvable private addresses of the peers
+//! in the resolving list on its own and reports them with their identity address. Like the filter
+//! accept list the resolving list shall not be changed while advertising, scanning or initiating
+//! uses it.
+
+use super::{get_command_size, HciCommand, HciCommandHeader, IsHciCommand};
+use crate::hci::BD_ADDRESS_SIZE;
+
+/// The type of the identity address of a device in the resolving list
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum IdentityAddressType {
+    Public = 0x00,
+    /// static random address
+    Random = 0x01,
+}
+
+/// The privacy mode used for a device in the resolving list
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PrivacyMode {
+    /// the device is only accepted with its resolvable private address
+    Network = 0x00,
+    /// the device is also accepted with its identity address
+    Device = 0x01,
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeSetRandomAddress {
+    header: HciCommandHeader,
+    address: [u8; BD_ADDRESS_SIZE],
+}
+
+impl HciCommandLeSetRandomAddress {
+    pub fn new(address: [u8; BD_ADDRESS_SIZE]) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeSetRandomAddress,
+                param_length: get_command_size::<Self>(),
+            },
+            address,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeSetRandomAddress {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeAddDeviceToResolvingList {
+    header: HciCommandHeader,
+    address_type: IdentityAddressType,
+    address: [u8; BD_ADDRESS_SIZE],
+    peer_irk: [u8; 16],
+    local_irk: [u8; 16],
+}
+
+impl HciCommandLeAddDeviceToResolvingList {
+    pub fn new(
+        address_type: IdentityAddressType,
+        address: [u8; BD_ADDRESS_SIZE],
+        peer_irk: [u8; 16],
+        local_irk: [u8; 16],
+    ) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeAddDeviceToResolvingList,
+                param_length: get_command_size::<Self>(),
+            },
+            address_type,
+            address,
+            peer_irk,
+            local_irk,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeAddDeviceToResolvingList {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeRemoveDeviceFromResolvingList {
+    header: HciCommandHeader,
+    address_type: IdentityAddressType,
+    address: [u8; BD_ADDRESS_SIZE],
+}
+
+impl HciCommandLeRemoveDeviceFromResolvingList {
+    pub fn new(address_type: IdentityAddressType, address: [u8; BD_ADDRESS_SIZE]) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeRemoveDeviceFromResolvingList,
+                param_length: get_command_size::<Self>(),
+            },
+            address_type,
+            address,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeRemoveDeviceFromResolvingList {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeClearResolvingList {
+    header: HciCommandHeader,
+}
+
+impl HciCommandLeClearResolvingList {
+    pub fn new() -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeClearResolvingList,
+                param_length: get_command_size::<Self>(),
+            },
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeClearResolvingList {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeReadResolvingListSize {
+    header: HciCommandHeader,
+}
+
+impl HciCommandLeReadResolvingListSize {
+    pub fn new() -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeReadResolvingListSize,
+                param_length: get_command_size::<Self>(),
+            },
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeReadResolvingListSize {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeSetAddressResolutionEnable {
+    header: HciCommandHeader,
+    enable: u8,
+}
+
+impl HciCommandLeSetAddressResolutionEnable {
+    pub fn new(enable: bool) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeSetAddressResolutionEnable,
+                param_length: get_command_size::<Self>(),
+            },
+            enable: enable as u8,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeSetAddressResolutionEnable {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeSetResolvablePrivateAddressTimeout {
+    header: HciCommandHeader,
+    /// the time in seconds the BT host uses a resolvable private address it has generated
+    timeout: u16,
+}
+
+impl HciCommandLeSetResolvablePrivateAddressTimeout {
+    pub fn new(timeout: u16) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeSetResolvablePrivateAddressTimeout,
+                param_length: get_command_size::<Self>(),
+            },
+            timeout,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeSetResolvablePrivateAddressTimeout {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeSetPrivacyMode {
+    header: HciCommandHeader,
+    address_type: IdentityAddressType,
+    address: [u8; BD_ADDRESS_SIZE],
+    mode: PrivacyMode,
+}
+
+impl HciCommandLeSetPrivacyMode {
+    pub fn new(
+        address_type: IdentityAddressType,
+        address: [u8; BD_ADDRESS_SIZE],
+        mode: PrivacyMode,
+    ) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeSetPrivacyMode,
+                param_length: get_command_size::<Self>(),
+            },
+            address_type,
+            address,
+            mode,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeSetPrivacyMode {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
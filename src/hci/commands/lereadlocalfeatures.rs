@@ -0,0 +1,48 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+//! # HCI LE Read Local Supported Features Command
+//! The command returns the LE features supported by the BT host as 64Bit mask.
+
+use super::{get_command_size, HciCommand, HciCommandHeader, IsHciCommand};
+
+/// LE feature - LE Encryption
+pub const LE_FEATURE_ENCRYPTION: u64 = 1 << 0;
+/// LE feature - Connection Parameters Request Procedure
+pub const LE_FEATURE_CONNECTION_PARAMETERS_REQUEST: u64 = 1 << 1;
+/// LE feature - LL Privacy
+pub const LE_FEATURE_LL_PRIVACY: u64 = 1 << 6;
+/// LE feature - LE 2M PHY
+pub const LE_FEATURE_2M_PHY: u64 = 1 << 8;
+/// LE feature - LE Coded PHY
+pub const LE_FEATURE_CODED_PHY: u64 = 1 << 11;
+/// LE feature - LE Extended Advertising
+pub const LE_FEATURE_EXTENDED_ADVERTISING: u64 = 1 << 12;
+/// LE feature - LE Periodic Advertising
+pub const LE_FEATURE_PERIODIC_ADVERTISING: u64 = 1 << 13;
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeReadLocalSupportedFeatures {
+    header: HciCommandHeader,
+}
+
+impl HciCommandLeReadLocalSupportedFeatures {
+    pub fn new() -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeReadLocalSupportedFeatures,
+                param_length: get_command_size::<Self>(),
+            },
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeReadLocalSupportedFeatures {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
@@ -0,0 +1,93 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+//! # HCI Link Key Request Reply Commands
+//!
+//! Answer the request of the BT host for the link key of a BR/EDR peer, either with the key
+//! stored for the peer or with the information that there is none. Without a key a peer not
+//! supporting Secure Simple Pairing requests a PIN code, which is rejected as legacy pairing is
+//! not supported.
+//!
+
+use super::{get_command_size, HciCommand, HciCommandHeader, IsHciCommand};
+use crate::hci::BD_ADDRESS_SIZE;
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+pub struct HciCommandLinkKeyRequestReply {
+    header: HciCommandHeader,
+    address: [u8; BD_ADDRESS_SIZE],
+    link_key: [u8; 16],
+}
+
+impl HciCommandLinkKeyRequestReply {
+    pub fn new(address: [u8; BD_ADDRESS_SIZE], link_key: [u8; 16]) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LinkKeyRequestReply,
+                param_length: get_command_size::<Self>(),
+            },
+            address,
+            link_key,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLinkKeyRequestReply {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+pub struct HciCommandLinkKeyRequestNegativeReply {
+    header: HciCommandHeader,
+    address: [u8; BD_ADDRESS_SIZE],
+}
+
+impl HciCommandLinkKeyRequestNegativeReply {
+    pub fn new(address: [u8; BD_ADDRESS_SIZE]) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LinkKeyRequestNegativeReply,
+                param_length: get_command_size::<Self>(),
+            },
+            address,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLinkKeyRequestNegativeReply {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+pub struct HciCommandPinCodeRequestNegativeReply {
+    header: HciCommandHeader,
+    address: [u8; BD_ADDRESS_SIZE],
+}
+
+impl HciCommandPinCodeRequestNegativeReply {
+    pub fn new(address: [u8; BD_ADDRESS_SIZE]) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::PinCodeRequestNegativeReply,
+                param_length: get_command_size::<Self>(),
+            },
+            address,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandPinCodeRequestNegativeReply {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
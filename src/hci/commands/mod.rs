@@ -15,12 +15,11 @@ use super::errors::*;
 use super::events::*;
 use super::packet::*;
 use super::*;
-use crate::alloc::{boxed::Box, collections::BTreeMap, sync::Arc};
+use crate::alloc::{boxed::Box, collections::{BTreeMap, VecDeque}, sync::Arc, vec::Vec};
 use crate::brain::*;
 use crate::hctl::HcTransportLayer;
 use crate::mem::size_of;
 use crate::pin::Pin;
-use crate::pin_utils::*;
 
 mod reset;
 pub use reset::*;
@@ -38,10 +37,47 @@ mod inquiry;
 pub use inquiry::*;
 mod acceptconnection;
 pub use acceptconnection::*;
+mod rejectconnection;
+pub use rejectconnection::*;
+mod linkkey;
+pub use linkkey::*;
+mod simplepairing;
+pub use simplepairing::*;
+mod disconnect;
+pub use disconnect::*;
+mod readbuffersize;
+pub use readbuffersize::*;
+mod readbdaddr;
+pub use readbdaddr::*;
+mod hostflowcontrol;
+pub use hostflowcontrol::*;
+mod seteventmask;
+pub use seteventmask::*;
+mod leextendedadvertising;
+pub use leextendedadvertising::*;
+mod leextendedscan;
+pub use leextendedscan::*;
+mod leperiodicsync;
+pub use leperiodicsync::*;
+mod lereadlocalfeatures;
+pub use lereadlocalfeatures::*;
+mod lecreateconnection;
+pub use lecreateconnection::*;
+mod leconnectionupdate;
+pub use leconnectionupdate::*;
+mod lefilteracceptlist;
+pub use lefilteracceptlist::*;
+mod leencryption;
+pub use leencryption::*;
+mod lep256;
+pub use lep256::*;
+mod leprivacy;
+pub use leprivacy::*;
 
 const LINK_COMMANDS: u16 = 0x1 << 10;
 const BASEBAND_COMMANDS: u16 = 0x03 << 10;
 const INFORMATION_COMMANDS: u16 = 0x4 << 10;
+const LE_COMMANDS: u16 = 0x08 << 10;
 const VENDOR_COMMANDS: u16 = 0x3F << 10;
 
 #[repr(u16)]
@@ -51,18 +87,80 @@ pub enum HciCommand {
     // OGF_LINK_CONTROL
     Inquiry = LINK_COMMANDS | 0x01,
     CreateConnection = LINK_COMMANDS | 0x05,
+    Disconnect = LINK_COMMANDS | 0x06,
     AcceptConnection = LINK_COMMANDS | 0x09,
+    RejectConnection = LINK_COMMANDS | 0x0A,
+    LinkKeyRequestReply = LINK_COMMANDS | 0x0B,
+    LinkKeyRequestNegativeReply = LINK_COMMANDS | 0x0C,
+    PinCodeRequestNegativeReply = LINK_COMMANDS | 0x0E,
+    IoCapabilityRequestReply = LINK_COMMANDS | 0x2B,
+    UserConfirmationRequestReply = LINK_COMMANDS | 0x2C,
+    UserConfirmationRequestNegativeReply = LINK_COMMANDS | 0x2D,
+    UserPasskeyRequestReply = LINK_COMMANDS | 0x2E,
+    UserPasskeyRequestNegativeReply = LINK_COMMANDS | 0x2F,
+    IoCapabilityRequestNegativeReply = LINK_COMMANDS | 0x34,
 
     // OGF_CONTROL_BASEBAND
+    SetEventMask = BASEBAND_COMMANDS | 0x01,
     Reset = BASEBAND_COMMANDS | 0x03,
     WriteLocalName = BASEBAND_COMMANDS | 0x13,
     WriteScanEnable = BASEBAND_COMMANDS | 0x1A,
     WriteClassOfDevice = BASEBAND_COMMANDS | 0x24,
+    SetControllerToHostFlowControl = BASEBAND_COMMANDS | 0x31,
+    HostBufferSize = BASEBAND_COMMANDS | 0x33,
+    HostNumberOfCompletedPackets = BASEBAND_COMMANDS | 0x35,
+    WriteSimplePairingMode = BASEBAND_COMMANDS | 0x56,
+    WriteLeHostSupport = BASEBAND_COMMANDS | 0x6D,
 
     // OGF_INFO_COMMANDS
     ReadVersionInfo = INFORMATION_COMMANDS | 0x01,
+    ReadBufferSize = INFORMATION_COMMANDS | 0x05,
     ReadBDAddr = INFORMATION_COMMANDS | 0x09,
 
+    // OGF_LE_CONTROLLER
+    LeSetEventMask = LE_COMMANDS | 0x01,
+    LeReadBufferSize = LE_COMMANDS | 0x02,
+    LeReadLocalSupportedFeatures = LE_COMMANDS | 0x03,
+    LeSetRandomAddress = LE_COMMANDS | 0x05,
+    LeCreateConnection = LE_COMMANDS | 0x0D,
+    LeCreateConnectionCancel = LE_COMMANDS | 0x0E,
+    LeReadFilterAcceptListSize = LE_COMMANDS | 0x0F,
+    LeClearFilterAcceptList = LE_COMMANDS | 0x10,
+    LeAddDeviceToFilterAcceptList = LE_COMMANDS | 0x11,
+    LeRemoveDeviceFromFilterAcceptList = LE_COMMANDS | 0x12,
+    LeConnectionUpdate = LE_COMMANDS | 0x13,
+    LeEncrypt = LE_COMMANDS | 0x17,
+    LeRand = LE_COMMANDS | 0x18,
+    LeEnableEncryption = LE_COMMANDS | 0x19,
+    LeLongTermKeyRequestReply = LE_COMMANDS | 0x1A,
+    LeLongTermKeyRequestNegativeReply = LE_COMMANDS | 0x1B,
+    LeRemoteConnectionParameterRequestReply = LE_COMMANDS | 0x20,
+    LeRemoteConnectionParameterRequestNegativeReply = LE_COMMANDS | 0x21,
+    LeReadLocalP256PublicKey = LE_COMMANDS | 0x25,
+    LeGenerateDhKey = LE_COMMANDS | 0x26,
+    LeAddDeviceToResolvingList = LE_COMMANDS | 0x27,
+    LeRemoveDeviceFromResolvingList = LE_COMMANDS | 0x28,
+    LeClearResolvingList = LE_COMMANDS | 0x29,
+    LeReadResolvingListSize = LE_COMMANDS | 0x2A,
+    LeSetAddressResolutionEnable = LE_COMMANDS | 0x2D,
+    LeSetResolvablePrivateAddressTimeout = LE_COMMANDS | 0x2E,
+    LeSetAdvertisingSetRandomAddress = LE_COMMANDS | 0x35,
+    LeSetExtendedAdvertisingParameters = LE_COMMANDS | 0x36,
+    LeSetExtendedAdvertisingData = LE_COMMANDS | 0x37,
+    LeSetExtendedScanResponseData = LE_COMMANDS | 0x38,
+    LeSetExtendedAdvertisingEnable = LE_COMMANDS | 0x39,
+    LeReadMaximumAdvertisingDataLength = LE_COMMANDS | 0x3A,
+    LeReadNumberOfSupportedAdvertisingSets = LE_COMMANDS | 0x3B,
+    LeRemoveAdvertisingSet = LE_COMMANDS | 0x3C,
+    LeClearAdvertisingSets = LE_COMMANDS | 0x3D,
+    LeSetExtendedScanParameters = LE_COMMANDS | 0x41,
+    LeSetExtendedScanEnable = LE_COMMANDS | 0x42,
+    LeExtendedCreateConnection = LE_COMMANDS | 0x43,
+    LePeriodicAdvertisingCreateSync = LE_COMMANDS | 0x44,
+    LePeriodicAdvertisingCreateSyncCancel = LE_COMMANDS | 0x45,
+    LePeriodicAdvertisingTerminateSync = LE_COMMANDS | 0x46,
+    LeSetPrivacyMode = LE_COMMANDS | 0x4E,
+
     // OGF_VENDOR_COMMANDS
     DownloadMiniDriver = VENDOR_COMMANDS | 0x2E,
     WriteRam = VENDOR_COMMANDS | 0x4C,
@@ -74,11 +172,159 @@ impl From<u16> for HciCommand {
         match orig {
             _ if orig == HciCommand::Inquiry as u16 => HciCommand::Inquiry,
             _ if orig == HciCommand::CreateConnection as u16 => HciCommand::CreateConnection,
+            _ if orig == HciCommand::Disconnect as u16 => HciCommand::Disconnect,
             _ if orig == HciCommand::AcceptConnection as u16 => HciCommand::AcceptConnection,
+            _ if orig == HciCommand::RejectConnection as u16 => HciCommand::RejectConnection,
+            _ if orig == HciCommand::LinkKeyRequestReply as u16 => HciCommand::LinkKeyRequestReply,
+            _ if orig == HciCommand::LinkKeyRequestNegativeReply as u16 => {
+                HciCommand::LinkKeyRequestNegativeReply
+            }
+            _ if orig == HciCommand::PinCodeRequestNegativeReply as u16 => {
+                HciCommand::PinCodeRequestNegativeReply
+            }
+            _ if orig == HciCommand::IoCapabilityRequestReply as u16 => {
+                HciCommand::IoCapabilityRequestReply
+            }
+            _ if orig == HciCommand::UserConfirmationRequestReply as u16 => {
+                HciCommand::UserConfirmationRequestReply
+            }
+            _ if orig == HciCommand::UserConfirmationRequestNegativeReply as u16 => {
+                HciCommand::UserConfirmationRequestNegativeReply
+            }
+            _ if orig == HciCommand::UserPasskeyRequestReply as u16 => {
+                HciCommand::UserPasskeyRequestReply
+            }
+            _ if orig == HciCommand::UserPasskeyRequestNegativeReply as u16 => {
+                HciCommand::UserPasskeyRequestNegativeReply
+            }
+            _ if orig == HciCommand::IoCapabilityRequestNegativeReply as u16 => {
+                HciCommand::IoCapabilityRequestNegativeReply
+            }
+            _ if orig == HciCommand::SetEventMask as u16 => HciCommand::SetEventMask,
             _ if orig == HciCommand::Reset as u16 => HciCommand::Reset,
             _ if orig == HciCommand::WriteClassOfDevice as u16 => HciCommand::WriteClassOfDevice,
+            _ if orig == HciCommand::SetControllerToHostFlowControl as u16 => {
+                HciCommand::SetControllerToHostFlowControl
+            }
+            _ if orig == HciCommand::HostBufferSize as u16 => HciCommand::HostBufferSize,
+            _ if orig == HciCommand::HostNumberOfCompletedPackets as u16 => {
+                HciCommand::HostNumberOfCompletedPackets
+            }
             _ if orig == HciCommand::WriteScanEnable as u16 => HciCommand::WriteScanEnable,
             _ if orig == HciCommand::WriteLocalName as u16 => HciCommand::WriteLocalName,
+            _ if orig == HciCommand::WriteSimplePairingMode as u16 => {
+                HciCommand::WriteSimplePairingMode
+            }
+            _ if orig == HciCommand::WriteLeHostSupport as u16 => HciCommand::WriteLeHostSupport,
+            _ if orig == HciCommand::ReadVersionInfo as u16 => HciCommand::ReadVersionInfo,
+            _ if orig == HciCommand::ReadBufferSize as u16 => HciCommand::ReadBufferSize,
+            _ if orig == HciCommand::ReadBDAddr as u16 => HciCommand::ReadBDAddr,
+            _ if orig == HciCommand::LeSetEventMask as u16 => HciCommand::LeSetEventMask,
+            _ if orig == HciCommand::LeReadBufferSize as u16 => HciCommand::LeReadBufferSize,
+            _ if orig == HciCommand::LeReadLocalSupportedFeatures as u16 => {
+                HciCommand::LeReadLocalSupportedFeatures
+            }
+            _ if orig == HciCommand::LeSetRandomAddress as u16 => HciCommand::LeSetRandomAddress,
+            _ if orig == HciCommand::LeCreateConnection as u16 => HciCommand::LeCreateConnection,
+            _ if orig == HciCommand::LeCreateConnectionCancel as u16 => {
+                HciCommand::LeCreateConnectionCancel
+            }
+            _ if orig == HciCommand::LeReadFilterAcceptListSize as u16 => {
+                HciCommand::LeReadFilterAcceptListSize
+            }
+            _ if orig == HciCommand::LeClearFilterAcceptList as u16 => {
+                HciCommand::LeClearFilterAcceptList
+            }
+            _ if orig == HciCommand::LeAddDeviceToFilterAcceptList as u16 => {
+                HciCommand::LeAddDeviceToFilterAcceptList
+            }
+            _ if orig == HciCommand::LeRemoveDeviceFromFilterAcceptList as u16 => {
+                HciCommand::LeRemoveDeviceFromFilterAcceptList
+            }
+            _ if orig == HciCommand::LeConnectionUpdate as u16 => HciCommand::LeConnectionUpdate,
+            _ if orig == HciCommand::LeEncrypt as u16 => HciCommand::LeEncrypt,
+            _ if orig == HciCommand::LeRand as u16 => HciCommand::LeRand,
+            _ if orig == HciCommand::LeEnableEncryption as u16 => HciCommand::LeEnableEncryption,
+            _ if orig == HciCommand::LeLongTermKeyRequestReply as u16 => {
+                HciCommand::LeLongTermKeyRequestReply
+            }
+            _ if orig == HciCommand::LeLongTermKeyRequestNegativeReply as u16 => {
+                HciCommand::LeLongTermKeyRequestNegativeReply
+            }
+            _ if orig == HciCommand::LeRemoteConnectionParameterRequestReply as u16 => {
+                HciCommand::LeRemoteConnectionParameterRequestReply
+            }
+            _ if orig == HciCommand::LeRemoteConnectionParameterRequestNegativeReply as u16 => {
+                HciCommand::LeRemoteConnectionParameterRequestNegativeReply
+            }
+            _ if orig == HciCommand::LeReadLocalP256PublicKey as u16 => {
+                HciCommand::LeReadLocalP256PublicKey
+            }
+            _ if orig == HciCommand::LeGenerateDhKey as u16 => HciCommand::LeGenerateDhKey,
+            _ if orig == HciCommand::LeAddDeviceToResolvingList as u16 => {
+                HciCommand::LeAddDeviceToResolvingList
+            }
+            _ if orig == HciCommand::LeRemoveDeviceFromResolvingList as u16 => {
+                HciCommand::LeRemoveDeviceFromResolvingList
+            }
+            _ if orig == HciCommand::LeClearResolvingList as u16 => {
+                HciCommand::LeClearResolvingList
+            }
+            _ if orig == HciCommand::LeReadResolvingListSize as u16 => {
+                HciCommand::LeReadResolvingListSize
+            }
+            _ if orig == HciCommand::LeSetAddressResolutionEnable as u16 => {
+                HciCommand::LeSetAddressResolutionEnable
+            }
+            _ if orig == HciCommand::LeSetResolvablePrivateAddressTimeout as u16 => {
+                HciCommand::LeSetResolvablePrivateAddressTimeout
+            }
+            _ if orig == HciCommand::LeSetAdvertisingSetRandomAddress as u16 => {
+                HciCommand::LeSetAdvertisingSetRandomAddress
+            }
+            _ if orig == HciCommand::LeSetExtendedAdvertisingParameters as u16 => {
+                HciCommand::LeSetExtendedAdvertisingParameters
+            }
+            _ if orig == HciCommand::LeSetExtendedAdvertisingData as u16 => {
+                HciCommand::LeSetExtendedAdvertisingData
+            }
+            _ if orig == HciCommand::LeSetExtendedScanResponseData as u16 => {
+                HciCommand::LeSetExtendedScanResponseData
+            }
+            _ if orig == HciCommand::LeSetExtendedAdvertisingEnable as u16 => {
+                HciCommand::LeSetExtendedAdvertisingEnable
+            }
+            _ if orig == HciCommand::LeReadMaximumAdvertisingDataLength as u16 => {
+                HciCommand::LeReadMaximumAdvertisingDataLength
+            }
+            _ if orig == HciCommand::LeReadNumberOfSupportedAdvertisingSets as u16 => {
+                HciCommand::LeReadNumberOfSupportedAdvertisingSets
+            }
+            _ if orig == HciCommand::LeRemoveAdvertisingSet as u16 => {
+                HciCommand::LeRemoveAdvertisingSet
+            }
+            _ if orig == HciCommand::LeClearAdvertisingSets as u16 => {
+                HciCommand::LeClearAdvertisingSets
+            }
+            _ if orig == HciCommand::LeSetExtendedScanParameters as u16 => {
+                HciCommand::LeSetExtendedScanParameters
+            }
+            _ if orig == HciCommand::LeSetExtendedScanEnable as u16 => {
+                HciCommand::LeSetExtendedScanEnable
+            }
+            _ if orig == HciCommand::LeExtendedCreateConnection as u16 => {
+                HciCommand::LeExtendedCreateConnection
+            }
+            _ if orig == HciCommand::LePeriodicAdvertisingCreateSync as u16 => {
+                HciCommand::LePeriodicAdvertisingCreateSync
+            }
+            _ if orig == HciCommand::LePeriodicAdvertisingCreateSyncCancel as u16 => {
+                HciCommand::LePeriodicAdvertisingCreateSyncCancel
+            }
+            _ if orig == HciCommand::LePeriodicAdvertisingTerminateSync as u16 => {
+                HciCommand::LePeriodicAdvertisingTerminateSync
+            }
+            _ if orig == HciCommand::LeSetPrivacyMode as u16 => HciCommand::LeSetPrivacyMode,
             _ if orig == HciCommand::DownloadMiniDriver as u16 => HciCommand::DownloadMiniDriver,
             _ if orig == HciCommand::WriteRam as u16 => HciCommand::WriteRam,
             _ if orig == HciCommand::LaunchRam as u16 => HciCommand::LaunchRam,
@@ -105,6 +351,44 @@ pub trait IsHciCommand: Sized + core::fmt::Debug {
     }
 }
 
+/// Address type the controller shall use for its own address in LE advertising, scanning and
+/// initiating
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum OwnAddressType {
+    Public = 0x00,
+    Random = 0x01,
+    /// use a resolvable private address if available in the resolving list, public otherwise
+    ResolvableOrPublic = 0x02,
+    /// use a resolvable private address if available in the resolving list, random otherwise
+    ResolvableOrRandom = 0x03,
+}
+
+/// Address type of a peer LE device
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum PeerAddressType {
+    Public = 0x00,
+    Random = 0x01,
+    /// public identity address of a peer whose resolvable private address has been resolved
+    PublicIdentity = 0x02,
+    /// static random identity address of a peer whose resolvable private address has been resolved
+    RandomIdentity = 0x03,
+    Unknown = 0xFF,
+}
+
+impl From<u8> for PeerAddressType {
+    fn from(orig: u8) -> Self {
+        match orig {
+            0x00 => PeerAddressType::Public,
+            0x01 => PeerAddressType::Random,
+            0x02 => PeerAddressType::PublicIdentity,
+            0x03 => PeerAddressType::RandomIdentity,
+            _ => PeerAddressType::Unknown,
+        }
+    }
+}
+
 /// Send a command asynchronously to the BT host controller.
 /// It takes a [HciCommand] and the required Host Controller Interface
 /// using a specific Transport Layer
@@ -113,10 +397,260 @@ pub fn send_command<C, T>(command: C, hci: Arc<DataLock<Hci<T>>>) -> impl Thinka
         C: commands::IsHciCommand,
         T: HcTransportLayer + 'static,
 {
-    SendCommandThinkable::new(command, hci)
+    SendCommandThinkable::new(command, hci).map(|result| result.map(|_| ()))
 }
 
-struct SendCommandThinkable<C, T>
+/// Send a list of commands of the same kind one after another to the BT host controller. The next
+/// command is only send once the previous one has been successfully processed by the host. This is
+/// typically used for commands that transfer data which need to be fragmented.
+pub fn send_commands<C, T>(
+    commands: Vec<C>,
+    hci: Arc<DataLock<Hci<T>>>,
+) -> impl Thinkable<Output = Result<(), BoxError>>
+where
+    C: commands::IsHciCommand,
+    T: HcTransportLayer + 'static,
+{
+    SendCommandsThinkable::new(commands, hci)
+}
+
+/// Boxed ``Thinkable`` that concludes once one or more commands has been processed by the BT host
+pub(crate) type CommandThinkable = Pin<Box<dyn Thinkable<Output = Result<(), BoxError>> + Send>>;
+
+/// Think on a chain of command ``Thinkable``s one after another. The chain concludes with the
+/// first error or once the last ``Thinkable`` of the chain has successfully concluded.
+pub(crate) struct CommandChainThinkable {
+    chain: VecDeque<CommandThinkable>,
+}
+
+impl CommandChainThinkable {
+    pub(crate) fn new(chain: Vec<CommandThinkable>) -> Self {
+        Self {
+            chain: chain.into_iter().collect(),
+        }
+    }
+}
+
+impl Thinkable for CommandChainThinkable {
+    type Output = Result<(), BoxError>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        // the chain does not hold any self-references, so it's save to access it unpinned
+        let this = unsafe { self.get_unchecked_mut() };
+        // this loop allows to immediately continue with the next link in the chain in case
+        // the current one concludes without being pending
+        loop {
+            let result = match this.chain.front_mut() {
+                Some(link) => match link.as_mut().think(cx) {
+                    Conclusion::Pending => return Conclusion::Pending,
+                    Conclusion::Ready(result) => result,
+                },
+                None => return Conclusion::Ready(Ok(())),
+            };
+            this.chain.pop_front();
+            if result.is_err() {
+                return Conclusion::Ready(result);
+            }
+        }
+    }
+}
+
+/// Send the replies to requests of the BT host received while dispatching an event. The replies
+/// are passed one after another from a single Thinkable in the order of the requests, a failed
+/// reply does not hold back the others.
+pub(crate) fn spawn_replies(replies: Vec<CommandThinkable>) {
+    let chain = replies
+        .into_iter()
+        .map(|reply| {
+            let reply: CommandThinkable = Box::pin(reply.map(|result| {
+                if let Err(error) = result {
+                    warn!("replying request of the BT host failed: {:?}", error);
+                }
+                Ok(())
+            }));
+            reply
+        })
+        .collect();
+    spawn(CommandChainThinkable::new(chain).map(|_| ()));
+}
+
+/// ``Thinkable`` that immediately concludes with the given value. This allows functions returning a
+/// ``Thinkable`` to conclude with an error before any command is send to the BT host.
+pub(crate) struct ReadyThinkable<O> {
+    value: Option<O>,
+}
+
+impl<O> ReadyThinkable<O> {
+    pub(crate) fn new(value: O) -> Self {
+        Self { value: Some(value) }
+    }
+}
+
+impl<O> Thinkable for ReadyThinkable<O> {
+    type Output = O;
+
+    fn think(self: Pin<&mut Self>, _: &mut Context<'_>) -> Conclusion<Self::Output> {
+        // the value is never pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        Conclusion::Ready(this.value.take().expect("ReadyThinkable already concluded"))
+    }
+}
+
+struct SendCommandsThinkable<C, T>
+where
+    C: commands::IsHciCommand,
+    T: HcTransportLayer + 'static,
+{
+    hci: Arc<DataLock<Hci<T>>>,
+    commands: VecDeque<C>,
+    current: Option<SendCommandThinkable<C, T>>,
+}
+
+impl<C, T> SendCommandsThinkable<C, T>
+where
+    C: commands::IsHciCommand,
+    T: HcTransportLayer,
+{
+    fn new(commands: Vec<C>, hci: Arc<DataLock<Hci<T>>>) -> Self {
+        Self {
+            hci,
+            commands: commands.into_iter().collect(),
+            current: None,
+        }
+    }
+}
+
+impl<C, T> Thinkable for SendCommandsThinkable<C, T>
+where
+    C: commands::IsHciCommand,
+    T: HcTransportLayer,
+{
+    type Output = Result<(), BoxError>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        // the current command is never moved out of this structure while pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        loop {
+            if this.current.is_none() {
+                match this.commands.pop_front() {
+                    Some(command) => {
+                        let hci = this.hci.clone();
+                        this.current.replace(SendCommandThinkable::new(command, hci));
+                    }
+                    None => return Conclusion::Ready(Ok(())),
+                }
+            }
+
+            let current = unsafe { Pin::new_unchecked(this.current.as_mut().unwrap()) };
+            match current.think(cx) {
+                Conclusion::Pending => return Conclusion::Pending,
+                Conclusion::Ready(Err(e)) => return Conclusion::Ready(Err(e)),
+                Conclusion::Ready(Ok(_)) => {
+                    this.current.take();
+                }
+            }
+        }
+    }
+}
+
+/// A command passed to the BT host waiting for its CommandComplete or CommandStatus event
+struct CommandInFlight {
+    id: u32,
+    op_code: HciCommand,
+    /// ``None`` once the Thinkable sending the command has been dropped, the response is then
+    /// dropped as well
+    waker: Option<Waker>,
+    response: Option<packet::HciPacket<Vec<u8>>>,
+}
+
+/// The commands passed to the BT host and those waiting for the BT host to accept further
+/// commands. Each command passed has its own entry, so several commands with the same opcode can
+/// be in flight at the same time.
+pub(crate) struct CommandQueue {
+    next_id: u32,
+    in_flight: VecDeque<CommandInFlight>,
+    /// the Thinkables waiting until the BT host accepts further commands
+    waiting: VecDeque<Waker>,
+}
+
+impl CommandQueue {
+    pub(crate) fn new() -> Self {
+        Self {
+            next_id: 0,
+            in_flight: VecDeque::new(),
+            waiting: VecDeque::new(),
+        }
+    }
+
+    /// A command has been passed to the BT host, this returns the id its response is taken with
+    fn sent(&mut self, op_code: HciCommand, waker: Waker) -> u32 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.in_flight.push_back(CommandInFlight {
+            id,
+            op_code,
+            waker: Some(waker),
+            response: None,
+        });
+        id
+    }
+
+    /// Wait until the BT host accepts further commands
+    fn wait(&mut self, waker: Waker) {
+        self.waiting.push_back(waker);
+    }
+
+    /// Take the response to the command with the given id. If there is none yet the waker is
+    /// updated to be woken once it is received.
+    fn take_response(&mut self, id: u32, waker: &Waker) -> Option<packet::HciPacket<Vec<u8>>> {
+        let index = self.in_flight.iter().position(|command| command.id == id)?;
+        if self.in_flight[index].response.is_some() {
+            return self.in_flight.remove(index).and_then(|command| command.response);
+        }
+        self.in_flight[index].waker.replace(waker.clone());
+        None
+    }
+
+    /// The Thinkable sending the command with the given id has been dropped
+    fn abandon(&mut self, id: u32) {
+        if let Some(index) = self.in_flight.iter().position(|command| command.id == id) {
+            if self.in_flight[index].response.is_some() {
+                self.in_flight.remove(index);
+            } else {
+                self.in_flight[index].waker.take();
+            }
+        }
+    }
+
+    /// The BT host responded to a command with the given opcode. The BT host responds to the
+    /// commands with the same opcode in the order they have been passed.
+    pub(crate) fn responded(&mut self, op_code: HciCommand, response: packet::HciPacket<Vec<u8>>) {
+        let index = self
+            .in_flight
+            .iter()
+            .position(|command| command.op_code == op_code && command.response.is_none());
+        if let Some(index) = index {
+            match self.in_flight[index].waker.as_ref() {
+                Some(waker) => {
+                    waker.wake_by_ref();
+                    self.in_flight[index].response.replace(response);
+                }
+                None => {
+                    self.in_flight.remove(index);
+                }
+            }
+        }
+    }
+
+    /// The BT host accepts further commands, wake all Thinkables waiting to pass one
+    pub(crate) fn wake_waiting(&mut self) {
+        for waker in self.waiting.drain(..) {
+            waker.wake_by_ref();
+        }
+    }
+}
+
+pub(crate) struct SendCommandThinkable<C, T>
 where
     C: commands::IsHciCommand,
     T: HcTransportLayer + 'static,
@@ -124,6 +658,8 @@ where
     hci: Arc<DataLock<Hci<T>>>,
     op_code: commands::HciCommand,
     packet: Option<packet::HciPacket<C>>,
+    /// the id of the command in the [CommandQueue] once passed to the BT host
+    id: Option<u32>,
 }
 
 impl<C, T> SendCommandThinkable<C, T>
@@ -131,10 +667,7 @@ where
     C: commands::IsHciCommand,
     T: HcTransportLayer,
 {
-    unsafe_unpinned!(packet: Option<packet::HciPacket<C>>);
-    unsafe_unpinned!(hci: Arc<DataLock<Hci<T>>>);
-
-    fn new(command: C, hci: Arc<DataLock<Hci<T>>>) -> Self {
+    pub(crate) fn new(command: C, hci: Arc<DataLock<Hci<T>>>) -> Self {
         Self {
             hci,
             op_code: command.op_code(),
@@ -142,6 +675,7 @@ where
                 p_type: packet::HciPacketType::Command,
                 p_data: command,
             }),
+            id: None,
         }
     }
 }
@@ -151,96 +685,84 @@ where
     C: commands::IsHciCommand,
     T: HcTransportLayer,
 {
-    type Output = Result<(), BoxError>;
+    /// The conclusion contains the return parameters of the command following the status if the
+    /// host responded with a CommandComplete event
+    type Output = Result<Vec<u8>, BoxError>;
 
-    fn think(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
-        // if there is a packet set this has not been passed to the host
-        if self.packet.is_some() {
-            // as we are about to progress, get the waker that will be registered fot this thinkable
-            let waker = cx.waker().clone();
-            // check if the host controller is able to accept a command
-            let accept_commands = self.hci.read().accept_commands.load(Ordering::Acquire);
-            if accept_commands >= 1 {
-                self.hci
-                    .read()
-                    .accept_commands
-                    .fetch_sub(1, Ordering::SeqCst);
-                info!("send to host {:?}", self.packet);
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        // neither the packet nor the id are pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        let op_code = this.op_code;
+        let mut hci = this.hci.lock();
+        let id = match this.id {
+            Some(id) => id,
+            None => {
+                // check if the host controller is able to accept a command
+                if hci.accept_commands.load(Ordering::Acquire) == 0 {
+                    info!("no more packets free to send to the host");
+                    // wait until the BT host accepts further commands, which it tells with the
+                    // next CommandComplete or CommandStatus event
+                    hci.command_queue.wait(cx.waker().clone());
+                    return Conclusion::Pending;
+                }
+                hci.accept_commands.fetch_sub(1, Ordering::SeqCst);
+                info!("send to host {:?}", this.packet);
                 // the host accepts this packet, so send it
-                let op_code = self.op_code;
-                let packet = self.as_mut().packet().take().unwrap();
-                let mut hci = self.as_mut().hci().lock();
-                hci.command_response.insert(op_code, (waker, None));
+                let packet = this.packet.take().expect("command already sent");
+                this.id = Some(hci.command_queue.sent(op_code, cx.waker().clone()));
                 if let Some(ref mut transport) = hci.transport_layer {
                     let _ = transport.send_packet(unsafe { packet.as_array_ref() });
                 }
-                Conclusion::Pending
-            } else {
-                info!("no more packets free to send to the host");
-                // we need to wait until we can send this packet to the host.
-                // register our wakre to wake this thinkable once data has been received as than
-                // we might be able to send this packet
-                //self.hci().lock().command_to_send_waker.push(waker);
-                Conclusion::Pending
-            }
-        } else {
-            let op_code = self.op_code;
-            // being here means we have send the packet and data has been received
-            // so read the data, it should be the corresponding response?
-            let mut hci = self.hci().lock();
-            // get the response assigned to the opcode from the receiver thinkable
-            if let Some(response) = hci.command_response.get_mut(&op_code) {
-                // if there has been a response assigned
-                if let Some(event) = response.1.take() {
-                    // we are done based on the response. If it was a CommandComplete event
-                    // we are done, if it is a CommandStatus event it depenmds on the returned status
-                    match events::HciEventCommandComplete::try_from(event) {
-                        Ok(complete) => {
-                            // the completion of a command also indicates how many commands will be
-                            // accepted from the host now
-                            hci.accept_commands
-                                .store(complete.num_cmd_packets, Ordering::Release);
-                            // remove the waker for this command
-                            hci.command_response.remove(&op_code);
-                            Conclusion::Ready(Ok(()))
-                        }
-                        Err(event) => match events::HciEventCommandStatus::try_from(event) {
-                            Ok(status) => {
-                                if status.status == 0x00 {
-                                    // the status of a command also indicates how many commands will be
-                                    // accepted from the host now
-                                    hci.accept_commands
-                                        .store(status.num_cmd_packets, Ordering::Release);
-                                    // remove the waker for this command
-                                    hci.command_response.remove(&op_code);
-                                    Conclusion::Ready(Ok(()))
-                                } else {
-                                    warn!("cmd {:?} failed with status {:?}", op_code, status.status);
-                                    Conclusion::Ready(Err(Box::new(HciError {})))
-                                }
-                            }
-                            Err(_) => Conclusion::Pending,
-                        },
-                    }
+                return Conclusion::Pending;
+            }
+        };
+
+        // get the response assigned to this command from the receiver thinkable
+        let event = match hci.command_queue.take_response(id, cx.waker()) {
+            Some(event) => event,
+            None => return Conclusion::Pending,
+        };
+        this.id = None;
+        // If it was a CommandComplete event we are done, if it is a CommandStatus event it
+        // depends on the returned status. The return parameters of a CommandComplete event
+        // follow the status.
+        let return_parameters = event.p_data.get(6..).map_or_else(Vec::new, |p| p.to_vec());
+        match events::HciEventCommandComplete::try_from(event) {
+            Ok(complete) => {
+                if complete.status == 0x00 {
+                    Conclusion::Ready(Ok(return_parameters))
                 } else {
-                    // we got waken but there was no response assigned to our command so let's get
-                    // waken again, the waker keeps registered and needs no re-registration
-                    Conclusion::Pending
+                    warn!("cmd {:?} failed with status {:?}", op_code, complete.status);
+                    Conclusion::Ready(Err(Box::new(HciError {})))
                 }
-            } else {
-                // well we got waken but there is nothing registered for this command to wait for
-                // the incoming data, where is this coming from ?
-                error!("Unknown reason for beeing woken");
-                unimplemented!();
-            }
-            /*
-            // in case ther was no packet for us let's get woken with the next receiving..
-            let waker = cx.waker().clone();
-            self.hci().lock().command_response_waker.push(waker);
-
-            // pending for the time beeing
-            Conclusion::Pending
-            */
+            }
+            Err(event) => match events::HciEventCommandStatus::try_from(event) {
+                Ok(status) => {
+                    if status.status == 0x00 {
+                        Conclusion::Ready(Ok(Vec::new()))
+                    } else {
+                        warn!("cmd {:?} failed with status {:?}", op_code, status.status);
+                        Conclusion::Ready(Err(Box::new(HciError {})))
+                    }
+                }
+                Err(_) => {
+                    error!("invalid response to cmd {:?}", op_code);
+                    Conclusion::Ready(Err(Box::new(HciError {})))
+                }
+            },
+        }
+    }
+}
+
+impl<C, T> Drop for SendCommandThinkable<C, T>
+where
+    C: commands::IsHciCommand,
+    T: HcTransportLayer + 'static,
+{
+    fn drop(&mut self) {
+        // the response to a command still in flight is dropped once received
+        if let Some(id) = self.id {
+            self.hci.lock().command_queue.abandon(id);
         }
     }
 }
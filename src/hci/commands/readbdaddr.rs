@@ -0,0 +1,33 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+//! # HCI Read BD_ADDR Command
+//! The command returns the public device address of the BT host.
+
+use super::{get_command_size, HciCommand, HciCommandHeader, IsHciCommand};
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandReadBdAddr {
+    header: HciCommandHeader,
+}
+
+impl HciCommandReadBdAddr {
+    pub fn new() -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::ReadBDAddr,
+                param_length: get_command_size::<Self>(),
+            },
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandReadBdAddr {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
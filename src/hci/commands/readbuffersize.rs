@@ -0,0 +1,58 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+//! # HCI Read Buffer Size Commands
+//! Those commands read the size and the number of the buffers the BT host provides for ACL data
+//! send to it. If the BT host reports no dedicated LE buffers, LE connections share the BR/EDR
+//! buffers.
+
+use super::{get_command_size, HciCommand, HciCommandHeader, IsHciCommand};
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandReadBufferSize {
+    header: HciCommandHeader,
+}
+
+impl HciCommandReadBufferSize {
+    pub fn new() -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::ReadBufferSize,
+                param_length: get_command_size::<Self>(),
+            },
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandReadBufferSize {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeReadBufferSize {
+    header: HciCommandHeader,
+}
+
+impl HciCommandLeReadBufferSize {
+    pub fn new() -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeReadBufferSize,
+                param_length: get_command_size::<Self>(),
+            },
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeReadBufferSize {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
@@ -0,0 +1,54 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+//! # HCI Reject Connection Request Command
+//!
+
+use crate::hci::BD_ADDRESS_SIZE;
+use super::{get_command_size, HciCommand, HciCommandHeader, IsHciCommand};
+
+/// Reject the connection due to limited resources
+pub const REJECT_REASON_LIMITED_RESOURCES: u8 = 0x0D;
+/// Reject the connection due to security reasons
+pub const REJECT_REASON_SECURITY: u8 = 0x0E;
+/// Reject the connection due to the address of the requesting device
+pub const REJECT_REASON_UNACCEPTABLE_ADDRESS: u8 = 0x0F;
+
+/// The reasons a connection request can be rejected with
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RejectReason {
+    LimitedResources = REJECT_REASON_LIMITED_RESOURCES,
+    Security = REJECT_REASON_SECURITY,
+    UnacceptableAddress = REJECT_REASON_UNACCEPTABLE_ADDRESS,
+}
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+pub struct HciCommandRejectConnection {
+    header: HciCommandHeader,
+    address: [u8; BD_ADDRESS_SIZE],
+    reason: u8,
+}
+
+impl HciCommandRejectConnection {
+    pub fn new(address: [u8; BD_ADDRESS_SIZE], reason: u8) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::RejectConnection,
+                param_length: get_command_size::<Self>(),
+            },
+            address,
+            reason,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandRejectConnection {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
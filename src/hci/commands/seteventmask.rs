@@ -0,0 +1,104 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+//! # HCI Event Mask Commands
+//! Those commands configure which events the BT host will send. LE events are only send if the
+//! LE Meta event is enabled in the event mask and the specific LE sub event is enabled in the LE
+//! event mask.
+
+use super::{get_command_size, HciCommand, HciCommandHeader, IsHciCommand};
+
+/// The event mask the BT host uses after a reset
+pub const EVENT_MASK_DEFAULT: u64 = 0x0000_1FFF_FFFF_FFFF;
+/// Bit in the event mask enabling the Encryption Key Refresh Complete event
+pub const EVENT_MASK_ENCRYPTION_KEY_REFRESH: u64 = 1 << 47;
+/// Bits in the event mask enabling the events of Secure Simple Pairing: IO Capability Request
+/// and Response, User Confirmation Request, User Passkey Request, Simple Pairing Complete and
+/// User Passkey Notification
+pub const EVENT_MASK_SIMPLE_PAIRING: u64 =
+    1 << 48 | 1 << 49 | 1 << 50 | 1 << 51 | 1 << 53 | 1 << 58;
+/// Bit in the event mask enabling the LE Meta event
+pub const EVENT_MASK_LE_META: u64 = 1 << 61;
+/// LE event mask enabling all LE sub events known to this crate
+pub const LE_EVENT_MASK_ALL: u64 = 0x0000_0000_000F_FFFF;
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandSetEventMask {
+    header: HciCommandHeader,
+    event_mask: u64,
+}
+
+impl HciCommandSetEventMask {
+    pub fn new(event_mask: u64) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::SetEventMask,
+                param_length: get_command_size::<Self>(),
+            },
+            event_mask,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandSetEventMask {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandLeSetEventMask {
+    header: HciCommandHeader,
+    le_event_mask: u64,
+}
+
+impl HciCommandLeSetEventMask {
+    pub fn new(le_event_mask: u64) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::LeSetEventMask,
+                param_length: get_command_size::<Self>(),
+            },
+            le_event_mask,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandLeSetEventMask {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone)]
+pub struct HciCommandWriteLeHostSupport {
+    header: HciCommandHeader,
+    le_supported_host: u8,
+    /// this parameter is not used by the BT host any more and shall always be 0
+    simultaneous_le_host: u8,
+}
+
+impl HciCommandWriteLeHostSupport {
+    pub fn new(le_supported_host: bool) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::WriteLeHostSupport,
+                param_length: get_command_size::<Self>(),
+            },
+            le_supported_host: le_supported_host as u8,
+            simultaneous_le_host: 0,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandWriteLeHostSupport {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
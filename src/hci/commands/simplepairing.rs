@@ -0,0 +1,212 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+//! # HCI Secure Simple Pairing Commands
+//!
+//! Enable Secure Simple Pairing and answer the requests of the BT host while pairing with a
+//! BR/EDR peer.
+//!
+
+use super::{get_command_size, HciCommand, HciCommandHeader, IsHciCommand};
+use crate::hci::BD_ADDRESS_SIZE;
+
+/// Authentication requirements: bonding without protection against man-in-the-middle attacks
+pub const AUTHENTICATION_GENERAL_BONDING: u8 = 0x04;
+/// Authentication requirements: bonding with protection against man-in-the-middle attacks
+pub const AUTHENTICATION_GENERAL_BONDING_MITM: u8 = 0x05;
+/// Reject reason of an IO capability request: pairing is not allowed
+pub const REJECT_REASON_PAIRING_NOT_ALLOWED: u8 = 0x18;
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+pub struct HciCommandWriteSimplePairingMode {
+    header: HciCommandHeader,
+    simple_pairing_mode: u8,
+}
+
+impl HciCommandWriteSimplePairingMode {
+    pub fn new(enable: bool) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::WriteSimplePairingMode,
+                param_length: get_command_size::<Self>(),
+            },
+            simple_pairing_mode: enable as u8,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandWriteSimplePairingMode {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+pub struct HciCommandIoCapabilityRequestReply {
+    header: HciCommandHeader,
+    address: [u8; BD_ADDRESS_SIZE],
+    /// 0x00 display only, 0x01 display yes/no, 0x02 keyboard only, 0x03 no input no output
+    io_capability: u8,
+    oob_data_present: u8,
+    /// the ``AUTHENTICATION_*`` requirements
+    authentication_requirements: u8,
+}
+
+impl HciCommandIoCapabilityRequestReply {
+    pub fn new(
+        address: [u8; BD_ADDRESS_SIZE],
+        io_capability: u8,
+        authentication_requirements: u8,
+    ) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::IoCapabilityRequestReply,
+                param_length: get_command_size::<Self>(),
+            },
+            address,
+            io_capability,
+            oob_data_present: 0x00,
+            authentication_requirements,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandIoCapabilityRequestReply {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+pub struct HciCommandIoCapabilityRequestNegativeReply {
+    header: HciCommandHeader,
+    address: [u8; BD_ADDRESS_SIZE],
+    reason: u8,
+}
+
+impl HciCommandIoCapabilityRequestNegativeReply {
+    pub fn new(address: [u8; BD_ADDRESS_SIZE], reason: u8) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::IoCapabilityRequestNegativeReply,
+                param_length: get_command_size::<Self>(),
+            },
+            address,
+            reason,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandIoCapabilityRequestNegativeReply {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+pub struct HciCommandUserConfirmationRequestReply {
+    header: HciCommandHeader,
+    address: [u8; BD_ADDRESS_SIZE],
+}
+
+impl HciCommandUserConfirmationRequestReply {
+    pub fn new(address: [u8; BD_ADDRESS_SIZE]) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::UserConfirmationRequestReply,
+                param_length: get_command_size::<Self>(),
+            },
+            address,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandUserConfirmationRequestReply {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+pub struct HciCommandUserConfirmationRequestNegativeReply {
+    header: HciCommandHeader,
+    address: [u8; BD_ADDRESS_SIZE],
+}
+
+impl HciCommandUserConfirmationRequestNegativeReply {
+    pub fn new(address: [u8; BD_ADDRESS_SIZE]) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::UserConfirmationRequestNegativeReply,
+                param_length: get_command_size::<Self>(),
+            },
+            address,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandUserConfirmationRequestNegativeReply {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+pub struct HciCommandUserPasskeyRequestReply {
+    header: HciCommandHeader,
+    address: [u8; BD_ADDRESS_SIZE],
+    passkey: u32,
+}
+
+impl HciCommandUserPasskeyRequestReply {
+    pub fn new(address: [u8; BD_ADDRESS_SIZE], passkey: u32) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::UserPasskeyRequestReply,
+                param_length: get_command_size::<Self>(),
+            },
+            address,
+            passkey,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandUserPasskeyRequestReply {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+pub struct HciCommandUserPasskeyRequestNegativeReply {
+    header: HciCommandHeader,
+    address: [u8; BD_ADDRESS_SIZE],
+}
+
+impl HciCommandUserPasskeyRequestNegativeReply {
+    pub fn new(address: [u8; BD_ADDRESS_SIZE]) -> Self {
+        Self {
+            header: HciCommandHeader {
+                op_code: HciCommand::UserPasskeyRequestNegativeReply,
+                param_length: get_command_size::<Self>(),
+            },
+            address,
+        }
+    }
+}
+
+impl IsHciCommand for HciCommandUserPasskeyRequestNegativeReply {
+    fn op_code(&self) -> HciCommand {
+        self.header.op_code
+    }
+}
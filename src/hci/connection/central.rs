@@ -0,0 +1,196 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # LE Central Role
+//!
+//! Initiate connections to advertising LE devices. If the BT host supports extended advertising
+//! the LE Extended Create Connection command is used, otherwise the legacy LE Create Connection
+//! command.
+//!
+
+use super::*;
+use crate::hci::commands::*;
+use crate::hci::errors::*;
+use crate::hci::events::{HciEventLeConnectionComplete, HciLeEventType};
+
+/// Status of the LE ConnectionComplete event if the BT host has timed out advertising
+const STATUS_ADVERTISING_TIMEOUT: u8 = 0x3C;
+
+enum ConnectLeState {
+    Initial,
+    /// read the LE features to decide which command is used to create the connection
+    ReadFeatures(Pin<Box<dyn Thinkable<Output = Result<u64, BoxError>>>>),
+    /// send the command to create the connection
+    Create(CommandThinkable),
+    /// wait for the LE ConnectionComplete event
+    Waiting,
+}
+
+/// This ``Thinkable`` initiates an LE connection and concludes once the connection has been
+/// created or the attempt has failed or was cancelled.
+pub struct ConnectLeThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    hci: Arc<DataLock<Hci<T>>>,
+    peer_address_type: PeerAddressType,
+    peer_address: [u8; BD_ADDRESS_SIZE],
+    parameters: LeConnectionParameters,
+    state: ConnectLeState,
+}
+
+impl<T> ConnectLeThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    pub fn new(
+        hci: Arc<DataLock<Hci<T>>>,
+        peer_address_type: PeerAddressType,
+        peer_address: [u8; BD_ADDRESS_SIZE],
+        parameters: LeConnectionParameters,
+    ) -> Self {
+        Self {
+            hci,
+            peer_address_type,
+            peer_address,
+            parameters,
+            state: ConnectLeState::Initial,
+        }
+    }
+
+    /// Create the Thinkable sending the create connection command that fits the features of the
+    /// BT host
+    fn create_command(&self, features: u64) -> CommandThinkable {
+        if features & LE_FEATURE_EXTENDED_ADVERTISING != 0 {
+            Box::pin(Hci::send_command(
+                self.hci.clone(),
+                HciCommandLeExtendedCreateConnection::new(
+                    self.peer_address_type,
+                    self.peer_address,
+                    &self.parameters,
+                ),
+            ))
+        } else {
+            Box::pin(Hci::send_command(
+                self.hci.clone(),
+                HciCommandLeCreateConnection::new(
+                    self.peer_address_type,
+                    self.peer_address,
+                    &self.parameters,
+                ),
+            ))
+        }
+    }
+
+    /// Register the waker for the LE ConnectionComplete events. Which of them is send by the BT
+    /// host depends on its features.
+    fn register(&self, waker: Waker) {
+        let mut hci = self.hci.lock();
+        hci.le_event_notify
+            .insert(HciLeEventType::ConnectionComplete, (waker.clone(), None));
+        hci.le_event_notify
+            .insert(HciLeEventType::EnhancedConnectionComplete, (waker, None));
+    }
+
+    fn unregister(&self) {
+        let mut hci = self.hci.lock();
+        hci.le_event_notify.remove(&HciLeEventType::ConnectionComplete);
+        hci.le_event_notify.remove(&HciLeEventType::EnhancedConnectionComplete);
+    }
+
+    /// Take the LE ConnectionComplete event of the connection initiated, if received
+    fn take_event(&self) -> Option<HciEventLeConnectionComplete> {
+        let mut hci = self.hci.lock();
+        for event_type in &[
+            HciLeEventType::ConnectionComplete,
+            HciLeEventType::EnhancedConnectionComplete,
+        ] {
+            let packet = hci
+                .le_event_notify
+                .get_mut(event_type)
+                .and_then(|notify| notify.1.take());
+            if let Some(Ok(event)) = packet.map(HciEventLeConnectionComplete::try_from) {
+                // connections created while advertising are not the one we are waiting for
+                if (event.status == 0x00 && event.role == HciConnectionRole::Master)
+                    || (event.status != 0x00 && event.status != STATUS_ADVERTISING_TIMEOUT)
+                {
+                    return Some(event);
+                }
+            }
+        }
+        None
+    }
+}
+
+impl<T> Thinkable for ConnectLeThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = Result<Connection<T>, BoxError>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        // the boxed Thinkables are pinned on their own, so it's save to access the state unpinned
+        let this = unsafe { self.get_unchecked_mut() };
+        loop {
+            let next_state = match this.state {
+                ConnectLeState::Initial => {
+                    let features = this.hci.read().le_features;
+                    match features {
+                        Some(features) => {
+                            this.register(cx.waker().clone());
+                            ConnectLeState::Create(this.create_command(features))
+                        }
+                        None => ConnectLeState::ReadFeatures(Box::pin(
+                            Hci::read_le_local_supported_features(this.hci.clone()),
+                        )),
+                    }
+                }
+                ConnectLeState::ReadFeatures(ref mut read) => match read.as_mut().think(cx) {
+                    Conclusion::Pending => return Conclusion::Pending,
+                    Conclusion::Ready(Err(e)) => return Conclusion::Ready(Err(e)),
+                    Conclusion::Ready(Ok(features)) => {
+                        this.register(cx.waker().clone());
+                        ConnectLeState::Create(this.create_command(features))
+                    }
+                },
+                ConnectLeState::Create(ref mut create) => match create.as_mut().think(cx) {
+                    Conclusion::Pending => return Conclusion::Pending,
+                    Conclusion::Ready(Err(e)) => {
+                        this.unregister();
+                        return Conclusion::Ready(Err(e));
+                    }
+                    // the command has been accepted, the LE ConnectionComplete event is
+                    // notified with the waker registered upfront
+                    Conclusion::Ready(Ok(_)) => ConnectLeState::Waiting,
+                },
+                ConnectLeState::Waiting => {
+                    let event = match this.take_event() {
+                        Some(event) => event,
+                        None => return Conclusion::Pending,
+                    };
+                    this.unregister();
+                    if event.status != 0x00 {
+                        // this is also the case if the connection attempt has been cancelled
+                        warn!("LE connection failed with status {}", event.status);
+                        return Conclusion::Ready(Err(Box::new(HciError {})));
+                    }
+                    info!("LE connection created: {:#X?}", event);
+                    // the connection has been registered while the event was dispatched
+                    let state = this.hci.read().connections.get(event.handle);
+                    return match state {
+                        Some(state) => {
+                            Conclusion::Ready(Ok(Connection::new(this.hci.clone(), state)))
+                        }
+                        None => Conclusion::Ready(Err(Box::new(HciError {}))),
+                    };
+                }
+            };
+
+            this.state = next_state;
+        }
+    }
+}
@@ -0,0 +1,245 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # LE Encryption
+//!
+//! The central starts the encryption of an LE connection with the long term key it shares with
+//! the peripheral. The BT host of the peripheral requests this key from its host, which answers
+//! with the key found for the random number and diversifier given or rejects the request. The
+//! requests are queued until taken with [Hci::next_long_term_key_request], usually by the
+//! security manager.
+//!
+
+use super::*;
+use crate::alloc::collections::VecDeque;
+use crate::hci::commands::*;
+use crate::hci::errors::*;
+use crate::hci::events::HciEventLeLongTermKeyRequest;
+
+/// Maximum number of long term key requests kept until they are taken. If there are more the
+/// oldest ones are dropped and the BT host will not get an answer to them.
+const MAX_PENDING_KEY_REQUESTS: usize = 8;
+
+/// The result of starting the encryption of a connection, the status and whether the encryption
+/// is enabled
+type EncryptionChange = (u8, bool);
+
+/// The state of the encryption of all LE connections
+pub(crate) struct LeEncryptionState {
+    /// the long term key requests of the BT host not yet taken
+    requests: VecDeque<HciEventLeLongTermKeyRequest>,
+    request_waker: Option<Waker>,
+    /// the latest encryption change per connection handle and the waker of the Thinkable
+    /// waiting for it
+    changes: BTreeMap<u16, (Option<Waker>, Option<EncryptionChange>)>,
+}
+
+impl LeEncryptionState {
+    pub(crate) fn new() -> Self {
+        Self {
+            requests: VecDeque::new(),
+            request_waker: None,
+            changes: BTreeMap::new(),
+        }
+    }
+
+    /// The BT host requests the long term key of a connection
+    pub(crate) fn requested(&mut self, request: HciEventLeLongTermKeyRequest) {
+        if self.requests.len() >= MAX_PENDING_KEY_REQUESTS {
+            warn!("long term key request queue full, drop oldest request");
+            self.requests.pop_front();
+        }
+        self.requests.push_back(request);
+        if let Some(waker) = self.request_waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// The encryption of a connection has been started, refreshed or stopped
+    pub(crate) fn changed(&mut self, handle: u16, status: u8, enabled: bool) {
+        let handle = handle & CONNECTION_HANDLE_MASK;
+        if let Some(change) = self.changes.get_mut(&handle) {
+            change.1.replace((status, enabled));
+            if let Some(waker) = change.0.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// The connection has been closed, so forget about its requests and wake the Thinkable
+    /// waiting for the encryption
+    pub(crate) fn closed(&mut self, handle: u16) {
+        self.requests
+            .retain(|request| request.handle & CONNECTION_HANDLE_MASK != handle);
+        if let Some((Some(waker), _)) = self.changes.remove(&handle) {
+            waker.wake();
+        }
+    }
+}
+
+/// This ``Thinkable`` concludes with the next long term key request of the BT host
+pub struct NextLongTermKeyRequestThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    hci: Arc<DataLock<Hci<T>>>,
+}
+
+impl<T> NextLongTermKeyRequestThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    pub(crate) fn new(hci: Arc<DataLock<Hci<T>>>) -> Self {
+        Self { hci }
+    }
+}
+
+impl<T> Thinkable for NextLongTermKeyRequestThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = HciEventLeLongTermKeyRequest;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        let mut hci = self.hci.lock();
+        match hci.le_encryption.requests.pop_front() {
+            Some(request) => Conclusion::Ready(request),
+            None => {
+                hci.le_encryption.request_waker.replace(cx.waker().clone());
+                Conclusion::Pending
+            }
+        }
+    }
+}
+
+enum EnableEncryptionState {
+    Initial,
+    Requested(CommandThinkable),
+    Waiting,
+}
+
+/// This ``Thinkable`` starts the encryption of an LE connection in the central role, or waits for
+/// the central to start it, and concludes once the encryption has been enabled
+pub struct EnableEncryptionThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    hci: Arc<DataLock<Hci<T>>>,
+    handle: u16,
+    random_number: [u8; 8],
+    ediv: u16,
+    ltk: [u8; 16],
+    state: EnableEncryptionState,
+}
+
+impl<T> EnableEncryptionThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    pub(crate) fn new(
+        hci: Arc<DataLock<Hci<T>>>,
+        handle: u16,
+        random_number: [u8; 8],
+        ediv: u16,
+        ltk: [u8; 16],
+    ) -> Self {
+        Self {
+            hci,
+            handle,
+            random_number,
+            ediv,
+            ltk,
+            state: EnableEncryptionState::Initial,
+        }
+    }
+
+    /// Wait for the encryption of the connection in the peripheral role started by the central.
+    /// Any change that happened earlier is forgotten.
+    pub(crate) fn awaiting(hci: Arc<DataLock<Hci<T>>>, handle: u16) -> Self {
+        hci.lock()
+            .le_encryption
+            .changes
+            .insert(handle, (None, None));
+        Self {
+            hci,
+            handle,
+            random_number: [0; 8],
+            ediv: 0,
+            ltk: [0; 16],
+            state: EnableEncryptionState::Waiting,
+        }
+    }
+}
+
+impl<T> Thinkable for EnableEncryptionThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = Result<(), BoxError>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        // the command Thinkable is pinned on its own, so it's save to access the state unpinned
+        let this = unsafe { self.get_unchecked_mut() };
+        loop {
+            let next_state = match this.state {
+                EnableEncryptionState::Initial => {
+                    // register for the change before the command is send and forget about any
+                    // change that has happened earlier
+                    this.hci
+                        .lock()
+                        .le_encryption
+                        .changes
+                        .insert(this.handle, (Some(cx.waker().clone()), None));
+                    EnableEncryptionState::Requested(Box::pin(Hci::send_command(
+                        this.hci.clone(),
+                        HciCommandLeEnableEncryption::new(
+                            this.handle,
+                            this.random_number,
+                            this.ediv,
+                            this.ltk,
+                        ),
+                    )))
+                }
+                EnableEncryptionState::Requested(ref mut command) => {
+                    match command.as_mut().think(cx) {
+                        Conclusion::Pending => return Conclusion::Pending,
+                        Conclusion::Ready(Err(e)) => {
+                            this.hci.lock().le_encryption.changes.remove(&this.handle);
+                            return Conclusion::Ready(Err(e));
+                        }
+                        Conclusion::Ready(Ok(_)) => EnableEncryptionState::Waiting,
+                    }
+                }
+                EnableEncryptionState::Waiting => {
+                    let mut hci = this.hci.lock();
+                    let change = match hci.le_encryption.changes.get_mut(&this.handle) {
+                        Some(change) => change,
+                        // the connection has been closed in the meantime
+                        None => return Conclusion::Ready(Err(Box::new(HciError {}))),
+                    };
+                    return match change.1 {
+                        Some((0x00, true)) => {
+                            hci.le_encryption.changes.remove(&this.handle);
+                            Conclusion::Ready(Ok(()))
+                        }
+                        Some((status, _)) => {
+                            warn!("LE encryption failed with status {}", status);
+                            hci.le_encryption.changes.remove(&this.handle);
+                            Conclusion::Ready(Err(Box::new(HciError {})))
+                        }
+                        None => {
+                            change.0.replace(cx.waker().clone());
+                            Conclusion::Pending
+                        }
+                    };
+                }
+            };
+
+            this.state = next_state;
+        }
+    }
+}
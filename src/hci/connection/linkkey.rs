@@ -0,0 +1,126 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # BR/EDR Link Keys
+//!
+//! The BT host notifies each link key created while pairing with a BR/EDR peer and requests the
+//! key again once a later connection to the peer is authenticated. The keys are kept in the
+//! [crate::bond::BondStore] set with [Hci::set_bond_store], the requests are answered with the
+//! key stored for the peer or rejected if there is none. Requests for a PIN code of legacy
+//! pairing are always rejected.
+//!
+
+use super::*;
+use crate::alloc::collections::VecDeque;
+use crate::bond::{self, BondRecord, LinkKey, MemoryBondStore, SharedBondStore};
+use crate::hci::commands::*;
+use crate::hci::events::{
+    HciEventLinkKeyNotification, HciEventLinkKeyRequest, HciEventPinCodeRequest,
+};
+
+/// The reply to a request of the BT host for the key of a BR/EDR peer
+pub(crate) enum LinkKeyReply {
+    /// the link key found for the peer, if any
+    LinkKey([u8; BD_ADDRESS_SIZE], Option<[u8; 16]>),
+    /// the PIN code of legacy pairing is rejected
+    PinCode([u8; BD_ADDRESS_SIZE]),
+}
+
+/// The link keys of the BR/EDR peers
+pub(crate) struct LinkKeyState {
+    store: SharedBondStore,
+    /// the requests of the BT host waiting to be replied
+    replies: VecDeque<LinkKeyReply>,
+}
+
+impl LinkKeyState {
+    pub(crate) fn new() -> Self {
+        Self {
+            store: bond::shared(MemoryBondStore::new()),
+            replies: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn set_store(&mut self, store: SharedBondStore) {
+        self.store = store;
+    }
+
+    /// The BT host requests the link key of a peer
+    pub(crate) fn requested(&mut self, request: &HciEventLinkKeyRequest) {
+        let address = request.address;
+        let link_key = self
+            .store
+            .read()
+            .load(&address)
+            .and_then(|record| record.link_key)
+            .map(|link_key| link_key.key);
+        self.replies
+            .push_back(LinkKeyReply::LinkKey(address, link_key));
+    }
+
+    /// The BT host requests the PIN code to pair with a peer not supporting Secure Simple Pairing
+    pub(crate) fn pin_code_requested(&mut self, request: &HciEventPinCodeRequest) {
+        let address = request.address;
+        info!("PIN code of {:X?} rejected", address);
+        self.replies.push_back(LinkKeyReply::PinCode(address));
+    }
+
+    /// The BT host created a new link key for a peer
+    pub(crate) fn notified(&mut self, notification: &HciEventLinkKeyNotification) {
+        let address = notification.address;
+        let mut store = self.store.lock();
+        // the addresses of BR/EDR peers are always public
+        let mut record = store
+            .load(&address)
+            .unwrap_or_else(|| BondRecord::new(0x00, address));
+        record.link_key = Some(LinkKey {
+            key: notification.link_key,
+            key_type: notification.key_type,
+        });
+        store.store(record);
+    }
+
+    /// Take the replies to the requests that need to be send to the BT host
+    pub(crate) fn take_replies(&mut self) -> VecDeque<LinkKeyReply> {
+        core::mem::replace(&mut self.replies, VecDeque::new())
+    }
+}
+
+/// Send the replies to the link key and PIN code requests of the BT host. The replies are send
+/// from their own Thinkable as the requests are received while the ``Hci`` is dispatching an
+/// event.
+pub(crate) fn reply_link_key_requests<T>(
+    hci: Arc<DataLock<Hci<T>>>,
+    replies: VecDeque<LinkKeyReply>,
+) where
+    T: HcTransportLayer + 'static,
+{
+    let replies = replies
+        .into_iter()
+        .map(|reply| {
+            let command: CommandThinkable = match reply {
+                LinkKeyReply::LinkKey(address, Some(link_key)) => Box::pin(Hci::send_command(
+                    hci.clone(),
+                    HciCommandLinkKeyRequestReply::new(address, link_key),
+                )),
+                LinkKeyReply::LinkKey(address, None) => {
+                    info!("no link key for {:X?}", address);
+                    Box::pin(Hci::send_command(
+                        hci.clone(),
+                        HciCommandLinkKeyRequestNegativeReply::new(address),
+                    ))
+                }
+                LinkKeyReply::PinCode(address) => Box::pin(Hci::send_command(
+                    hci.clone(),
+                    HciCommandPinCodeRequestNegativeReply::new(address),
+                )),
+            };
+            command
+        })
+        .collect();
+    spawn_replies(replies);
+}
@@ -10,13 +10,28 @@
 
 use super::*;
 use crate::hci::events::{
+    DisconnectReason,
     HciEventConnectionRequest,
     HciEventConnectionComplete,
 };
+use crate::hci::commands::{CommandThinkable, PeerAddressType};
 use crate::hctl::HcTransportLayer;
 use crate::sync::atomic::{AtomicBool, Ordering};
 
-#[derive(Debug, Copy, Clone)]
+mod central;
+pub use central::*;
+mod encryption;
+pub use encryption::*;
+mod linkkey;
+pub(crate) use linkkey::*;
+mod simplepairing;
+pub(crate) use simplepairing::*;
+mod parameters;
+pub use parameters::*;
+mod registry;
+pub use registry::*;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 #[repr(u8)]
 pub enum HciConnectionLinkType {
     /// Synchronous Connection Oriented
@@ -26,7 +41,7 @@ pub enum HciConnectionLinkType {
     Unknown,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 #[repr(u8)]
 pub enum HciConnectionRole {
     Master = 0x00,
@@ -34,6 +49,16 @@ pub enum HciConnectionRole {
     Unknown,
 }
 
+impl From<u8> for HciConnectionRole {
+    fn from(orig: u8) -> Self {
+        match orig {
+            0x00 => HciConnectionRole::Master,
+            0x01 => HciConnectionRole::Slave,
+            _ => HciConnectionRole::Unknown,
+        }
+    }
+}
+
 impl From<u8> for HciConnectionLinkType {
     fn from(orig: u8) -> Self {
         match orig {
@@ -44,7 +69,7 @@ impl From<u8> for HciConnectionLinkType {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 #[repr(u8)]
 pub enum HciEncryptionType {
     Disabled = 0x00,
@@ -64,6 +89,132 @@ impl From<u8> for HciEncryptionType {
     }
 }
 
+/// A connection to a peer device. The connection object stays valid once the connection has
+/// been closed, but does no longer reflect any changes.
+pub struct Connection<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    hci: Arc<DataLock<Hci<T>>>,
+    state: SharedConnectionState,
+}
+
+impl<T> Clone for Connection<T>
+where
+    T: HcTransportLayer,
+{
+    fn clone(&self) -> Self {
+        Self {
+            hci: self.hci.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<T> Connection<T>
+where
+    T: HcTransportLayer,
+{
+    pub(crate) fn new(hci: Arc<DataLock<Hci<T>>>, state: SharedConnectionState) -> Self {
+        Self { hci, state }
+    }
+
+    /// The current details of the connection
+    pub fn info(&self) -> ConnectionInfo {
+        self.state.read().info
+    }
+
+    /// The connection handle assigned by the BT host
+    pub fn handle(&self) -> u16 {
+        self.info().handle
+    }
+
+    /// The role this device has within the connection
+    pub fn role(&self) -> HciConnectionRole {
+        self.info().role
+    }
+
+    pub fn peer_address_type(&self) -> PeerAddressType {
+        self.info().peer_address_type
+    }
+
+    pub fn peer_address(&self) -> [u8; BD_ADDRESS_SIZE] {
+        self.info().peer_address
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.info().encrypted
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state.read().disconnect_reason.is_none()
+    }
+
+    /// Returns a ``Thinkable`` that concludes with the reason once the connection has been closed
+    pub fn disconnected(&self) -> DisconnectedThinkable {
+        DisconnectedThinkable::new(self.state.clone())
+    }
+
+    /// Close the connection with the given reason. This concludes once the connection has been
+    /// closed with the reason reported by the BT host.
+    pub fn disconnect(&self, reason: DisconnectReason) -> DisconnectThinkable {
+        let command: CommandThinkable = Box::pin(Hci::send_command(
+            self.hci.clone(),
+            commands::HciCommandDisconnect::new(self.handle(), reason.code()),
+        ));
+        DisconnectThinkable::new(command, self.state.clone())
+    }
+
+    /// Send a PDU to the peer. The PDU is fragmented to fit into the ACL buffers of the BT host.
+    pub fn send_acl(&self, pdu: Vec<u8>) -> acl::SendAclThinkable<T> {
+        acl::SendAclThinkable::new(self.hci.clone(), self.handle(), pdu)
+    }
+
+    /// Returns a ``Thinkable`` that concludes with the next PDU received from the peer or with
+    /// ``None`` once the connection has been closed
+    pub fn recv_acl(&self) -> acl::RecvAclThinkable<T> {
+        acl::RecvAclThinkable::new(self.hci.clone(), self.state.clone())
+    }
+
+    /// Start the encryption of this LE connection in the central role with the long term key
+    /// identified towards the peripheral by the random number and encrypted diversifier. This
+    /// concludes once the encryption has been enabled.
+    pub fn enable_encryption(
+        &self,
+        random_number: [u8; 8],
+        ediv: u16,
+        ltk: [u8; 16],
+    ) -> EnableEncryptionThinkable<T> {
+        EnableEncryptionThinkable::new(self.hci.clone(), self.handle(), random_number, ediv, ltk)
+    }
+
+    /// Request new timing parameters for this LE connection. This concludes with the parameters
+    /// in use once the update has been completed.
+    pub fn update_parameters(
+        &self,
+        parameters: commands::LeConnectionUpdateParameters,
+    ) -> UpdateParametersThinkable<T> {
+        UpdateParametersThinkable::new(self.hci.clone(), self.handle(), parameters)
+    }
+}
+
+/// The decision on an inbound connection request
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ConnectionRequestDecision {
+    /// accept the connection and request to switch to the master role
+    AcceptAsMaster,
+    /// accept the connection and remain in the slave role
+    AcceptAsSlave,
+    /// reject the connection with the given reason
+    Reject(commands::RejectReason),
+}
+
+/// Callback deciding on inbound connection requests. It can inspect the address, the class of
+/// device and the link type of the requesting device. The callback is called while the ``Hci``
+/// is locked, so it must not call back into it.
+pub type ConnectionRequestPolicy =
+    Box<dyn FnMut(&HciEventConnectionRequest) -> ConnectionRequestDecision + Send>;
+
 pub struct HandleInboundConnectionsThinkable<T>
 where T: HcTransportLayer + 'static {
     hci: Arc<DataLock<Hci<T>>>,
@@ -99,17 +250,36 @@ where T: HcTransportLayer
                 match HciEventConnectionRequest::try_from(request) {
                     Ok(request) => {
                         info!("Connection request from: {:#X?}", request);
-                        // just spawn the connection accepted command for any incomming device?
-                        // TODO: implement a filter to not accept any arbitrary device to connect
-                        spawn(
-                            Hci::send_command(
+                        // without a policy any incomming device is accepted
+                        let decision = match hci.connection_request_policy {
+                            Some(ref mut policy) => policy(&request),
+                            None => ConnectionRequestDecision::AcceptAsSlave,
+                        };
+                        let address = request.address();
+                        let command: commands::CommandThinkable = match decision {
+                            ConnectionRequestDecision::AcceptAsMaster => Box::pin(Hci::send_command(
                                 self.hci.clone(),
                                 commands::HciCommandAcceptConnection::new(
-                                    request.address(),
+                                    address,
+                                    HciConnectionRole::Master,
+                                ),
+                            )),
+                            ConnectionRequestDecision::AcceptAsSlave => Box::pin(Hci::send_command(
+                                self.hci.clone(),
+                                commands::HciCommandAcceptConnection::new(
+                                    address,
                                     HciConnectionRole::Slave,
-                                )
-                            ).map(|_| info!("connection accepted sent"))
-                        );
+                                ),
+                            )),
+                            ConnectionRequestDecision::Reject(reason) => Box::pin(Hci::send_command(
+                                self.hci.clone(),
+                                commands::HciCommandRejectConnection::new(address, reason as u8),
+                            )),
+                        };
+                        spawn(command.map(move |result| match result {
+                            Ok(_) => info!("connection request answered with {:?}", decision),
+                            Err(_) => warn!("answering connection request failed"),
+                        }));
                     },
                     Err(data) => warn!("wrong event received in connection thinkable {:#X?}", data),
                 }
@@ -0,0 +1,244 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # LE Connection Parameters
+//!
+//! The timing of an LE connection can be updated by either side of the connection. Updates
+//! requested by the peer, either with the link layer procedure or with the L2CAP signaling, are
+//! passed to the [ConnectionParameterPolicy] if one is set, which decides whether the requested
+//! parameters are accepted. Without a policy any request is accepted.
+//!
+
+use super::*;
+use crate::alloc::collections::VecDeque;
+use crate::hci::commands::*;
+use crate::hci::errors::*;
+use crate::hci::events::{
+    HciEventLeConnectionUpdateComplete, HciEventLeRemoteConnectionParameterRequest,
+};
+
+/// Callback deciding whether the connection parameters requested by a peer for the connection
+/// with the given handle are accepted
+pub type ConnectionParameterPolicy =
+    Box<dyn FnMut(u16, &LeConnectionUpdateParameters) -> bool + Send>;
+
+/// The state of the connection parameter negotiation of all LE connections
+pub(crate) struct LeConnectionParameterState {
+    policy: Option<ConnectionParameterPolicy>,
+    /// the requests of peers together with the decision of the policy, waiting to be replied
+    replies: VecDeque<(u16, LeConnectionUpdateParameters, bool)>,
+    /// the latest update per connection handle and the waker of the Thinkable waiting for it
+    updates: BTreeMap<u16, (Option<Waker>, Option<HciEventLeConnectionUpdateComplete>)>,
+}
+
+impl LeConnectionParameterState {
+    pub(crate) fn new() -> Self {
+        Self {
+            policy: None,
+            replies: VecDeque::new(),
+            updates: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn set_policy(&mut self, policy: Option<ConnectionParameterPolicy>) {
+        self.policy = policy;
+    }
+
+    /// Let the policy decide whether the connection parameters requested by a peer are accepted
+    pub(crate) fn decide(
+        &mut self,
+        handle: u16,
+        parameters: &LeConnectionUpdateParameters,
+    ) -> bool {
+        match self.policy {
+            Some(ref mut policy) => policy(handle, parameters),
+            None => true,
+        }
+    }
+
+    /// A peer has requested new connection parameters, let the policy decide on them
+    pub(crate) fn requested(&mut self, request: HciEventLeRemoteConnectionParameterRequest) {
+        let handle = request.handle;
+        let parameters = LeConnectionUpdateParameters::new(
+            request.interval_min,
+            request.interval_max,
+            request.latency,
+            request.supervision_timeout,
+        );
+        let accept = self.decide(handle, &parameters);
+        self.replies.push_back((handle, parameters, accept));
+    }
+
+    /// Take the replies to the peer requests that need to be send to the BT host
+    pub(crate) fn take_replies(&mut self) -> VecDeque<(u16, LeConnectionUpdateParameters, bool)> {
+        core::mem::replace(&mut self.replies, VecDeque::new())
+    }
+
+    /// The connection parameters have been updated
+    pub(crate) fn updated(&mut self, event: HciEventLeConnectionUpdateComplete) {
+        let handle = event.handle;
+        info!("LE connection update complete: {:#X?}", event);
+        let update = self.updates.entry(handle).or_insert((None, None));
+        update.1.replace(event);
+        if let Some(waker) = update.0.take() {
+            waker.wake();
+        }
+    }
+
+    /// The connection has been closed, so forget about its updates and wake the Thinkable
+    /// waiting for an update
+    pub(crate) fn closed(&mut self, handle: u16) {
+        if let Some((Some(waker), _)) = self.updates.remove(&handle) {
+            waker.wake();
+        }
+    }
+}
+
+/// Send the replies to the connection parameter requests of peers. Each reply is send from its
+/// own Thinkable as the requests are received while the ``Hci`` is dispatching an event.
+pub(crate) fn reply_parameter_requests<T>(
+    hci: Arc<DataLock<Hci<T>>>,
+    replies: VecDeque<(u16, LeConnectionUpdateParameters, bool)>,
+) where
+    T: HcTransportLayer + 'static,
+{
+    for (handle, parameters, accept) in replies {
+        if accept {
+            spawn(
+                Hci::send_command(
+                    hci.clone(),
+                    HciCommandLeRemoteConnectionParameterRequestReply::new(handle, &parameters),
+                )
+                .map(move |result| {
+                    if result.is_err() {
+                        warn!("replying connection parameter request of {} failed", handle);
+                    }
+                }),
+            );
+        } else {
+            info!("connection parameter request of {} rejected", handle);
+            spawn(
+                Hci::send_command(
+                    hci.clone(),
+                    HciCommandLeRemoteConnectionParameterRequestNegativeReply::new(
+                        handle,
+                        REASON_UNACCEPTABLE_CONNECTION_PARAMETERS,
+                    ),
+                )
+                .map(move |result| {
+                    if result.is_err() {
+                        warn!("rejecting connection parameter request of {} failed", handle);
+                    }
+                }),
+            );
+        }
+    }
+}
+
+enum UpdateParametersState {
+    Initial,
+    Requested(CommandThinkable),
+    Waiting,
+}
+
+/// This ``Thinkable`` requests new parameters for an LE connection and concludes once the update
+/// has been completed
+pub struct UpdateParametersThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    hci: Arc<DataLock<Hci<T>>>,
+    handle: u16,
+    parameters: LeConnectionUpdateParameters,
+    state: UpdateParametersState,
+}
+
+impl<T> UpdateParametersThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    pub(crate) fn new(
+        hci: Arc<DataLock<Hci<T>>>,
+        handle: u16,
+        parameters: LeConnectionUpdateParameters,
+    ) -> Self {
+        Self {
+            hci,
+            handle,
+            parameters,
+            state: UpdateParametersState::Initial,
+        }
+    }
+}
+
+impl<T> Thinkable for UpdateParametersThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = Result<HciEventLeConnectionUpdateComplete, BoxError>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        // the command Thinkable is pinned on its own, so it's save to access the state unpinned
+        let this = unsafe { self.get_unchecked_mut() };
+        loop {
+            let next_state = match this.state {
+                UpdateParametersState::Initial => {
+                    // register for the update before the command is send and forget about any
+                    // update that has been completed earlier
+                    this.hci
+                        .lock()
+                        .le_connection_parameters
+                        .updates
+                        .insert(this.handle, (Some(cx.waker().clone()), None));
+                    UpdateParametersState::Requested(Box::pin(Hci::send_command(
+                        this.hci.clone(),
+                        HciCommandLeConnectionUpdate::new(this.handle, &this.parameters),
+                    )))
+                }
+                UpdateParametersState::Requested(ref mut command) => {
+                    match command.as_mut().think(cx) {
+                        Conclusion::Pending => return Conclusion::Pending,
+                        Conclusion::Ready(Err(e)) => {
+                            if let Some(update) = this
+                                .hci
+                                .lock()
+                                .le_connection_parameters
+                                .updates
+                                .get_mut(&this.handle)
+                            {
+                                update.0.take();
+                            }
+                            return Conclusion::Ready(Err(e));
+                        }
+                        Conclusion::Ready(Ok(_)) => UpdateParametersState::Waiting,
+                    }
+                }
+                UpdateParametersState::Waiting => {
+                    let mut hci = this.hci.lock();
+                    let update = match hci.le_connection_parameters.updates.get_mut(&this.handle) {
+                        Some(update) => update,
+                        // the connection has been closed in the meantime
+                        None => return Conclusion::Ready(Err(Box::new(HciError {}))),
+                    };
+                    return match update.1 {
+                        Some(event) if event.status == 0x00 => Conclusion::Ready(Ok(event)),
+                        Some(event) => {
+                            warn!("LE connection update failed with status {}", event.status);
+                            Conclusion::Ready(Err(Box::new(HciError {})))
+                        }
+                        None => {
+                            update.0.replace(cx.waker().clone());
+                            Conclusion::Pending
+                        }
+                    };
+                }
+            };
+
+            this.state = next_state;
+        }
+    }
+}
@@ -0,0 +1,353 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # Connection Registry
+//!
+//! The registry tracks every connection of the BT host. It is updated from the connection related
+//! events while they are dispatched by the ``Hci``. The state of each connection is shared with the
+//! [Connection] objects handed out, so they stay valid even after the connection has been closed
+//! and its handle is re-used by the BT host.
+//!
+
+use super::*;
+use crate::alloc::collections::VecDeque;
+use crate::hci::events::{
+    DisconnectReason, HciEventConnectionComplete, HciEventDisconnectionComplete,
+    HciEventEncryptionChange, HciEventLeConnectionComplete, HciEventLeConnectionUpdateComplete,
+    HciEventRoleChange,
+};
+
+/// Maximum number of connections created by peers that are kept until they are taken. If there
+/// are more the oldest ones are dropped from this list, but stay registered.
+const MAX_PENDING_CONNECTIONS: usize = 8;
+
+/// Only the least 12 bits of a connection handle are used
+pub(crate) const CONNECTION_HANDLE_MASK: u16 = 0x0FFF;
+
+/// The transport a connection is established on
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ConnectionTransport {
+    BrEdr,
+    Le,
+}
+
+/// The details of a connection
+#[derive(Debug, Copy, Clone)]
+pub struct ConnectionInfo {
+    pub handle: u16,
+    pub transport: ConnectionTransport,
+    pub link_type: HciConnectionLinkType,
+    /// the role this device has within the connection
+    pub role: HciConnectionRole,
+    pub peer_address_type: PeerAddressType,
+    pub peer_address: [u8; BD_ADDRESS_SIZE],
+    pub encrypted: bool,
+    /// LE connection interval in units of 1.25ms, 0 for BR/EDR connections
+    pub interval: u16,
+    /// LE peripheral latency, 0 for BR/EDR connections
+    pub latency: u16,
+    /// LE supervision timeout in units of 10ms, 0 for BR/EDR connections
+    pub supervision_timeout: u16,
+}
+
+/// The state of a connection shared between the registry and the [Connection] objects
+pub(crate) struct ConnectionState {
+    pub(crate) info: ConnectionInfo,
+    /// the reason the connection has been closed with, ``None`` while it is connected
+    pub(crate) disconnect_reason: Option<DisconnectReason>,
+    /// Thinkables waiting for the connection to be closed
+    pub(crate) disconnect_wakers: Vec<Waker>,
+    /// the status of a disconnection that failed, until it is taken by the [DisconnectThinkable]
+    /// waiting for it
+    pub(crate) disconnect_failed: Option<u8>,
+}
+
+pub(crate) type SharedConnectionState = Arc<DataLock<ConnectionState>>;
+
+/// All connections of the BT host
+pub(crate) struct ConnectionRegistry {
+    connections: BTreeMap<u16, SharedConnectionState>,
+    /// connections created by peers that are not yet taken by the application
+    incoming: VecDeque<u16>,
+    incoming_waker: Option<Waker>,
+    /// the roles of BR/EDR peers that changed before their connection has been completed
+    pending_roles: BTreeMap<[u8; BD_ADDRESS_SIZE], HciConnectionRole>,
+}
+
+impl ConnectionRegistry {
+    pub(crate) fn new() -> Self {
+        Self {
+            connections: BTreeMap::new(),
+            incoming: VecDeque::new(),
+            incoming_waker: None,
+            pending_roles: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn get(&self, handle: u16) -> Option<SharedConnectionState> {
+        self.connections
+            .get(&(handle & CONNECTION_HANDLE_MASK))
+            .cloned()
+    }
+
+    /// The details of all connections currently established
+    pub(crate) fn infos(&self) -> Vec<ConnectionInfo> {
+        self.connections
+            .values()
+            .map(|state| state.read().info)
+            .collect()
+    }
+
+    fn register(&mut self, info: ConnectionInfo, incoming: bool) {
+        info!("connection registered: {:#X?}", info);
+        let handle = info.handle;
+        self.connections.insert(
+            handle,
+            Arc::new(DataLock::new(ConnectionState {
+                info,
+                disconnect_reason: None,
+                disconnect_wakers: Vec::new(),
+                disconnect_failed: None,
+            })),
+        );
+        if incoming {
+            if self.incoming.len() >= MAX_PENDING_CONNECTIONS {
+                warn!("incoming connection queue full, drop oldest connection");
+                self.incoming.pop_front();
+            }
+            self.incoming.push_back(handle);
+            if let Some(waker) = self.incoming_waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// A BR/EDR connection has been created. Inbound connections start in the slave role, a
+    /// switch to the master role requested when accepting the connection is reported with a
+    /// RoleChange event before the connection is complete.
+    pub(crate) fn connected(&mut self, event: &HciEventConnectionComplete) {
+        let role = self.pending_roles.remove(&event.address());
+        if event.status() != 0x00 {
+            return;
+        }
+        self.register(
+            ConnectionInfo {
+                handle: event.handle() & CONNECTION_HANDLE_MASK,
+                transport: ConnectionTransport::BrEdr,
+                link_type: event.link_type(),
+                role: role.unwrap_or(HciConnectionRole::Slave),
+                peer_address_type: PeerAddressType::Public,
+                peer_address: event.address(),
+                encrypted: event.encryption_mode() != HciEncryptionType::Disabled,
+                interval: 0,
+                latency: 0,
+                supervision_timeout: 0,
+            },
+            true,
+        );
+    }
+
+    /// An LE connection has been created. Connections created while advertising are handed out
+    /// as incoming connections, the ones initiated by this device to the Thinkable initiating it.
+    pub(crate) fn le_connected(&mut self, event: &HciEventLeConnectionComplete) {
+        if event.status != 0x00 {
+            return;
+        }
+        self.register(
+            ConnectionInfo {
+                handle: event.handle & CONNECTION_HANDLE_MASK,
+                transport: ConnectionTransport::Le,
+                link_type: HciConnectionLinkType::Acl,
+                role: event.role,
+                peer_address_type: event.peer_address_type,
+                peer_address: event.peer_address,
+                encrypted: false,
+                interval: event.interval,
+                latency: event.latency,
+                supervision_timeout: event.supervision_timeout,
+            },
+            event.role == HciConnectionRole::Slave,
+        );
+    }
+
+    pub(crate) fn le_updated(&mut self, event: &HciEventLeConnectionUpdateComplete) {
+        if event.status != 0x00 {
+            return;
+        }
+        if let Some(state) = self.get(event.handle) {
+            let mut state = state.lock();
+            state.info.interval = event.interval;
+            state.info.latency = event.latency;
+            state.info.supervision_timeout = event.supervision_timeout;
+        }
+    }
+
+    /// The role within a BR/EDR connection has changed, the event only contains the address of
+    /// the peer. A role changed while accepting a connection is kept until the connection is
+    /// complete.
+    pub(crate) fn role_changed(&mut self, event: &HciEventRoleChange) {
+        if event.status != 0x00 {
+            return;
+        }
+        let mut known = false;
+        for state in self.connections.values() {
+            let mut state = state.lock();
+            if state.info.transport == ConnectionTransport::BrEdr
+                && state.info.peer_address == event.address
+            {
+                state.info.role = event.new_role;
+                known = true;
+            }
+        }
+        if !known {
+            self.pending_roles.insert(event.address, event.new_role);
+        }
+    }
+
+    pub(crate) fn encryption_changed(&mut self, event: &HciEventEncryptionChange) {
+        if event.status != 0x00 {
+            return;
+        }
+        if let Some(state) = self.get(event.handle) {
+            state.lock().info.encrypted = event.encryption_enabled != 0x00;
+        }
+    }
+
+    /// The connection has been closed, wake everyone waiting for this. If closing the connection
+    /// failed the connection stays open, but the ones waiting are woken to see the failure.
+    pub(crate) fn disconnected(&mut self, event: &HciEventDisconnectionComplete) {
+        let handle = event.handle & CONNECTION_HANDLE_MASK;
+        if event.status != 0x00 {
+            let status = event.status;
+            warn!("closing connection {} failed: {:#X}", handle, status);
+            if let Some(state) = self.get(handle) {
+                let mut state = state.lock();
+                state.disconnect_failed.replace(status);
+                for waker in state.disconnect_wakers.drain(..) {
+                    waker.wake();
+                }
+            }
+            return;
+        }
+        self.incoming.retain(|incoming| *incoming != handle);
+        if let Some(state) = self.connections.remove(&handle) {
+            info!("connection {} closed with reason {:?}", handle, event.reason);
+            let mut state = state.lock();
+            state.disconnect_reason.replace(event.reason);
+            for waker in state.disconnect_wakers.drain(..) {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// This ``Thinkable`` concludes with the next connection created by a peer
+pub struct NextConnectionThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    hci: Arc<DataLock<Hci<T>>>,
+}
+
+impl<T> NextConnectionThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    pub(crate) fn new(hci: Arc<DataLock<Hci<T>>>) -> Self {
+        Self { hci }
+    }
+}
+
+impl<T> Thinkable for NextConnectionThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = Connection<T>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        let mut hci = self.hci.lock();
+        while let Some(handle) = hci.connections.incoming.pop_front() {
+            if let Some(state) = hci.connections.get(handle) {
+                return Conclusion::Ready(Connection::new(self.hci.clone(), state));
+            }
+        }
+        hci.connections.incoming_waker.replace(cx.waker().clone());
+        Conclusion::Pending
+    }
+}
+
+/// This ``Thinkable`` concludes with the reason once the connection has been closed
+pub struct DisconnectedThinkable {
+    state: SharedConnectionState,
+}
+
+impl DisconnectedThinkable {
+    pub(crate) fn new(state: SharedConnectionState) -> Self {
+        Self { state }
+    }
+}
+
+impl Thinkable for DisconnectedThinkable {
+    type Output = DisconnectReason;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        let mut state = self.state.lock();
+        match state.disconnect_reason {
+            Some(reason) => Conclusion::Ready(reason),
+            None => {
+                state.disconnect_wakers.push(cx.waker().clone());
+                Conclusion::Pending
+            }
+        }
+    }
+}
+
+enum DisconnectState {
+    Requested(CommandThinkable),
+    Waiting,
+}
+
+/// This ``Thinkable`` requests to close a connection and concludes once it has been closed or
+/// closing it failed
+pub struct DisconnectThinkable {
+    state: DisconnectState,
+    disconnected: DisconnectedThinkable,
+}
+
+impl DisconnectThinkable {
+    pub(crate) fn new(command: CommandThinkable, state: SharedConnectionState) -> Self {
+        Self {
+            state: DisconnectState::Requested(command),
+            disconnected: DisconnectedThinkable::new(state),
+        }
+    }
+}
+
+impl Thinkable for DisconnectThinkable {
+    type Output = Result<DisconnectReason, BoxError>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        // the command Thinkable is pinned on its own and the DisconnectedThinkable is Unpin, so
+        // it's save to access them unpinned
+        let this = unsafe { self.get_unchecked_mut() };
+        if let DisconnectState::Requested(ref mut command) = this.state {
+            match command.as_mut().think(cx) {
+                Conclusion::Pending => return Conclusion::Pending,
+                Conclusion::Ready(Err(e)) => return Conclusion::Ready(Err(e)),
+                Conclusion::Ready(Ok(_)) => this.state = DisconnectState::Waiting,
+            }
+        }
+
+        if this.disconnected.state.lock().disconnect_failed.take().is_some() {
+            return Conclusion::Ready(Err(Box::new(errors::HciError {})));
+        }
+        match Pin::new(&mut this.disconnected).think(cx) {
+            Conclusion::Ready(reason) => Conclusion::Ready(Ok(reason)),
+            Conclusion::Pending => Conclusion::Pending,
+        }
+    }
+}
@@ -0,0 +1,210 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # Secure Simple Pairing
+//!
+//! The BT host pairs with BR/EDR peers on its own and only requests the IO capability of this
+//! device and the interaction with the user from its host. These requests are passed to the
+//! [crate::agent::PairingAgent] set with [Hci::set_pairing_agent]. Without an agent pairing is
+//! rejected.
+//!
+
+use super::*;
+use crate::agent::{AgentReply, PairingPeer, SharedPairingAgent};
+use crate::alloc::collections::VecDeque;
+use crate::hci::commands::*;
+use crate::hci::events::{
+    HciEventAuthenticationComplete, HciEventIoCapabilityRequest, HciEventIoCapabilityResponse,
+    HciEventSimplePairingComplete, HciEventUserConfirmationRequest,
+    HciEventUserPasskeyNotification, HciEventUserPasskeyRequest,
+};
+use crate::smp::IoCapability;
+
+/// A reply to a request of the BT host while pairing
+pub(crate) enum SimplePairingReply {
+    /// the IO capability and the authentication requirements, ``None`` rejects the pairing
+    IoCapability([u8; BD_ADDRESS_SIZE], Option<(u8, u8)>),
+    Confirmation([u8; BD_ADDRESS_SIZE], bool),
+    Passkey([u8; BD_ADDRESS_SIZE], Option<u32>),
+}
+
+/// The state of the pairings with BR/EDR peers
+pub(crate) struct SimplePairingState {
+    agent: Option<SharedPairingAgent>,
+    /// the IO capability of the peers currently pairing
+    peers: BTreeMap<[u8; BD_ADDRESS_SIZE], u8>,
+    /// the replies to the requests of the BT host answered by the agent at once
+    replies: VecDeque<SimplePairingReply>,
+}
+
+impl SimplePairingState {
+    pub(crate) fn new() -> Self {
+        Self {
+            agent: None,
+            peers: BTreeMap::new(),
+            replies: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn set_agent(&mut self, agent: Option<SharedPairingAgent>) {
+        self.agent = agent;
+    }
+
+    /// The BT host requests the IO capability of this device
+    pub(crate) fn io_capability_requested(&mut self, request: &HciEventIoCapabilityRequest) {
+        let reply = self.agent.as_ref().map(|agent| {
+            let io_capability = io_capability(agent.read().io_capability());
+            // protection against man-in-the-middle attacks is requested if the user can be
+            // involved
+            let requirements = if io_capability == IoCapability::NoInputNoOutput as u8 {
+                AUTHENTICATION_GENERAL_BONDING
+            } else {
+                AUTHENTICATION_GENERAL_BONDING_MITM
+            };
+            (io_capability, requirements)
+        });
+        if reply.is_none() {
+            info!(
+                "no pairing agent, reject pairing with {:X?}",
+                request.address
+            );
+        }
+        self.replies
+            .push_back(SimplePairingReply::IoCapability(request.address, reply));
+    }
+
+    /// The BT host passes the IO capability of the peer
+    pub(crate) fn io_capability_responded(&mut self, response: &HciEventIoCapabilityResponse) {
+        self.peers.insert(response.address, response.io_capability);
+    }
+
+    /// The user need to confirm the numeric value or to authorize the pairing
+    pub(crate) fn confirmation_requested(
+        &mut self,
+        request: &HciEventUserConfirmationRequest,
+        peer: PairingPeer,
+    ) {
+        let peer_io_capability = self.peers.get(&request.address).copied();
+        let reply = match self.agent {
+            Some(ref agent) => {
+                let mut agent = agent.lock();
+                let display = io_capability(agent.io_capability())
+                    == IoCapability::DisplayYesNo as u8
+                    && (peer_io_capability == Some(IoCapability::DisplayOnly as u8)
+                        || peer_io_capability == Some(IoCapability::DisplayYesNo as u8));
+                if display {
+                    agent.confirm_value(peer, request.numeric_value)
+                } else {
+                    agent.authorize(peer)
+                }
+            }
+            None => AgentReply::Now(false),
+        };
+        if let AgentReply::Now(confirmed) = reply {
+            self.replies
+                .push_back(SimplePairingReply::Confirmation(request.address, confirmed));
+        }
+    }
+
+    /// The user need to enter the passkey displayed by the peer
+    pub(crate) fn passkey_requested(
+        &mut self,
+        request: &HciEventUserPasskeyRequest,
+        peer: PairingPeer,
+    ) {
+        let reply = match self.agent {
+            Some(ref agent) => agent.lock().request_passkey(peer),
+            None => AgentReply::Now(None),
+        };
+        if let AgentReply::Now(passkey) = reply {
+            self.replies
+                .push_back(SimplePairingReply::Passkey(request.address, passkey));
+        }
+    }
+
+    /// The passkey need to be displayed to the user
+    pub(crate) fn passkey_notified(
+        &mut self,
+        notification: &HciEventUserPasskeyNotification,
+        peer: PairingPeer,
+    ) {
+        if let Some(ref agent) = self.agent {
+            agent.lock().display_passkey(peer, notification.passkey);
+        }
+    }
+
+    /// The pairing with a peer has finished
+    pub(crate) fn completed(&mut self, complete: &HciEventSimplePairingComplete) {
+        if complete.status != 0x00 {
+            warn!(
+                "pairing with {:X?} failed with status {}",
+                complete.address, complete.status
+            );
+        }
+        self.peers.remove(&complete.address);
+    }
+
+    /// The authentication of a connection initiated by this side has finished
+    pub(crate) fn authenticated(&mut self, complete: &HciEventAuthenticationComplete) {
+        if complete.status != 0x00 {
+            let handle = complete.handle;
+            warn!(
+                "authentication of connection {} failed with status {}",
+                handle, complete.status
+            );
+        }
+    }
+
+    /// Take the replies to the requests that need to be send to the BT host
+    pub(crate) fn take_replies(&mut self) -> VecDeque<SimplePairingReply> {
+        core::mem::replace(&mut self.replies, VecDeque::new())
+    }
+}
+
+/// The IO capability of Secure Simple Pairing, which does not know keyboard and display
+fn io_capability(io_capability: IoCapability) -> u8 {
+    match io_capability {
+        IoCapability::KeyboardDisplay => IoCapability::DisplayYesNo as u8,
+        io_capability => io_capability as u8,
+    }
+}
+
+/// Send the replies to the requests of the BT host while pairing. The replies are send from
+/// their own Thinkable as the requests are received while the ``Hci`` is dispatching an event.
+pub(crate) fn reply_simple_pairing<T>(
+    hci: Arc<DataLock<Hci<T>>>,
+    replies: VecDeque<SimplePairingReply>,
+) where
+    T: HcTransportLayer + 'static,
+{
+    let replies = replies
+        .into_iter()
+        .map(|reply| match reply {
+            SimplePairingReply::IoCapability(address, Some((io_capability, requirements))) => {
+                let command: CommandThinkable = Box::pin(Hci::send_command(
+                    hci.clone(),
+                    HciCommandIoCapabilityRequestReply::new(address, io_capability, requirements),
+                ));
+                command
+            }
+            SimplePairingReply::IoCapability(address, None) => Box::pin(Hci::send_command(
+                hci.clone(),
+                HciCommandIoCapabilityRequestNegativeReply::new(
+                    address,
+                    REJECT_REASON_PAIRING_NOT_ALLOWED,
+                ),
+            )),
+            SimplePairingReply::Confirmation(address, confirmed) => {
+                Hci::reply_user_confirmation(hci.clone(), address, confirmed)
+            }
+            SimplePairingReply::Passkey(address, passkey) => {
+                Hci::reply_user_passkey(hci.clone(), address, passkey)
+            }
+        })
+        .collect();
+    spawn_replies(replies);
+}
@@ -29,3 +29,29 @@ impl core::fmt::Debug for HciError {
         <HciError as core::fmt::Display>::fmt(self, f)
     }
 }
+
+/// Error raised if a request can not be passed to the BT host as the given parameters are invalid
+/// or exceed the limits of the BT host
+pub struct HciParameterError {
+    reason: &'static str,
+}
+
+impl HciParameterError {
+    pub fn new(reason: &'static str) -> Self {
+        Self { reason }
+    }
+}
+
+impl Error for HciParameterError {}
+
+impl core::fmt::Display for HciParameterError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Invalid Hci parameter: {}", self.reason)
+    }
+}
+
+impl core::fmt::Debug for HciParameterError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        <HciParameterError as core::fmt::Display>::fmt(self, f)
+    }
+}
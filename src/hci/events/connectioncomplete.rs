@@ -12,14 +12,16 @@ use crate::alloc::vec::Vec;
 use crate::convert::TryFrom;
 use crate::hci::events::{HciEventHeader, HciEventType};
 use crate::hci::packet::HciPacket;
+use crate::hci::BD_ADDRESS_SIZE;
 use crate::hci::connection::{
     HciConnectionLinkType,
     HciEncryptionType,
 };
 
-/// The CommandComplete event will be send/received if the processing of a command has been finished
+/// The ConnectionComplete event is send once a new BR/EDR connection has been created or the
+/// attempt to create one has failed
 #[repr(C, packed)]
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 pub struct HciEventConnectionComplete {
     header: HciEventHeader,
     status: u8,
@@ -29,6 +31,28 @@ pub struct HciEventConnectionComplete {
     encryption_mode: HciEncryptionType,
 }
 
+impl HciEventConnectionComplete {
+    pub fn status(&self) -> u8 {
+        self.status
+    }
+
+    pub fn handle(&self) -> u16 {
+        self.handle
+    }
+
+    pub fn address(&self) -> [u8; BD_ADDRESS_SIZE] {
+        self.address
+    }
+
+    pub fn link_type(&self) -> HciConnectionLinkType {
+        self.link_type
+    }
+
+    pub fn encryption_mode(&self) -> HciEncryptionType {
+        self.encryption_mode
+    }
+}
+
 impl TryFrom<HciPacket<Vec<u8>>> for HciEventConnectionComplete {
     type Error = HciPacket<Vec<u8>>;
 
@@ -0,0 +1,111 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # HCI Disconnection Complete Event
+//!
+
+use crate::alloc::vec::Vec;
+use crate::convert::TryFrom;
+use crate::hci::events::{HciEventHeader, HciEventType};
+use crate::hci::packet::HciPacket;
+
+/// The reason a connection has been closed with. The same codes are used to request the
+/// disconnection.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DisconnectReason {
+    AuthenticationFailure,
+    ConnectionTimeout,
+    RemoteUserTerminated,
+    RemoteLowResources,
+    RemotePowerOff,
+    LocalHostTerminated,
+    UnsupportedRemoteFeature,
+    PairingWithUnitKeyNotSupported,
+    LmpResponseTimeout,
+    MicFailure,
+    UnacceptableConnectionParameters,
+    ConnectionFailedToEstablish,
+    /// any other error code
+    Other(u8),
+}
+
+impl DisconnectReason {
+    /// The error code of this reason
+    pub fn code(&self) -> u8 {
+        match self {
+            DisconnectReason::AuthenticationFailure => 0x05,
+            DisconnectReason::ConnectionTimeout => 0x08,
+            DisconnectReason::RemoteUserTerminated => 0x13,
+            DisconnectReason::RemoteLowResources => 0x14,
+            DisconnectReason::RemotePowerOff => 0x15,
+            DisconnectReason::LocalHostTerminated => 0x16,
+            DisconnectReason::UnsupportedRemoteFeature => 0x1A,
+            DisconnectReason::LmpResponseTimeout => 0x22,
+            DisconnectReason::PairingWithUnitKeyNotSupported => 0x29,
+            DisconnectReason::MicFailure => 0x3D,
+            DisconnectReason::UnacceptableConnectionParameters => 0x3B,
+            DisconnectReason::ConnectionFailedToEstablish => 0x3E,
+            DisconnectReason::Other(code) => *code,
+        }
+    }
+}
+
+impl From<u8> for DisconnectReason {
+    fn from(orig: u8) -> Self {
+        match orig {
+            0x05 => DisconnectReason::AuthenticationFailure,
+            0x08 => DisconnectReason::ConnectionTimeout,
+            0x13 => DisconnectReason::RemoteUserTerminated,
+            0x14 => DisconnectReason::RemoteLowResources,
+            0x15 => DisconnectReason::RemotePowerOff,
+            0x16 => DisconnectReason::LocalHostTerminated,
+            0x1A => DisconnectReason::UnsupportedRemoteFeature,
+            0x22 => DisconnectReason::LmpResponseTimeout,
+            0x29 => DisconnectReason::PairingWithUnitKeyNotSupported,
+            0x3B => DisconnectReason::UnacceptableConnectionParameters,
+            0x3D => DisconnectReason::MicFailure,
+            0x3E => DisconnectReason::ConnectionFailedToEstablish,
+            _ => DisconnectReason::Other(orig),
+        }
+    }
+}
+
+/// The DisconnectionComplete event is send once a connection has been terminated by either side
+/// of the connection
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
+pub struct HciEventDisconnectionComplete {
+    pub header: HciEventHeader,
+    pub status: u8,
+    /// connection handle, only the least 12Bit's are used
+    pub handle: u16,
+    pub reason: DisconnectReason,
+}
+
+impl TryFrom<HciPacket<Vec<u8>>> for HciEventDisconnectionComplete {
+    type Error = HciPacket<Vec<u8>>;
+
+    fn try_from(orig: HciPacket<Vec<u8>>) -> Result<Self, Self::Error> {
+        let raw_event = orig.p_data;
+        if raw_event[0] == HciEventType::DisconnectionComplete as u8 {
+            Ok(HciEventDisconnectionComplete {
+                header: HciEventHeader {
+                    evt_code: raw_event[0].into(),
+                    param_length: raw_event[1],
+                },
+                status: raw_event[2],
+                handle: raw_event[3] as u16 | (raw_event[4] as u16) << 8,
+                reason: raw_event[5].into(),
+            })
+        } else {
+            Err(HciPacket {
+                p_type: orig.p_type,
+                p_data: raw_event,
+            })
+        }
+    }
+}
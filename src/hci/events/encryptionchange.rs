@@ -0,0 +1,86 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # HCI Encryption Change Event
+//!
+
+use crate::alloc::vec::Vec;
+use crate::convert::TryFrom;
+use crate::hci::events::{HciEventHeader, HciEventType};
+use crate::hci::packet::HciPacket;
+
+/// The EncryptionChange event is send once the encryption of a connection has been enabled or
+/// disabled
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
+pub struct HciEventEncryptionChange {
+    pub header: HciEventHeader,
+    pub status: u8,
+    /// connection handle, only the least 12Bit's are used
+    pub handle: u16,
+    /// 0x00 encryption off, 0x01 encryption on (E0 for BR/EDR, AES-CCM for LE), 0x02 encryption
+    /// on with AES-CCM for BR/EDR
+    pub encryption_enabled: u8,
+}
+
+impl TryFrom<HciPacket<Vec<u8>>> for HciEventEncryptionChange {
+    type Error = HciPacket<Vec<u8>>;
+
+    fn try_from(orig: HciPacket<Vec<u8>>) -> Result<Self, Self::Error> {
+        let raw_event = orig.p_data;
+        if raw_event[0] == HciEventType::EncryptionChange as u8 {
+            Ok(HciEventEncryptionChange {
+                header: HciEventHeader {
+                    evt_code: raw_event[0].into(),
+                    param_length: raw_event[1],
+                },
+                status: raw_event[2],
+                handle: raw_event[3] as u16 | (raw_event[4] as u16) << 8,
+                encryption_enabled: raw_event[5],
+            })
+        } else {
+            Err(HciPacket {
+                p_type: orig.p_type,
+                p_data: raw_event,
+            })
+        }
+    }
+}
+
+/// The EncryptionKeyRefreshComplete event is send instead of the EncryptionChange event once the
+/// encryption of an already encrypted connection has been started with a new key
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
+pub struct HciEventEncryptionKeyRefreshComplete {
+    pub header: HciEventHeader,
+    pub status: u8,
+    /// connection handle, only the least 12Bit's are used
+    pub handle: u16,
+}
+
+impl TryFrom<HciPacket<Vec<u8>>> for HciEventEncryptionKeyRefreshComplete {
+    type Error = HciPacket<Vec<u8>>;
+
+    fn try_from(orig: HciPacket<Vec<u8>>) -> Result<Self, Self::Error> {
+        let raw_event = orig.p_data;
+        if raw_event[0] == HciEventType::EncryptionKeyRefreshComplete as u8 {
+            Ok(HciEventEncryptionKeyRefreshComplete {
+                header: HciEventHeader {
+                    evt_code: raw_event[0].into(),
+                    param_length: raw_event[1],
+                },
+                status: raw_event[2],
+                handle: raw_event[3] as u16 | (raw_event[4] as u16) << 8,
+            })
+        } else {
+            Err(HciPacket {
+                p_type: orig.p_type,
+                p_data: raw_event,
+            })
+        }
+    }
+}
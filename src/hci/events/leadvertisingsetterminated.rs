@@ -0,0 +1,58 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # HCI LE Advertising Set Terminated Event
+//!
+
+use crate::alloc::vec::Vec;
+use crate::convert::TryFrom;
+use crate::hci::events::{HciEventHeader, HciEventType, HciLeEventType};
+use crate::hci::packet::HciPacket;
+
+/// The AdvertisingSetTerminated event is send if an advertising set has stopped advertising,
+/// either because the duration or the maximum number of advertising events has been reached or
+/// because a connection has been created.
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
+pub struct HciEventLeAdvertisingSetTerminated {
+    pub header: HciEventHeader,
+    pub sub_event: HciLeEventType,
+    pub status: u8,
+    pub handle: u8,
+    /// handle of the connection that has been created if the advertising stopped due to this
+    pub connection_handle: u16,
+    /// number of completed extended advertising events
+    pub num_completed_events: u8,
+}
+
+impl TryFrom<HciPacket<Vec<u8>>> for HciEventLeAdvertisingSetTerminated {
+    type Error = HciPacket<Vec<u8>>;
+
+    fn try_from(orig: HciPacket<Vec<u8>>) -> Result<Self, Self::Error> {
+        let raw_event = orig.p_data;
+        if raw_event[0] == HciEventType::LeMeta as u8
+            && raw_event[2] == HciLeEventType::AdvertisingSetTerminated as u8
+        {
+            Ok(HciEventLeAdvertisingSetTerminated {
+                header: HciEventHeader {
+                    evt_code: raw_event[0].into(),
+                    param_length: raw_event[1],
+                },
+                sub_event: raw_event[2].into(),
+                status: raw_event[3],
+                handle: raw_event[4],
+                connection_handle: raw_event[5] as u16 | (raw_event[6] as u16) << 8,
+                num_completed_events: raw_event[7],
+            })
+        } else {
+            Err(HciPacket {
+                p_type: orig.p_type,
+                p_data: raw_event,
+            })
+        }
+    }
+}
@@ -0,0 +1,96 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # HCI LE Connection Complete Event
+//!
+
+use crate::alloc::vec::Vec;
+use crate::convert::TryFrom;
+use crate::hci::commands::PeerAddressType;
+use crate::hci::connection::HciConnectionRole;
+use crate::hci::events::{HciEventHeader, HciEventType, HciLeEventType};
+use crate::hci::packet::HciPacket;
+use crate::hci::BD_ADDRESS_SIZE;
+
+/// The LE ConnectionComplete event is send once a new LE connection has been created or the
+/// attempt to create one has failed. This covers the ConnectionComplete and the
+/// EnhancedConnectionComplete LE events. The latter additionally contains the resolvable private
+/// addresses used for the connection.
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
+pub struct HciEventLeConnectionComplete {
+    pub header: HciEventHeader,
+    pub sub_event: HciLeEventType,
+    pub status: u8,
+    /// connection handle, only the least 12Bit's are used
+    pub handle: u16,
+    pub role: HciConnectionRole,
+    pub peer_address_type: PeerAddressType,
+    pub peer_address: [u8; BD_ADDRESS_SIZE],
+    /// the resolvable private address used by this device, all 0 if not used
+    pub local_resolvable_address: [u8; BD_ADDRESS_SIZE],
+    /// the resolvable private address used by the peer, all 0 if not used
+    pub peer_resolvable_address: [u8; BD_ADDRESS_SIZE],
+    /// connection interval in units of 1.25ms
+    pub interval: u16,
+    pub latency: u16,
+    /// supervision timeout in units of 10ms
+    pub supervision_timeout: u16,
+    pub clock_accuracy: u8,
+}
+
+impl TryFrom<HciPacket<Vec<u8>>> for HciEventLeConnectionComplete {
+    type Error = HciPacket<Vec<u8>>;
+
+    fn try_from(orig: HciPacket<Vec<u8>>) -> Result<Self, Self::Error> {
+        let raw_event = orig.p_data;
+        if raw_event[0] == HciEventType::LeMeta as u8
+            && (raw_event[2] == HciLeEventType::ConnectionComplete as u8
+                || raw_event[2] == HciLeEventType::EnhancedConnectionComplete as u8)
+        {
+            let mut event = HciEventLeConnectionComplete {
+                header: HciEventHeader {
+                    evt_code: raw_event[0].into(),
+                    param_length: raw_event[1],
+                },
+                sub_event: raw_event[2].into(),
+                status: raw_event[3],
+                handle: raw_event[4] as u16 | (raw_event[5] as u16) << 8,
+                role: raw_event[6].into(),
+                peer_address_type: raw_event[7].into(),
+                peer_address: [0; BD_ADDRESS_SIZE],
+                local_resolvable_address: [0; BD_ADDRESS_SIZE],
+                peer_resolvable_address: [0; BD_ADDRESS_SIZE],
+                interval: 0,
+                latency: 0,
+                supervision_timeout: 0,
+                clock_accuracy: 0,
+            };
+            event.peer_address.copy_from_slice(&raw_event[8..14]);
+            // the enhanced event contains the resolvable private addresses in front of the
+            // connection parameters
+            let offset = if event.sub_event == HciLeEventType::EnhancedConnectionComplete {
+                event.local_resolvable_address.copy_from_slice(&raw_event[14..20]);
+                event.peer_resolvable_address.copy_from_slice(&raw_event[20..26]);
+                26
+            } else {
+                14
+            };
+            event.interval = raw_event[offset] as u16 | (raw_event[offset + 1] as u16) << 8;
+            event.latency = raw_event[offset + 2] as u16 | (raw_event[offset + 3] as u16) << 8;
+            event.supervision_timeout =
+                raw_event[offset + 4] as u16 | (raw_event[offset + 5] as u16) << 8;
+            event.clock_accuracy = raw_event[offset + 6];
+            Ok(event)
+        } else {
+            Err(HciPacket {
+                p_type: orig.p_type,
+                p_data: raw_event,
+            })
+        }
+    }
+}
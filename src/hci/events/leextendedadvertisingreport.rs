@@ -0,0 +1,153 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # HCI LE Extended Advertising Report Event
+//!
+
+use crate::alloc::vec::Vec;
+use crate::convert::TryFrom;
+use crate::hci::commands::PeerAddressType;
+use crate::hci::events::{HciEventHeader, HciEventType, HciLeEventType};
+use crate::hci::packet::HciPacket;
+use crate::hci::BD_ADDRESS_SIZE;
+
+/// Size of the fixed part of each report within the event
+const REPORT_HEADER_SIZE: usize = 24;
+
+/// Advertising report event type - the advertising is connectable
+pub const ADV_REPORT_CONNECTABLE: u16 = 0x0001;
+/// Advertising report event type - the advertising is scannable
+pub const ADV_REPORT_SCANNABLE: u16 = 0x0002;
+/// Advertising report event type - the advertising is directed
+pub const ADV_REPORT_DIRECTED: u16 = 0x0004;
+/// Advertising report event type - the report contains scan response data
+pub const ADV_REPORT_SCAN_RESPONSE: u16 = 0x0008;
+/// Advertising report event type - the report is from legacy advertising PDU's
+pub const ADV_REPORT_LEGACY: u16 = 0x0010;
+
+/// Completeness of the advertising data passed with a report
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AdvertisingDataStatus {
+    Complete,
+    /// the data is incomplete, more data will follow with the next report
+    Incomplete,
+    /// the data is incomplete, but no more data will follow
+    Truncated,
+}
+
+impl From<u8> for AdvertisingDataStatus {
+    fn from(orig: u8) -> Self {
+        match orig {
+            0x00 => AdvertisingDataStatus::Complete,
+            0x01 => AdvertisingDataStatus::Incomplete,
+            _ => AdvertisingDataStatus::Truncated,
+        }
+    }
+}
+
+/// A single report of an advertiser found while scanning
+#[derive(Debug, Clone)]
+pub struct ExtendedAdvertisingReport {
+    /// combination of the ``ADV_REPORT_*`` values and the data status in bits 5 and 6
+    pub event_type: u16,
+    pub address_type: PeerAddressType,
+    pub address: [u8; BD_ADDRESS_SIZE],
+    pub primary_phy: u8,
+    /// the PHY used for the auxiliary advertising packets, 0 if there are none
+    pub secondary_phy: u8,
+    /// advertising set identifier, 0xFF if not available
+    pub sid: u8,
+    /// TX power in dBm, 0x7F if not available
+    pub tx_power: i8,
+    /// RSSI in dBm, 0x7F if not available
+    pub rssi: i8,
+    /// interval of the periodic advertising in units of 1.25ms, 0 if there is none
+    pub periodic_advertising_interval: u16,
+    pub direct_address_type: PeerAddressType,
+    pub direct_address: [u8; BD_ADDRESS_SIZE],
+    pub data: Vec<u8>,
+}
+
+impl ExtendedAdvertisingReport {
+    /// Completeness of the data in this report
+    pub fn data_status(&self) -> AdvertisingDataStatus {
+        (((self.event_type >> 5) & 0x3) as u8).into()
+    }
+
+    pub fn is_connectable(&self) -> bool {
+        self.event_type & ADV_REPORT_CONNECTABLE != 0
+    }
+
+    pub fn is_scan_response(&self) -> bool {
+        self.event_type & ADV_REPORT_SCAN_RESPONSE != 0
+    }
+}
+
+/// The ExtendedAdvertisingReport event is send while scanning with one or more reports of
+/// advertisers found
+#[derive(Debug)]
+pub struct HciEventLeExtendedAdvertisingReport {
+    pub header: HciEventHeader,
+    pub sub_event: HciLeEventType,
+    pub reports: Vec<ExtendedAdvertisingReport>,
+}
+
+impl TryFrom<HciPacket<Vec<u8>>> for HciEventLeExtendedAdvertisingReport {
+    type Error = HciPacket<Vec<u8>>;
+
+    fn try_from(orig: HciPacket<Vec<u8>>) -> Result<Self, Self::Error> {
+        let raw_event = orig.p_data;
+        if raw_event[0] == HciEventType::LeMeta as u8
+            && raw_event[2] == HciLeEventType::ExtendedAdvertisingReport as u8
+        {
+            let num_reports = raw_event[3] as usize;
+            let mut reports = Vec::with_capacity(num_reports);
+            let mut offset = 4;
+            for _ in 0..num_reports {
+                // stop decoding if the event does not contain the announced number of reports
+                if raw_event.len() < offset + REPORT_HEADER_SIZE {
+                    break;
+                }
+                let raw = &raw_event[offset..];
+                let data_length = raw[23] as usize;
+                let data_end = (REPORT_HEADER_SIZE + data_length).min(raw.len());
+                let mut report = ExtendedAdvertisingReport {
+                    event_type: raw[0] as u16 | (raw[1] as u16) << 8,
+                    address_type: raw[2].into(),
+                    address: [0; BD_ADDRESS_SIZE],
+                    primary_phy: raw[9],
+                    secondary_phy: raw[10],
+                    sid: raw[11],
+                    tx_power: raw[12] as i8,
+                    rssi: raw[13] as i8,
+                    periodic_advertising_interval: raw[14] as u16 | (raw[15] as u16) << 8,
+                    direct_address_type: raw[16].into(),
+                    direct_address: [0; BD_ADDRESS_SIZE],
+                    data: raw[REPORT_HEADER_SIZE..data_end].to_vec(),
+                };
+                report.address.copy_from_slice(&raw[3..9]);
+                report.direct_address.copy_from_slice(&raw[17..23]);
+                reports.push(report);
+                offset += REPORT_HEADER_SIZE + data_length;
+            }
+
+            Ok(HciEventLeExtendedAdvertisingReport {
+                header: HciEventHeader {
+                    evt_code: raw_event[0].into(),
+                    param_length: raw_event[1],
+                },
+                sub_event: raw_event[2].into(),
+                reports,
+            })
+        } else {
+            Err(HciPacket {
+                p_type: orig.p_type,
+                p_data: raw_event,
+            })
+        }
+    }
+}
@@ -0,0 +1,60 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # HCI LE Long Term Key Request Event
+//!
+
+use crate::alloc::vec::Vec;
+use crate::convert::TryFrom;
+use crate::hci::events::{HciEventHeader, HciEventType, HciLeEventType};
+use crate::hci::packet::HciPacket;
+
+/// The LongTermKeyRequest event is send in the peripheral role if the central starts the
+/// encryption of a connection. The request need to be answered with either the reply or the
+/// negative reply command.
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
+pub struct HciEventLeLongTermKeyRequest {
+    pub header: HciEventHeader,
+    pub sub_event: HciLeEventType,
+    /// connection handle, only the least 12Bit's are used
+    pub handle: u16,
+    /// the random number identifying the key, 0 for the short term key of legacy pairing and
+    /// the long term key of secure connections
+    pub random_number: [u8; 8],
+    /// the encrypted diversifier identifying the key
+    pub ediv: u16,
+}
+
+impl TryFrom<HciPacket<Vec<u8>>> for HciEventLeLongTermKeyRequest {
+    type Error = HciPacket<Vec<u8>>;
+
+    fn try_from(orig: HciPacket<Vec<u8>>) -> Result<Self, Self::Error> {
+        let raw_event = orig.p_data;
+        if raw_event[0] == HciEventType::LeMeta as u8
+            && raw_event[2] == HciLeEventType::LongTermKeyRequest as u8
+        {
+            let mut random_number = [0; 8];
+            random_number.copy_from_slice(&raw_event[5..13]);
+            Ok(HciEventLeLongTermKeyRequest {
+                header: HciEventHeader {
+                    evt_code: raw_event[0].into(),
+                    param_length: raw_event[1],
+                },
+                sub_event: raw_event[2].into(),
+                handle: raw_event[3] as u16 | (raw_event[4] as u16) << 8,
+                random_number,
+                ediv: raw_event[13] as u16 | (raw_event[14] as u16) << 8,
+            })
+        } else {
+            Err(HciPacket {
+                p_type: orig.p_type,
+                p_data: raw_event,
+            })
+        }
+    }
+}
@@ -0,0 +1,93 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # HCI LE P-256 Events
+//!
+
+use crate::alloc::vec::Vec;
+use crate::convert::TryFrom;
+use crate::hci::events::{HciEventHeader, HciEventType, HciLeEventType};
+use crate::hci::packet::HciPacket;
+
+/// The ReadLocalP256PublicKeyComplete event passes the public key of the key pair created by the
+/// BT host, the X coordinate first
+#[repr(C, packed)]
+#[derive(Copy, Clone)]
+pub struct HciEventLeReadLocalP256PublicKeyComplete {
+    pub header: HciEventHeader,
+    pub sub_event: HciLeEventType,
+    pub status: u8,
+    pub public_key: [u8; 64],
+}
+
+impl TryFrom<HciPacket<Vec<u8>>> for HciEventLeReadLocalP256PublicKeyComplete {
+    type Error = HciPacket<Vec<u8>>;
+
+    fn try_from(orig: HciPacket<Vec<u8>>) -> Result<Self, Self::Error> {
+        let raw_event = orig.p_data;
+        if raw_event.len() >= 68
+            && raw_event[0] == HciEventType::LeMeta as u8
+            && raw_event[2] == HciLeEventType::ReadLocalP256PublicKeyComplete as u8
+        {
+            let mut public_key = [0; 64];
+            public_key.copy_from_slice(&raw_event[4..68]);
+            Ok(HciEventLeReadLocalP256PublicKeyComplete {
+                header: HciEventHeader {
+                    evt_code: raw_event[0].into(),
+                    param_length: raw_event[1],
+                },
+                sub_event: raw_event[2].into(),
+                status: raw_event[3],
+                public_key,
+            })
+        } else {
+            Err(HciPacket {
+                p_type: orig.p_type,
+                p_data: raw_event,
+            })
+        }
+    }
+}
+
+/// The GenerateDhKeyComplete event passes the Diffie-Hellman key calculated by the BT host
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
+pub struct HciEventLeGenerateDhKeyComplete {
+    pub header: HciEventHeader,
+    pub sub_event: HciLeEventType,
+    pub status: u8,
+    pub dhkey: [u8; 32],
+}
+
+impl TryFrom<HciPacket<Vec<u8>>> for HciEventLeGenerateDhKeyComplete {
+    type Error = HciPacket<Vec<u8>>;
+
+    fn try_from(orig: HciPacket<Vec<u8>>) -> Result<Self, Self::Error> {
+        let raw_event = orig.p_data;
+        if raw_event.len() >= 36
+            && raw_event[0] == HciEventType::LeMeta as u8
+            && raw_event[2] == HciLeEventType::GenerateDhKeyComplete as u8
+        {
+            let mut dhkey = [0; 32];
+            dhkey.copy_from_slice(&raw_event[4..36]);
+            Ok(HciEventLeGenerateDhKeyComplete {
+                header: HciEventHeader {
+                    evt_code: raw_event[0].into(),
+                    param_length: raw_event[1],
+                },
+                sub_event: raw_event[2].into(),
+                status: raw_event[3],
+                dhkey,
+            })
+        } else {
+            Err(HciPacket {
+                p_type: orig.p_type,
+                p_data: raw_event,
+            })
+        }
+    }
+}
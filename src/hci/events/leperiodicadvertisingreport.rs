@@ -0,0 +1,63 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # HCI LE Periodic Advertising Report Event
+//!
+
+use crate::alloc::vec::Vec;
+use crate::convert::TryFrom;
+use crate::hci::events::{AdvertisingDataStatus, HciEventHeader, HciEventType, HciLeEventType};
+use crate::hci::packet::HciPacket;
+
+/// The PeriodicAdvertisingReport event is send for periodic advertising received from an
+/// advertiser the BT host is synchronized to
+#[derive(Debug, Clone)]
+pub struct HciEventLePeriodicAdvertisingReport {
+    pub header: HciEventHeader,
+    pub sub_event: HciLeEventType,
+    pub sync_handle: u16,
+    /// TX power in dBm, 0x7F if not available
+    pub tx_power: i8,
+    /// RSSI in dBm, 0x7F if not available
+    pub rssi: i8,
+    pub cte_type: u8,
+    pub data_status: AdvertisingDataStatus,
+    pub data: Vec<u8>,
+}
+
+impl TryFrom<HciPacket<Vec<u8>>> for HciEventLePeriodicAdvertisingReport {
+    type Error = HciPacket<Vec<u8>>;
+
+    fn try_from(orig: HciPacket<Vec<u8>>) -> Result<Self, Self::Error> {
+        let raw_event = orig.p_data;
+        if raw_event.len() >= 10
+            && raw_event[0] == HciEventType::LeMeta as u8
+            && raw_event[2] == HciLeEventType::PeriodicAdvertisingReport as u8
+        {
+            let data_length = raw_event[9] as usize;
+            let data_end = (10 + data_length).min(raw_event.len());
+            Ok(HciEventLePeriodicAdvertisingReport {
+                header: HciEventHeader {
+                    evt_code: raw_event[0].into(),
+                    param_length: raw_event[1],
+                },
+                sub_event: raw_event[2].into(),
+                sync_handle: raw_event[3] as u16 | (raw_event[4] as u16) << 8,
+                tx_power: raw_event[5] as i8,
+                rssi: raw_event[6] as i8,
+                cte_type: raw_event[7],
+                data_status: raw_event[8].into(),
+                data: raw_event[10..data_end].to_vec(),
+            })
+        } else {
+            Err(HciPacket {
+                p_type: orig.p_type,
+                p_data: raw_event,
+            })
+        }
+    }
+}
@@ -0,0 +1,68 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # HCI LE Periodic Advertising Sync Established Event
+//!
+
+use crate::alloc::vec::Vec;
+use crate::convert::TryFrom;
+use crate::hci::commands::PeerAddressType;
+use crate::hci::events::{HciEventHeader, HciEventType, HciLeEventType};
+use crate::hci::packet::HciPacket;
+use crate::hci::BD_ADDRESS_SIZE;
+
+/// The PeriodicAdvertisingSyncEstablished event is send once the BT host has synchronized to a
+/// periodic advertising or the attempt to do so has failed or was cancelled
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
+pub struct HciEventLePeriodicAdvertisingSyncEstablished {
+    pub header: HciEventHeader,
+    pub sub_event: HciLeEventType,
+    pub status: u8,
+    pub sync_handle: u16,
+    pub sid: u8,
+    pub address_type: PeerAddressType,
+    pub address: [u8; BD_ADDRESS_SIZE],
+    pub phy: u8,
+    /// periodic advertising interval in units of 1.25ms
+    pub interval: u16,
+    pub clock_accuracy: u8,
+}
+
+impl TryFrom<HciPacket<Vec<u8>>> for HciEventLePeriodicAdvertisingSyncEstablished {
+    type Error = HciPacket<Vec<u8>>;
+
+    fn try_from(orig: HciPacket<Vec<u8>>) -> Result<Self, Self::Error> {
+        let raw_event = orig.p_data;
+        if raw_event[0] == HciEventType::LeMeta as u8
+            && raw_event[2] == HciLeEventType::PeriodicAdvertisingSyncEstablished as u8
+        {
+            let mut event = HciEventLePeriodicAdvertisingSyncEstablished {
+                header: HciEventHeader {
+                    evt_code: raw_event[0].into(),
+                    param_length: raw_event[1],
+                },
+                sub_event: raw_event[2].into(),
+                status: raw_event[3],
+                sync_handle: raw_event[4] as u16 | (raw_event[5] as u16) << 8,
+                sid: raw_event[6],
+                address_type: raw_event[7].into(),
+                address: [0; BD_ADDRESS_SIZE],
+                phy: raw_event[14],
+                interval: raw_event[15] as u16 | (raw_event[16] as u16) << 8,
+                clock_accuracy: raw_event[17],
+            };
+            event.address.copy_from_slice(&raw_event[8..14]);
+            Ok(event)
+        } else {
+            Err(HciPacket {
+                p_type: orig.p_type,
+                p_data: raw_event,
+            })
+        }
+    }
+}
@@ -0,0 +1,50 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # HCI LE Periodic Advertising Sync Lost Event
+//!
+
+use crate::alloc::vec::Vec;
+use crate::convert::TryFrom;
+use crate::hci::events::{HciEventHeader, HciEventType, HciLeEventType};
+use crate::hci::packet::HciPacket;
+
+/// The PeriodicAdvertisingSyncLost event is send if the BT host has lost the synchronization to
+/// a periodic advertising
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
+pub struct HciEventLePeriodicAdvertisingSyncLost {
+    pub header: HciEventHeader,
+    pub sub_event: HciLeEventType,
+    pub sync_handle: u16,
+}
+
+impl TryFrom<HciPacket<Vec<u8>>> for HciEventLePeriodicAdvertisingSyncLost {
+    type Error = HciPacket<Vec<u8>>;
+
+    fn try_from(orig: HciPacket<Vec<u8>>) -> Result<Self, Self::Error> {
+        let raw_event = orig.p_data;
+        if raw_event.len() >= 5
+            && raw_event[0] == HciEventType::LeMeta as u8
+            && raw_event[2] == HciLeEventType::PeriodicAdvertisingSyncLost as u8
+        {
+            Ok(HciEventLePeriodicAdvertisingSyncLost {
+                header: HciEventHeader {
+                    evt_code: raw_event[0].into(),
+                    param_length: raw_event[1],
+                },
+                sub_event: raw_event[2].into(),
+                sync_handle: raw_event[3] as u16 | (raw_event[4] as u16) << 8,
+            })
+        } else {
+            Err(HciPacket {
+                p_type: orig.p_type,
+                p_data: raw_event,
+            })
+        }
+    }
+}
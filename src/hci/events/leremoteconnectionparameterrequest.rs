@@ -0,0 +1,62 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # HCI LE Remote Connection Parameter Request Event
+//!
+
+use crate::alloc::vec::Vec;
+use crate::convert::TryFrom;
+use crate::hci::events::{HciEventHeader, HciEventType, HciLeEventType};
+use crate::hci::packet::HciPacket;
+
+/// The RemoteConnectionParameterRequest event is send if the peer requests to change the timing
+/// of an LE connection. The request need to be answered with either the reply or the negative
+/// reply command.
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
+pub struct HciEventLeRemoteConnectionParameterRequest {
+    pub header: HciEventHeader,
+    pub sub_event: HciLeEventType,
+    /// connection handle, only the least 12Bit's are used
+    pub handle: u16,
+    /// minimum connection interval in units of 1.25ms
+    pub interval_min: u16,
+    /// maximum connection interval in units of 1.25ms
+    pub interval_max: u16,
+    pub latency: u16,
+    /// supervision timeout in units of 10ms
+    pub supervision_timeout: u16,
+}
+
+impl TryFrom<HciPacket<Vec<u8>>> for HciEventLeRemoteConnectionParameterRequest {
+    type Error = HciPacket<Vec<u8>>;
+
+    fn try_from(orig: HciPacket<Vec<u8>>) -> Result<Self, Self::Error> {
+        let raw_event = orig.p_data;
+        if raw_event[0] == HciEventType::LeMeta as u8
+            && raw_event[2] == HciLeEventType::RemoteConnectionParameterRequest as u8
+        {
+            Ok(HciEventLeRemoteConnectionParameterRequest {
+                header: HciEventHeader {
+                    evt_code: raw_event[0].into(),
+                    param_length: raw_event[1],
+                },
+                sub_event: raw_event[2].into(),
+                handle: raw_event[3] as u16 | (raw_event[4] as u16) << 8,
+                interval_min: raw_event[5] as u16 | (raw_event[6] as u16) << 8,
+                interval_max: raw_event[7] as u16 | (raw_event[8] as u16) << 8,
+                latency: raw_event[9] as u16 | (raw_event[10] as u16) << 8,
+                supervision_timeout: raw_event[11] as u16 | (raw_event[12] as u16) << 8,
+            })
+        } else {
+            Err(HciPacket {
+                p_type: orig.p_type,
+                p_data: raw_event,
+            })
+        }
+    }
+}
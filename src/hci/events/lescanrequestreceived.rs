@@ -0,0 +1,57 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # HCI LE Scan Request Received Event
+//!
+
+use crate::alloc::vec::Vec;
+use crate::convert::TryFrom;
+use crate::hci::commands::PeerAddressType;
+use crate::hci::events::{HciEventHeader, HciEventType, HciLeEventType};
+use crate::hci::packet::HciPacket;
+use crate::hci::BD_ADDRESS_SIZE;
+
+/// The ScanRequestReceived event is send if an advertising set with enabled scan request
+/// notifications received a scan request
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
+pub struct HciEventLeScanRequestReceived {
+    pub header: HciEventHeader,
+    pub sub_event: HciLeEventType,
+    pub handle: u8,
+    pub scanner_address_type: PeerAddressType,
+    pub scanner_address: [u8; BD_ADDRESS_SIZE],
+}
+
+impl TryFrom<HciPacket<Vec<u8>>> for HciEventLeScanRequestReceived {
+    type Error = HciPacket<Vec<u8>>;
+
+    fn try_from(orig: HciPacket<Vec<u8>>) -> Result<Self, Self::Error> {
+        let raw_event = orig.p_data;
+        if raw_event[0] == HciEventType::LeMeta as u8
+            && raw_event[2] == HciLeEventType::ScanRequestReceived as u8
+        {
+            let mut event = HciEventLeScanRequestReceived {
+                header: HciEventHeader {
+                    evt_code: raw_event[0].into(),
+                    param_length: raw_event[1],
+                },
+                sub_event: raw_event[2].into(),
+                handle: raw_event[3],
+                scanner_address_type: raw_event[4].into(),
+                scanner_address: [0; BD_ADDRESS_SIZE],
+            };
+            event.scanner_address.copy_from_slice(&raw_event[5..11]);
+            Ok(event)
+        } else {
+            Err(HciPacket {
+                p_type: orig.p_type,
+                p_data: raw_event,
+            })
+        }
+    }
+}
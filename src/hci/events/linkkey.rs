@@ -0,0 +1,137 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # HCI Link Key Events
+//!
+
+use crate::alloc::vec::Vec;
+use crate::convert::TryFrom;
+use crate::hci::events::{HciEventHeader, HciEventType};
+use crate::hci::packet::HciPacket;
+use crate::hci::BD_ADDRESS_SIZE;
+
+/// Link key type: combination key of legacy pairing
+pub const LINK_KEY_TYPE_COMBINATION: u8 = 0x00;
+/// Link key type: combination key created with the debug key pair of secure simple pairing
+pub const LINK_KEY_TYPE_DEBUG_COMBINATION: u8 = 0x03;
+/// Link key type: unauthenticated combination key created with P-192
+pub const LINK_KEY_TYPE_UNAUTHENTICATED_P192: u8 = 0x04;
+/// Link key type: authenticated combination key created with P-192
+pub const LINK_KEY_TYPE_AUTHENTICATED_P192: u8 = 0x05;
+/// Link key type: the combination key has been changed
+pub const LINK_KEY_TYPE_CHANGED_COMBINATION: u8 = 0x06;
+/// Link key type: unauthenticated combination key created with P-256
+pub const LINK_KEY_TYPE_UNAUTHENTICATED_P256: u8 = 0x07;
+/// Link key type: authenticated combination key created with P-256
+pub const LINK_KEY_TYPE_AUTHENTICATED_P256: u8 = 0x08;
+
+/// The LinkKeyRequest event is send if the BT host needs the link key of a BR/EDR peer to
+/// authenticate the connection. The request need to be answered with either the reply or the
+/// negative reply command.
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
+pub struct HciEventLinkKeyRequest {
+    pub header: HciEventHeader,
+    pub address: [u8; BD_ADDRESS_SIZE],
+}
+
+impl TryFrom<HciPacket<Vec<u8>>> for HciEventLinkKeyRequest {
+    type Error = HciPacket<Vec<u8>>;
+
+    fn try_from(orig: HciPacket<Vec<u8>>) -> Result<Self, Self::Error> {
+        let raw_event = orig.p_data;
+        if raw_event[0] == HciEventType::LinkKeyRequest as u8 && raw_event.len() >= 8 {
+            let mut address = [0; BD_ADDRESS_SIZE];
+            address.copy_from_slice(&raw_event[2..8]);
+            Ok(HciEventLinkKeyRequest {
+                header: HciEventHeader {
+                    evt_code: raw_event[0].into(),
+                    param_length: raw_event[1],
+                },
+                address,
+            })
+        } else {
+            Err(HciPacket {
+                p_type: orig.p_type,
+                p_data: raw_event,
+            })
+        }
+    }
+}
+
+/// The PinCodeRequest event is send if the BT host needs a PIN code to pair with a BR/EDR peer
+/// that does not support Secure Simple Pairing
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
+pub struct HciEventPinCodeRequest {
+    pub header: HciEventHeader,
+    pub address: [u8; BD_ADDRESS_SIZE],
+}
+
+impl TryFrom<HciPacket<Vec<u8>>> for HciEventPinCodeRequest {
+    type Error = HciPacket<Vec<u8>>;
+
+    fn try_from(orig: HciPacket<Vec<u8>>) -> Result<Self, Self::Error> {
+        let raw_event = orig.p_data;
+        if raw_event[0] == HciEventType::PinCodeRequest as u8 && raw_event.len() >= 8 {
+            let mut address = [0; BD_ADDRESS_SIZE];
+            address.copy_from_slice(&raw_event[2..8]);
+            Ok(HciEventPinCodeRequest {
+                header: HciEventHeader {
+                    evt_code: raw_event[0].into(),
+                    param_length: raw_event[1],
+                },
+                address,
+            })
+        } else {
+            Err(HciPacket {
+                p_type: orig.p_type,
+                p_data: raw_event,
+            })
+        }
+    }
+}
+
+/// The LinkKeyNotification event is send once a new link key has been created for a BR/EDR
+/// peer. The host need to keep the key to authenticate later connections to the peer.
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
+pub struct HciEventLinkKeyNotification {
+    pub header: HciEventHeader,
+    pub address: [u8; BD_ADDRESS_SIZE],
+    pub link_key: [u8; 16],
+    /// the ``LINK_KEY_TYPE_*`` of the key
+    pub key_type: u8,
+}
+
+impl TryFrom<HciPacket<Vec<u8>>> for HciEventLinkKeyNotification {
+    type Error = HciPacket<Vec<u8>>;
+
+    fn try_from(orig: HciPacket<Vec<u8>>) -> Result<Self, Self::Error> {
+        let raw_event = orig.p_data;
+        if raw_event[0] == HciEventType::LinkKeyNotification as u8 && raw_event.len() >= 25 {
+            let mut address = [0; BD_ADDRESS_SIZE];
+            address.copy_from_slice(&raw_event[2..8]);
+            let mut link_key = [0; 16];
+            link_key.copy_from_slice(&raw_event[8..24]);
+            Ok(HciEventLinkKeyNotification {
+                header: HciEventHeader {
+                    evt_code: raw_event[0].into(),
+                    param_length: raw_event[1],
+                },
+                address,
+                link_key,
+                key_type: raw_event[24],
+            })
+        } else {
+            Err(HciPacket {
+                p_type: orig.p_type,
+                p_data: raw_event,
+            })
+        }
+    }
+}
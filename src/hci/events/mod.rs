@@ -22,6 +22,40 @@ mod connectionrequest;
 pub use connectionrequest::*;
 mod connectioncomplete;
 pub use connectioncomplete::*;
+mod leadvertisingsetterminated;
+pub use leadvertisingsetterminated::*;
+mod lescanrequestreceived;
+pub use lescanrequestreceived::*;
+mod leextendedadvertisingreport;
+pub use leextendedadvertisingreport::*;
+mod leperiodicadvertisingsyncestablished;
+pub use leperiodicadvertisingsyncestablished::*;
+mod leperiodicadvertisingreport;
+pub use leperiodicadvertisingreport::*;
+mod leperiodicadvertisingsynclost;
+pub use leperiodicadvertisingsynclost::*;
+mod disconnectioncomplete;
+pub use disconnectioncomplete::*;
+mod rolechange;
+pub use rolechange::*;
+mod encryptionchange;
+pub use encryptionchange::*;
+mod numberofcompletedpackets;
+pub use numberofcompletedpackets::*;
+mod leconnectioncomplete;
+pub use leconnectioncomplete::*;
+mod leconnectionupdatecomplete;
+pub use leconnectionupdatecomplete::*;
+mod leremoteconnectionparameterrequest;
+pub use leremoteconnectionparameterrequest::*;
+mod lelongtermkeyrequest;
+pub use lelongtermkeyrequest::*;
+mod lep256;
+pub use lep256::*;
+mod linkkey;
+pub use linkkey::*;
+mod simplepairing;
+pub use simplepairing::*;
 
 #[repr(u8)]
 #[derive(Eq, PartialEq, Ord, PartialOrd, Debug, Copy, Clone)]
@@ -34,6 +68,7 @@ pub enum HciEventType {
     DisconnectionComplete = 0x5,
     AuthenticationComplete = 0x6,
     RemoteNameRequestComplete = 0x7,
+    EncryptionChange = 0x8,
     CommandComplete = 0xE,
     CommandStatus = 0xF,
     RoleChange = 0x12,
@@ -42,6 +77,14 @@ pub enum HciEventType {
     LinkKeyRequest = 0x17,
     LinkKeyNotification = 0x18,
     MaxSlotsChange = 0x1B,
+    EncryptionKeyRefreshComplete = 0x30,
+    IoCapabilityRequest = 0x31,
+    IoCapabilityResponse = 0x32,
+    UserConfirmationRequest = 0x33,
+    UserPasskeyRequest = 0x34,
+    SimplePairingComplete = 0x36,
+    UserPasskeyNotification = 0x3B,
+    LeMeta = 0x3E,
 }
 
 impl From<u8> for HciEventType {
@@ -54,6 +97,7 @@ impl From<u8> for HciEventType {
             0x05 => HciEventType::DisconnectionComplete,
             0x06 => HciEventType::AuthenticationComplete,
             0x07 => HciEventType::RemoteNameRequestComplete,
+            0x08 => HciEventType::EncryptionChange,
             0x0E => HciEventType::CommandComplete,
             0x0F => HciEventType::CommandStatus,
             0x12 => HciEventType::RoleChange,
@@ -62,11 +106,75 @@ impl From<u8> for HciEventType {
             0x17 => HciEventType::LinkKeyRequest,
             0x18 => HciEventType::LinkKeyNotification,
             0x1B => HciEventType::MaxSlotsChange,
+            0x30 => HciEventType::EncryptionKeyRefreshComplete,
+            0x31 => HciEventType::IoCapabilityRequest,
+            0x32 => HciEventType::IoCapabilityResponse,
+            0x33 => HciEventType::UserConfirmationRequest,
+            0x34 => HciEventType::UserPasskeyRequest,
+            0x36 => HciEventType::SimplePairingComplete,
+            0x3B => HciEventType::UserPasskeyNotification,
+            0x3E => HciEventType::LeMeta,
             _ => HciEventType::Unknown,
         }
     }
 }
 
+/// The LE events are all send from the BT host as LE Meta event. The actual LE event is given
+/// by the sub event code as the first parameter of the LE Meta event
+#[repr(u8)]
+#[derive(Eq, PartialEq, Ord, PartialOrd, Debug, Copy, Clone)]
+pub enum HciLeEventType {
+    Unknown = 0x0,
+    ConnectionComplete = 0x01,
+    AdvertisingReport = 0x02,
+    ConnectionUpdateComplete = 0x03,
+    ReadRemoteFeaturesComplete = 0x04,
+    LongTermKeyRequest = 0x05,
+    RemoteConnectionParameterRequest = 0x06,
+    DataLengthChange = 0x07,
+    ReadLocalP256PublicKeyComplete = 0x08,
+    GenerateDhKeyComplete = 0x09,
+    EnhancedConnectionComplete = 0x0A,
+    DirectedAdvertisingReport = 0x0B,
+    PhyUpdateComplete = 0x0C,
+    ExtendedAdvertisingReport = 0x0D,
+    PeriodicAdvertisingSyncEstablished = 0x0E,
+    PeriodicAdvertisingReport = 0x0F,
+    PeriodicAdvertisingSyncLost = 0x10,
+    ScanTimeout = 0x11,
+    AdvertisingSetTerminated = 0x12,
+    ScanRequestReceived = 0x13,
+    ChannelSelectionAlgorithm = 0x14,
+}
+
+impl From<u8> for HciLeEventType {
+    fn from(orig: u8) -> Self {
+        match orig {
+            0x01 => HciLeEventType::ConnectionComplete,
+            0x02 => HciLeEventType::AdvertisingReport,
+            0x03 => HciLeEventType::ConnectionUpdateComplete,
+            0x04 => HciLeEventType::ReadRemoteFeaturesComplete,
+            0x05 => HciLeEventType::LongTermKeyRequest,
+            0x06 => HciLeEventType::RemoteConnectionParameterRequest,
+            0x07 => HciLeEventType::DataLengthChange,
+            0x08 => HciLeEventType::ReadLocalP256PublicKeyComplete,
+            0x09 => HciLeEventType::GenerateDhKeyComplete,
+            0x0A => HciLeEventType::EnhancedConnectionComplete,
+            0x0B => HciLeEventType::DirectedAdvertisingReport,
+            0x0C => HciLeEventType::PhyUpdateComplete,
+            0x0D => HciLeEventType::ExtendedAdvertisingReport,
+            0x0E => HciLeEventType::PeriodicAdvertisingSyncEstablished,
+            0x0F => HciLeEventType::PeriodicAdvertisingReport,
+            0x10 => HciLeEventType::PeriodicAdvertisingSyncLost,
+            0x11 => HciLeEventType::ScanTimeout,
+            0x12 => HciLeEventType::AdvertisingSetTerminated,
+            0x13 => HciLeEventType::ScanRequestReceived,
+            0x14 => HciLeEventType::ChannelSelectionAlgorithm,
+            _ => HciLeEventType::Unknown,
+        }
+    }
+}
+
 #[repr(C, packed)]
 #[derive(Copy, Clone, Debug)]
 pub struct HciEventHeader {
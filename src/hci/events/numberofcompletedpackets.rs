@@ -0,0 +1,61 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # HCI Number Of Completed Packets Event
+//!
+
+use crate::alloc::vec::Vec;
+use crate::convert::TryFrom;
+use crate::hci::events::{HciEventHeader, HciEventType};
+use crate::hci::packet::HciPacket;
+
+/// The number of ACL data packets of one connection the BT host has completed
+#[derive(Copy, Clone, Debug)]
+pub struct CompletedPackets {
+    /// connection handle, only the least 12Bit's are used
+    pub handle: u16,
+    pub num_completed: u16,
+}
+
+/// The NumberOfCompletedPackets event is send once the BT host has completed ACL data packets
+/// and freed the buffers that held them
+#[derive(Debug)]
+pub struct HciEventNumberOfCompletedPackets {
+    pub header: HciEventHeader,
+    pub completed: Vec<CompletedPackets>,
+}
+
+impl TryFrom<HciPacket<Vec<u8>>> for HciEventNumberOfCompletedPackets {
+    type Error = HciPacket<Vec<u8>>;
+
+    fn try_from(orig: HciPacket<Vec<u8>>) -> Result<Self, Self::Error> {
+        let raw_event = orig.p_data;
+        if raw_event[0] == HciEventType::NumberOfCompletedPackets as u8 {
+            let num_handles = raw_event[2] as usize;
+            let completed = raw_event[3..]
+                .chunks_exact(4)
+                .take(num_handles)
+                .map(|raw| CompletedPackets {
+                    handle: raw[0] as u16 | (raw[1] as u16) << 8,
+                    num_completed: raw[2] as u16 | (raw[3] as u16) << 8,
+                })
+                .collect();
+            Ok(HciEventNumberOfCompletedPackets {
+                header: HciEventHeader {
+                    evt_code: raw_event[0].into(),
+                    param_length: raw_event[1],
+                },
+                completed,
+            })
+        } else {
+            Err(HciPacket {
+                p_type: orig.p_type,
+                p_data: raw_event,
+            })
+        }
+    }
+}
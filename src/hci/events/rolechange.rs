@@ -0,0 +1,53 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # HCI Role Change Event
+//!
+
+use crate::alloc::vec::Vec;
+use crate::convert::TryFrom;
+use crate::hci::connection::HciConnectionRole;
+use crate::hci::events::{HciEventHeader, HciEventType};
+use crate::hci::packet::HciPacket;
+use crate::hci::BD_ADDRESS_SIZE;
+
+/// The RoleChange event is send once the role of this device within the BR/EDR connection to the
+/// given device has changed
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
+pub struct HciEventRoleChange {
+    pub header: HciEventHeader,
+    pub status: u8,
+    pub address: [u8; BD_ADDRESS_SIZE],
+    pub new_role: HciConnectionRole,
+}
+
+impl TryFrom<HciPacket<Vec<u8>>> for HciEventRoleChange {
+    type Error = HciPacket<Vec<u8>>;
+
+    fn try_from(orig: HciPacket<Vec<u8>>) -> Result<Self, Self::Error> {
+        let raw_event = orig.p_data;
+        if raw_event[0] == HciEventType::RoleChange as u8 {
+            let mut event = HciEventRoleChange {
+                header: HciEventHeader {
+                    evt_code: raw_event[0].into(),
+                    param_length: raw_event[1],
+                },
+                status: raw_event[2],
+                address: [0; BD_ADDRESS_SIZE],
+                new_role: raw_event[9].into(),
+            };
+            event.address.copy_from_slice(&raw_event[3..9]);
+            Ok(event)
+        } else {
+            Err(HciPacket {
+                p_type: orig.p_type,
+                p_data: raw_event,
+            })
+        }
+    }
+}
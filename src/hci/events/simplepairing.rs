@@ -0,0 +1,248 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # HCI Secure Simple Pairing Events
+//!
+//! The events the BT host sends while pairing with a BR/EDR peer with Secure Simple Pairing.
+//!
+
+use crate::alloc::vec::Vec;
+use crate::convert::TryFrom;
+use crate::hci::events::{HciEventHeader, HciEventType};
+use crate::hci::packet::HciPacket;
+use crate::hci::BD_ADDRESS_SIZE;
+
+/// The IoCapabilityRequest event is send once pairing starts. The request need to be answered
+/// with either the reply or the negative reply command.
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
+pub struct HciEventIoCapabilityRequest {
+    pub header: HciEventHeader,
+    pub address: [u8; BD_ADDRESS_SIZE],
+}
+
+impl TryFrom<HciPacket<Vec<u8>>> for HciEventIoCapabilityRequest {
+    type Error = HciPacket<Vec<u8>>;
+
+    fn try_from(orig: HciPacket<Vec<u8>>) -> Result<Self, Self::Error> {
+        let raw_event = orig.p_data;
+        if raw_event[0] == HciEventType::IoCapabilityRequest as u8 && raw_event.len() >= 8 {
+            Ok(HciEventIoCapabilityRequest {
+                header: header(&raw_event),
+                address: address(&raw_event),
+            })
+        } else {
+            Err(HciPacket {
+                p_type: orig.p_type,
+                p_data: raw_event,
+            })
+        }
+    }
+}
+
+/// The IoCapabilityResponse event passes the IO capability of the peer
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
+pub struct HciEventIoCapabilityResponse {
+    pub header: HciEventHeader,
+    pub address: [u8; BD_ADDRESS_SIZE],
+    /// 0x00 display only, 0x01 display yes/no, 0x02 keyboard only, 0x03 no input no output
+    pub io_capability: u8,
+    pub oob_data_present: u8,
+    pub authentication_requirements: u8,
+}
+
+impl TryFrom<HciPacket<Vec<u8>>> for HciEventIoCapabilityResponse {
+    type Error = HciPacket<Vec<u8>>;
+
+    fn try_from(orig: HciPacket<Vec<u8>>) -> Result<Self, Self::Error> {
+        let raw_event = orig.p_data;
+        if raw_event[0] == HciEventType::IoCapabilityResponse as u8 && raw_event.len() >= 11 {
+            Ok(HciEventIoCapabilityResponse {
+                header: header(&raw_event),
+                address: address(&raw_event),
+                io_capability: raw_event[8],
+                oob_data_present: raw_event[9],
+                authentication_requirements: raw_event[10],
+            })
+        } else {
+            Err(HciPacket {
+                p_type: orig.p_type,
+                p_data: raw_event,
+            })
+        }
+    }
+}
+
+/// The UserConfirmationRequest event is send if the user need to confirm the numeric value
+/// displayed on both devices, or if the pairing without authentication need to be authorized.
+/// The request need to be answered with either the reply or the negative reply command.
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
+pub struct HciEventUserConfirmationRequest {
+    pub header: HciEventHeader,
+    pub address: [u8; BD_ADDRESS_SIZE],
+    pub numeric_value: u32,
+}
+
+impl TryFrom<HciPacket<Vec<u8>>> for HciEventUserConfirmationRequest {
+    type Error = HciPacket<Vec<u8>>;
+
+    fn try_from(orig: HciPacket<Vec<u8>>) -> Result<Self, Self::Error> {
+        let raw_event = orig.p_data;
+        if raw_event[0] == HciEventType::UserConfirmationRequest as u8 && raw_event.len() >= 12 {
+            Ok(HciEventUserConfirmationRequest {
+                header: header(&raw_event),
+                address: address(&raw_event),
+                numeric_value: value(&raw_event),
+            })
+        } else {
+            Err(HciPacket {
+                p_type: orig.p_type,
+                p_data: raw_event,
+            })
+        }
+    }
+}
+
+/// The UserPasskeyRequest event is send if the user need to enter the passkey displayed by the
+/// peer. The request need to be answered with either the reply or the negative reply command.
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
+pub struct HciEventUserPasskeyRequest {
+    pub header: HciEventHeader,
+    pub address: [u8; BD_ADDRESS_SIZE],
+}
+
+impl TryFrom<HciPacket<Vec<u8>>> for HciEventUserPasskeyRequest {
+    type Error = HciPacket<Vec<u8>>;
+
+    fn try_from(orig: HciPacket<Vec<u8>>) -> Result<Self, Self::Error> {
+        let raw_event = orig.p_data;
+        if raw_event[0] == HciEventType::UserPasskeyRequest as u8 && raw_event.len() >= 8 {
+            Ok(HciEventUserPasskeyRequest {
+                header: header(&raw_event),
+                address: address(&raw_event),
+            })
+        } else {
+            Err(HciPacket {
+                p_type: orig.p_type,
+                p_data: raw_event,
+            })
+        }
+    }
+}
+
+/// The UserPasskeyNotification event is send if the passkey need to be displayed to the user,
+/// who enters it on the peer
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
+pub struct HciEventUserPasskeyNotification {
+    pub header: HciEventHeader,
+    pub address: [u8; BD_ADDRESS_SIZE],
+    pub passkey: u32,
+}
+
+impl TryFrom<HciPacket<Vec<u8>>> for HciEventUserPasskeyNotification {
+    type Error = HciPacket<Vec<u8>>;
+
+    fn try_from(orig: HciPacket<Vec<u8>>) -> Result<Self, Self::Error> {
+        let raw_event = orig.p_data;
+        if raw_event[0] == HciEventType::UserPasskeyNotification as u8 && raw_event.len() >= 12 {
+            Ok(HciEventUserPasskeyNotification {
+                header: header(&raw_event),
+                address: address(&raw_event),
+                passkey: value(&raw_event),
+            })
+        } else {
+            Err(HciPacket {
+                p_type: orig.p_type,
+                p_data: raw_event,
+            })
+        }
+    }
+}
+
+/// The SimplePairingComplete event is send once the pairing with the peer has finished
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
+pub struct HciEventSimplePairingComplete {
+    pub header: HciEventHeader,
+    pub status: u8,
+    pub address: [u8; BD_ADDRESS_SIZE],
+}
+
+impl TryFrom<HciPacket<Vec<u8>>> for HciEventSimplePairingComplete {
+    type Error = HciPacket<Vec<u8>>;
+
+    fn try_from(orig: HciPacket<Vec<u8>>) -> Result<Self, Self::Error> {
+        let raw_event = orig.p_data;
+        if raw_event[0] == HciEventType::SimplePairingComplete as u8 && raw_event.len() >= 9 {
+            let mut address = [0; BD_ADDRESS_SIZE];
+            address.copy_from_slice(&raw_event[3..9]);
+            Ok(HciEventSimplePairingComplete {
+                header: header(&raw_event),
+                status: raw_event[2],
+                address,
+            })
+        } else {
+            Err(HciPacket {
+                p_type: orig.p_type,
+                p_data: raw_event,
+            })
+        }
+    }
+}
+
+/// The AuthenticationComplete event is send to the side that initiated the authentication of a
+/// BR/EDR connection once it has finished, which includes pairing if there was no link key
+#[repr(C, packed)]
+#[derive(Copy, Clone, Debug)]
+pub struct HciEventAuthenticationComplete {
+    pub header: HciEventHeader,
+    pub status: u8,
+    pub handle: u16,
+}
+
+impl TryFrom<HciPacket<Vec<u8>>> for HciEventAuthenticationComplete {
+    type Error = HciPacket<Vec<u8>>;
+
+    fn try_from(orig: HciPacket<Vec<u8>>) -> Result<Self, Self::Error> {
+        let raw_event = orig.p_data;
+        if raw_event[0] == HciEventType::AuthenticationComplete as u8 && raw_event.len() >= 5 {
+            Ok(HciEventAuthenticationComplete {
+                header: header(&raw_event),
+                status: raw_event[2],
+                handle: (raw_event[4] as u16) << 8 | raw_event[3] as u16,
+            })
+        } else {
+            Err(HciPacket {
+                p_type: orig.p_type,
+                p_data: raw_event,
+            })
+        }
+    }
+}
+
+fn header(raw_event: &[u8]) -> HciEventHeader {
+    HciEventHeader {
+        evt_code: raw_event[0].into(),
+        param_length: raw_event[1],
+    }
+}
+
+/// The address of the peer, the first parameter of most events
+fn address(raw_event: &[u8]) -> [u8; BD_ADDRESS_SIZE] {
+    let mut address = [0; BD_ADDRESS_SIZE];
+    address.copy_from_slice(&raw_event[2..8]);
+    address
+}
+
+/// The numeric value or passkey following the address
+fn value(raw_event: &[u8]) -> u32 {
+    u32::from_le_bytes([raw_event[8], raw_event[9], raw_event[10], raw_event[11]])
+}
@@ -10,6 +10,7 @@
 use crate::alloc::boxed::Box;
 use crate::alloc::collections::BTreeMap;
 use crate::alloc::sync::Arc;
+use crate::alloc::vec;
 use crate::alloc::vec::Vec;
 use crate::brain::{waker::*, *};
 use crate::convert::TryFrom;
@@ -25,11 +26,18 @@ pub mod commands;
 pub mod packet;
 use packet::HciPacketType;
 pub mod events;
-use events::HciEventType;
+use events::{HciEventType, HciLeEventType};
 pub mod errors;
 mod firmware;
 mod inquiry;
 pub mod connection;
+pub mod advertising;
+use advertising::AdvertisingHandle;
+pub mod scanning;
+pub mod periodicsync;
+pub mod privacy;
+pub mod acl;
+mod p256;
 
 mod init;
 use init::*;
@@ -48,10 +56,23 @@ pub struct Hci<T: HcTransportLayer + 'static> {
     transport_layer: Option<Box<T>>,
     accept_commands: AtomicU8, //Semaphore,
     recv_waker: Option<Waker>,
-    //command_response_waker: Vec<Waker>,
-    //command_to_send_waker: Vec<Waker>,
-    command_response: BTreeMap<commands::HciCommand, (Waker, Option<packet::HciPacket<Vec<u8>>>)>,
+    /// the commands passed to the BT host and those waiting to be passed
+    command_queue: commands::CommandQueue,
     event_notify: BTreeMap<events::HciEventType, (Waker, Option<packet::HciPacket<Vec<u8>>>)>,
+    le_event_notify: BTreeMap<events::HciLeEventType, (Waker, Option<packet::HciPacket<Vec<u8>>>)>,
+    /// the LE features supported by the BT host, once read from it
+    le_features: Option<u64>,
+    advertising_sets: advertising::AdvertisingSets,
+    extended_scan: scanning::ExtendedScan,
+    periodic_syncs: periodicsync::PeriodicSyncs,
+    privacy: privacy::PrivacyState,
+    le_connection_parameters: connection::LeConnectionParameterState,
+    le_encryption: connection::LeEncryptionState,
+    link_keys: connection::LinkKeyState,
+    simple_pairing: connection::SimplePairingState,
+    connections: connection::ConnectionRegistry,
+    connection_request_policy: Option<connection::ConnectionRequestPolicy>,
+    acl: acl::AclState,
 }
 
 impl<T: HcTransportLayer + 'static> Hci<T> {
@@ -62,10 +83,21 @@ impl<T: HcTransportLayer + 'static> Hci<T> {
             // initially the host accepts 1 packet at a time
             accept_commands: AtomicU8::new(1), //Semaphore::new(1),
             recv_waker: None,
-            //command_response_waker: Vec::new(),
-            //command_to_send_waker: Vec::new(),
-            command_response: BTreeMap::new(),
+            command_queue: commands::CommandQueue::new(),
             event_notify: BTreeMap::new(),
+            le_event_notify: BTreeMap::new(),
+            le_features: None,
+            advertising_sets: advertising::AdvertisingSets::new(),
+            extended_scan: scanning::ExtendedScan::new(),
+            periodic_syncs: periodicsync::PeriodicSyncs::new(),
+            privacy: privacy::PrivacyState::new(),
+            le_connection_parameters: connection::LeConnectionParameterState::new(),
+            le_encryption: connection::LeEncryptionState::new(),
+            link_keys: connection::LinkKeyState::new(),
+            simple_pairing: connection::SimplePairingState::new(),
+            connections: connection::ConnectionRegistry::new(),
+            connection_request_policy: None,
+            acl: acl::AclState::new(),
         }));
 
         let hci_clone = hci.clone();
@@ -96,6 +128,89 @@ impl<T: HcTransportLayer + 'static> Hci<T> {
         RecvPacketThinkable::new(this)
     }
 
+    /// Set the policy deciding on inbound connection requests handled by the Thinkable returned
+    /// from [Hci::serve_connections]. Passing ``None`` accepts any request in the slave role.
+    pub fn set_connection_request_policy(
+        this: Arc<DataLock<Self>>,
+        policy: Option<connection::ConnectionRequestPolicy>,
+    ) {
+        this.lock().connection_request_policy = policy;
+    }
+
+    /// Set the store keeping the link keys of the BR/EDR peers. The link key requests of the BT
+    /// host are answered from this store and new link keys are stored there. Without a store set
+    /// the keys are kept in memory only.
+    pub fn set_bond_store(this: Arc<DataLock<Self>>, store: crate::bond::SharedBondStore) {
+        this.lock().link_keys.set_store(store);
+    }
+
+    /// Set the agent declaring the IO capability of this device and interacting with the user
+    /// while pairing with BR/EDR peers. Passing ``None`` rejects the pairing requests.
+    pub fn set_pairing_agent(
+        this: Arc<DataLock<Self>>,
+        agent: Option<crate::agent::SharedPairingAgent>,
+    ) {
+        this.lock().simple_pairing.set_agent(agent);
+    }
+
+    /// Enable Secure Simple Pairing with BR/EDR peers
+    pub fn enable_simple_pairing(
+        this: Arc<DataLock<Self>>,
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        Self::send_command(this, commands::HciCommandWriteSimplePairingMode::new(true))
+    }
+
+    /// Pass whether the user confirmed the numeric value or authorized the pairing with the
+    /// BR/EDR peer with the given address after the pairing agent deferred the decision
+    pub fn reply_user_confirmation(
+        this: Arc<DataLock<Self>>,
+        address: [u8; BD_ADDRESS_SIZE],
+        confirmed: bool,
+    ) -> commands::CommandThinkable {
+        if confirmed {
+            Box::pin(Self::send_command(
+                this,
+                commands::HciCommandUserConfirmationRequestReply::new(address),
+            ))
+        } else {
+            Box::pin(Self::send_command(
+                this,
+                commands::HciCommandUserConfirmationRequestNegativeReply::new(address),
+            ))
+        }
+    }
+
+    /// Pass the passkey entered by the user while pairing with the BR/EDR peer with the given
+    /// address after the pairing agent deferred the entry. Passing ``None`` cancels the pairing.
+    /// A passkey above 999999 cancels the pairing as well and concludes with an error.
+    pub fn reply_user_passkey(
+        this: Arc<DataLock<Self>>,
+        address: [u8; BD_ADDRESS_SIZE],
+        passkey: Option<u32>,
+    ) -> commands::CommandThinkable {
+        match passkey {
+            Some(passkey) if passkey > 999_999 => Box::pin(
+                Self::send_command(
+                    this,
+                    commands::HciCommandUserPasskeyRequestNegativeReply::new(address),
+                )
+                .map(|result| {
+                    result?;
+                    let error = errors::HciParameterError::new("passkey above 999999");
+                    Err(Box::new(error) as BoxError)
+                }),
+            ),
+            Some(passkey) => Box::pin(Self::send_command(
+                this,
+                commands::HciCommandUserPasskeyRequestReply::new(address, passkey),
+            )),
+            None => Box::pin(Self::send_command(
+                this,
+                commands::HciCommandUserPasskeyRequestNegativeReply::new(address),
+            )),
+        }
+    }
+
     pub fn serve_connections(this: Arc<DataLock<Self>>) -> impl Thinkable<Output = ()> {
         info!("start connections thinkable");
         connection::HandleInboundConnectionsThinkable::new(this)
@@ -105,6 +220,31 @@ impl<T: HcTransportLayer + 'static> Hci<T> {
         Self::send_command(this, commands::HciCommandReset::new())
     }
 
+    /// Enable the LE features of the BT host. This enables the LE host support and configures the
+    /// event masks so that the BT host sends the LE Meta events with all LE sub events as well as
+    /// the events of Secure Simple Pairing
+    pub fn enable_le(this: Arc<DataLock<Self>>) -> impl Thinkable<Output = Result<(), BoxError>> {
+        commands::CommandChainThinkable::new(vec![
+            Box::pin(Self::send_command(
+                this.clone(),
+                commands::HciCommandWriteLeHostSupport::new(true),
+            )),
+            Box::pin(Self::send_command(
+                this.clone(),
+                commands::HciCommandSetEventMask::new(
+                    commands::EVENT_MASK_DEFAULT
+                        | commands::EVENT_MASK_ENCRYPTION_KEY_REFRESH
+                        | commands::EVENT_MASK_SIMPLE_PAIRING
+                        | commands::EVENT_MASK_LE_META,
+                ),
+            )),
+            Box::pin(Self::send_command(
+                this,
+                commands::HciCommandLeSetEventMask::new(commands::LE_EVENT_MASK_ALL),
+            )),
+        ])
+    }
+
     pub fn upload_firmware(
         this: Arc<DataLock<Self>>,
     ) -> impl Thinkable<Output = Result<(), BoxError>> {
@@ -142,12 +282,645 @@ impl<T: HcTransportLayer + 'static> Hci<T> {
         inquiry::InquireDevicesThinkable::new(this, commands::InquiryLength::Sec(5))
     }
 
+    /// Read the public device address of the BT host
+    pub fn read_bd_address(
+        this: Arc<DataLock<Self>>,
+    ) -> impl Thinkable<Output = Result<[u8; BD_ADDRESS_SIZE], BoxError>> {
+        Self::send_command_with_result(this, commands::HciCommandReadBdAddr::new()).map(|result| {
+            let parameters = result?;
+            if parameters.len() < BD_ADDRESS_SIZE {
+                return Err(Box::new(errors::HciError {}) as BoxError);
+            }
+            let mut address = [0; BD_ADDRESS_SIZE];
+            address.copy_from_slice(&parameters[..BD_ADDRESS_SIZE]);
+            Ok(address)
+        })
+    }
+
+    /// Encrypt 16 bytes of plaintext with the given key using the AES-128 block cipher of the
+    /// BT host. Key, plaintext and the encrypted data concluded with are little endian.
+    pub fn encrypt(
+        this: Arc<DataLock<Self>>,
+        key: [u8; 16],
+        plaintext: [u8; 16],
+    ) -> impl Thinkable<Output = Result<[u8; 16], BoxError>> {
+        Self::send_command_with_result(this, commands::HciCommandLeEncrypt::new(key, plaintext))
+            .map(|result| {
+                let parameters = result?;
+                if parameters.len() < 16 {
+                    return Err(Box::new(errors::HciError {}) as BoxError);
+                }
+                let mut encrypted = [0; 16];
+                encrypted.copy_from_slice(&parameters[..16]);
+                Ok(encrypted)
+            })
+    }
+
+    /// Get 8 random bytes from the random number generator of the BT host
+    pub fn rand(this: Arc<DataLock<Self>>) -> impl Thinkable<Output = Result<[u8; 8], BoxError>> {
+        Self::send_command_with_result(this, commands::HciCommandLeRand::new()).map(|result| {
+            let parameters = result?;
+            if parameters.len() < 8 {
+                return Err(Box::new(errors::HciError {}) as BoxError);
+            }
+            let mut random = [0; 8];
+            random.copy_from_slice(&parameters[..8]);
+            Ok(random)
+        })
+    }
+
+    /// Returns a ``Thinkable`` that concludes with the next request of the BT host for the long
+    /// term key of an LE connection in the peripheral role. Each request need to be answered
+    /// with [Hci::reply_long_term_key_request].
+    pub fn next_long_term_key_request(
+        this: Arc<DataLock<Self>>,
+    ) -> connection::NextLongTermKeyRequestThinkable<T> {
+        connection::NextLongTermKeyRequestThinkable::new(this)
+    }
+
+    /// Answer the request for the long term key of the connection with the given handle. Passing
+    /// ``None`` rejects the request as there is no key for the connection.
+    pub fn reply_long_term_key_request(
+        this: Arc<DataLock<Self>>,
+        handle: u16,
+        ltk: Option<[u8; 16]>,
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        let command: commands::CommandThinkable = match ltk {
+            Some(ltk) => Box::pin(Self::send_command(
+                this,
+                commands::HciCommandLeLongTermKeyRequestReply::new(handle, ltk),
+            )),
+            None => Box::pin(Self::send_command(
+                this,
+                commands::HciCommandLeLongTermKeyRequestNegativeReply::new(handle),
+            )),
+        };
+        command
+    }
+
+    /// Let the BT host create a new P-256 key pair and read its public key. The X and Y
+    /// coordinates of the key are little endian, the X coordinate first.
+    pub fn read_local_p256_public_key(
+        this: Arc<DataLock<Self>>,
+    ) -> impl Thinkable<Output = Result<[u8; 64], BoxError>> {
+        let command = Box::pin(Self::send_command(
+            this.clone(),
+            commands::HciCommandLeReadLocalP256PublicKey::new(),
+        ));
+        p256::P256Thinkable::new(this, HciLeEventType::ReadLocalP256PublicKeyComplete, command)
+            .map(|result| {
+                match events::HciEventLeReadLocalP256PublicKeyComplete::try_from(result?) {
+                    Ok(complete) if complete.status == 0x00 => Ok(complete.public_key),
+                    _ => Err(Box::new(errors::HciError {}) as BoxError),
+                }
+            })
+    }
+
+    /// Calculate the Diffie-Hellman key of the remote public key and the private key of the
+    /// key pair created with [Hci::read_local_p256_public_key]. The key is little endian.
+    pub fn generate_dhkey(
+        this: Arc<DataLock<Self>>,
+        remote_public_key: [u8; 64],
+    ) -> impl Thinkable<Output = Result<[u8; 32], BoxError>> {
+        let command = Box::pin(Self::send_command(
+            this.clone(),
+            commands::HciCommandLeGenerateDhKey::new(remote_public_key),
+        ));
+        p256::P256Thinkable::new(this, HciLeEventType::GenerateDhKeyComplete, command).map(
+            |result| match events::HciEventLeGenerateDhKeyComplete::try_from(result?) {
+                Ok(complete) if complete.status == 0x00 => Ok(complete.dhkey),
+                _ => Err(Box::new(errors::HciError {}) as BoxError),
+            },
+        )
+    }
+
+    /// Read the LE features supported by the BT host. The features are also kept to decide which
+    /// commands are used for operations that are supported with different commands.
+    pub fn read_le_local_supported_features(
+        this: Arc<DataLock<Self>>,
+    ) -> impl Thinkable<Output = Result<u64, BoxError>> {
+        let this_clone = this.clone();
+        let command = commands::HciCommandLeReadLocalSupportedFeatures::new();
+        Self::send_command_with_result(this, command)
+            .map(move |result| {
+                let parameters = result?;
+                if parameters.len() < 8 {
+                    return Err(Box::new(errors::HciError {}) as BoxError);
+                }
+                let features = parameters[..8]
+                    .iter()
+                    .rev()
+                    .fold(0u64, |features, byte| features << 8 | *byte as u64);
+                this_clone.lock().le_features.replace(features);
+                Ok(features)
+            })
+    }
+
+    /// Initiate an LE connection to the given peer device. This concludes with the connection
+    /// once the LE ConnectionComplete event has been received. The attempt can be cancelled with
+    /// [Hci::cancel_connect_le], which lets this conclude with an error.
+    pub fn connect_le(
+        this: Arc<DataLock<Self>>,
+        peer_address_type: commands::PeerAddressType,
+        peer_address: [u8; BD_ADDRESS_SIZE],
+        parameters: commands::LeConnectionParameters,
+    ) -> impl Thinkable<Output = Result<connection::Connection<T>, BoxError>> {
+        connection::ConnectLeThinkable::new(this, peer_address_type, peer_address, parameters)
+    }
+
+    /// Cancel the pending LE connection attempt
+    pub fn cancel_connect_le(
+        this: Arc<DataLock<Self>>,
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        Self::send_command(this, commands::HciCommandLeCreateConnectionCancel::new())
+    }
+
+    /// Read the size and number of the ACL buffers of the BT host for BR/EDR and LE. The ACL data
+    /// send is fragmented to fit into those buffers and only as many packets are passed to the
+    /// BT host as it has free buffers. Until the buffers are read only one packet at a time is
+    /// passed to the BT host.
+    pub fn read_acl_buffer_size(
+        this: Arc<DataLock<Self>>,
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        commands::CommandChainThinkable::new(vec![
+            Box::pin(acl::read_bredr_buffer_size(this.clone())),
+            Box::pin(acl::read_le_buffer_size(this)),
+        ])
+    }
+
+    /// Enable the flow control of the ACL data send from the BT host. The BT host only sends as
+    /// many ACL data packets with the given maximum data length as buffers are announced. The
+    /// buffers are released once the PDU's received have been taken from their connection, the
+    /// L2CAP queues they are passed on to are bounded and drop the oldest data once full.
+    pub fn enable_host_flow_control(
+        this: Arc<DataLock<Self>>,
+        acl_data_length: u16,
+        num_packets: u16,
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        // the host buffer is recorded before the flow control is enabled, so the packets received
+        // right after enabling it are released as well
+        let buffer_this = this.clone();
+        let enable_this = this.clone();
+        let buffer_size: commands::CommandThinkable = Box::pin(
+            Self::send_command(
+                this.clone(),
+                commands::HciCommandHostBufferSize::new(acl_data_length, num_packets),
+            )
+            .map(move |result| {
+                if result.is_ok() {
+                    buffer_this.lock().acl.set_host_buffer(Some(acl::AclBufferSize {
+                        max_data_length: acl_data_length,
+                        num_packets,
+                    }));
+                }
+                result
+            }),
+        );
+        let enable: commands::CommandThinkable = Box::pin(
+            Self::send_command(
+                this,
+                commands::HciCommandSetControllerToHostFlowControl::new(
+                    commands::HOST_FLOW_CONTROL_ACL,
+                ),
+            )
+            .map(move |result| {
+                if result.is_err() {
+                    enable_this.lock().acl.set_host_buffer(None);
+                }
+                result
+            }),
+        );
+        commands::CommandChainThinkable::new(vec![buffer_size, enable])
+    }
+
+    /// Disable the flow control of the ACL data send from the BT host
+    pub fn disable_host_flow_control(
+        this: Arc<DataLock<Self>>,
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        let this_clone = this.clone();
+        Self::send_command(
+            this,
+            commands::HciCommandSetControllerToHostFlowControl::new(commands::HOST_FLOW_CONTROL_OFF),
+        )
+        .map(move |result| {
+            if result.is_ok() {
+                this_clone.lock().acl.set_host_buffer(None);
+            }
+            result
+        })
+    }
+
+    /// Returns a ``Thinkable`` that concludes with the next connection created by a peer. This
+    /// covers inbound BR/EDR connections as well as LE connections created while advertising.
+    pub fn next_connection(
+        this: Arc<DataLock<Self>>,
+    ) -> impl Thinkable<Output = connection::Connection<T>> {
+        connection::NextConnectionThinkable::new(this)
+    }
+
+    /// Get the connection with the given handle if it is established
+    pub fn connection(
+        this: Arc<DataLock<Self>>,
+        handle: u16,
+    ) -> Option<connection::Connection<T>> {
+        let state = this.read().connections.get(handle);
+        state.map(|state| connection::Connection::new(this, state))
+    }
+
+    /// The details of all connections currently established
+    pub fn connections(this: Arc<DataLock<Self>>) -> Vec<connection::ConnectionInfo> {
+        this.read().connections.infos()
+    }
+
+    /// Set the policy deciding whether connection parameters requested by a peer are accepted.
+    /// Passing ``None`` accepts any request.
+    pub fn set_connection_parameter_policy(
+        this: Arc<DataLock<Self>>,
+        policy: Option<connection::ConnectionParameterPolicy>,
+    ) {
+        this.lock().le_connection_parameters.set_policy(policy);
+    }
+
+    /// Read the number of devices the filter accept list of the BT host is able to hold
+    pub fn read_filter_accept_list_size(
+        this: Arc<DataLock<Self>>,
+    ) -> impl Thinkable<Output = Result<u8, BoxError>> {
+        Self::send_command_with_result(this, commands::HciCommandLeReadFilterAcceptListSize::new())
+            .map(|result| {
+                let parameters = result?;
+                if parameters.is_empty() {
+                    return Err(Box::new(errors::HciError {}) as BoxError);
+                }
+                Ok(parameters[0])
+            })
+    }
+
+    /// Remove all devices from the filter accept list
+    pub fn clear_filter_accept_list(
+        this: Arc<DataLock<Self>>,
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        Self::send_command(this, commands::HciCommandLeClearFilterAcceptList::new())
+    }
+
+    /// Add a device to the filter accept list. Only the devices in this list are accepted when
+    /// advertising, scanning or initiating connections uses a filter policy based on the filter
+    /// accept list, e.g. to only let known peers connect or be reported.
+    pub fn add_device_to_filter_accept_list(
+        this: Arc<DataLock<Self>>,
+        address_type: commands::FilterAcceptListAddressType,
+        address: [u8; BD_ADDRESS_SIZE],
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        Self::send_command(
+            this,
+            commands::HciCommandLeAddDeviceToFilterAcceptList::new(address_type, address),
+        )
+    }
+
+    /// Remove a device from the filter accept list
+    pub fn remove_device_from_filter_accept_list(
+        this: Arc<DataLock<Self>>,
+        address_type: commands::FilterAcceptListAddressType,
+        address: [u8; BD_ADDRESS_SIZE],
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        Self::send_command(
+            this,
+            commands::HciCommandLeRemoveDeviceFromFilterAcceptList::new(address_type, address),
+        )
+    }
+
+    /// The random address of the BT host set last, ``None`` if it has not been set
+    pub fn random_address(this: Arc<DataLock<Self>>) -> Option<[u8; BD_ADDRESS_SIZE]> {
+        this.read().privacy.random_address()
+    }
+
+    /// Set the random address the BT host uses when scanning or initiating connections with a
+    /// random own address
+    pub fn set_random_address(
+        this: Arc<DataLock<Self>>,
+        address: [u8; BD_ADDRESS_SIZE],
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        privacy::set_random_address(this, address)
+    }
+
+    /// Generate a static random address. The address shall only change with a power cycle, so it
+    /// need to be kept if it is set as the identity address of this device.
+    pub fn generate_static_address(
+        this: Arc<DataLock<Self>>,
+    ) -> impl Thinkable<Output = Result<[u8; BD_ADDRESS_SIZE], BoxError>> {
+        privacy::generate_random_address(this, privacy::RandomAddressType::Static, [0; 16])
+    }
+
+    /// Generate a non-resolvable private address
+    pub fn generate_non_resolvable_address(
+        this: Arc<DataLock<Self>>,
+    ) -> impl Thinkable<Output = Result<[u8; BD_ADDRESS_SIZE], BoxError>> {
+        privacy::generate_random_address(this, privacy::RandomAddressType::NonResolvable, [0; 16])
+    }
+
+    /// Generate a resolvable private address with the identity resolving key of this device
+    pub fn generate_resolvable_address(
+        this: Arc<DataLock<Self>>,
+        irk: [u8; 16],
+    ) -> impl Thinkable<Output = Result<[u8; BD_ADDRESS_SIZE], BoxError>> {
+        privacy::generate_random_address(this, privacy::RandomAddressType::Resolvable, irk)
+    }
+
+    /// Use resolvable private addresses generated with the identity resolving key of this device
+    /// as random address. A new address is set right away, which this concludes with, and then
+    /// every ``timeout`` seconds until [Hci::stop_address_rotation] is called. Starting the
+    /// rotation again replaces the current one.
+    pub fn start_address_rotation(
+        this: Arc<DataLock<Self>>,
+        irk: [u8; 16],
+        timeout: u16,
+    ) -> impl Thinkable<Output = Result<[u8; BD_ADDRESS_SIZE], BoxError>> {
+        privacy::start_address_rotation(this, irk, timeout)
+    }
+
+    /// Stop the rotation of resolvable private addresses, the current address is kept
+    pub fn stop_address_rotation(this: Arc<DataLock<Self>>) {
+        this.lock().privacy.next_rotation();
+    }
+
+    /// Read the number of devices the resolving list of the BT host is able to hold
+    pub fn read_resolving_list_size(
+        this: Arc<DataLock<Self>>,
+    ) -> impl Thinkable<Output = Result<u8, BoxError>> {
+        Self::send_command_with_result(this, commands::HciCommandLeReadResolvingListSize::new())
+            .map(|result| {
+                let parameters = result?;
+                if parameters.is_empty() {
+                    return Err(Box::new(errors::HciError {}) as BoxError);
+                }
+                Ok(parameters[0])
+            })
+    }
+
+    /// Remove all devices from the resolving list
+    pub fn clear_resolving_list(
+        this: Arc<DataLock<Self>>,
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        Self::send_command(this, commands::HciCommandLeClearResolvingList::new())
+    }
+
+    /// Add a device to the resolving list with its identity address and its identity resolving
+    /// key. With address resolution enabled the BT host resolves the addresses of the device and
+    /// uses resolvable private addresses generated with the local identity resolving key towards
+    /// it, if the own address type asks for them.
+    pub fn add_device_to_resolving_list(
+        this: Arc<DataLock<Self>>,
+        address_type: commands::IdentityAddressType,
+        address: [u8; BD_ADDRESS_SIZE],
+        peer_irk: [u8; 16],
+        local_irk: [u8; 16],
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        Self::send_command(
+            this,
+            commands::HciCommandLeAddDeviceToResolvingList::new(
+                address_type,
+                address,
+                peer_irk,
+                local_irk,
+            ),
+        )
+    }
+
+    /// Add all peers of the bond store that distributed their identity to the resolving list
+    pub fn add_bonds_to_resolving_list(
+        this: Arc<DataLock<Self>>,
+        store: crate::bond::SharedBondStore,
+        local_irk: [u8; 16],
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        privacy::add_bonds_to_resolving_list(this, store, local_irk)
+    }
+
+    /// Remove a device from the resolving list
+    pub fn remove_device_from_resolving_list(
+        this: Arc<DataLock<Self>>,
+        address_type: commands::IdentityAddressType,
+        address: [u8; BD_ADDRESS_SIZE],
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        Self::send_command(
+            this,
+            commands::HciCommandLeRemoveDeviceFromResolvingList::new(address_type, address),
+        )
+    }
+
+    /// Enable or disable the resolution of resolvable private addresses by the BT host. Peers
+    /// resolved are reported and connected with the identity address types.
+    pub fn set_address_resolution_enable(
+        this: Arc<DataLock<Self>>,
+        enable: bool,
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        Self::send_command(this, commands::HciCommandLeSetAddressResolutionEnable::new(enable))
+    }
+
+    /// Set the time in seconds the BT host uses a resolvable private address it has generated
+    /// for a device of the resolving list
+    pub fn set_resolvable_private_address_timeout(
+        this: Arc<DataLock<Self>>,
+        timeout: u16,
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        Self::send_command(
+            this,
+            commands::HciCommandLeSetResolvablePrivateAddressTimeout::new(timeout),
+        )
+    }
+
+    /// Set the privacy mode of a device in the resolving list. Peers that advertise with their
+    /// identity address are only accepted in the device privacy mode.
+    pub fn set_privacy_mode(
+        this: Arc<DataLock<Self>>,
+        address_type: commands::IdentityAddressType,
+        address: [u8; BD_ADDRESS_SIZE],
+        mode: commands::PrivacyMode,
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        Self::send_command(
+            this,
+            commands::HciCommandLeSetPrivacyMode::new(address_type, address, mode),
+        )
+    }
+
+    /// Read the maximum length of advertising data the BT host supports for one advertising set
+    pub fn read_max_advertising_data_length(
+        this: Arc<DataLock<Self>>,
+    ) -> impl Thinkable<Output = Result<u16, BoxError>> {
+        advertising::read_max_advertising_data_length(this)
+    }
+
+    /// Read the number of advertising sets the BT host is able to run at the same time
+    pub fn read_number_of_supported_advertising_sets(
+        this: Arc<DataLock<Self>>,
+    ) -> impl Thinkable<Output = Result<u8, BoxError>> {
+        advertising::read_number_of_supported_advertising_sets(this)
+    }
+
+    /// Create a new advertising set with the given parameters, advertising data and scan response
+    /// data. The data is fragmented as required. Empty data is not passed to the BT host.
+    /// The advertising set need to be enabled with [Hci::enable_advertising_sets] to start
+    /// advertising. This concludes with the handle of the advertising set created.
+    pub fn create_advertising_set(
+        this: Arc<DataLock<Self>>,
+        parameters: commands::AdvertisingParameters,
+        advertising_data: Vec<u8>,
+        scan_response_data: Vec<u8>,
+    ) -> impl Thinkable<Output = Result<AdvertisingHandle, BoxError>> {
+        advertising::create_advertising_set(this, parameters, advertising_data, scan_response_data)
+    }
+
+    /// Replace the advertising data of an existing advertising set
+    pub fn set_advertising_data(
+        this: Arc<DataLock<Self>>,
+        handle: AdvertisingHandle,
+        data: Vec<u8>,
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        advertising::set_advertising_data(this, handle, data, false)
+    }
+
+    /// Replace the scan response data of an existing advertising set
+    pub fn set_scan_response_data(
+        this: Arc<DataLock<Self>>,
+        handle: AdvertisingHandle,
+        data: Vec<u8>,
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        advertising::set_advertising_data(this, handle, data, true)
+    }
+
+    /// Set the random address used by an advertising set that is configured to use a random
+    /// own address
+    pub fn set_advertising_set_random_address(
+        this: Arc<DataLock<Self>>,
+        handle: AdvertisingHandle,
+        address: [u8; BD_ADDRESS_SIZE],
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        Self::send_command(
+            this,
+            commands::HciCommandLeSetAdvertisingSetRandomAddress::new(handle, address),
+        )
+    }
+
+    /// Start advertising of the given advertising sets
+    pub fn enable_advertising_sets(
+        this: Arc<DataLock<Self>>,
+        sets: Vec<commands::AdvertisingSetEnable>,
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        advertising::enable_advertising_sets(this, sets)
+    }
+
+    /// Stop advertising of the given advertising sets. Passing an empty list stops all
+    /// advertising sets.
+    pub fn disable_advertising_sets(
+        this: Arc<DataLock<Self>>,
+        handles: Vec<AdvertisingHandle>,
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        advertising::disable_advertising_sets(this, handles)
+    }
+
+    /// Remove an advertising set from the BT host. The set need to be disabled.
+    pub fn remove_advertising_set(
+        this: Arc<DataLock<Self>>,
+        handle: AdvertisingHandle,
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        advertising::remove_advertising_set(this, handle)
+    }
+
+    /// Remove all advertising sets from the BT host. All sets need to be disabled.
+    pub fn clear_advertising_sets(
+        this: Arc<DataLock<Self>>,
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        advertising::clear_advertising_sets(this)
+    }
+
+    /// Configure the parameters used for extended scanning
+    pub fn set_extended_scan_parameters(
+        this: Arc<DataLock<Self>>,
+        parameters: commands::ExtendedScanParameters,
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        Self::send_command(
+            this,
+            commands::HciCommandLeSetExtendedScanParameters::new(&parameters),
+        )
+    }
+
+    /// Start extended scanning. The scanning stops after the given duration in units of 10ms.
+    /// A duration of 0 scans until [Hci::stop_extended_scan] is called. The advertising found is
+    /// handed out with [Hci::next_advertising_report].
+    pub fn start_extended_scan(
+        this: Arc<DataLock<Self>>,
+        filter_duplicates: commands::ScanFilterDuplicates,
+        duration: u16,
+        period: u16,
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        scanning::start_extended_scan(this, filter_duplicates, duration, period)
+    }
+
+    /// Stop extended scanning
+    pub fn stop_extended_scan(
+        this: Arc<DataLock<Self>>,
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        scanning::stop_extended_scan(this)
+    }
+
+    /// Returns a ``Thinkable`` concluding with the next advertising report received while
+    /// scanning. Fragmented advertising data is already reassembled. It concludes with ``None``
+    /// once scanning has stopped and all reports has been taken.
+    pub fn next_advertising_report(
+        this: Arc<DataLock<Self>>,
+    ) -> impl Thinkable<Output = Option<events::ExtendedAdvertisingReport>> {
+        scanning::NextAdvertisingReportThinkable::new(this)
+    }
+
+    /// Synchronize to the periodic advertising of the given advertiser. The advertiser is
+    /// identified by its address and the advertising set identifier reported while scanning.
+    /// The ``sync_timeout`` is given in units of 10ms. This concludes once the synchronization has
+    /// been established.
+    pub fn create_periodic_advertising_sync(
+        this: Arc<DataLock<Self>>,
+        sid: u8,
+        address_type: commands::PeerAddressType,
+        address: [u8; BD_ADDRESS_SIZE],
+        skip: u16,
+        sync_timeout: u16,
+    ) -> impl Thinkable<Output = Result<periodicsync::PeriodicAdvertisingSync<T>, BoxError>> {
+        periodicsync::create_periodic_advertising_sync(
+            this,
+            sid,
+            address_type,
+            address,
+            skip,
+            sync_timeout,
+        )
+    }
+
+    /// Cancel the pending request to synchronize to a periodic advertising
+    pub fn cancel_periodic_advertising_sync(
+        this: Arc<DataLock<Self>>,
+    ) -> impl Thinkable<Output = Result<(), BoxError>> {
+        Self::send_command(
+            this,
+            commands::HciCommandLePeriodicAdvertisingCreateSyncCancel::new(),
+        )
+    }
+
     /// Send a HCI Command packet to the host controller. This operation finishes if the Host
     /// either response with a CommandComplete or a CommandStatus event
     pub fn send_command<C>(
         this: Arc<DataLock<Self>>,
         command: C,
     ) -> impl Thinkable<Output = Result<(), BoxError>>
+    where
+        C: commands::IsHciCommand,
+    {
+        commands::SendCommandThinkable::new(command, this).map(|result| result.map(|_| ()))
+    }
+
+    /// Send a HCI Command packet to the host controller. This operation finishes if the Host
+    /// either response with a CommandComplete or a CommandStatus event. It concludes with the
+    /// return parameters following the status that are passed with the CommandComplete event.
+    pub fn send_command_with_result<C>(
+        this: Arc<DataLock<Self>>,
+        command: C,
+    ) -> impl Thinkable<Output = Result<Vec<u8>, BoxError>>
     where
         C: commands::IsHciCommand,
     {
@@ -197,98 +970,36 @@ where
         } else {
             // at any other time we got here because data was received, so now start reading the
             // incomming data and trigger the corresponding processing
-            let mut hci = self.hci().lock();
-            if let Some(ref mut transport) = hci.transport_layer {
-                // using a small buffer to be able to read the first important bytes from the BT Host
-                let mut buff: [u8; 10] = [0; 10];
-                // read 1 byte to begin with to the buffer. This contains the packet type we are about
-                // to receive
-                let _ = transport.recv_packet(&mut buff[..1]);
-                // it has been seen that even if a packet has been fully received and a new one is
-                // expected there are some arbitrary 0x0 values coming in from BT Host, so ignore them
-                // and wait for a packet to begin
-                if buff[0] != 0 {
-                    // we are receiving a packet
-                    let packet_type: HciPacketType = buff[0].into();
-                    // read the next bytes beeing the content of this packet, however their
-                    // meaning/format depends on the packet type
-                    match packet_type {
-                        HciPacketType::Event => {
-                            // info!("received event");
-                            // event packet: read the next 2 bytes containing the event type
-                            // and the parameter size that need to be retreived additionaly
-                            let _ = transport.recv_packet(&mut buff[1..3]);
-                            let param_size = buff[2] as usize;
-                            // create the generic buffer receiving the whole packet data
-                            let mut packet_data = buff.to_vec();
-                            // resize the buffer to be able to hold the data already read (3 bytes)
-                            // and the parameter that are about to come
-                            packet_data.resize(param_size + 3, 0);
-                            let _ = transport.recv_packet(&mut packet_data[3..]);
-                            // well we now have the whole packet received. Based on the event type
-                            // we need to notify/wake the corresponding Thinkables that has registered
-                            // themself to such an event packet
-                            let event_type: HciEventType = packet_data[1].into();
-                            match event_type {
-                                HciEventType::CommandComplete => {
-                                    // CommandComplete event, get the command that has been completed
-                                    // to wake the right thinkable
-                                    let command = commands::HciCommand::from(
-                                        (packet_data[5] as u16) << 8 | packet_data[4] as u16,
-                                    );
-                                    // if we have a waker registered for this command
-                                    // fill up the corresponding response and wake the waker
-                                    if let Some(command_response) =
-                                        hci.command_response.get_mut(&command)
-                                    {
-                                        command_response
-                                            .1
-                                            .replace(packet::HciPacket::from(packet_data));
-                                        command_response.0.wake_by_ref();
-                                    }
-                                },
-                                HciEventType::CommandStatus => {
-                                    // CommandStatus event, get the command that has responded with
-                                    // status and wake the right thinkable
-                                    let command = commands::HciCommand::from(
-                                        (packet_data[6] as u16) << 8 | packet_data[5] as u16,
-                                    );
-                                    // if we have a waker registered for this command
-                                    // fill up the corresponding response and wake the waker
-                                    if let Some(command_response) =
-                                        hci.command_response.get_mut(&command)
-                                    {
-                                        command_response
-                                            .1
-                                            .replace(packet::HciPacket::from(packet_data));
-                                        command_response.0.wake_by_ref();
-                                    }
-                                }
-                                _ => {
-                                    if let Some(event_notify) = hci.event_notify.get_mut(&event_type) {
-                                        event_notify.1.replace(packet::HciPacket::from(packet_data));
-                                        event_notify.0.wake_by_ref();
-                                    } else {
-                                        error!("event type {:?} doesn't notify anyone", event_type);
-                                        unimplemented!()
-                                    }
-                                },
-                            }
-                        }
-                        HciPacketType::Command => {
-                            info!("received command");
+            let this = self.hci().clone();
+            let mut hci = this.lock();
+            let packet = match hci.transport_layer {
+                Some(ref mut transport) => recv_packet(transport.as_mut()),
+                None => None,
+            };
+            if let Some((packet_type, packet_data)) = packet {
+                match packet_type {
+                    HciPacketType::Event => {
+                        hci.dispatch_event(packet_data);
+                        // requests of peers that has been dispatched are replied from their
+                        // own Thinkables
+                        let replies = hci.le_connection_parameters.take_replies();
+                        if !replies.is_empty() {
+                            connection::reply_parameter_requests(this.clone(), replies);
                         }
-                        HciPacketType::AclData => {
-                            info!("received ACL Data");
+                        let replies = hci.link_keys.take_replies();
+                        if !replies.is_empty() {
+                            connection::reply_link_key_requests(this.clone(), replies);
                         }
-                        _ => {
-                            // well, what to do with an unknown packet type ?
-                            // we might panic here as we cannot know how much data to read to
-                            // stop where a new fresh known packet starts
-                            error!("received packet: {:?} / raw: {}", packet_type, buff[0]);
-                            panic!("can't handle unknown THost packet type");
+                        let replies = hci.simple_pairing.take_replies();
+                        if !replies.is_empty() {
+                            connection::reply_simple_pairing(this.clone(), replies);
                         }
                     }
+                    HciPacketType::Command => {
+                        info!("received command");
+                    }
+                    HciPacketType::AclData => hci.receive_acl_packet(&packet_data),
+                    _ => (),
                 }
             }
         }
@@ -296,3 +1007,388 @@ where
         Conclusion::Pending
     }
 }
+
+/// Read the next packet from the transport layer. This returns the type of the packet and the
+/// whole packet data received (including the packet type)
+fn recv_packet<T>(transport: &mut T) -> Option<(HciPacketType, Vec<u8>)>
+where
+    T: HcTransportLayer,
+{
+    // using a small buffer to be able to read the first important bytes from the BT Host
+    let mut buff: [u8; 10] = [0; 10];
+    // read 1 byte to begin with to the buffer. This contains the packet type we are about
+    // to receive
+    let _ = transport.recv_packet(&mut buff[..1]);
+    // it has been seen that even if a packet has been fully received and a new one is
+    // expected there are some arbitrary 0x0 values coming in from BT Host, so ignore them
+    // and wait for a packet to begin
+    if buff[0] == 0 {
+        return None;
+    }
+    // we are receiving a packet
+    let packet_type: HciPacketType = buff[0].into();
+    // read the next bytes beeing the content of this packet, however their
+    // meaning/format depends on the packet type
+    match packet_type {
+        HciPacketType::Event => {
+            // info!("received event");
+            // event packet: read the next 2 bytes containing the event type
+            // and the parameter size that need to be retreived additionaly
+            let _ = transport.recv_packet(&mut buff[1..3]);
+            let param_size = buff[2] as usize;
+            // create the generic buffer receiving the whole packet data
+            let mut packet_data = buff.to_vec();
+            // resize the buffer to be able to hold the data already read (3 bytes)
+            // and the parameter that are about to come
+            packet_data.resize(param_size + 3, 0);
+            let _ = transport.recv_packet(&mut packet_data[3..]);
+            Some((packet_type, packet_data))
+        }
+        HciPacketType::AclData => {
+            // ACL data packet: read the next 4 bytes containing the connection handle with the
+            // packet flags and the data size that need to be retreived additionaly
+            let _ = transport.recv_packet(&mut buff[1..5]);
+            let data_size = buff[3] as usize | (buff[4] as usize) << 8;
+            let mut packet_data = buff[..5].to_vec();
+            packet_data.resize(data_size + 5, 0);
+            let _ = transport.recv_packet(&mut packet_data[5..]);
+            Some((packet_type, packet_data))
+        }
+        HciPacketType::Command => Some((packet_type, Vec::new())),
+        _ => {
+            // well, what to do with an unknown packet type ?
+            // we might panic here as we cannot know how much data to read to
+            // stop where a new fresh known packet starts
+            error!("received packet: {:?} / raw: {}", packet_type, buff[0]);
+            panic!("can't handle unknown THost packet type");
+        }
+    }
+}
+
+impl<T: HcTransportLayer + 'static> Hci<T> {
+    /// Let the connection parameter policy decide whether the parameters requested by a peer are
+    /// accepted
+    pub(crate) fn accept_connection_parameters(
+        &mut self,
+        handle: u16,
+        parameters: &commands::LeConnectionUpdateParameters,
+    ) -> bool {
+        self.le_connection_parameters.decide(handle, parameters)
+    }
+
+    /// Dispatch a received event packet. Based on the event type we need to notify/wake the
+    /// corresponding Thinkables that has registered themself to such an event packet
+    fn dispatch_event(&mut self, packet_data: Vec<u8>) {
+        let event_type: HciEventType = packet_data[1].into();
+        match event_type {
+            HciEventType::CommandComplete => {
+                // CommandComplete event, get the command that has been completed
+                // to wake the right thinkable
+                let num_cmd_packets = packet_data[3];
+                let command =
+                    commands::HciCommand::from((packet_data[5] as u16) << 8 | packet_data[4] as u16);
+                self.command_responded(command, num_cmd_packets, packet_data);
+            }
+            HciEventType::CommandStatus => {
+                // CommandStatus event, get the command that has responded with
+                // status and wake the right thinkable
+                let num_cmd_packets = packet_data[4];
+                let command =
+                    commands::HciCommand::from((packet_data[6] as u16) << 8 | packet_data[5] as u16);
+                self.command_responded(command, num_cmd_packets, packet_data);
+            }
+            HciEventType::LeMeta => self.dispatch_le_event(packet_data),
+            HciEventType::LinkKeyRequest => {
+                let packet = packet::HciPacket::from(packet_data);
+                if let Ok(request) = events::HciEventLinkKeyRequest::try_from(packet) {
+                    self.link_keys.requested(&request);
+                }
+            }
+            HciEventType::PinCodeRequest => {
+                let packet = packet::HciPacket::from(packet_data);
+                if let Ok(request) = events::HciEventPinCodeRequest::try_from(packet) {
+                    self.link_keys.pin_code_requested(&request);
+                }
+            }
+            HciEventType::LinkKeyNotification => {
+                let packet = packet::HciPacket::from(packet_data);
+                if let Ok(notification) = events::HciEventLinkKeyNotification::try_from(packet) {
+                    self.link_keys.notified(&notification);
+                }
+            }
+            HciEventType::IoCapabilityRequest
+            | HciEventType::IoCapabilityResponse
+            | HciEventType::UserConfirmationRequest
+            | HciEventType::UserPasskeyRequest
+            | HciEventType::UserPasskeyNotification
+            | HciEventType::SimplePairingComplete
+            | HciEventType::AuthenticationComplete => {
+                self.dispatch_simple_pairing_event(event_type, packet_data)
+            }
+            HciEventType::NumberOfCompletedPackets => {
+                let packet = packet::HciPacket::from(packet_data);
+                if let Ok(completed) = events::HciEventNumberOfCompletedPackets::try_from(packet) {
+                    self.acl.completed(&completed);
+                    self.send_acl_packets();
+                }
+            }
+            HciEventType::ConnectionComplete
+            | HciEventType::DisconnectionComplete
+            | HciEventType::RoleChange
+            | HciEventType::EncryptionChange
+            | HciEventType::EncryptionKeyRefreshComplete => {
+                self.update_connections(event_type, &packet_data);
+                // the connection events are only passed on if someone has registered for them
+                if let Some(event_notify) = self.event_notify.get_mut(&event_type) {
+                    event_notify.1.replace(packet::HciPacket::from(packet_data));
+                    event_notify.0.wake_by_ref();
+                }
+            }
+            _ => {
+                if let Some(event_notify) = self.event_notify.get_mut(&event_type) {
+                    event_notify.1.replace(packet::HciPacket::from(packet_data));
+                    event_notify.0.wake_by_ref();
+                } else {
+                    warn!("event type {:?} doesn't notify anyone", event_type);
+                }
+            }
+        }
+    }
+
+    /// The BT host responded to a command with a CommandComplete or CommandStatus event. The
+    /// event also tells how many commands the BT host accepts now, which might be the only
+    /// reason for the event if it is sent for no command at all.
+    fn command_responded(
+        &mut self,
+        command: commands::HciCommand,
+        num_cmd_packets: u8,
+        packet_data: Vec<u8>,
+    ) {
+        self.accept_commands.store(num_cmd_packets, Ordering::Release);
+        if command != commands::HciCommand::Unknown {
+            self.command_queue
+                .responded(command, packet::HciPacket::from(packet_data));
+        }
+        if num_cmd_packets > 0 {
+            self.command_queue.wake_waiting();
+        }
+    }
+
+    /// Pass a received Secure Simple Pairing event to the pairing state
+    fn dispatch_simple_pairing_event(&mut self, event_type: HciEventType, packet_data: Vec<u8>) {
+        let packet = packet::HciPacket::from(packet_data);
+        match event_type {
+            HciEventType::IoCapabilityRequest => {
+                if let Ok(request) = events::HciEventIoCapabilityRequest::try_from(packet) {
+                    self.simple_pairing.io_capability_requested(&request);
+                }
+            }
+            HciEventType::IoCapabilityResponse => {
+                if let Ok(response) = events::HciEventIoCapabilityResponse::try_from(packet) {
+                    self.simple_pairing.io_capability_responded(&response);
+                }
+            }
+            HciEventType::UserConfirmationRequest => {
+                if let Ok(request) = events::HciEventUserConfirmationRequest::try_from(packet) {
+                    let peer = self.pairing_peer(request.address);
+                    self.simple_pairing.confirmation_requested(&request, peer);
+                }
+            }
+            HciEventType::UserPasskeyRequest => {
+                if let Ok(request) = events::HciEventUserPasskeyRequest::try_from(packet) {
+                    let peer = self.pairing_peer(request.address);
+                    self.simple_pairing.passkey_requested(&request, peer);
+                }
+            }
+            HciEventType::UserPasskeyNotification => {
+                if let Ok(notification) = events::HciEventUserPasskeyNotification::try_from(packet)
+                {
+                    let peer = self.pairing_peer(notification.address);
+                    self.simple_pairing.passkey_notified(&notification, peer);
+                }
+            }
+            HciEventType::SimplePairingComplete => {
+                if let Ok(complete) = events::HciEventSimplePairingComplete::try_from(packet) {
+                    self.simple_pairing.completed(&complete);
+                }
+            }
+            HciEventType::AuthenticationComplete => {
+                if let Ok(complete) = events::HciEventAuthenticationComplete::try_from(packet) {
+                    self.simple_pairing.authenticated(&complete);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// The BR/EDR peer with the given address passed to the pairing agent
+    fn pairing_peer(&self, address: [u8; BD_ADDRESS_SIZE]) -> crate::agent::PairingPeer {
+        let handle = self
+            .connections
+            .infos()
+            .iter()
+            .find(|info| {
+                info.transport == connection::ConnectionTransport::BrEdr
+                    && info.peer_address == address
+            })
+            .map(|info| info.handle);
+        crate::agent::PairingPeer {
+            transport: connection::ConnectionTransport::BrEdr,
+            handle,
+            address,
+        }
+    }
+
+    /// Update the connection registry from a connection related event
+    fn update_connections(&mut self, event_type: HciEventType, packet_data: &[u8]) {
+        let packet = packet::HciPacket::from(packet_data.to_vec());
+        match event_type {
+            HciEventType::ConnectionComplete => {
+                if let Ok(complete) = events::HciEventConnectionComplete::try_from(packet) {
+                    self.connections.connected(&complete);
+                }
+            }
+            HciEventType::DisconnectionComplete => {
+                if let Ok(complete) = events::HciEventDisconnectionComplete::try_from(packet) {
+                    self.connections.disconnected(&complete);
+                    self.le_connection_parameters.closed(complete.handle);
+                    self.le_encryption.closed(complete.handle);
+                    self.acl.closed(complete.handle);
+                    // the buffers of the closed connection are free for other connections
+                    self.send_acl_packets();
+                }
+            }
+            HciEventType::RoleChange => {
+                if let Ok(change) = events::HciEventRoleChange::try_from(packet) {
+                    self.connections.role_changed(&change);
+                }
+            }
+            HciEventType::EncryptionChange => {
+                if let Ok(change) = events::HciEventEncryptionChange::try_from(packet) {
+                    self.connections.encryption_changed(&change);
+                    self.le_encryption.changed(
+                        change.handle,
+                        change.status,
+                        change.encryption_enabled != 0x00,
+                    );
+                }
+            }
+            HciEventType::EncryptionKeyRefreshComplete => {
+                if let Ok(complete) = events::HciEventEncryptionKeyRefreshComplete::try_from(packet)
+                {
+                    // the connection stays encrypted, now with the new key
+                    self.le_encryption.changed(complete.handle, complete.status, true);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Dispatch a received LE Meta event packet based on its sub event code. Some LE events
+    /// update the state kept within the ``Hci`` before any registered Thinkable is woken
+    fn dispatch_le_event(&mut self, packet_data: Vec<u8>) {
+        let event_type: HciLeEventType = packet_data[3].into();
+        if event_type == HciLeEventType::PeriodicAdvertisingSyncEstablished {
+            // the periodic sync state is created before the event is passed on to the
+            // Thinkable that requested the synchronization
+            let packet = packet::HciPacket::from(packet_data.clone());
+            if let Ok(established) =
+                events::HciEventLePeriodicAdvertisingSyncEstablished::try_from(packet)
+            {
+                self.periodic_syncs.established(&established);
+            }
+        }
+        if event_type == HciLeEventType::ConnectionComplete
+            || event_type == HciLeEventType::EnhancedConnectionComplete
+        {
+            // the connection is registered before the event is passed on to the Thinkable that
+            // initiated the connection
+            let packet = packet::HciPacket::from(packet_data.clone());
+            if let Ok(complete) = events::HciEventLeConnectionComplete::try_from(packet) {
+                self.connections.le_connected(&complete);
+            }
+        }
+        let packet = packet::HciPacket::from(packet_data);
+        let packet = match event_type {
+            HciLeEventType::AdvertisingSetTerminated => {
+                match events::HciEventLeAdvertisingSetTerminated::try_from(packet) {
+                    Ok(terminated) => {
+                        self.advertising_sets.terminated(&terminated);
+                        return;
+                    }
+                    Err(packet) => packet,
+                }
+            }
+            HciLeEventType::ExtendedAdvertisingReport => {
+                match events::HciEventLeExtendedAdvertisingReport::try_from(packet) {
+                    Ok(report) => {
+                        self.extended_scan.reported(report);
+                        return;
+                    }
+                    Err(packet) => packet,
+                }
+            }
+            HciLeEventType::ScanTimeout => {
+                self.extended_scan.timeout();
+                return;
+            }
+            HciLeEventType::PeriodicAdvertisingReport => {
+                match events::HciEventLePeriodicAdvertisingReport::try_from(packet) {
+                    Ok(report) => {
+                        self.periodic_syncs.reported(report);
+                        return;
+                    }
+                    Err(packet) => packet,
+                }
+            }
+            HciLeEventType::ConnectionUpdateComplete => {
+                match events::HciEventLeConnectionUpdateComplete::try_from(packet) {
+                    Ok(complete) => {
+                        self.connections.le_updated(&complete);
+                        self.le_connection_parameters.updated(complete);
+                        return;
+                    }
+                    Err(packet) => packet,
+                }
+            }
+            HciLeEventType::RemoteConnectionParameterRequest => {
+                match events::HciEventLeRemoteConnectionParameterRequest::try_from(packet) {
+                    Ok(request) => {
+                        self.le_connection_parameters.requested(request);
+                        return;
+                    }
+                    Err(packet) => packet,
+                }
+            }
+            HciLeEventType::LongTermKeyRequest => {
+                match events::HciEventLeLongTermKeyRequest::try_from(packet) {
+                    Ok(request) => {
+                        self.le_encryption.requested(request);
+                        return;
+                    }
+                    Err(packet) => packet,
+                }
+            }
+            HciLeEventType::PeriodicAdvertisingSyncLost => {
+                match events::HciEventLePeriodicAdvertisingSyncLost::try_from(packet) {
+                    Ok(lost) => {
+                        self.periodic_syncs.lost(lost.sync_handle);
+                        return;
+                    }
+                    Err(packet) => packet,
+                }
+            }
+            _ => packet,
+        };
+
+        if let Some(event_notify) = self.le_event_notify.get_mut(&event_type) {
+            event_notify.1.replace(packet);
+            event_notify.0.wake_by_ref();
+        } else {
+            // LE events might arrive after the Thinkable waiting for them has already concluded,
+            // for example advertising reports after scanning has been disabled. So there is no
+            // need to panic here
+            warn!("LE event type {:?} doesn't notify anyone", event_type);
+        }
+    }
+}
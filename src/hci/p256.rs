@@ -0,0 +1,103 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # LE P-256 Key Agreement
+//!
+//! The BT host creates the P-256 key pair and calculates the Diffie-Hellman key used by LE
+//! Secure Connections. Both commands are answered with a command status first, the result is
+//! passed with an LE event. There can only be one of each operation at a time.
+//!
+
+use super::*;
+use crate::hci::commands::*;
+use crate::hci::errors::*;
+
+enum P256State {
+    Initial,
+    Requested,
+    Waiting,
+}
+
+/// This ``Thinkable`` sends one of the P-256 commands and concludes with the LE event passing
+/// its result
+pub struct P256Thinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    hci: Arc<DataLock<Hci<T>>>,
+    event_type: HciLeEventType,
+    command: CommandThinkable,
+    state: P256State,
+}
+
+impl<T> P256Thinkable<T>
+where
+    T: HcTransportLayer,
+{
+    pub(crate) fn new(
+        hci: Arc<DataLock<Hci<T>>>,
+        event_type: HciLeEventType,
+        command: CommandThinkable,
+    ) -> Self {
+        Self {
+            hci,
+            event_type,
+            command,
+            state: P256State::Initial,
+        }
+    }
+}
+
+impl<T> Thinkable for P256Thinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = Result<packet::HciPacket<Vec<u8>>, BoxError>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        // the command Thinkable is pinned on its own, so it's save to access the state unpinned
+        let this = unsafe { self.get_unchecked_mut() };
+        loop {
+            match this.state {
+                P256State::Initial => {
+                    // register for the event before the command is send, as the event may
+                    // arrive right after the command status
+                    let mut hci = this.hci.lock();
+                    if hci.le_event_notify.contains_key(&this.event_type) {
+                        warn!("P-256 operation {:?} already running", this.event_type);
+                        return Conclusion::Ready(Err(Box::new(HciError {})));
+                    }
+                    hci.le_event_notify
+                        .insert(this.event_type, (cx.waker().clone(), None));
+                    this.state = P256State::Requested;
+                }
+                P256State::Requested => match this.command.as_mut().think(cx) {
+                    Conclusion::Pending => return Conclusion::Pending,
+                    Conclusion::Ready(Err(e)) => {
+                        this.hci.lock().le_event_notify.remove(&this.event_type);
+                        return Conclusion::Ready(Err(e));
+                    }
+                    Conclusion::Ready(Ok(_)) => this.state = P256State::Waiting,
+                },
+                P256State::Waiting => {
+                    let mut hci = this.hci.lock();
+                    let event = hci
+                        .le_event_notify
+                        .get_mut(&this.event_type)
+                        .and_then(|notify| notify.1.take());
+                    return match event {
+                        Some(event) => {
+                            hci.le_event_notify.remove(&this.event_type);
+                            Conclusion::Ready(Ok(event))
+                        }
+                        None => Conclusion::Pending,
+                    };
+                }
+            }
+        }
+    }
+}
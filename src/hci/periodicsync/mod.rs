@@ -0,0 +1,313 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # LE Periodic Advertising Synchronization
+//!
+//! Once synchronized to the periodic advertising of an advertiser the BT host sends a
+//! PeriodicAdvertisingReport event for each periodic advertising received. The reports are
+//! handed out one after another from the [PeriodicAdvertisingSync] until the synchronization
+//! has been lost or terminated.
+//!
+
+use super::*;
+use crate::alloc::collections::VecDeque;
+use crate::hci::commands::*;
+use crate::hci::errors::*;
+use crate::hci::events::{
+    AdvertisingDataStatus, HciEventLePeriodicAdvertisingReport,
+    HciEventLePeriodicAdvertisingSyncEstablished, HciLeEventType,
+};
+use crate::hctl::HcTransportLayer;
+
+/// Maximum number of reports kept per synchronization until they are taken. If there are more
+/// reports received the oldest ones are dropped.
+const MAX_PENDING_REPORTS: usize = 16;
+
+/// The state of one periodic advertising synchronization
+struct PeriodicSyncState {
+    reports: VecDeque<HciEventLePeriodicAdvertisingReport>,
+    /// report with incomplete data waiting for further fragments
+    partial: Option<HciEventLePeriodicAdvertisingReport>,
+    waker: Option<Waker>,
+}
+
+/// The periodic advertising synchronizations established by the BT host
+pub(crate) struct PeriodicSyncs {
+    syncs: BTreeMap<u16, PeriodicSyncState>,
+}
+
+impl PeriodicSyncs {
+    pub(crate) fn new() -> Self {
+        Self {
+            syncs: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn established(&mut self, event: &HciEventLePeriodicAdvertisingSyncEstablished) {
+        if event.status == 0x00 {
+            self.syncs.insert(
+                event.sync_handle,
+                PeriodicSyncState {
+                    reports: VecDeque::new(),
+                    partial: None,
+                    waker: None,
+                },
+            );
+        }
+    }
+
+    /// Add a PeriodicAdvertisingReport. Reports with incomplete data are kept until the last
+    /// fragment has been received.
+    pub(crate) fn reported(&mut self, report: HciEventLePeriodicAdvertisingReport) {
+        if let Some(sync) = self.syncs.get_mut(&report.sync_handle) {
+            let status = report.data_status;
+            let report = match sync.partial.take() {
+                Some(mut partial) => {
+                    partial.data.extend_from_slice(&report.data);
+                    partial.data_status = report.data_status;
+                    partial.rssi = report.rssi;
+                    partial
+                }
+                None => report,
+            };
+
+            if status == AdvertisingDataStatus::Incomplete {
+                sync.partial.replace(report);
+            } else {
+                if sync.reports.len() >= MAX_PENDING_REPORTS {
+                    warn!("periodic advertising report queue full, drop oldest report");
+                    sync.reports.pop_front();
+                }
+                sync.reports.push_back(report);
+                if let Some(waker) = sync.waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+
+    /// The synchronization has been lost or terminated. It is removed, so its handle can be
+    /// re-used by the BT host, and the one waiting for its next report is woken.
+    pub(crate) fn lost(&mut self, sync_handle: u16) {
+        if let Some(mut sync) = self.syncs.remove(&sync_handle) {
+            info!("periodic advertising sync {} lost", sync_handle);
+            if let Some(waker) = sync.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// A synchronization to the periodic advertising of an advertiser
+pub struct PeriodicAdvertisingSync<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    hci: Arc<DataLock<Hci<T>>>,
+    established: HciEventLePeriodicAdvertisingSyncEstablished,
+}
+
+impl<T> PeriodicAdvertisingSync<T>
+where
+    T: HcTransportLayer,
+{
+    pub fn sync_handle(&self) -> u16 {
+        self.established.sync_handle
+    }
+
+    /// The details of the synchronized periodic advertising
+    pub fn details(&self) -> &HciEventLePeriodicAdvertisingSyncEstablished {
+        &self.established
+    }
+
+    /// Returns a ``Thinkable`` that concludes with the next periodic advertising report. It
+    /// concludes with ``None`` once the synchronization has been lost or terminated.
+    pub fn next_report(&self) -> NextPeriodicReportThinkable<T> {
+        NextPeriodicReportThinkable {
+            hci: self.hci.clone(),
+            sync_handle: self.sync_handle(),
+        }
+    }
+
+    /// Terminate the synchronization
+    pub fn terminate(self) -> impl Thinkable<Output = Result<(), BoxError>> {
+        let hci_clone = self.hci.clone();
+        let sync_handle = self.sync_handle();
+        Hci::send_command(
+            self.hci,
+            HciCommandLePeriodicAdvertisingTerminateSync::new(sync_handle),
+        )
+        .map(move |result| {
+            let mut hci = hci_clone.lock();
+            hci.periodic_syncs.lost(sync_handle);
+            result
+        })
+    }
+}
+
+/// This ``Thinkable`` concludes with the next periodic advertising report received for a
+/// synchronization or with ``None`` if it has been lost.
+pub struct NextPeriodicReportThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    hci: Arc<DataLock<Hci<T>>>,
+    sync_handle: u16,
+}
+
+impl<T> Thinkable for NextPeriodicReportThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = Option<HciEventLePeriodicAdvertisingReport>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        let mut hci = self.hci.lock();
+        match hci.periodic_syncs.syncs.get_mut(&self.sync_handle) {
+            Some(sync) => match sync.reports.pop_front() {
+                Some(report) => Conclusion::Ready(Some(report)),
+                None => {
+                    sync.waker.replace(cx.waker().clone());
+                    Conclusion::Pending
+                }
+            },
+            // the synchronization has been lost or terminated
+            None => Conclusion::Ready(None),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum CreateSyncState {
+    Initial,
+    Requested,
+    Waiting,
+}
+
+/// This ``Thinkable`` requests the BT host to synchronize to a periodic advertising and concludes
+/// once the synchronization has been established
+pub struct CreateSyncThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    hci: Arc<DataLock<Hci<T>>>,
+    state: CreateSyncState,
+    command: SendCommandThinkable<HciCommandLePeriodicAdvertisingCreateSync, T>,
+}
+
+impl<T> CreateSyncThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    unsafe_unpinned!(state: CreateSyncState);
+    unsafe_unpinned!(command: SendCommandThinkable<HciCommandLePeriodicAdvertisingCreateSync, T>);
+
+    pub fn new(
+        hci: Arc<DataLock<Hci<T>>>,
+        command: HciCommandLePeriodicAdvertisingCreateSync,
+    ) -> Self {
+        Self {
+            hci: hci.clone(),
+            state: CreateSyncState::Initial,
+            command: SendCommandThinkable::new(command, hci),
+        }
+    }
+}
+
+impl<T> Thinkable for CreateSyncThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = Result<PeriodicAdvertisingSync<T>, BoxError>;
+
+    fn think(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        loop {
+            match self.state {
+                CreateSyncState::Initial => {
+                    // register for the SyncEstablished event before the command is send, as the
+                    // event may arrive right after the command status
+                    let waker = cx.waker().clone();
+                    self.hci.lock().le_event_notify.insert(
+                        HciLeEventType::PeriodicAdvertisingSyncEstablished,
+                        (waker, None),
+                    );
+                    *self.as_mut().state() = CreateSyncState::Requested;
+                }
+                CreateSyncState::Requested => {
+                    let command = unsafe { Pin::new_unchecked(self.as_mut().command()) };
+                    match command.think(cx) {
+                        Conclusion::Pending => return Conclusion::Pending,
+                        Conclusion::Ready(Err(e)) => {
+                            self.hci
+                                .lock()
+                                .le_event_notify
+                                .remove(&HciLeEventType::PeriodicAdvertisingSyncEstablished);
+                            return Conclusion::Ready(Err(e));
+                        }
+                        Conclusion::Ready(Ok(_)) => {
+                            // the command has been accepted and the SyncEstablished event uses
+                            // the waker registered initially
+                            *self.as_mut().state() = CreateSyncState::Waiting;
+                        }
+                    }
+                }
+                CreateSyncState::Waiting => {
+                    let mut hci = self.hci.lock();
+                    let event = hci
+                        .le_event_notify
+                        .get_mut(&HciLeEventType::PeriodicAdvertisingSyncEstablished)
+                        .and_then(|notify| notify.1.take());
+                    let event = match event {
+                        Some(event) => event,
+                        None => return Conclusion::Pending,
+                    };
+
+                    hci.le_event_notify
+                        .remove(&HciLeEventType::PeriodicAdvertisingSyncEstablished);
+                    drop(hci);
+                    return match HciEventLePeriodicAdvertisingSyncEstablished::try_from(event) {
+                        Ok(established) if established.status == 0x00 => {
+                            Conclusion::Ready(Ok(PeriodicAdvertisingSync {
+                                hci: self.hci.clone(),
+                                established,
+                            }))
+                        }
+                        Ok(established) => {
+                            warn!("periodic advertising sync failed with status {}", established.status);
+                            Conclusion::Ready(Err(Box::new(HciError {})))
+                        }
+                        Err(_) => Conclusion::Ready(Err(Box::new(HciError {}))),
+                    };
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn create_periodic_advertising_sync<T>(
+    hci: Arc<DataLock<Hci<T>>>,
+    sid: u8,
+    address_type: PeerAddressType,
+    address: [u8; BD_ADDRESS_SIZE],
+    skip: u16,
+    sync_timeout: u16,
+) -> CreateSyncThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    CreateSyncThinkable::new(
+        hci,
+        HciCommandLePeriodicAdvertisingCreateSync::new(
+            0,
+            sid,
+            address_type,
+            address,
+            skip,
+            sync_timeout,
+        ),
+    )
+}
@@ -0,0 +1,365 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # LE Privacy
+//!
+//! A device using privacy does not reveal its identity address over the air. It uses random
+//! addresses instead, that are either static for the power cycle, non-resolvable or resolvable
+//! with its identity resolving key (IRK). A resolvable private address (RPA) is changed
+//! periodically, only peers knowing the IRK are able to tell that the addresses belong to the
+//! same device. Peers distribute their IRK while bonding, their addresses can either be resolved
+//! on this side with [resolve_address] or by the BT host once they are in its resolving list.
+//!
+//! The random address set with [Hci::set_random_address] is used for scanning and initiating
+//! connections with a random own address. Advertising sets have their own random address, see
+//! [Hci::set_advertising_set_random_address].
+//!
+
+use super::*;
+use crate::bond::SharedBondStore;
+use crate::hctl::HcTransportLayer;
+use crate::smp::crypto;
+
+/// The time in seconds a resolvable private address is used, as recommended by the specification
+pub const DEFAULT_RPA_TIMEOUT: u16 = 900;
+
+/// The kinds of random addresses, given by the two most significant bits of the address
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RandomAddressType {
+    /// the address does not change during a power cycle, it could be used as identity address
+    Static,
+    /// the address can not be related to the device
+    NonResolvable,
+    /// the address can be related to the device by peers knowing its identity resolving key
+    Resolvable,
+}
+
+/// The kind of the given random address, ``None`` for the reserved kind
+pub fn random_address_type(address: &[u8; BD_ADDRESS_SIZE]) -> Option<RandomAddressType> {
+    match address[BD_ADDRESS_SIZE - 1] >> 6 {
+        0b11 => Some(RandomAddressType::Static),
+        0b00 => Some(RandomAddressType::NonResolvable),
+        0b01 => Some(RandomAddressType::Resolvable),
+        _ => None,
+    }
+}
+
+/// The static random address built from the given random bytes
+pub fn static_address(random: [u8; BD_ADDRESS_SIZE]) -> [u8; BD_ADDRESS_SIZE] {
+    let mut address = random;
+    address[BD_ADDRESS_SIZE - 1] |= 0xC0;
+    ensure_random_part(&mut address);
+    address
+}
+
+/// The non-resolvable private address built from the given random bytes. The address shall not
+/// be the public address of the device.
+pub fn non_resolvable_address(random: [u8; BD_ADDRESS_SIZE]) -> [u8; BD_ADDRESS_SIZE] {
+    let mut address = random;
+    address[BD_ADDRESS_SIZE - 1] &= 0x3F;
+    ensure_random_part(&mut address);
+    address
+}
+
+/// The resolvable private address built from the identity resolving key and the random bytes
+/// of the prand part of the address
+pub fn resolvable_address(irk: &[u8; 16], random: [u8; 3]) -> [u8; BD_ADDRESS_SIZE] {
+    let mut prand = random;
+    prand[2] = prand[2] & 0x3F | 0x40;
+    ensure_random_part(&mut prand);
+    let hash = crypto::ah(irk, &prand);
+    let mut address = [0; BD_ADDRESS_SIZE];
+    address[..3].copy_from_slice(&hash);
+    address[3..].copy_from_slice(&prand);
+    address
+}
+
+/// Whether the given address is a resolvable private address of the device with the identity
+/// resolving key given
+pub fn resolve_address(irk: &[u8; 16], address: &[u8; BD_ADDRESS_SIZE]) -> bool {
+    if random_address_type(address) != Some(RandomAddressType::Resolvable) {
+        return false;
+    }
+    let prand = [address[3], address[4], address[5]];
+    crypto::ah(irk, &prand) == address[..3]
+}
+
+/// The random part of an address shall neither be all zeros nor all ones. The two most
+/// significant bits of the last byte are the kind of the address and not part of it.
+fn ensure_random_part(bytes: &mut [u8]) {
+    let last = bytes.len() - 1;
+    let zeros = bytes[..last].iter().all(|byte| *byte == 0x00) && bytes[last] & 0x3F == 0x00;
+    let ones = bytes[..last].iter().all(|byte| *byte == 0xFF) && bytes[last] & 0x3F == 0x3F;
+    if zeros || ones {
+        bytes[0] ^= 0x01;
+    }
+}
+
+/// The random address of the BT host and the rotation of resolvable private addresses
+pub(crate) struct PrivacyState {
+    random_address: Option<[u8; BD_ADDRESS_SIZE]>,
+    /// counts the starts and stops of the rotation, a pending rotation only changes the address
+    /// if no other one has been started or stopped since
+    rotation: u32,
+}
+
+impl PrivacyState {
+    pub(crate) fn new() -> Self {
+        Self {
+            random_address: None,
+            rotation: 0,
+        }
+    }
+
+    pub(crate) fn random_address(&self) -> Option<[u8; BD_ADDRESS_SIZE]> {
+        self.random_address
+    }
+
+    /// Start a new rotation, stopping the current one. This returns the new rotation.
+    pub(crate) fn next_rotation(&mut self) -> u32 {
+        self.rotation = self.rotation.wrapping_add(1);
+        self.rotation
+    }
+}
+
+/// Generate a random address of the given kind with the random number generator of the BT host.
+/// Resolvable private addresses are generated with the identity resolving key given.
+pub(crate) fn generate_random_address<T>(
+    hci: Arc<DataLock<Hci<T>>>,
+    address_type: RandomAddressType,
+    irk: [u8; 16],
+) -> impl Thinkable<Output = Result<[u8; BD_ADDRESS_SIZE], BoxError>>
+where
+    T: HcTransportLayer + 'static,
+{
+    Hci::rand(hci).map(move |result| {
+        let random = result?;
+        let mut bytes = [0; BD_ADDRESS_SIZE];
+        bytes.copy_from_slice(&random[..BD_ADDRESS_SIZE]);
+        Ok(match address_type {
+            RandomAddressType::Static => static_address(bytes),
+            RandomAddressType::NonResolvable => non_resolvable_address(bytes),
+            RandomAddressType::Resolvable => {
+                resolvable_address(&irk, [bytes[0], bytes[1], bytes[2]])
+            }
+        })
+    })
+}
+
+/// Set the random address of the BT host and keep it once set
+pub(crate) fn set_random_address<T>(
+    hci: Arc<DataLock<Hci<T>>>,
+    address: [u8; BD_ADDRESS_SIZE],
+) -> impl Thinkable<Output = Result<(), BoxError>>
+where
+    T: HcTransportLayer + 'static,
+{
+    let hci_clone = hci.clone();
+    Hci::send_command(hci, commands::HciCommandLeSetRandomAddress::new(address)).map(
+        move |result| {
+            result?;
+            hci_clone.lock().privacy.random_address = Some(address);
+            Ok(())
+        },
+    )
+}
+
+/// Generate a new resolvable private address and set it as random address of the BT host
+fn renew_resolvable_address<T>(
+    hci: Arc<DataLock<Hci<T>>>,
+    irk: [u8; 16],
+) -> RenewAddressThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    let generate: AddressThinkable = Box::pin(generate_random_address(
+        hci.clone(),
+        RandomAddressType::Resolvable,
+        irk,
+    ));
+    RenewAddressThinkable {
+        hci,
+        generate,
+        set: None,
+    }
+}
+
+/// Start the rotation of resolvable private addresses. A new address is generated and set
+/// right away and then every ``timeout`` seconds until the rotation is stopped.
+pub(crate) fn start_address_rotation<T>(
+    hci: Arc<DataLock<Hci<T>>>,
+    irk: [u8; 16],
+    timeout: u16,
+) -> impl Thinkable<Output = Result<[u8; BD_ADDRESS_SIZE], BoxError>>
+where
+    T: HcTransportLayer + 'static,
+{
+    let rotation = hci.lock().privacy.next_rotation();
+    let hci_clone = hci.clone();
+    renew_resolvable_address(hci, irk).map(move |result| {
+        if result.is_ok() {
+            schedule_rotation(hci_clone, irk, timeout, rotation);
+        }
+        result
+    })
+}
+
+/// Renew the resolvable private address once the timeout has passed, as long as the rotation
+/// has not been stopped
+fn schedule_rotation<T>(hci: Arc<DataLock<Hci<T>>>, irk: [u8; 16], timeout: u16, rotation: u32)
+where
+    T: HcTransportLayer + 'static,
+{
+    spawn(wait(Mseconds(timeout as u64 * 1000), ()).map(move |_| {
+        if hci.read().privacy.rotation != rotation {
+            return;
+        }
+        let hci_clone = hci.clone();
+        spawn(renew_resolvable_address(hci, irk).map(move |result| {
+            if let Err(error) = result {
+                warn!(
+                    "renewing the resolvable private address failed: {:?}",
+                    error
+                );
+            }
+            // a failed renewal is retried with the next rotation
+            schedule_rotation(hci_clone, irk, timeout, rotation);
+        }));
+    }));
+}
+
+/// Add the peers of the bond store that distributed their identity to the resolving list
+pub(crate) fn add_bonds_to_resolving_list<T>(
+    hci: Arc<DataLock<Hci<T>>>,
+    store: SharedBondStore,
+    local_irk: [u8; 16],
+) -> impl Thinkable<Output = Result<(), BoxError>>
+where
+    T: HcTransportLayer + 'static,
+{
+    let records = store.read().records();
+    let chain = records
+        .iter()
+        .filter_map(|record| record.le.and_then(|bond| bond.peer_identity))
+        .map(|identity| {
+            let address_type = if identity.address_type == 0x00 {
+                commands::IdentityAddressType::Public
+            } else {
+                commands::IdentityAddressType::Random
+            };
+            let command: commands::CommandThinkable = Box::pin(Hci::send_command(
+                hci.clone(),
+                commands::HciCommandLeAddDeviceToResolvingList::new(
+                    address_type,
+                    identity.address,
+                    identity.irk,
+                    local_irk,
+                ),
+            ));
+            command
+        })
+        .collect();
+    commands::CommandChainThinkable::new(chain)
+}
+
+/// Boxed ``Thinkable`` concluding with a random address
+type AddressThinkable =
+    Pin<Box<dyn Thinkable<Output = Result<[u8; BD_ADDRESS_SIZE], BoxError>> + Send>>;
+
+/// This ``Thinkable`` generates a new address and then sets it as random address of the BT host
+struct RenewAddressThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    hci: Arc<DataLock<Hci<T>>>,
+    generate: AddressThinkable,
+    /// the address generated and the ``Thinkable`` setting it
+    set: Option<([u8; BD_ADDRESS_SIZE], commands::CommandThinkable)>,
+}
+
+impl<T> Thinkable for RenewAddressThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = Result<[u8; BD_ADDRESS_SIZE], BoxError>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        // both steps are pinned on their own
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.set.is_none() {
+            let address = match this.generate.as_mut().think(cx) {
+                Conclusion::Pending => return Conclusion::Pending,
+                Conclusion::Ready(Err(e)) => return Conclusion::Ready(Err(e)),
+                Conclusion::Ready(Ok(address)) => address,
+            };
+            let set: commands::CommandThinkable =
+                Box::pin(set_random_address(this.hci.clone(), address));
+            this.set = Some((address, set));
+        }
+        let (address, set) = this.set.as_mut().unwrap();
+        match set.as_mut().think(cx) {
+            Conclusion::Pending => Conclusion::Pending,
+            Conclusion::Ready(result) => Conclusion::Ready(result.map(|_| *address)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The identity resolving key of the sample data of ah, least significant byte first
+    const IRK: [u8; 16] = [
+        0x9b, 0x7d, 0x39, 0x0a, 0xa6, 0x10, 0x10, 0x34, 0x05, 0xad, 0xc8, 0x57, 0xa3, 0x34, 0x02,
+        0xec,
+    ];
+
+    #[test]
+    fn resolvable_address_sample_data() {
+        let address = resolvable_address(&IRK, [0x94, 0x81, 0x70]);
+        assert_eq!(address, [0xaa, 0xfb, 0x0d, 0x94, 0x81, 0x70]);
+        assert_eq!(
+            random_address_type(&address),
+            Some(RandomAddressType::Resolvable)
+        );
+        assert!(resolve_address(&IRK, &address));
+    }
+
+    #[test]
+    fn resolve_only_with_own_key() {
+        let other_irk = [0x5A; 16];
+        for random in [[0x00, 0x00, 0x00], [0x12, 0x34, 0xFF], [0xFF, 0xFF, 0xFF]].iter() {
+            let address = resolvable_address(&IRK, *random);
+            assert_eq!(
+                random_address_type(&address),
+                Some(RandomAddressType::Resolvable)
+            );
+            assert!(resolve_address(&IRK, &address));
+            assert!(!resolve_address(&other_irk, &address));
+        }
+    }
+
+    #[test]
+    fn random_address_types() {
+        let random = [0x00; BD_ADDRESS_SIZE];
+        let address = static_address(random);
+        assert_eq!(
+            random_address_type(&address),
+            Some(RandomAddressType::Static)
+        );
+        assert!(!resolve_address(&IRK, &address));
+        let address = non_resolvable_address([0xFF; BD_ADDRESS_SIZE]);
+        assert_eq!(
+            random_address_type(&address),
+            Some(RandomAddressType::NonResolvable)
+        );
+        assert_eq!(
+            random_address_type(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x80]),
+            None
+        );
+    }
+}
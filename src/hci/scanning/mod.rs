@@ -0,0 +1,183 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # LE Extended Scanning
+//!
+//! While scanning the BT host reports the advertising received with ExtendedAdvertisingReport
+//! events. Advertising data that does not fit into a single event is reported in several
+//! fragments. Those fragments are reassembled here before the report is handed out.
+//!
+
+use super::*;
+use crate::alloc::collections::VecDeque;
+use crate::hci::commands::*;
+use crate::hci::events::{
+    AdvertisingDataStatus, ExtendedAdvertisingReport, HciEventLeExtendedAdvertisingReport,
+};
+use crate::hctl::HcTransportLayer;
+
+/// Maximum number of reports kept until they are taken. If there are more reports received the
+/// oldest ones are dropped.
+const MAX_PENDING_REPORTS: usize = 32;
+
+/// Identifies the advertiser a report belongs to while reassembling fragmented advertising data
+type AdvertiserKey = (PeerAddressType, [u8; BD_ADDRESS_SIZE], u8);
+
+/// The state of the extended scanning
+pub(crate) struct ExtendedScan {
+    active: bool,
+    reports: VecDeque<ExtendedAdvertisingReport>,
+    /// reports with incomplete data waiting for further fragments
+    partial: BTreeMap<AdvertiserKey, ExtendedAdvertisingReport>,
+    waker: Option<Waker>,
+}
+
+impl ExtendedScan {
+    pub(crate) fn new() -> Self {
+        Self {
+            active: false,
+            reports: VecDeque::new(),
+            partial: BTreeMap::new(),
+            waker: None,
+        }
+    }
+
+    fn start(&mut self) {
+        self.active = true;
+        self.reports.clear();
+        self.partial.clear();
+    }
+
+    fn stop(&mut self) {
+        self.active = false;
+        self.partial.clear();
+        self.wake();
+    }
+
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Add the reports of an ExtendedAdvertisingReport event. Reports with incomplete data are
+    /// kept until the last fragment has been received.
+    pub(crate) fn reported(&mut self, event: HciEventLeExtendedAdvertisingReport) {
+        for report in event.reports {
+            let key = (report.address_type, report.address, report.sid);
+            let status = report.data_status();
+            let report = match self.partial.remove(&key) {
+                Some(mut partial) => {
+                    // a further fragment of the data, the latest report defines the status
+                    partial.data.extend_from_slice(&report.data);
+                    partial.event_type = report.event_type;
+                    partial.rssi = report.rssi;
+                    partial
+                }
+                None => report,
+            };
+
+            if status == AdvertisingDataStatus::Incomplete {
+                self.partial.insert(key, report);
+            } else {
+                if self.reports.len() >= MAX_PENDING_REPORTS {
+                    warn!("advertising report queue full, drop oldest report");
+                    self.reports.pop_front();
+                }
+                self.reports.push_back(report);
+            }
+        }
+        self.wake();
+    }
+
+    /// The BT host has stopped scanning as the scan duration has elapsed
+    pub(crate) fn timeout(&mut self) {
+        info!("extended scan timed out");
+        self.stop();
+    }
+}
+
+pub(crate) fn start_extended_scan<T>(
+    hci: Arc<DataLock<Hci<T>>>,
+    filter_duplicates: ScanFilterDuplicates,
+    duration: u16,
+    period: u16,
+) -> impl Thinkable<Output = Result<(), BoxError>>
+where
+    T: HcTransportLayer + 'static,
+{
+    let hci_clone = hci.clone();
+    // the reports may arrive before the command completion is processed, so the scanning state
+    // is prepared upfront
+    hci.lock().extended_scan.start();
+    Hci::send_command(
+        hci,
+        HciCommandLeSetExtendedScanEnable::new(true, filter_duplicates, duration, period),
+    )
+    .map(move |result| {
+        if result.is_err() {
+            hci_clone.lock().extended_scan.stop();
+        }
+        result
+    })
+}
+
+pub(crate) fn stop_extended_scan<T>(
+    hci: Arc<DataLock<Hci<T>>>,
+) -> impl Thinkable<Output = Result<(), BoxError>>
+where
+    T: HcTransportLayer + 'static,
+{
+    let hci_clone = hci.clone();
+    Hci::send_command(
+        hci,
+        HciCommandLeSetExtendedScanEnable::new(false, ScanFilterDuplicates::Disabled, 0, 0),
+    )
+    .map(move |result| {
+        hci_clone.lock().extended_scan.stop();
+        result
+    })
+}
+
+/// This ``Thinkable`` concludes with the next advertising report received while scanning. It
+/// concludes with ``None`` once scanning has stopped and all reports has been taken.
+pub struct NextAdvertisingReportThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    hci: Arc<DataLock<Hci<T>>>,
+}
+
+impl<T> NextAdvertisingReportThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    pub fn new(hci: Arc<DataLock<Hci<T>>>) -> Self {
+        Self { hci }
+    }
+}
+
+impl<T> Thinkable for NextAdvertisingReportThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = Option<ExtendedAdvertisingReport>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        let mut hci = self.hci.lock();
+        let scan = &mut hci.extended_scan;
+        if let Some(report) = scan.reports.pop_front() {
+            Conclusion::Ready(Some(report))
+        } else if !scan.active {
+            Conclusion::Ready(None)
+        } else {
+            // wait for the next report to arrive
+            scan.waker.replace(cx.waker().clone());
+            Conclusion::Pending
+        }
+    }
+}
@@ -0,0 +1,1007 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # BR/EDR Connection Oriented Channels
+//!
+//! Dynamic channels opened on a BR/EDR connection for a protocol/service multiplexer (PSM).
+//! Servers register the PSM they are listening on and accept the channels opened by peers, clients
+//! open channels to the PSM of a peer. Once a channel is connected both sides configure the
+//! channel with the options they would like to use for the data they receive. The channel is open
+//! as soon as both configurations are done. In basic mode each SDU is send as one B-frame, in
+//! enhanced retransmission and streaming mode the SDU's are passed to the [RetransmissionState].
+//!
+
+use super::*;
+
+/// Last channel id of the BR/EDR dynamic channels
+pub const BREDR_DYNAMIC_CID_END: u16 = 0xFFFF;
+
+/// Connection response result: the connection is established
+pub const CONNECTION_SUCCESSFUL: u16 = 0x0000;
+/// Connection response result: the connection is pending
+pub const CONNECTION_PENDING: u16 = 0x0001;
+/// Connection response result: the PSM is not supported
+pub const CONNECTION_PSM_NOT_SUPPORTED: u16 = 0x0002;
+/// Connection response result: the connection is refused due to a security block
+pub const CONNECTION_SECURITY_BLOCK: u16 = 0x0003;
+/// Connection response result: no resources available
+pub const CONNECTION_NO_RESOURCES: u16 = 0x0004;
+/// Connection response result: the source channel id is invalid
+pub const CONNECTION_INVALID_SOURCE_CID: u16 = 0x0006;
+/// Connection response result: the source channel id is already in use
+pub const CONNECTION_SOURCE_CID_ALREADY_ALLOCATED: u16 = 0x0007;
+
+/// Maximum number of configuration requests send for a channel if the peer does not accept the
+/// options
+const MAX_CONFIG_ATTEMPTS: u8 = 3;
+/// Maximum number of SDU's kept per basic mode channel until they are taken. If there are more
+/// SDU's received the oldest ones are dropped.
+const MAX_PENDING_SDUS: usize = 8;
+/// Maximum number of channels opened by peers waiting to be accepted per PSM
+const MAX_PENDING_CHANNELS: usize = 4;
+
+/// The state of one BR/EDR connection oriented channel
+pub(crate) struct ChannelState {
+    remote_cid: u16,
+    config: ChannelConfig,
+    /// the MTU the peer has configured for the data it receives
+    remote_mtu: u16,
+    /// the flush timeout the peer has configured for the data it receives
+    remote_flush_timeout: u16,
+    /// the retransmission and flow control the peer has configured for the data it receives
+    remote_rfc: Option<RetransmissionAndFlowControl>,
+    /// whether the peer requests the frame check sequence
+    remote_fcs: bool,
+    /// the retransmission and monitor timeout the peer has given for the data we send
+    timeouts: (u16, u16),
+    /// the state of the enhanced retransmission or streaming mode once the channel is open
+    ertm: Option<RetransmissionState>,
+    /// the PSM of a channel opened by the peer until it is passed to the server
+    incoming_psm: Option<u16>,
+    config_attempts: u8,
+    /// the peer has accepted our configuration
+    local_configured: bool,
+    /// we have accepted the configuration of the peer
+    remote_configured: bool,
+    /// the result of the connection response if the peer refused the channel
+    refused: Option<u16>,
+    sdus: VecDeque<Vec<u8>>,
+    recv_waker: Option<Waker>,
+    open_waker: Option<Waker>,
+    /// the waker of the Thinkable waiting for the SDU's queued to be send
+    send_waker: Option<Waker>,
+}
+
+impl ChannelState {
+    fn new(config: ChannelConfig, remote_cid: u16, incoming_psm: Option<u16>) -> Self {
+        Self {
+            remote_cid,
+            config,
+            remote_mtu: DEFAULT_MTU,
+            remote_flush_timeout: FLUSH_TIMEOUT_INFINITE,
+            remote_rfc: None,
+            remote_fcs: true,
+            timeouts: (DEFAULT_RETRANSMISSION_TIMEOUT, DEFAULT_MONITOR_TIMEOUT),
+            ertm: None,
+            incoming_psm,
+            config_attempts: 0,
+            local_configured: false,
+            remote_configured: false,
+            refused: None,
+            sdus: VecDeque::new(),
+            recv_waker: None,
+            open_waker: None,
+            send_waker: None,
+        }
+    }
+
+    pub(super) fn remote_cid(&self) -> u16 {
+        self.remote_cid
+    }
+
+    fn is_open(&self) -> bool {
+        self.local_configured && self.remote_configured
+    }
+
+    /// Evaluate the options requested by the peer. This returns the result of the configuration
+    /// together with the options to be send back to the peer.
+    fn evaluate(&mut self, options: Vec<ConfigOption>) -> (u16, Vec<ConfigOption>) {
+        let mut unknown = Vec::new();
+        let mut unacceptable = Vec::new();
+        let mut mtu = self.remote_mtu;
+        let mut flush_timeout = self.remote_flush_timeout;
+        let mut rfc = self.remote_rfc;
+        let mut fcs = self.remote_fcs;
+        for option in options {
+            match option.kind() {
+                OPTION_MTU if option.value_u16() < MIN_MTU_BREDR => {
+                    unacceptable.push(ConfigOption::mtu(MIN_MTU_BREDR))
+                }
+                OPTION_MTU => mtu = option.value_u16(),
+                OPTION_FLUSH_TIMEOUT => flush_timeout = option.value_u16(),
+                // only best effort is provided, which every peer has to accept
+                OPTION_QOS => (),
+                OPTION_RETRANSMISSION_AND_FLOW_CONTROL => {
+                    rfc = RetransmissionAndFlowControl::parse(&option)
+                }
+                OPTION_FCS => fcs = option.data.first() != Some(&FCS_NONE),
+                _ if option.is_hint() => (),
+                _ => unknown.push(option),
+            }
+        }
+
+        // both directions need to use the same mode
+        let mode = rfc.map_or(ChannelMode::Basic, |rfc| rfc.mode);
+        let rfc_valid = match rfc {
+            Some(rfc) if mode == ChannelMode::EnhancedRetransmission => {
+                rfc.tx_window > 0 && rfc.tx_window <= MAX_TX_WINDOW && rfc.mps > 0
+            }
+            Some(rfc) => rfc.mps > 0,
+            None => true,
+        };
+        if mode != self.config.mode || !rfc_valid {
+            unacceptable.push(
+                self.config
+                    .retransmission_and_flow_control(
+                        DEFAULT_RETRANSMISSION_TIMEOUT,
+                        DEFAULT_MONITOR_TIMEOUT,
+                    )
+                    .to_option(),
+            );
+        }
+
+        if !unknown.is_empty() {
+            (CONFIG_UNKNOWN_OPTIONS, unknown)
+        } else if !unacceptable.is_empty() {
+            (CONFIG_UNACCEPTABLE_PARAMETERS, unacceptable)
+        } else {
+            self.remote_mtu = mtu;
+            self.remote_flush_timeout = flush_timeout;
+            self.remote_rfc = rfc;
+            self.remote_fcs = fcs;
+            // the response carries the timeouts the peer shall use for the data it sends
+            let options = rfc
+                .filter(|rfc| rfc.mode != ChannelMode::Basic)
+                .map(|rfc| RetransmissionAndFlowControl {
+                    retransmission_timeout: DEFAULT_RETRANSMISSION_TIMEOUT,
+                    monitor_timeout: DEFAULT_MONITOR_TIMEOUT,
+                    ..rfc
+                })
+                .map(|rfc| rfc.to_option())
+                .into_iter()
+                .collect();
+            (CONFIG_SUCCESS, options)
+        }
+    }
+
+    /// The peer accepted our options. The response might contain the timeouts to be used for the
+    /// data we send.
+    fn accepted(&mut self, options: Option<Vec<ConfigOption>>) {
+        let rfc = options
+            .unwrap_or_default()
+            .iter()
+            .filter(|option| option.kind() == OPTION_RETRANSMISSION_AND_FLOW_CONTROL)
+            .filter_map(RetransmissionAndFlowControl::parse)
+            .next();
+        if let Some(rfc) = rfc {
+            if rfc.retransmission_timeout != 0 {
+                self.timeouts.0 = rfc.retransmission_timeout;
+            }
+            if rfc.monitor_timeout != 0 {
+                self.timeouts.1 = rfc.monitor_timeout;
+            }
+        }
+        self.local_configured = true;
+    }
+
+    /// The peer did not accept our options but suggested the values given
+    fn adopt(&mut self, options: Vec<ConfigOption>) {
+        for option in options {
+            match option.kind() {
+                OPTION_MTU => self.config.mtu = core::cmp::max(option.value_u16(), MIN_MTU_BREDR),
+                OPTION_FLUSH_TIMEOUT => self.config.flush_timeout = option.value_u16(),
+                OPTION_RETRANSMISSION_AND_FLOW_CONTROL => {
+                    if let Some(rfc) = RetransmissionAndFlowControl::parse(&option) {
+                        if let ChannelMode::Other(_) = rfc.mode {
+                            continue;
+                        }
+                        self.config.mode = rfc.mode;
+                        if rfc.tx_window > 0 && rfc.tx_window <= MAX_TX_WINDOW {
+                            self.config.tx_window = rfc.tx_window;
+                        }
+                        if rfc.mps > 0 {
+                            self.config.mps = rfc.mps;
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// The channel is open, start the enhanced retransmission or streaming mode if configured
+    fn open(&mut self) {
+        if self.config.mode == ChannelMode::Basic {
+            return;
+        }
+        let remote = self
+            .remote_rfc
+            .unwrap_or_else(|| self.config.retransmission_and_flow_control(0, 0));
+        self.ertm = Some(RetransmissionState::new(
+            &self.config,
+            &remote,
+            self.config.fcs || self.remote_fcs,
+            self.remote_cid,
+            self.timeouts.0,
+            self.timeouts.1,
+        ));
+    }
+
+    /// The channel has been closed, wake the Thinkables waiting on it
+    fn closed(mut self) {
+        if let Some(waker) = self.recv_waker.take() {
+            waker.wake();
+        }
+        if let Some(waker) = self.open_waker.take() {
+            waker.wake();
+        }
+        if let Some(waker) = self.send_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// The channels opened by peers for a PSM registered
+pub(crate) struct ChannelServerState {
+    config: ChannelConfig,
+    /// the connection handle and local channel id of the open channels waiting to be accepted
+    incoming: VecDeque<(u16, u16)>,
+    waker: Option<Waker>,
+}
+
+impl<T> L2cap<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    /// Register a PSM peers could open BR/EDR channels for. The channels are configured with the
+    /// given configuration and need to be accepted from the returned [ChannelServer].
+    pub fn register_psm(
+        this: Arc<DataLock<Self>>,
+        psm: u16,
+        config: ChannelConfig,
+    ) -> Result<ChannelServer<T>, BoxError> {
+        let mut l2cap = this.lock();
+        // a valid PSM is odd and the least significant bit of the upper octet is 0
+        if psm & 0x0101 != 0x0001 || !config.is_valid() || l2cap.psm_servers.contains_key(&psm) {
+            return Err(Box::new(L2capError::new("PSM not available")));
+        }
+        l2cap.psm_servers.insert(
+            psm,
+            ChannelServerState {
+                config,
+                incoming: VecDeque::new(),
+                waker: None,
+            },
+        );
+        drop(l2cap);
+        Ok(ChannelServer { l2cap: this, psm })
+    }
+
+    /// Open a channel to the given PSM of the peer of a BR/EDR connection. This concludes with the
+    /// channel once it has been configured by both sides.
+    pub fn connect_psm(
+        this: Arc<DataLock<Self>>,
+        connection: &Connection<T>,
+        psm: u16,
+        config: ChannelConfig,
+    ) -> ConnectChannelThinkable<T> {
+        let handle = connection.handle();
+        let mut thinkable = ConnectChannelThinkable {
+            l2cap: this.clone(),
+            connection: connection.clone(),
+            local_cid: 0,
+            send: None,
+            error: None,
+        };
+        if connection.info().transport != ConnectionTransport::BrEdr || !config.is_valid() {
+            thinkable.error = Some(Box::new(L2capError::new("invalid channel parameters")));
+            return thinkable;
+        }
+
+        let mut l2cap = this.lock();
+        let local_cid = match l2cap.allocate_cid(handle, BREDR_DYNAMIC_CID_END) {
+            Some(cid) => cid,
+            None => {
+                thinkable.error = Some(Box::new(L2capError::new("no channel id available")));
+                return thinkable;
+            }
+        };
+        l2cap
+            .channels
+            .insert((handle, local_cid), ChannelState::new(config, 0, None));
+        let mut data = Vec::with_capacity(4);
+        put_u16(&mut data, psm);
+        put_u16(&mut data, local_cid);
+        let request = SignalingPacket::new(
+            SignalingCode::ConnectionRequest,
+            l2cap.next_identifier(),
+            data,
+        );
+        drop(l2cap);
+
+        thinkable.local_cid = local_cid;
+        thinkable.send = Some(connection.send_acl(basic_frame(CID_SIGNALING, &request.to_bytes())));
+        thinkable
+    }
+
+    /// A peer requests to open a channel. This returns the response and our configuration
+    /// request if the channel has been opened.
+    pub(super) fn connection_requested(
+        &mut self,
+        handle: u16,
+        request: &SignalingPacket,
+    ) -> Vec<SignalingPacket> {
+        let psm = get_u16(&request.data, 0);
+        let remote_cid = get_u16(&request.data, 2);
+
+        let config = self.psm_servers.get(&psm).map(|server| server.config);
+        let result = match config {
+            None => CONNECTION_PSM_NOT_SUPPORTED,
+            Some(_) if remote_cid < DYNAMIC_CID_START => CONNECTION_INVALID_SOURCE_CID,
+            Some(_)
+                if self
+                    .channels
+                    .iter()
+                    .any(|(key, state)| key.0 == handle && state.remote_cid == remote_cid) =>
+            {
+                CONNECTION_SOURCE_CID_ALREADY_ALLOCATED
+            }
+            Some(_) if self.psm_servers[&psm].incoming.len() >= MAX_PENDING_CHANNELS => {
+                CONNECTION_NO_RESOURCES
+            }
+            Some(_) => CONNECTION_SUCCESSFUL,
+        };
+        let local_cid = match (result, config) {
+            (CONNECTION_SUCCESSFUL, Some(config)) => {
+                self.allocate_cid(handle, BREDR_DYNAMIC_CID_END).map(|cid| {
+                    self.channels.insert(
+                        (handle, cid),
+                        ChannelState::new(config, remote_cid, Some(psm)),
+                    );
+                    cid
+                })
+            }
+            _ => None,
+        };
+        let result = match (result, local_cid) {
+            (CONNECTION_SUCCESSFUL, None) => CONNECTION_NO_RESOURCES,
+            _ => result,
+        };
+        if result != CONNECTION_SUCCESSFUL {
+            info!("connection for PSM {:#X} refused: {:#X}", psm, result);
+        }
+
+        let mut data = Vec::with_capacity(8);
+        put_u16(&mut data, local_cid.unwrap_or(0));
+        put_u16(&mut data, remote_cid);
+        put_u16(&mut data, result);
+        // no further information on the status
+        put_u16(&mut data, 0);
+        let mut packets = vec![SignalingPacket::new(
+            SignalingCode::ConnectionResponse,
+            request.identifier,
+            data,
+        )];
+        if let Some(local_cid) = local_cid {
+            packets.extend(self.configure_request(handle, local_cid));
+        }
+        packets
+    }
+
+    /// The peer responded to our connection request. This returns our configuration request if
+    /// the channel has been opened.
+    pub(super) fn connection_responded(
+        &mut self,
+        handle: u16,
+        response: &SignalingPacket,
+    ) -> Option<SignalingPacket> {
+        let remote_cid = get_u16(&response.data, 0);
+        let local_cid = get_u16(&response.data, 2);
+        let result = get_u16(&response.data, 4);
+        let state = self.channels.get_mut(&(handle, local_cid))?;
+        match result {
+            CONNECTION_SUCCESSFUL => {
+                state.remote_cid = remote_cid;
+                self.configure_request(handle, local_cid)
+            }
+            CONNECTION_PENDING => None,
+            _ => {
+                state.refused = Some(result);
+                if let Some(waker) = state.open_waker.take() {
+                    waker.wake();
+                }
+                None
+            }
+        }
+    }
+
+    /// Create the configuration request with our options for a channel
+    fn configure_request(&mut self, handle: u16, local_cid: u16) -> Option<SignalingPacket> {
+        let state = self.channels.get_mut(&(handle, local_cid))?;
+        state.config_attempts += 1;
+        let mut data = Vec::new();
+        put_u16(&mut data, state.remote_cid);
+        put_u16(&mut data, 0);
+        for option in state.config.options() {
+            option.put(&mut data);
+        }
+        Some(SignalingPacket::new(
+            SignalingCode::ConfigureRequest,
+            self.next_identifier(),
+            data,
+        ))
+    }
+
+    /// The peer requests its configuration of a channel, returns the response to be send
+    pub(super) fn configure_requested(
+        &mut self,
+        handle: u16,
+        request: &SignalingPacket,
+    ) -> SignalingPacket {
+        let local_cid = get_u16(&request.data, 0);
+        let flags = get_u16(&request.data, 2);
+        let state = match self.channels.get_mut(&(handle, local_cid)) {
+            Some(state) => state,
+            None => {
+                let mut reason_data = Vec::with_capacity(4);
+                put_u16(&mut reason_data, local_cid);
+                put_u16(&mut reason_data, 0);
+                return SignalingPacket::command_reject(
+                    request.identifier,
+                    REJECT_INVALID_CID,
+                    &reason_data,
+                );
+            }
+        };
+        let (result, options) = match ConfigOption::parse_all(request.data.get(4..).unwrap_or(&[]))
+        {
+            Some(options) => state.evaluate(options),
+            None => (CONFIG_REJECTED, Vec::new()),
+        };
+        let remote_cid = state.remote_cid;
+        if result == CONFIG_SUCCESS && flags & CONFIG_FLAG_CONTINUATION == 0 {
+            state.remote_configured = true;
+            self.configured(handle, local_cid);
+        }
+
+        let mut data = Vec::new();
+        put_u16(&mut data, remote_cid);
+        put_u16(&mut data, flags & CONFIG_FLAG_CONTINUATION);
+        put_u16(&mut data, result);
+        for option in options {
+            option.put(&mut data);
+        }
+        SignalingPacket::new(SignalingCode::ConfigureResponse, request.identifier, data)
+    }
+
+    /// The peer responded to our configuration request. This returns the signaling command to be
+    /// send next, which is either a new configuration request or the disconnection request if the
+    /// configuration failed.
+    pub(super) fn configure_responded(
+        &mut self,
+        handle: u16,
+        response: &SignalingPacket,
+    ) -> Option<SignalingPacket> {
+        let local_cid = get_u16(&response.data, 0);
+        let flags = get_u16(&response.data, 2);
+        let result = get_u16(&response.data, 4);
+        let options = ConfigOption::parse_all(response.data.get(6..).unwrap_or(&[]));
+        let state = self.channels.get_mut(&(handle, local_cid))?;
+        match (result, options) {
+            (CONFIG_SUCCESS, options) => {
+                if flags & CONFIG_FLAG_CONTINUATION == 0 {
+                    state.accepted(options);
+                    self.configured(handle, local_cid);
+                }
+                None
+            }
+            (CONFIG_PENDING, _) => None,
+            (CONFIG_UNACCEPTABLE_PARAMETERS, Some(options))
+                if state.config_attempts < MAX_CONFIG_ATTEMPTS =>
+            {
+                state.adopt(options);
+                self.configure_request(handle, local_cid)
+            }
+            _ => {
+                warn!(
+                    "configuration of L2CAP channel {:#X} failed: {:#X}",
+                    local_cid, result
+                );
+                let remote_cid = state.remote_cid;
+                self.remove_channel(handle, local_cid);
+                let mut data = Vec::with_capacity(4);
+                put_u16(&mut data, remote_cid);
+                put_u16(&mut data, local_cid);
+                Some(SignalingPacket::new(
+                    SignalingCode::DisconnectionRequest,
+                    self.next_identifier(),
+                    data,
+                ))
+            }
+        }
+    }
+
+    /// One side of the configuration is done. Once the channel is open it is passed to the one
+    /// waiting for it.
+    fn configured(&mut self, handle: u16, local_cid: u16) {
+        let state = match self.channels.get_mut(&(handle, local_cid)) {
+            Some(state) if state.is_open() => state,
+            _ => return,
+        };
+        info!(
+            "L2CAP channel {:#X} open in {:?} mode",
+            local_cid, state.config.mode
+        );
+        state.open();
+        if let Some(waker) = state.open_waker.take() {
+            waker.wake();
+        }
+        if let Some(psm) = state.incoming_psm.take() {
+            if let Some(server) = self.psm_servers.get_mut(&psm) {
+                server.incoming.push_back((handle, local_cid));
+                if let Some(waker) = server.waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+
+    /// A frame has been received on a dynamic channel. The frame contains the basic L2CAP
+    /// header. This returns the retransmission timer to be started if any.
+    pub(super) fn received_frame(
+        &mut self,
+        handle: u16,
+        cid: u16,
+        frame: &[u8],
+    ) -> Option<ErtmTimer> {
+        let state = self.channels.get_mut(&(handle, cid))?;
+        if let Some(ref mut ertm) = state.ertm {
+            let mut actions = ErtmActions::default();
+            ertm.received(frame, &mut actions);
+            return self.apply_ertm(handle, cid, actions);
+        }
+
+        let payload = &frame[L2CAP_HEADER_SIZE..];
+        if payload.len() > state.config.mtu as usize {
+            warn!("SDU exceeding the MTU of L2CAP channel {:#X} dropped", cid);
+            return None;
+        }
+        if state.sdus.len() >= MAX_PENDING_SDUS {
+            warn!("L2CAP channel {:#X} queue full, drop oldest SDU", cid);
+            state.sdus.pop_front();
+        }
+        state.sdus.push_back(payload.to_vec());
+        if let Some(waker) = state.recv_waker.take() {
+            waker.wake();
+        }
+        None
+    }
+
+    /// A retransmission or monitor timer of a channel has expired. This returns the timer to be
+    /// started next if any.
+    pub(super) fn ertm_timeout(
+        &mut self,
+        handle: u16,
+        cid: u16,
+        generation: u32,
+    ) -> Option<ErtmTimer> {
+        let ertm = self.channels.get_mut(&(handle, cid))?.ertm.as_mut()?;
+        let mut actions = ErtmActions::default();
+        ertm.timeout(generation, &mut actions);
+        self.apply_ertm(handle, cid, actions)
+    }
+
+    /// Carry out the actions of the enhanced retransmission or streaming mode of a channel. The
+    /// frames are queued in order to the ACL data path. This returns the timer to be started if
+    /// any.
+    fn apply_ertm(&mut self, handle: u16, cid: u16, actions: ErtmActions) -> Option<ErtmTimer> {
+        let hci = self.hci.clone();
+        let mut hci = hci.lock();
+        for pdu in actions.pdus.iter() {
+            hci.queue_acl_pdu(handle, pdu);
+        }
+        let state = self.channels.get_mut(&(handle, cid))?;
+        if !actions.sdus.is_empty() {
+            for sdu in actions.sdus {
+                if state.sdus.len() >= MAX_PENDING_SDUS {
+                    warn!("L2CAP channel {:#X} queue full, drop oldest SDU", cid);
+                    state.sdus.pop_front();
+                }
+                state.sdus.push_back(sdu);
+            }
+            if let Some(waker) = state.recv_waker.take() {
+                waker.wake();
+            }
+        }
+        if state.ertm.as_ref().map_or(false, |ertm| ertm.all_send()) {
+            if let Some(waker) = state.send_waker.take() {
+                waker.wake();
+            }
+        }
+        if actions.disconnect {
+            warn!(
+                "L2CAP channel {:#X} closed as the peer does not respond",
+                cid
+            );
+            let remote_cid = self.remove_channel(handle, cid)?;
+            let mut data = Vec::with_capacity(4);
+            put_u16(&mut data, remote_cid);
+            put_u16(&mut data, cid);
+            let request = SignalingPacket::new(
+                SignalingCode::DisconnectionRequest,
+                self.next_identifier(),
+                data,
+            );
+            hci.queue_acl_pdu(handle, &basic_frame(CID_SIGNALING, &request.to_bytes()));
+            return None;
+        }
+        actions.timer
+    }
+
+    /// The connection has been closed, close all of its channels
+    pub(super) fn channels_closed(&mut self, handle: u16) {
+        let cids: Vec<u16> = self
+            .channels
+            .keys()
+            .filter(|key| key.0 == handle)
+            .map(|key| key.1)
+            .collect();
+        for cid in cids {
+            self.remove_channel(handle, cid);
+        }
+    }
+
+    /// Remove a BR/EDR channel. This returns the remote channel id if the channel existed.
+    pub(super) fn remove_channel(&mut self, handle: u16, local_cid: u16) -> Option<u16> {
+        let state = self.channels.remove(&(handle, local_cid))?;
+        let remote_cid = state.remote_cid;
+        state.closed();
+        for server in self.psm_servers.values_mut() {
+            server
+                .incoming
+                .retain(|incoming| *incoming != (handle, local_cid));
+        }
+        Some(remote_cid)
+    }
+}
+
+/// Start a retransmission or monitor timer of a channel in enhanced retransmission mode
+pub(super) fn start_ertm_timer<T>(
+    l2cap: Arc<DataLock<L2cap<T>>>,
+    handle: u16,
+    cid: u16,
+    timer: ErtmTimer,
+) where
+    T: HcTransportLayer + 'static,
+{
+    spawn(wait(Mseconds(timer.timeout as u64), ()).map(move |_| {
+        let next = l2cap.lock().ertm_timeout(handle, cid, timer.generation);
+        if let Some(next) = next {
+            start_ertm_timer(l2cap, handle, cid, next);
+        }
+    }));
+}
+
+/// Accepts the BR/EDR channels opened by peers for a PSM
+pub struct ChannelServer<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    l2cap: Arc<DataLock<L2cap<T>>>,
+    psm: u16,
+}
+
+impl<T> ChannelServer<T>
+where
+    T: HcTransportLayer,
+{
+    pub fn psm(&self) -> u16 {
+        self.psm
+    }
+
+    /// Returns a ``Thinkable`` that concludes with the next channel opened and configured by a
+    /// peer
+    pub fn accept(&self) -> AcceptChannelThinkable<T> {
+        AcceptChannelThinkable {
+            l2cap: self.l2cap.clone(),
+            psm: self.psm,
+        }
+    }
+}
+
+/// This ``Thinkable`` concludes with the next channel opened by a peer for a PSM
+pub struct AcceptChannelThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    l2cap: Arc<DataLock<L2cap<T>>>,
+    psm: u16,
+}
+
+impl<T> Thinkable for AcceptChannelThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = Channel<T>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        let mut l2cap = self.l2cap.lock();
+        let hci = l2cap.hci.clone();
+        let server = match l2cap.psm_servers.get_mut(&self.psm) {
+            Some(server) => server,
+            None => return Conclusion::Pending,
+        };
+        while let Some((handle, local_cid)) = server.incoming.pop_front() {
+            if let Some(connection) = Hci::connection(hci.clone(), handle) {
+                return Conclusion::Ready(Channel {
+                    l2cap: self.l2cap.clone(),
+                    connection,
+                    local_cid,
+                });
+            }
+        }
+        server.waker.replace(cx.waker().clone());
+        Conclusion::Pending
+    }
+}
+
+/// This ``Thinkable`` opens a BR/EDR channel and concludes with the channel once it has been
+/// configured by both sides
+pub struct ConnectChannelThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    l2cap: Arc<DataLock<L2cap<T>>>,
+    connection: Connection<T>,
+    local_cid: u16,
+    send: Option<SendAclThinkable<T>>,
+    error: Option<BoxError>,
+}
+
+impl<T> Thinkable for ConnectChannelThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = Result<Channel<T>, BoxError>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        // none of the fields is structurally pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        if let Some(error) = this.error.take() {
+            return Conclusion::Ready(Err(error));
+        }
+        let key = (this.connection.handle(), this.local_cid);
+        if let Some(ref mut send) = this.send {
+            match Pin::new(send).think(cx) {
+                Conclusion::Pending => return Conclusion::Pending,
+                Conclusion::Ready(Err(error)) => {
+                    this.l2cap.lock().remove_channel(key.0, key.1);
+                    return Conclusion::Ready(Err(error));
+                }
+                Conclusion::Ready(Ok(_)) => {
+                    this.send.take();
+                }
+            }
+        }
+
+        let mut l2cap = this.l2cap.lock();
+        let state = match l2cap.channels.get_mut(&key) {
+            Some(state) => state,
+            None => return Conclusion::Ready(Err(Box::new(L2capError::new("channel closed")))),
+        };
+        if let Some(result) = state.refused {
+            l2cap.remove_channel(key.0, key.1);
+            return Conclusion::Ready(Err(Box::new(L2capConnectionRefused { result })));
+        }
+        if !state.is_open() {
+            state.open_waker.replace(cx.waker().clone());
+            return Conclusion::Pending;
+        }
+        Conclusion::Ready(Ok(Channel {
+            l2cap: this.l2cap.clone(),
+            connection: this.connection.clone(),
+            local_cid: this.local_cid,
+        }))
+    }
+}
+
+/// A BR/EDR connection oriented channel to a peer
+pub struct Channel<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    l2cap: Arc<DataLock<L2cap<T>>>,
+    connection: Connection<T>,
+    local_cid: u16,
+}
+
+impl<T> Channel<T>
+where
+    T: HcTransportLayer,
+{
+    pub fn connection(&self) -> &Connection<T> {
+        &self.connection
+    }
+
+    pub fn local_cid(&self) -> u16 {
+        self.local_cid
+    }
+
+    /// The maximum size of an SDU the peer accepts. This is 0 if the channel has been closed.
+    pub fn remote_mtu(&self) -> u16 {
+        let key = (self.connection.handle(), self.local_cid);
+        self.l2cap
+            .read()
+            .channels
+            .get(&key)
+            .map_or(0, |state| state.remote_mtu)
+    }
+
+    /// The mode the channel has been configured with. This is basic mode if the channel has been
+    /// closed.
+    pub fn mode(&self) -> ChannelMode {
+        let key = (self.connection.handle(), self.local_cid);
+        self.l2cap
+            .read()
+            .channels
+            .get(&key)
+            .map_or(ChannelMode::Basic, |state| state.config.mode)
+    }
+
+    /// Send an SDU to the peer. In basic mode it concludes once the SDU is passed to the BT host.
+    /// In enhanced retransmission and streaming mode it concludes once all I-frames of the SDU
+    /// have been send, which depends on the transmit window.
+    pub fn send(&self, sdu: &[u8]) -> SendChannelThinkable<T> {
+        let key = (self.connection.handle(), self.local_cid);
+        let mut l2cap = self.l2cap.lock();
+        let state = match l2cap.channels.get_mut(&key) {
+            Some(state) if sdu.len() > state.remote_mtu as usize => {
+                return SendChannelThinkable::failed(L2capError::new("SDU exceeds MTU"))
+            }
+            Some(state) => state,
+            None => return SendChannelThinkable::failed(L2capError::new("channel closed")),
+        };
+        let ertm = match state.ertm {
+            Some(ref mut ertm) => ertm,
+            None => {
+                let remote_cid = state.remote_cid;
+                drop(l2cap);
+                return SendChannelThinkable {
+                    send: Some(self.connection.send_acl(basic_frame(remote_cid, sdu))),
+                    queued: None,
+                    error: None,
+                };
+            }
+        };
+        let mut actions = ErtmActions::default();
+        ertm.queue_sdu(sdu, &mut actions);
+        let timer = l2cap.apply_ertm(key.0, key.1, actions);
+        drop(l2cap);
+        if let Some(timer) = timer {
+            start_ertm_timer(self.l2cap.clone(), key.0, key.1, timer);
+        }
+        SendChannelThinkable {
+            send: None,
+            queued: Some((self.l2cap.clone(), key)),
+            error: None,
+        }
+    }
+
+    /// Returns a ``Thinkable`` that concludes with the next SDU received from the peer or with
+    /// ``None`` once the channel has been closed
+    pub fn recv(&self) -> RecvChannelThinkable<T> {
+        RecvChannelThinkable {
+            l2cap: self.l2cap.clone(),
+            handle: self.connection.handle(),
+            local_cid: self.local_cid,
+        }
+    }
+
+    /// Close the channel. Data received afterwards is dropped.
+    pub fn disconnect(&self) -> DisconnectChannelThinkable<T> {
+        L2cap::disconnect_channel(self.l2cap.clone(), &self.connection, self.local_cid)
+    }
+}
+
+/// This ``Thinkable`` sends an SDU on a BR/EDR channel
+pub struct SendChannelThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    /// the B-frame send in basic mode
+    send: Option<SendAclThinkable<T>>,
+    /// the channel the SDU has been queued to in enhanced retransmission or streaming mode
+    queued: Option<(Arc<DataLock<L2cap<T>>>, (u16, u16))>,
+    error: Option<BoxError>,
+}
+
+impl<T> SendChannelThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    fn failed(error: L2capError) -> Self {
+        Self {
+            send: None,
+            queued: None,
+            error: Some(Box::new(error)),
+        }
+    }
+}
+
+impl<T> Thinkable for SendChannelThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = Result<(), BoxError>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        // none of the fields is structurally pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        if let Some(error) = this.error.take() {
+            return Conclusion::Ready(Err(error));
+        }
+        if let Some(ref mut send) = this.send {
+            return Pin::new(send).think(cx);
+        }
+        let (l2cap, key) = match this.queued {
+            Some(ref queued) => queued,
+            None => return Conclusion::Pending,
+        };
+        let mut l2cap = l2cap.lock();
+        match l2cap.channels.get_mut(key) {
+            Some(state) if state.ertm.as_ref().map_or(true, |ertm| ertm.all_send()) => {
+                Conclusion::Ready(Ok(()))
+            }
+            Some(state) => {
+                state.send_waker.replace(cx.waker().clone());
+                Conclusion::Pending
+            }
+            None => Conclusion::Ready(Err(Box::new(L2capError::new("channel closed")))),
+        }
+    }
+}
+
+/// This ``Thinkable`` concludes with the next SDU received on a BR/EDR channel
+pub struct RecvChannelThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    l2cap: Arc<DataLock<L2cap<T>>>,
+    handle: u16,
+    local_cid: u16,
+}
+
+impl<T> Thinkable for RecvChannelThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = Option<Vec<u8>>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        let mut l2cap = self.l2cap.lock();
+        match l2cap.channels.get_mut(&(self.handle, self.local_cid)) {
+            Some(state) => match state.sdus.pop_front() {
+                Some(sdu) => Conclusion::Ready(Some(sdu)),
+                None => {
+                    state.recv_waker.replace(cx.waker().clone());
+                    Conclusion::Pending
+                }
+            },
+            None => Conclusion::Ready(None),
+        }
+    }
+}
@@ -0,0 +1,303 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # L2CAP Channel Configuration
+//!
+//! BR/EDR connection oriented channels are configured by each side sending a Configuration
+//! Request with the options it would like to use for the data it receives. Options not given keep
+//! their default value. The retransmission and flow control option selects the mode of the
+//! channel, which has to be the same for both directions.
+//!
+
+use crate::alloc::vec;
+use crate::alloc::vec::Vec;
+
+use super::signaling::{get_u16, put_u16};
+
+/// The MTU of a BR/EDR channel if not configured otherwise
+pub const DEFAULT_MTU: u16 = 672;
+/// The minimum MTU of a BR/EDR channel
+pub const MIN_MTU_BREDR: u16 = 48;
+/// Flush timeout value meaning the packets are never flushed
+pub const FLUSH_TIMEOUT_INFINITE: u16 = 0xFFFF;
+
+/// Configuration option carrying the MTU
+pub const OPTION_MTU: u8 = 0x01;
+/// Configuration option carrying the flush timeout
+pub const OPTION_FLUSH_TIMEOUT: u8 = 0x02;
+/// Configuration option carrying the quality of service
+pub const OPTION_QOS: u8 = 0x03;
+/// Configuration option carrying the retransmission and flow control mode
+pub const OPTION_RETRANSMISSION_AND_FLOW_CONTROL: u8 = 0x04;
+/// Configuration option carrying the frame check sequence type
+pub const OPTION_FCS: u8 = 0x05;
+/// Bit set in the option type if the option is only a hint and may be ignored by the receiver
+pub const OPTION_HINT: u8 = 0x80;
+
+/// Configuration response result: the options are accepted
+pub const CONFIG_SUCCESS: u16 = 0x0000;
+/// Configuration response result: the options are not acceptable
+pub const CONFIG_UNACCEPTABLE_PARAMETERS: u16 = 0x0001;
+/// Configuration response result: the request is rejected
+pub const CONFIG_REJECTED: u16 = 0x0002;
+/// Configuration response result: the request contains unknown options
+pub const CONFIG_UNKNOWN_OPTIONS: u16 = 0x0003;
+/// Configuration response result: the configuration is not finished yet
+pub const CONFIG_PENDING: u16 = 0x0004;
+/// Configuration flag signaling more options follow in the next request or response
+pub const CONFIG_FLAG_CONTINUATION: u16 = 0x0001;
+
+/// FCS option value: no frame check sequence
+pub const FCS_NONE: u8 = 0x00;
+/// FCS option value: 16 bit frame check sequence
+pub const FCS_16: u8 = 0x01;
+
+/// The retransmission timeout in milliseconds proposed to the peer
+pub const DEFAULT_RETRANSMISSION_TIMEOUT: u16 = 2000;
+/// The monitor timeout in milliseconds proposed to the peer
+pub const DEFAULT_MONITOR_TIMEOUT: u16 = 12000;
+/// The transmit window requested if not configured otherwise
+pub const DEFAULT_TX_WINDOW: u8 = 10;
+/// The largest transmit window without the extended window size option
+pub const MAX_TX_WINDOW: u8 = 63;
+
+/// The mode of a BR/EDR channel
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ChannelMode {
+    Basic,
+    EnhancedRetransmission,
+    Streaming,
+    Other(u8),
+}
+
+impl ChannelMode {
+    pub fn code(&self) -> u8 {
+        match self {
+            ChannelMode::Basic => 0x00,
+            ChannelMode::EnhancedRetransmission => 0x03,
+            ChannelMode::Streaming => 0x04,
+            ChannelMode::Other(code) => *code,
+        }
+    }
+}
+
+impl From<u8> for ChannelMode {
+    fn from(orig: u8) -> Self {
+        match orig {
+            0x00 => ChannelMode::Basic,
+            0x03 => ChannelMode::EnhancedRetransmission,
+            0x04 => ChannelMode::Streaming,
+            _ => ChannelMode::Other(orig),
+        }
+    }
+}
+
+/// The value of the retransmission and flow control option
+#[derive(Debug, Copy, Clone)]
+pub struct RetransmissionAndFlowControl {
+    pub mode: ChannelMode,
+    /// the number of unacknowledged I-frames the sender of the option is able to receive
+    pub tx_window: u8,
+    /// the number of transmissions of an I-frame before the channel is closed, 0 for infinite
+    pub max_transmit: u8,
+    /// the retransmission timeout in milliseconds, only given in a configuration response
+    pub retransmission_timeout: u16,
+    /// the monitor timeout in milliseconds, only given in a configuration response
+    pub monitor_timeout: u16,
+    /// the maximum payload of an I-frame the sender of the option is able to receive
+    pub mps: u16,
+}
+
+impl RetransmissionAndFlowControl {
+    pub fn parse(option: &ConfigOption) -> Option<Self> {
+        if option.data.len() < 9 {
+            return None;
+        }
+        Some(Self {
+            mode: option.data[0].into(),
+            tx_window: option.data[1],
+            max_transmit: option.data[2],
+            retransmission_timeout: get_u16(&option.data, 3),
+            monitor_timeout: get_u16(&option.data, 5),
+            mps: get_u16(&option.data, 7),
+        })
+    }
+
+    pub fn to_option(&self) -> ConfigOption {
+        let mut data = Vec::with_capacity(9);
+        data.push(self.mode.code());
+        data.push(self.tx_window);
+        data.push(self.max_transmit);
+        put_u16(&mut data, self.retransmission_timeout);
+        put_u16(&mut data, self.monitor_timeout);
+        put_u16(&mut data, self.mps);
+        ConfigOption::new(OPTION_RETRANSMISSION_AND_FLOW_CONTROL, data)
+    }
+}
+
+/// One option of a Configuration Request or Response
+#[derive(Debug, Clone)]
+pub struct ConfigOption {
+    pub option_type: u8,
+    pub data: Vec<u8>,
+}
+
+impl ConfigOption {
+    pub fn new(option_type: u8, data: Vec<u8>) -> Self {
+        Self { option_type, data }
+    }
+
+    pub fn mtu(mtu: u16) -> Self {
+        let mut data = Vec::with_capacity(2);
+        put_u16(&mut data, mtu);
+        Self::new(OPTION_MTU, data)
+    }
+
+    pub fn flush_timeout(flush_timeout: u16) -> Self {
+        let mut data = Vec::with_capacity(2);
+        put_u16(&mut data, flush_timeout);
+        Self::new(OPTION_FLUSH_TIMEOUT, data)
+    }
+
+    /// The option type without the hint bit
+    pub fn kind(&self) -> u8 {
+        self.option_type & !OPTION_HINT
+    }
+
+    pub fn is_hint(&self) -> bool {
+        self.option_type & OPTION_HINT != 0
+    }
+
+    /// The value of options carrying a single u16
+    pub fn value_u16(&self) -> u16 {
+        get_u16(&self.data, 0)
+    }
+
+    /// Parse the options of a Configuration Request or Response. This returns ``None`` if the
+    /// options are malformed.
+    pub fn parse_all(data: &[u8]) -> Option<Vec<ConfigOption>> {
+        let mut options = Vec::new();
+        let mut offset = 0;
+        while offset < data.len() {
+            let length = *data.get(offset + 1)? as usize;
+            let value = data.get(offset + 2..offset + 2 + length)?;
+            options.push(ConfigOption::new(data[offset], value.to_vec()));
+            offset += 2 + length;
+        }
+        Some(options)
+    }
+
+    /// Append the option to the data of a Configuration Request or Response
+    pub fn put(&self, data: &mut Vec<u8>) {
+        data.push(self.option_type);
+        data.push(self.data.len() as u8);
+        data.extend_from_slice(&self.data);
+    }
+}
+
+/// The configuration of the receiving side of a BR/EDR channel
+#[derive(Debug, Copy, Clone)]
+pub struct ChannelConfig {
+    /// the maximum size of an SDU received
+    pub mtu: u16,
+    /// the time in milliseconds the BT host tries to deliver a packet before it is flushed
+    pub flush_timeout: u16,
+    /// the mode requested for the channel
+    pub mode: ChannelMode,
+    /// the number of unacknowledged I-frames that can be received in enhanced retransmission mode
+    pub tx_window: u8,
+    /// the number of transmissions of an I-frame the peer should try, 0 for infinite
+    pub max_transmit: u8,
+    /// the maximum payload of an I-frame received
+    pub mps: u16,
+    /// whether the frame check sequence is requested in enhanced retransmission and streaming
+    /// mode. It is only omitted if both sides do not request it.
+    pub fcs: bool,
+}
+
+impl ChannelConfig {
+    pub fn new(mtu: u16, flush_timeout: u16) -> Self {
+        Self {
+            mtu,
+            flush_timeout,
+            mode: ChannelMode::Basic,
+            tx_window: DEFAULT_TX_WINDOW,
+            max_transmit: 3,
+            mps: mtu,
+            fcs: true,
+        }
+    }
+
+    /// The configuration of a channel in enhanced retransmission mode with the given MTU
+    pub fn enhanced_retransmission(mtu: u16) -> Self {
+        Self {
+            mode: ChannelMode::EnhancedRetransmission,
+            ..Self::new(mtu, FLUSH_TIMEOUT_INFINITE)
+        }
+    }
+
+    /// The configuration of a channel in streaming mode with the given MTU
+    pub fn streaming(mtu: u16) -> Self {
+        Self {
+            mode: ChannelMode::Streaming,
+            ..Self::new(mtu, FLUSH_TIMEOUT_INFINITE)
+        }
+    }
+
+    pub(crate) fn is_valid(&self) -> bool {
+        let mode_valid = match self.mode {
+            ChannelMode::Basic => true,
+            ChannelMode::EnhancedRetransmission => {
+                self.tx_window > 0 && self.tx_window <= MAX_TX_WINDOW && self.mps > 0
+            }
+            ChannelMode::Streaming => self.mps > 0,
+            ChannelMode::Other(_) => false,
+        };
+        self.mtu >= MIN_MTU_BREDR && self.flush_timeout > 0 && mode_valid
+    }
+
+    /// The retransmission and flow control option describing the receiving side of this
+    /// configuration. The timeouts are only given in the configuration response.
+    pub(crate) fn retransmission_and_flow_control(
+        &self,
+        retransmission_timeout: u16,
+        monitor_timeout: u16,
+    ) -> RetransmissionAndFlowControl {
+        RetransmissionAndFlowControl {
+            mode: self.mode,
+            tx_window: self.tx_window,
+            max_transmit: self.max_transmit,
+            retransmission_timeout,
+            monitor_timeout,
+            mps: self.mps,
+        }
+    }
+
+    /// The options to request from the peer. Options with their default value are not send.
+    pub(crate) fn options(&self) -> Vec<ConfigOption> {
+        let mut options = Vec::new();
+        if self.mtu != DEFAULT_MTU {
+            options.push(ConfigOption::mtu(self.mtu));
+        }
+        if self.flush_timeout != FLUSH_TIMEOUT_INFINITE {
+            options.push(ConfigOption::flush_timeout(self.flush_timeout));
+        }
+        if self.mode != ChannelMode::Basic {
+            options.push(self.retransmission_and_flow_control(0, 0).to_option());
+            if !self.fcs {
+                options.push(ConfigOption::new(OPTION_FCS, vec![FCS_NONE]));
+            }
+        }
+        options
+    }
+}
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        Self::new(DEFAULT_MTU, FLUSH_TIMEOUT_INFINITE)
+    }
+}
@@ -0,0 +1,780 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # LE Credit Based Connection Oriented Channels
+//!
+//! Channels opened on an LE connection for a simplified protocol/service multiplexer (SPSM). Each
+//! SDU send is segmented into K-frames that fit the MPS of the receiver, and every K-frame
+//! consumes one credit granted by the receiver. Received SDU's are reassembled and the credits are
+//! given back to the peer as the SDU's are taken from the channel.
+//!
+
+use super::*;
+
+/// First channel id of the LE dynamic channels
+pub const LE_DYNAMIC_CID_START: u16 = DYNAMIC_CID_START;
+/// Last channel id of the LE dynamic channels
+pub const LE_DYNAMIC_CID_END: u16 = 0x007F;
+/// Minimum MTU of an LE credit based channel
+pub const LE_CREDIT_MIN_MTU: u16 = 23;
+/// Minimum MPS of an LE credit based channel
+pub const LE_CREDIT_MIN_MPS: u16 = 23;
+/// Maximum MPS of an LE credit based channel
+pub const LE_CREDIT_MAX_MPS: u16 = 65533;
+
+/// LE Credit Based Connection response result: the connection is established
+pub const LE_CONNECTION_SUCCESSFUL: u16 = 0x0000;
+/// LE Credit Based Connection response result: the SPSM is not supported
+pub const LE_CONNECTION_SPSM_NOT_SUPPORTED: u16 = 0x0002;
+/// LE Credit Based Connection response result: no resources available
+pub const LE_CONNECTION_NO_RESOURCES: u16 = 0x0004;
+/// LE Credit Based Connection response result: the source channel id is invalid
+pub const LE_CONNECTION_INVALID_SOURCE_CID: u16 = 0x0009;
+/// LE Credit Based Connection response result: the source channel id is already in use
+pub const LE_CONNECTION_SOURCE_CID_ALREADY_ALLOCATED: u16 = 0x000A;
+/// LE Credit Based Connection response result: the parameters are not acceptable
+pub const LE_CONNECTION_UNACCEPTABLE_PARAMETERS: u16 = 0x000B;
+
+/// Size of the SDU length field in the first K-frame of each SDU
+const SDU_LENGTH_SIZE: usize = 2;
+/// Maximum number of reassembled SDU's kept per channel. The peer does not get new credits while
+/// this number of SDU's is waiting to be taken from the channel.
+const MAX_PENDING_SDUS: usize = 8;
+/// Maximum number of channels opened by peers waiting to be accepted per SPSM
+const MAX_PENDING_CHANNELS: usize = 4;
+
+/// The parameters of the receiving side of an LE credit based channel
+#[derive(Debug, Copy, Clone)]
+pub struct CreditChannelConfig {
+    /// the maximum size of an SDU received
+    pub mtu: u16,
+    /// the maximum payload size of a K-frame received
+    pub mps: u16,
+    /// the number of K-frames the peer may send without waiting for new credits
+    pub credits: u16,
+}
+
+impl CreditChannelConfig {
+    pub fn new(mtu: u16, mps: u16, credits: u16) -> Self {
+        Self { mtu, mps, credits }
+    }
+
+    fn is_valid(&self) -> bool {
+        self.mtu >= LE_CREDIT_MIN_MTU
+            && self.mps >= LE_CREDIT_MIN_MPS
+            && self.mps <= LE_CREDIT_MAX_MPS
+            && self.credits > 0
+    }
+}
+
+impl Default for CreditChannelConfig {
+    fn default() -> Self {
+        Self::new(512, 247, 8)
+    }
+}
+
+/// The state of one LE credit based channel
+pub(crate) struct CreditChannelState {
+    remote_cid: u16,
+    config: CreditChannelConfig,
+    remote_mtu: u16,
+    remote_mps: u16,
+    /// the number of K-frames the peer may still send
+    rx_credits: u16,
+    /// the number of K-frames that may still be send to the peer
+    tx_credits: u16,
+    /// the SDU currently reassembled with its announced length
+    sdu: Option<(usize, Vec<u8>)>,
+    sdus: VecDeque<Vec<u8>>,
+    recv_waker: Option<Waker>,
+    send_waker: Option<Waker>,
+}
+
+impl CreditChannelState {
+    fn new(config: CreditChannelConfig) -> Self {
+        Self {
+            remote_cid: 0,
+            config,
+            remote_mtu: 0,
+            remote_mps: 0,
+            rx_credits: config.credits,
+            tx_credits: 0,
+            sdu: None,
+            sdus: VecDeque::new(),
+            recv_waker: None,
+            send_waker: None,
+        }
+    }
+
+    pub(super) fn remote_cid(&self) -> u16 {
+        self.remote_cid
+    }
+
+    /// The channel has been closed, wake the Thinkables waiting on it
+    fn closed(mut self) {
+        if let Some(waker) = self.recv_waker.take() {
+            waker.wake();
+        }
+        if let Some(waker) = self.send_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// The channels opened by peers for an SPSM registered
+pub(crate) struct CreditServerState {
+    config: CreditChannelConfig,
+    /// the connection handle and local channel id of the channels waiting to be accepted
+    incoming: VecDeque<(u16, u16)>,
+    waker: Option<Waker>,
+}
+
+impl<T> L2cap<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    /// Register an SPSM peers could open LE credit based channels for. The channels are opened
+    /// with the given configuration and need to be accepted from the returned [CreditServer].
+    pub fn register_le_psm(
+        this: Arc<DataLock<Self>>,
+        spsm: u16,
+        config: CreditChannelConfig,
+    ) -> Result<CreditServer<T>, BoxError> {
+        let mut l2cap = this.lock();
+        if spsm == 0 || spsm > 0x00FF || !config.is_valid() || l2cap.le_servers.contains_key(&spsm)
+        {
+            return Err(Box::new(L2capError::new("SPSM not available")));
+        }
+        l2cap.le_servers.insert(
+            spsm,
+            CreditServerState {
+                config,
+                incoming: VecDeque::new(),
+                waker: None,
+            },
+        );
+        drop(l2cap);
+        Ok(CreditServer { l2cap: this, spsm })
+    }
+
+    /// Open an LE credit based channel to the given SPSM of the peer of an LE connection
+    pub fn connect_le_psm(
+        this: Arc<DataLock<Self>>,
+        connection: &Connection<T>,
+        spsm: u16,
+        config: CreditChannelConfig,
+    ) -> ConnectCreditChannelThinkable<T> {
+        let handle = connection.handle();
+        let mut thinkable = ConnectCreditChannelThinkable {
+            l2cap: this.clone(),
+            connection: connection.clone(),
+            local_cid: 0,
+            request: None,
+            error: None,
+        };
+        if connection.info().transport != ConnectionTransport::Le || !config.is_valid() {
+            thinkable.error = Some(Box::new(L2capError::new("invalid channel parameters")));
+            return thinkable;
+        }
+
+        let mut l2cap = this.lock();
+        let local_cid = match l2cap.allocate_cid(handle, LE_DYNAMIC_CID_END) {
+            Some(cid) => cid,
+            None => {
+                thinkable.error = Some(Box::new(L2capError::new("no channel id available")));
+                return thinkable;
+            }
+        };
+        l2cap
+            .credit_channels
+            .insert((handle, local_cid), CreditChannelState::new(config));
+        drop(l2cap);
+
+        let mut data = Vec::with_capacity(10);
+        put_u16(&mut data, spsm);
+        put_u16(&mut data, local_cid);
+        put_u16(&mut data, config.mtu);
+        put_u16(&mut data, config.mps);
+        put_u16(&mut data, config.credits);
+        thinkable.local_cid = local_cid;
+        thinkable.request = Some(Self::send_request(
+            this,
+            connection,
+            SignalingCode::LeCreditBasedConnectionRequest,
+            data,
+        ));
+        thinkable
+    }
+
+    /// A peer requests to open an LE credit based channel, returns the response to be send
+    pub(super) fn le_connection_requested(
+        &mut self,
+        handle: u16,
+        request: &SignalingPacket,
+    ) -> SignalingPacket {
+        let spsm = get_u16(&request.data, 0);
+        let remote_cid = get_u16(&request.data, 2);
+        let remote_mtu = get_u16(&request.data, 4);
+        let remote_mps = get_u16(&request.data, 6);
+        let credits = get_u16(&request.data, 8);
+
+        let config = self.le_servers.get(&spsm).map(|server| server.config);
+        let result = match config {
+            None => LE_CONNECTION_SPSM_NOT_SUPPORTED,
+            Some(_) if remote_cid < LE_DYNAMIC_CID_START || remote_cid > LE_DYNAMIC_CID_END => {
+                LE_CONNECTION_INVALID_SOURCE_CID
+            }
+            Some(_)
+                if self
+                    .credit_channels
+                    .iter()
+                    .any(|(key, state)| key.0 == handle && state.remote_cid == remote_cid) =>
+            {
+                LE_CONNECTION_SOURCE_CID_ALREADY_ALLOCATED
+            }
+            Some(_)
+                if remote_mtu < LE_CREDIT_MIN_MTU
+                    || remote_mps < LE_CREDIT_MIN_MPS
+                    || remote_mps > LE_CREDIT_MAX_MPS =>
+            {
+                LE_CONNECTION_UNACCEPTABLE_PARAMETERS
+            }
+            Some(_) => LE_CONNECTION_SUCCESSFUL,
+        };
+
+        let mut data = Vec::with_capacity(10);
+        let opened = match (result, config) {
+            (LE_CONNECTION_SUCCESSFUL, Some(config)) => self.open_incoming(handle, spsm, config),
+            _ => None,
+        };
+        match opened {
+            Some(local_cid) => {
+                let state = self.credit_channels.get_mut(&(handle, local_cid)).unwrap();
+                state.remote_cid = remote_cid;
+                state.remote_mtu = remote_mtu;
+                state.remote_mps = remote_mps;
+                state.tx_credits = credits;
+                put_u16(&mut data, local_cid);
+                put_u16(&mut data, state.config.mtu);
+                put_u16(&mut data, state.config.mps);
+                put_u16(&mut data, state.config.credits);
+                put_u16(&mut data, LE_CONNECTION_SUCCESSFUL);
+            }
+            None => {
+                data.extend_from_slice(&[0; 8]);
+                let result = match result {
+                    LE_CONNECTION_SUCCESSFUL => LE_CONNECTION_NO_RESOURCES,
+                    _ => result,
+                };
+                info!(
+                    "LE credit based connection for SPSM {:#X} refused: {:#X}",
+                    spsm, result
+                );
+                put_u16(&mut data, result);
+            }
+        }
+        SignalingPacket::new(
+            SignalingCode::LeCreditBasedConnectionResponse,
+            request.identifier,
+            data,
+        )
+    }
+
+    /// Open a channel requested by a peer and pass it to the server of the SPSM. This returns the
+    /// local channel id if there are resources available for the channel.
+    fn open_incoming(
+        &mut self,
+        handle: u16,
+        spsm: u16,
+        config: CreditChannelConfig,
+    ) -> Option<u16> {
+        if self.le_servers.get(&spsm)?.incoming.len() >= MAX_PENDING_CHANNELS {
+            return None;
+        }
+        let local_cid = self.allocate_cid(handle, LE_DYNAMIC_CID_END)?;
+        self.credit_channels
+            .insert((handle, local_cid), CreditChannelState::new(config));
+        let server = self.le_servers.get_mut(&spsm)?;
+        server.incoming.push_back((handle, local_cid));
+        if let Some(waker) = server.waker.take() {
+            waker.wake();
+        }
+        Some(local_cid)
+    }
+
+    /// The peer has granted new credits for sending K-frames. This returns the Disconnection
+    /// Request to be send to the peer if the credits overflow.
+    pub(super) fn credits_received(&mut self, handle: u16, data: &[u8]) -> Option<SignalingPacket> {
+        let remote_cid = get_u16(data, 0);
+        let credits = get_u16(data, 2);
+        let state = self
+            .credit_channels
+            .iter_mut()
+            .find(|(key, state)| key.0 == handle && state.remote_cid == remote_cid);
+        let (local_cid, state) = match state {
+            Some((key, state)) => (key.1, state),
+            None => {
+                warn!("credits for unknown L2CAP channel {:#X}", remote_cid);
+                return None;
+            }
+        };
+        match state.tx_credits.checked_add(credits) {
+            Some(tx_credits) => state.tx_credits = tx_credits,
+            None => return self.credit_channel_violated(handle, local_cid, "credits overflow"),
+        }
+        if let Some(waker) = state.send_waker.take() {
+            waker.wake();
+        }
+        None
+    }
+
+    /// Remove an LE credit based channel. This returns the remote channel id if the channel
+    /// existed.
+    pub(super) fn remove_credit_channel(&mut self, handle: u16, local_cid: u16) -> Option<u16> {
+        let state = self.credit_channels.remove(&(handle, local_cid))?;
+        let remote_cid = state.remote_cid;
+        state.closed();
+        for server in self.le_servers.values_mut() {
+            server
+                .incoming
+                .retain(|incoming| *incoming != (handle, local_cid));
+        }
+        Some(remote_cid)
+    }
+
+    /// A K-frame has been received on an LE credit based channel. This returns the Disconnection
+    /// Request to be send to the peer if the K-frame violates the channel parameters.
+    pub(super) fn received_k_frame(
+        &mut self,
+        handle: u16,
+        cid: u16,
+        payload: &[u8],
+    ) -> Option<SignalingPacket> {
+        let state = self.credit_channels.get_mut(&(handle, cid))?;
+        if state.rx_credits == 0 {
+            return self.credit_channel_violated(handle, cid, "K-frame without credits");
+        }
+        state.rx_credits -= 1;
+        if payload.len() > state.config.mps as usize {
+            return self.credit_channel_violated(handle, cid, "K-frame exceeding the MPS");
+        }
+
+        let (length, sdu) = match state.sdu.take() {
+            None if payload.len() < SDU_LENGTH_SIZE => {
+                return self.credit_channel_violated(handle, cid, "K-frame without SDU length");
+            }
+            None => {
+                let length = get_u16(payload, 0) as usize;
+                if length > state.config.mtu as usize {
+                    return self.credit_channel_violated(handle, cid, "SDU exceeding the MTU");
+                }
+                (length, payload[SDU_LENGTH_SIZE..].to_vec())
+            }
+            Some((length, mut sdu)) => {
+                sdu.extend_from_slice(payload);
+                (length, sdu)
+            }
+        };
+        if sdu.len() > length {
+            return self.credit_channel_violated(handle, cid, "SDU exceeding its length");
+        }
+        if sdu.len() < length {
+            state.sdu = Some((length, sdu));
+        } else {
+            state.sdus.push_back(sdu);
+            if let Some(waker) = state.recv_waker.take() {
+                waker.wake();
+            }
+        }
+        None
+    }
+
+    /// The peer has violated the parameters of an LE credit based channel, which requires the
+    /// channel to be closed. The channel is removed and the Disconnection Request to be send to
+    /// the peer is returned.
+    fn credit_channel_violated(
+        &mut self,
+        handle: u16,
+        local_cid: u16,
+        reason: &str,
+    ) -> Option<SignalingPacket> {
+        warn!("L2CAP channel {:#X} closed: {}", local_cid, reason);
+        let remote_cid = self.remove_credit_channel(handle, local_cid)?;
+        let mut data = Vec::with_capacity(4);
+        put_u16(&mut data, remote_cid);
+        put_u16(&mut data, local_cid);
+        Some(SignalingPacket::new(
+            SignalingCode::DisconnectionRequest,
+            self.next_identifier(),
+            data,
+        ))
+    }
+
+    /// Give credits back to the peer once it has used up half of them. This returns the
+    /// signaling command that need to be send to the peer if there are credits to be granted.
+    pub(super) fn grant_credits(&mut self, handle: u16, cid: u16) -> Option<SignalingPacket> {
+        let state = self.credit_channels.get_mut(&(handle, cid))?;
+        if state.sdus.len() >= MAX_PENDING_SDUS || state.rx_credits > state.config.credits / 2 {
+            return None;
+        }
+        let credits = state.config.credits - state.rx_credits;
+        state.rx_credits = state.config.credits;
+
+        let mut data = Vec::with_capacity(4);
+        put_u16(&mut data, cid);
+        put_u16(&mut data, credits);
+        Some(SignalingPacket::new(
+            SignalingCode::FlowControlCredit,
+            self.next_identifier(),
+            data,
+        ))
+    }
+
+    /// The connection has been closed, close all of its channels
+    pub(super) fn credit_channels_closed(&mut self, handle: u16) {
+        let cids: Vec<u16> = self
+            .credit_channels
+            .keys()
+            .filter(|key| key.0 == handle)
+            .map(|key| key.1)
+            .collect();
+        for cid in cids {
+            self.remove_credit_channel(handle, cid);
+        }
+    }
+}
+
+/// Accepts the LE credit based channels opened by peers for an SPSM
+pub struct CreditServer<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    l2cap: Arc<DataLock<L2cap<T>>>,
+    spsm: u16,
+}
+
+impl<T> CreditServer<T>
+where
+    T: HcTransportLayer,
+{
+    pub fn spsm(&self) -> u16 {
+        self.spsm
+    }
+
+    /// Returns a ``Thinkable`` that concludes with the next channel opened by a peer
+    pub fn accept(&self) -> AcceptCreditChannelThinkable<T> {
+        AcceptCreditChannelThinkable {
+            l2cap: self.l2cap.clone(),
+            spsm: self.spsm,
+        }
+    }
+}
+
+/// This ``Thinkable`` concludes with the next channel opened by a peer for an SPSM
+pub struct AcceptCreditChannelThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    l2cap: Arc<DataLock<L2cap<T>>>,
+    spsm: u16,
+}
+
+impl<T> Thinkable for AcceptCreditChannelThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = CreditChannel<T>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        let mut l2cap = self.l2cap.lock();
+        let hci = l2cap.hci.clone();
+        let server = match l2cap.le_servers.get_mut(&self.spsm) {
+            Some(server) => server,
+            None => return Conclusion::Pending,
+        };
+        while let Some((handle, local_cid)) = server.incoming.pop_front() {
+            if let Some(connection) = Hci::connection(hci.clone(), handle) {
+                return Conclusion::Ready(CreditChannel {
+                    l2cap: self.l2cap.clone(),
+                    connection,
+                    local_cid,
+                });
+            }
+        }
+        server.waker.replace(cx.waker().clone());
+        Conclusion::Pending
+    }
+}
+
+/// This ``Thinkable`` opens an LE credit based channel and concludes with the channel once the
+/// peer has accepted it
+pub struct ConnectCreditChannelThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    l2cap: Arc<DataLock<L2cap<T>>>,
+    connection: Connection<T>,
+    local_cid: u16,
+    request: Option<SignalingRequestThinkable<T>>,
+    error: Option<BoxError>,
+}
+
+impl<T> Thinkable for ConnectCreditChannelThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = Result<CreditChannel<T>, BoxError>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        // none of the fields is structurally pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        if let Some(error) = this.error.take() {
+            return Conclusion::Ready(Err(error));
+        }
+        let response = match this.request {
+            Some(ref mut request) => match Pin::new(request).think(cx) {
+                Conclusion::Pending => return Conclusion::Pending,
+                Conclusion::Ready(response) => response,
+            },
+            None => return Conclusion::Pending,
+        };
+        this.request.take();
+
+        let key = (this.connection.handle(), this.local_cid);
+        let mut l2cap = this.l2cap.lock();
+        let response = match response {
+            Ok(response)
+                if response.code == SignalingCode::LeCreditBasedConnectionResponse
+                    && response.is_complete() =>
+            {
+                response
+            }
+            Ok(_) => {
+                l2cap.credit_channels.remove(&key);
+                return Conclusion::Ready(Err(Box::new(L2capError::new("request rejected"))));
+            }
+            Err(error) => {
+                l2cap.credit_channels.remove(&key);
+                return Conclusion::Ready(Err(error));
+            }
+        };
+        let result = get_u16(&response.data, 8);
+        if result != LE_CONNECTION_SUCCESSFUL {
+            l2cap.credit_channels.remove(&key);
+            return Conclusion::Ready(Err(Box::new(L2capConnectionRefused { result })));
+        }
+        let remote_cid = get_u16(&response.data, 0);
+        let remote_mtu = get_u16(&response.data, 2);
+        let remote_mps = get_u16(&response.data, 4);
+        if remote_cid < LE_DYNAMIC_CID_START
+            || remote_cid > LE_DYNAMIC_CID_END
+            || remote_mtu < LE_CREDIT_MIN_MTU
+            || remote_mps < LE_CREDIT_MIN_MPS
+            || remote_mps > LE_CREDIT_MAX_MPS
+        {
+            l2cap.credit_channels.remove(&key);
+            return Conclusion::Ready(Err(Box::new(L2capError::new("invalid response"))));
+        }
+        match l2cap.credit_channels.get_mut(&key) {
+            Some(state) => {
+                state.remote_cid = remote_cid;
+                state.remote_mtu = remote_mtu;
+                state.remote_mps = remote_mps;
+                state.tx_credits = get_u16(&response.data, 6);
+            }
+            None => return Conclusion::Ready(Err(Box::new(L2capError::new("channel closed")))),
+        }
+        Conclusion::Ready(Ok(CreditChannel {
+            l2cap: this.l2cap.clone(),
+            connection: this.connection.clone(),
+            local_cid: this.local_cid,
+        }))
+    }
+}
+
+/// An LE credit based channel to a peer. Only one SDU should be send at a time on each channel
+/// as the K-frames of SDU's send in parallel would be interleaved.
+pub struct CreditChannel<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    l2cap: Arc<DataLock<L2cap<T>>>,
+    connection: Connection<T>,
+    local_cid: u16,
+}
+
+impl<T> CreditChannel<T>
+where
+    T: HcTransportLayer,
+{
+    pub fn connection(&self) -> &Connection<T> {
+        &self.connection
+    }
+
+    pub fn local_cid(&self) -> u16 {
+        self.local_cid
+    }
+
+    /// The maximum size of an SDU the peer accepts. This is 0 if the channel has been closed.
+    pub fn remote_mtu(&self) -> u16 {
+        let key = (self.connection.handle(), self.local_cid);
+        self.l2cap
+            .read()
+            .credit_channels
+            .get(&key)
+            .map_or(0, |state| state.remote_mtu)
+    }
+
+    /// Send an SDU to the peer. It concludes once all K-frames of the SDU are passed to the BT
+    /// host.
+    pub fn send(&self, sdu: &[u8]) -> SendSduThinkable<T> {
+        let mut thinkable = SendSduThinkable {
+            l2cap: self.l2cap.clone(),
+            connection: self.connection.clone(),
+            local_cid: self.local_cid,
+            frames: VecDeque::new(),
+            send: None,
+            error: None,
+        };
+        let key = (self.connection.handle(), self.local_cid);
+        let l2cap = self.l2cap.read();
+        let state = match l2cap.credit_channels.get(&key) {
+            Some(state) => state,
+            None => {
+                thinkable.error = Some(Box::new(L2capError::new("channel closed")));
+                return thinkable;
+            }
+        };
+        if sdu.len() > state.remote_mtu as usize {
+            thinkable.error = Some(Box::new(L2capError::new("SDU exceeds MTU")));
+            return thinkable;
+        }
+
+        let mps = state.remote_mps as usize;
+        let first = core::cmp::min(sdu.len(), mps - SDU_LENGTH_SIZE);
+        let mut frame = Vec::with_capacity(SDU_LENGTH_SIZE + first);
+        put_u16(&mut frame, sdu.len() as u16);
+        frame.extend_from_slice(&sdu[..first]);
+        thinkable.frames.push_back(frame);
+        for chunk in sdu[first..].chunks(mps) {
+            thinkable.frames.push_back(chunk.to_vec());
+        }
+        thinkable
+    }
+
+    /// Returns a ``Thinkable`` that concludes with the next SDU received from the peer or with
+    /// ``None`` once the channel has been closed
+    pub fn recv(&self) -> RecvSduThinkable<T> {
+        RecvSduThinkable {
+            l2cap: self.l2cap.clone(),
+            connection: self.connection.clone(),
+            local_cid: self.local_cid,
+        }
+    }
+
+    /// Close the channel. Data received afterwards is dropped.
+    pub fn disconnect(&self) -> DisconnectChannelThinkable<T> {
+        L2cap::disconnect_channel(self.l2cap.clone(), &self.connection, self.local_cid)
+    }
+}
+
+/// This ``Thinkable`` sends the K-frames of an SDU as credits are available
+pub struct SendSduThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    l2cap: Arc<DataLock<L2cap<T>>>,
+    connection: Connection<T>,
+    local_cid: u16,
+    frames: VecDeque<Vec<u8>>,
+    send: Option<SendAclThinkable<T>>,
+    error: Option<BoxError>,
+}
+
+impl<T> Thinkable for SendSduThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = Result<(), BoxError>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        // none of the fields is structurally pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        if let Some(error) = this.error.take() {
+            return Conclusion::Ready(Err(error));
+        }
+        loop {
+            if let Some(ref mut send) = this.send {
+                match Pin::new(send).think(cx) {
+                    Conclusion::Pending => return Conclusion::Pending,
+                    Conclusion::Ready(Err(error)) => return Conclusion::Ready(Err(error)),
+                    Conclusion::Ready(Ok(_)) => {
+                        this.send.take();
+                    }
+                }
+            }
+            let frame = match this.frames.pop_front() {
+                Some(frame) => frame,
+                None => return Conclusion::Ready(Ok(())),
+            };
+
+            let key = (this.connection.handle(), this.local_cid);
+            let mut l2cap = this.l2cap.lock();
+            let state = match l2cap.credit_channels.get_mut(&key) {
+                Some(state) => state,
+                None => return Conclusion::Ready(Err(Box::new(L2capError::new("channel closed")))),
+            };
+            if state.tx_credits == 0 {
+                this.frames.push_front(frame);
+                state.send_waker.replace(cx.waker().clone());
+                return Conclusion::Pending;
+            }
+            state.tx_credits -= 1;
+            let remote_cid = state.remote_cid;
+            drop(l2cap);
+            this.send = Some(this.connection.send_acl(basic_frame(remote_cid, &frame)));
+        }
+    }
+}
+
+/// This ``Thinkable`` concludes with the next SDU received on a channel
+pub struct RecvSduThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    l2cap: Arc<DataLock<L2cap<T>>>,
+    connection: Connection<T>,
+    local_cid: u16,
+}
+
+impl<T> Thinkable for RecvSduThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = Option<Vec<u8>>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        let handle = self.connection.handle();
+        let mut l2cap = self.l2cap.lock();
+        let sdu = match l2cap.credit_channels.get_mut(&(handle, self.local_cid)) {
+            Some(state) => match state.sdus.pop_front() {
+                Some(sdu) => sdu,
+                None => {
+                    state.recv_waker.replace(cx.waker().clone());
+                    return Conclusion::Pending;
+                }
+            },
+            None => return Conclusion::Ready(None),
+        };
+        let grant = l2cap.grant_credits(handle, self.local_cid);
+        drop(l2cap);
+        if let Some(packet) = grant {
+            send_signaling(&self.connection, packet);
+        }
+        Conclusion::Ready(Some(sdu))
+    }
+}
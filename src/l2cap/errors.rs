@@ -0,0 +1,56 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # L2CAP Errors
+//!
+
+use crate::error::Error;
+
+/// Error raised if an L2CAP request can not be processed
+pub struct L2capError {
+    reason: &'static str,
+}
+
+impl L2capError {
+    pub fn new(reason: &'static str) -> Self {
+        Self { reason }
+    }
+}
+
+impl Error for L2capError {}
+
+impl core::fmt::Display for L2capError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "L2CAP error: {}", self.reason)
+    }
+}
+
+impl core::fmt::Debug for L2capError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        <L2capError as core::fmt::Display>::fmt(self, f)
+    }
+}
+
+/// Error raised if the peer refused to open a channel. It contains the result code of the
+/// connection response.
+pub struct L2capConnectionRefused {
+    pub result: u16,
+}
+
+impl Error for L2capConnectionRefused {}
+
+impl core::fmt::Display for L2capConnectionRefused {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "L2CAP connection refused: {:#X}", self.result)
+    }
+}
+
+impl core::fmt::Debug for L2capConnectionRefused {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        <L2capConnectionRefused as core::fmt::Display>::fmt(self, f)
+    }
+}
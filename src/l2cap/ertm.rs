@@ -0,0 +1,647 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # Enhanced Retransmission and Streaming Mode
+//!
+//! In these modes the SDU's of a BR/EDR channel are segmented into I-frames carrying sequence
+//! numbers. In enhanced retransmission mode the receiver acknowledges the I-frames either with
+//! S-frames or with the I-frames it sends itself. Frames not acknowledged in time are polled for
+//! and send again. In streaming mode the I-frames are never acknowledged and the receiver skips
+//! the SDU's of lost frames. Each frame is protected by a frame check sequence unless both sides
+//! opted out of it.
+//!
+
+use super::*;
+
+/// Size of the enhanced control field
+const CONTROL_SIZE: usize = 2;
+/// Size of the frame check sequence
+const FCS_SIZE: usize = 2;
+/// Size of the SDU length field in the I-frame starting a segmented SDU
+const SDU_LENGTH_SIZE: usize = 2;
+/// The sequence numbers are counted modulo 64
+const SEQUENCE_MODULO: u8 = 64;
+
+/// Compute the frame check sequence, which is the CRC-16 with the polynomial
+/// x^16 + x^15 + x^2 + 1, over the given data
+pub fn fcs(data: &[u8]) -> u16 {
+    data.iter().fold(0, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u16, |crc, _| {
+            if crc & 1 != 0 {
+                (crc >> 1) ^ 0xA001
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+/// The segment of an SDU an I-frame carries
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SegmentationAndReassembly {
+    Unsegmented = 0,
+    Start = 1,
+    End = 2,
+    Continuation = 3,
+}
+
+impl From<u8> for SegmentationAndReassembly {
+    fn from(orig: u8) -> Self {
+        match orig & 0x3 {
+            0 => SegmentationAndReassembly::Unsegmented,
+            1 => SegmentationAndReassembly::Start,
+            2 => SegmentationAndReassembly::End,
+            _ => SegmentationAndReassembly::Continuation,
+        }
+    }
+}
+
+/// The function of an S-frame
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SupervisoryFunction {
+    ReceiverReady = 0,
+    Reject = 1,
+    ReceiverNotReady = 2,
+    SelectiveReject = 3,
+}
+
+impl From<u8> for SupervisoryFunction {
+    fn from(orig: u8) -> Self {
+        match orig & 0x3 {
+            0 => SupervisoryFunction::ReceiverReady,
+            1 => SupervisoryFunction::Reject,
+            2 => SupervisoryFunction::ReceiverNotReady,
+            _ => SupervisoryFunction::SelectiveReject,
+        }
+    }
+}
+
+/// The enhanced control field of an I-frame or S-frame
+#[derive(Debug, Copy, Clone)]
+pub enum Control {
+    Information {
+        tx_seq: u8,
+        req_seq: u8,
+        final_bit: bool,
+        sar: SegmentationAndReassembly,
+    },
+    Supervisory {
+        function: SupervisoryFunction,
+        req_seq: u8,
+        poll: bool,
+        final_bit: bool,
+    },
+}
+
+impl Control {
+    pub fn parse(value: u16) -> Self {
+        let req_seq = ((value >> 8) & 0x3F) as u8;
+        let final_bit = value & 0x80 != 0;
+        if value & 0x1 == 0 {
+            Control::Information {
+                tx_seq: ((value >> 1) & 0x3F) as u8,
+                req_seq,
+                final_bit,
+                sar: ((value >> 14) as u8).into(),
+            }
+        } else {
+            Control::Supervisory {
+                function: ((value >> 2) as u8).into(),
+                req_seq,
+                poll: value & 0x10 != 0,
+                final_bit,
+            }
+        }
+    }
+
+    pub fn to_u16(&self) -> u16 {
+        match *self {
+            Control::Information {
+                tx_seq,
+                req_seq,
+                final_bit,
+                sar,
+            } => {
+                (tx_seq as u16 & 0x3F) << 1
+                    | (final_bit as u16) << 7
+                    | (req_seq as u16 & 0x3F) << 8
+                    | (sar as u16) << 14
+            }
+            Control::Supervisory {
+                function,
+                req_seq,
+                poll,
+                final_bit,
+            } => {
+                0x1 | (function as u16) << 2
+                    | (poll as u16) << 4
+                    | (final_bit as u16) << 7
+                    | (req_seq as u16 & 0x3F) << 8
+            }
+        }
+    }
+}
+
+/// An I-frame send but not yet acknowledged
+struct SentFrame {
+    tx_seq: u8,
+    sar: SegmentationAndReassembly,
+    data: Vec<u8>,
+    transmissions: u8,
+}
+
+/// The timer a channel requests to be started
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct ErtmTimer {
+    /// identifies the timer, a timer is ignored once a newer one has been started
+    pub(crate) generation: u32,
+    /// the timeout in milliseconds
+    pub(crate) timeout: u16,
+}
+
+/// The results of processing a frame, an SDU to be send or a timeout
+#[derive(Default)]
+pub(crate) struct ErtmActions {
+    /// the PDU's to be send to the peer
+    pub(crate) pdus: Vec<Vec<u8>>,
+    /// the SDU's received completely
+    pub(crate) sdus: Vec<Vec<u8>>,
+    pub(crate) timer: Option<ErtmTimer>,
+    /// the channel need to be closed as the peer does not respond or violates the protocol
+    pub(crate) disconnect: bool,
+}
+
+/// The state of a channel in enhanced retransmission or streaming mode
+pub(crate) struct RetransmissionState {
+    streaming: bool,
+    fcs: bool,
+    remote_cid: u16,
+    /// the maximum size of an SDU received
+    mtu: u16,
+    /// the number of unacknowledged I-frames we are able to receive
+    rx_window: u8,
+    /// the number of unacknowledged I-frames the peer is able to receive
+    tx_window: u8,
+    max_transmit: u8,
+    retransmission_timeout: u16,
+    monitor_timeout: u16,
+    remote_mps: u16,
+    next_tx_seq: u8,
+    expected_ack_seq: u8,
+    unacked: VecDeque<SentFrame>,
+    /// the segments of the SDU's waiting for the transmit window to open
+    pending: VecDeque<(SegmentationAndReassembly, Vec<u8>)>,
+    remote_busy: bool,
+    /// a poll has been send and the final bit of the peer is awaited
+    wait_final: bool,
+    polls: u8,
+    timer: Option<u32>,
+    timer_generation: u32,
+    expected_tx_seq: u8,
+    reject_sent: bool,
+    /// the SDU currently reassembled with its announced length
+    sdu: Option<(usize, Vec<u8>)>,
+}
+
+impl RetransmissionState {
+    /// Create the state of a channel. The ``remote`` option is the one requested by the peer for
+    /// the data it receives and the timeouts are the ones the peer has given for the data we send.
+    pub(crate) fn new(
+        config: &ChannelConfig,
+        remote: &RetransmissionAndFlowControl,
+        fcs: bool,
+        remote_cid: u16,
+        retransmission_timeout: u16,
+        monitor_timeout: u16,
+    ) -> Self {
+        Self {
+            streaming: config.mode == ChannelMode::Streaming,
+            fcs,
+            remote_cid,
+            mtu: config.mtu,
+            rx_window: config.tx_window,
+            tx_window: remote.tx_window,
+            max_transmit: remote.max_transmit,
+            retransmission_timeout,
+            monitor_timeout,
+            remote_mps: remote.mps,
+            next_tx_seq: 0,
+            expected_ack_seq: 0,
+            unacked: VecDeque::new(),
+            pending: VecDeque::new(),
+            remote_busy: false,
+            wait_final: false,
+            polls: 0,
+            timer: None,
+            timer_generation: 0,
+            expected_tx_seq: 0,
+            reject_sent: false,
+            sdu: None,
+        }
+    }
+
+    /// Whether all segments of the SDU's queued have been send at least once
+    pub(crate) fn all_send(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Segment an SDU to fit the MPS of the peer and send as many segments as the transmit window
+    /// allows
+    pub(crate) fn queue_sdu(&mut self, sdu: &[u8], actions: &mut ErtmActions) {
+        let mps = self.remote_mps as usize;
+        if sdu.len() <= mps {
+            self.pending
+                .push_back((SegmentationAndReassembly::Unsegmented, sdu.to_vec()));
+        } else {
+            // the SDU length is part of the first segment
+            let first = core::cmp::max(mps.saturating_sub(SDU_LENGTH_SIZE), 1);
+            let mut data = Vec::with_capacity(SDU_LENGTH_SIZE + first);
+            put_u16(&mut data, sdu.len() as u16);
+            data.extend_from_slice(&sdu[..first]);
+            self.pending
+                .push_back((SegmentationAndReassembly::Start, data));
+            let mut chunks = sdu[first..].chunks(mps).peekable();
+            while let Some(chunk) = chunks.next() {
+                let sar = match chunks.peek() {
+                    Some(_) => SegmentationAndReassembly::Continuation,
+                    None => SegmentationAndReassembly::End,
+                };
+                self.pending.push_back((sar, chunk.to_vec()));
+            }
+        }
+        self.transmit(actions);
+    }
+
+    /// Process a frame received from the peer. The frame contains the basic L2CAP header.
+    pub(crate) fn received(&mut self, frame: &[u8], actions: &mut ErtmActions) {
+        let mut frame = frame;
+        if self.fcs {
+            if frame.len() < L2CAP_HEADER_SIZE + CONTROL_SIZE + FCS_SIZE {
+                return;
+            }
+            let end = frame.len() - FCS_SIZE;
+            if fcs(&frame[..end]) != get_u16(frame, end) {
+                warn!("L2CAP frame with invalid FCS dropped");
+                return;
+            }
+            frame = &frame[..end];
+        }
+        if frame.len() < L2CAP_HEADER_SIZE + CONTROL_SIZE {
+            return;
+        }
+        let control = Control::parse(get_u16(frame, L2CAP_HEADER_SIZE));
+        let payload = &frame[L2CAP_HEADER_SIZE + CONTROL_SIZE..];
+        match control {
+            Control::Information { tx_seq, sar, .. } if self.streaming => {
+                if tx_seq != self.expected_tx_seq {
+                    // frames have been lost, so the SDU reassembled is incomplete
+                    self.sdu = None;
+                }
+                self.expected_tx_seq = (tx_seq + 1) % SEQUENCE_MODULO;
+                self.reassemble(sar, payload, actions);
+            }
+            Control::Information {
+                tx_seq,
+                req_seq,
+                final_bit,
+                sar,
+            } => {
+                if self.received_ack(req_seq, final_bit, actions).is_some() {
+                    self.received_information(tx_seq, sar, payload, actions);
+                }
+            }
+            // S-frames are not used in streaming mode
+            Control::Supervisory { .. } if self.streaming => (),
+            Control::Supervisory {
+                function,
+                req_seq,
+                poll,
+                final_bit,
+            } => self.received_supervisory(function, req_seq, poll, final_bit, actions),
+        }
+    }
+
+    /// A timer has expired. Without an acknowledgement in time the peer is polled until it
+    /// responds or the maximum number of transmissions is reached.
+    pub(crate) fn timeout(&mut self, generation: u32, actions: &mut ErtmActions) {
+        if self.timer != Some(generation) {
+            return;
+        }
+        self.timer = None;
+        if self.wait_final {
+            if self.max_transmit != 0 && self.polls >= self.max_transmit {
+                actions.disconnect = true;
+                return;
+            }
+        } else if self.unacked.is_empty() {
+            return;
+        } else {
+            self.polls = 0;
+        }
+        self.polls += 1;
+        self.wait_final = true;
+        actions
+            .pdus
+            .push(self.supervisory_frame(SupervisoryFunction::ReceiverReady, true, false));
+        self.start_timer(self.monitor_timeout, actions);
+    }
+
+    /// Process the acknowledgement and the final bit of a frame received. This returns whether
+    /// the final bit answered our poll or ``None`` if the acknowledgement is invalid.
+    fn received_ack(
+        &mut self,
+        req_seq: u8,
+        final_bit: bool,
+        actions: &mut ErtmActions,
+    ) -> Option<bool> {
+        let final_received = final_bit && self.wait_final;
+        if final_received {
+            self.wait_final = false;
+            self.polls = 0;
+            self.timer = None;
+        }
+        // the frames acknowledged need to be ones that have been send
+        let acked = (req_seq + SEQUENCE_MODULO - self.expected_ack_seq) % SEQUENCE_MODULO;
+        if acked as usize > self.unacked.len() {
+            warn!(
+                "invalid acknowledgement on L2CAP channel {:#X}",
+                self.remote_cid
+            );
+            actions.disconnect = true;
+            return None;
+        }
+        for _ in 0..acked {
+            self.unacked.pop_front();
+        }
+        self.expected_ack_seq = req_seq;
+        if final_received {
+            self.retransmit_all(actions);
+        } else if acked > 0 && !self.wait_final {
+            if self.unacked.is_empty() {
+                self.timer = None;
+            } else {
+                self.start_timer(self.retransmission_timeout, actions);
+            }
+        }
+        Some(final_received)
+    }
+
+    fn received_information(
+        &mut self,
+        tx_seq: u8,
+        sar: SegmentationAndReassembly,
+        payload: &[u8],
+        actions: &mut ErtmActions,
+    ) {
+        if tx_seq == self.expected_tx_seq {
+            self.expected_tx_seq = (tx_seq + 1) % SEQUENCE_MODULO;
+            self.reject_sent = false;
+            self.reassemble(sar, payload, actions);
+        } else if (tx_seq + SEQUENCE_MODULO - self.expected_tx_seq) % SEQUENCE_MODULO
+            < self.rx_window
+        {
+            // frames are missing, request to send them again starting with the one expected
+            if !self.reject_sent {
+                self.reject_sent = true;
+                actions.pdus.push(self.supervisory_frame(
+                    SupervisoryFunction::Reject,
+                    false,
+                    false,
+                ));
+            }
+            self.transmit(actions);
+            return;
+        }
+        // frames received again are acknowledged as well as the acknowledgement might got lost
+        if self.transmit(actions) == 0 {
+            actions.pdus.push(self.supervisory_frame(
+                SupervisoryFunction::ReceiverReady,
+                false,
+                false,
+            ));
+        }
+    }
+
+    fn received_supervisory(
+        &mut self,
+        function: SupervisoryFunction,
+        req_seq: u8,
+        poll: bool,
+        final_bit: bool,
+        actions: &mut ErtmActions,
+    ) {
+        if function == SupervisoryFunction::SelectiveReject {
+            // the frame rejected is not acknowledged by the S-frame
+            self.retransmit(req_seq, poll, actions);
+        } else {
+            let final_received = match self.received_ack(req_seq, final_bit, actions) {
+                Some(final_received) => final_received,
+                None => return,
+            };
+            match function {
+                SupervisoryFunction::ReceiverNotReady => self.remote_busy = true,
+                SupervisoryFunction::Reject if !final_received => {
+                    self.remote_busy = false;
+                    self.retransmit_all(actions);
+                }
+                _ => self.remote_busy = false,
+            }
+            if poll {
+                actions.pdus.push(self.supervisory_frame(
+                    SupervisoryFunction::ReceiverReady,
+                    false,
+                    true,
+                ));
+            }
+        }
+        self.transmit(actions);
+    }
+
+    /// Reassemble the SDU's from the segments received
+    fn reassemble(
+        &mut self,
+        sar: SegmentationAndReassembly,
+        payload: &[u8],
+        actions: &mut ErtmActions,
+    ) {
+        match sar {
+            SegmentationAndReassembly::Unsegmented => {
+                if self.sdu.take().is_some() {
+                    warn!(
+                        "incomplete SDU on L2CAP channel {:#X} dropped",
+                        self.remote_cid
+                    );
+                }
+                if payload.len() <= self.mtu as usize {
+                    actions.sdus.push(payload.to_vec());
+                }
+            }
+            SegmentationAndReassembly::Start => {
+                let length = get_u16(payload, 0) as usize;
+                if payload.len() < SDU_LENGTH_SIZE || length > self.mtu as usize {
+                    warn!(
+                        "SDU exceeding the MTU of L2CAP channel {:#X}",
+                        self.remote_cid
+                    );
+                    self.sdu = None;
+                    return;
+                }
+                self.sdu = Some((length, payload[SDU_LENGTH_SIZE..].to_vec()));
+            }
+            SegmentationAndReassembly::Continuation | SegmentationAndReassembly::End => {
+                let (length, mut sdu) = match self.sdu.take() {
+                    Some(sdu) => sdu,
+                    None => return,
+                };
+                sdu.extend_from_slice(payload);
+                let end = sar == SegmentationAndReassembly::End;
+                if sdu.len() > length || (end && sdu.len() != length) {
+                    warn!(
+                        "SDU with invalid length on L2CAP channel {:#X}",
+                        self.remote_cid
+                    );
+                } else if end {
+                    actions.sdus.push(sdu);
+                } else {
+                    self.sdu = Some((length, sdu));
+                }
+            }
+        }
+    }
+
+    /// Send the pending segments as long as the transmit window allows. This returns the number
+    /// of I-frames send.
+    fn transmit(&mut self, actions: &mut ErtmActions) -> usize {
+        let mut count = 0;
+        while !self.wait_final
+            && !self.remote_busy
+            && (self.streaming || self.unacked.len() < self.tx_window as usize)
+        {
+            let (sar, data) = match self.pending.pop_front() {
+                Some(segment) => segment,
+                None => break,
+            };
+            let tx_seq = self.next_tx_seq;
+            self.next_tx_seq = (tx_seq + 1) % SEQUENCE_MODULO;
+            count += 1;
+            if self.streaming {
+                actions
+                    .pdus
+                    .push(self.information_frame(tx_seq, 0, false, sar, &data));
+                continue;
+            }
+            actions.pdus.push(self.information_frame(
+                tx_seq,
+                self.expected_tx_seq,
+                false,
+                sar,
+                &data,
+            ));
+            self.unacked.push_back(SentFrame {
+                tx_seq,
+                sar,
+                data,
+                transmissions: 1,
+            });
+            if self.timer.is_none() {
+                self.start_timer(self.retransmission_timeout, actions);
+            }
+        }
+        count
+    }
+
+    /// Send all unacknowledged I-frames again
+    fn retransmit_all(&mut self, actions: &mut ErtmActions) {
+        let frames: Vec<u8> = self.unacked.iter().map(|frame| frame.tx_seq).collect();
+        for tx_seq in frames {
+            self.retransmit(tx_seq, false, actions);
+        }
+    }
+
+    /// Send the unacknowledged I-frame with the given sequence number again
+    fn retransmit(&mut self, tx_seq: u8, final_bit: bool, actions: &mut ErtmActions) {
+        let frame = match self.unacked.iter_mut().find(|frame| frame.tx_seq == tx_seq) {
+            Some(frame) => frame,
+            None => return,
+        };
+        frame.transmissions = frame.transmissions.saturating_add(1);
+        if self.max_transmit != 0 && frame.transmissions > self.max_transmit {
+            actions.disconnect = true;
+            return;
+        }
+        let (sar, data) = (frame.sar, frame.data.clone());
+        actions.pdus.push(self.information_frame(
+            tx_seq,
+            self.expected_tx_seq,
+            final_bit,
+            sar,
+            &data,
+        ));
+        if !self.wait_final {
+            self.start_timer(self.retransmission_timeout, actions);
+        }
+    }
+
+    fn start_timer(&mut self, timeout: u16, actions: &mut ErtmActions) {
+        self.timer_generation = self.timer_generation.wrapping_add(1);
+        self.timer = Some(self.timer_generation);
+        actions.timer = Some(ErtmTimer {
+            generation: self.timer_generation,
+            timeout,
+        });
+    }
+
+    fn information_frame(
+        &self,
+        tx_seq: u8,
+        req_seq: u8,
+        final_bit: bool,
+        sar: SegmentationAndReassembly,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let control = Control::Information {
+            tx_seq,
+            req_seq,
+            final_bit,
+            sar,
+        };
+        self.frame(control, data)
+    }
+
+    /// Create an S-frame acknowledging all I-frames received
+    fn supervisory_frame(
+        &self,
+        function: SupervisoryFunction,
+        poll: bool,
+        final_bit: bool,
+    ) -> Vec<u8> {
+        let control = Control::Supervisory {
+            function,
+            req_seq: self.expected_tx_seq,
+            poll,
+            final_bit,
+        };
+        self.frame(control, &[])
+    }
+
+    fn frame(&self, control: Control, data: &[u8]) -> Vec<u8> {
+        let fcs_size = if self.fcs { FCS_SIZE } else { 0 };
+        let length = CONTROL_SIZE + data.len() + fcs_size;
+        let mut frame = Vec::with_capacity(L2CAP_HEADER_SIZE + length);
+        put_u16(&mut frame, length as u16);
+        put_u16(&mut frame, self.remote_cid);
+        put_u16(&mut frame, control.to_u16());
+        frame.extend_from_slice(data);
+        if self.fcs {
+            let fcs = fcs(&frame);
+            put_u16(&mut frame, fcs);
+        }
+        frame
+    }
+}
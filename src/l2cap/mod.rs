@@ -0,0 +1,864 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # Logical Link Control and Adaptation Protocol
+//!
+//! L2CAP runs on top of the ACL data path of a connection and multiplexes the PDU's received by
+//! their channel id. The signaling channels are handled here, while higher layers like ATT or SMP
+//! register the fixed channels they use. Each connection need to be served by the Thinkable
+//! returned from [L2cap::serve_connection] to get its PDU's dispatched.
+//!
+
+pub mod channel;
+use channel::*;
+pub mod config;
+use config::*;
+pub mod credit;
+use credit::*;
+pub mod ertm;
+use ertm::*;
+pub mod errors;
+use errors::*;
+pub mod signaling;
+use signaling::*;
+
+use crate::alloc::boxed::Box;
+use crate::alloc::collections::{BTreeMap, VecDeque};
+use crate::alloc::sync::Arc;
+use crate::alloc::vec;
+use crate::alloc::vec::Vec;
+use crate::brain::{waker::*, *};
+use crate::error::BoxError;
+use crate::hci::acl::{RecvAclThinkable, SendAclThinkable};
+use crate::hci::commands::{HciCommandLeConnectionUpdate, LeConnectionUpdateParameters};
+use crate::hci::connection::{Connection, ConnectionTransport, HciConnectionRole};
+use crate::hci::Hci;
+use crate::hctl::HcTransportLayer;
+use crate::lock::*;
+use crate::pin::Pin;
+use crate::{info, warn};
+
+/// Channel id of the BR/EDR signaling channel
+pub const CID_SIGNALING: u16 = 0x0001;
+/// Channel id of the attribute protocol
+pub const CID_ATT: u16 = 0x0004;
+/// Channel id of the LE signaling channel
+pub const CID_LE_SIGNALING: u16 = 0x0005;
+/// Channel id of the security manager protocol
+pub const CID_SMP: u16 = 0x0006;
+/// First channel id of the dynamic channels
+pub const DYNAMIC_CID_START: u16 = 0x0040;
+/// Size of the L2CAP basic header
+pub const L2CAP_HEADER_SIZE: usize = 4;
+/// Maximum number of PDU's kept per fixed channel until they are taken. If there are more PDU's
+/// received the oldest ones are dropped.
+const MAX_PENDING_PDUS: usize = 16;
+
+/// Build a basic L2CAP frame for the given channel
+pub fn basic_frame(cid: u16, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(L2CAP_HEADER_SIZE + payload.len());
+    put_u16(&mut frame, payload.len() as u16);
+    put_u16(&mut frame, cid);
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// The PDU's received on a fixed channel together with the connection handle they are received
+/// from
+struct FixedChannelQueue {
+    pdus: VecDeque<(u16, Vec<u8>)>,
+    waker: Option<Waker>,
+}
+
+/// A signaling request waiting for its response
+struct PendingRequest {
+    waker: Option<Waker>,
+    response: Option<SignalingPacket>,
+    /// the connection has been closed before the response has been received
+    closed: bool,
+}
+
+pub struct L2cap<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    hci: Arc<DataLock<Hci<T>>>,
+    fixed_channels: BTreeMap<u16, FixedChannelQueue>,
+    /// the signaling requests send, by connection handle and identifier
+    pending: BTreeMap<(u16, u8), PendingRequest>,
+    next_identifier: u8,
+    /// the SPSM's registered for LE credit based channels
+    le_servers: BTreeMap<u16, CreditServerState>,
+    /// the LE credit based channels by connection handle and local channel id
+    credit_channels: BTreeMap<(u16, u16), CreditChannelState>,
+    /// the PSM's registered for BR/EDR channels
+    psm_servers: BTreeMap<u16, ChannelServerState>,
+    /// the BR/EDR channels by connection handle and local channel id
+    channels: BTreeMap<(u16, u16), ChannelState>,
+}
+
+impl<T> L2cap<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    pub fn new(hci: Arc<DataLock<Hci<T>>>) -> Arc<DataLock<Self>> {
+        Arc::new(DataLock::new(Self {
+            hci,
+            fixed_channels: BTreeMap::new(),
+            pending: BTreeMap::new(),
+            next_identifier: 1,
+            le_servers: BTreeMap::new(),
+            credit_channels: BTreeMap::new(),
+            psm_servers: BTreeMap::new(),
+            channels: BTreeMap::new(),
+        }))
+    }
+
+    /// This returns the ``Thinkable`` dispatching the PDU's received on the given connection. It
+    /// concludes once the connection has been closed. This should be spawned to the Brain for
+    /// every connection established.
+    pub fn serve_connection(
+        this: Arc<DataLock<Self>>,
+        connection: &Connection<T>,
+    ) -> ServeConnectionThinkable<T> {
+        ServeConnectionThinkable::new(this, connection.clone())
+    }
+
+    /// Register a fixed channel used by a higher layer. The signaling channels can not be
+    /// registered and each channel can only be registered once.
+    pub fn register_fixed_channel(
+        this: Arc<DataLock<Self>>,
+        cid: u16,
+    ) -> Result<FixedChannel<T>, BoxError> {
+        let mut l2cap = this.lock();
+        if cid == CID_SIGNALING
+            || cid == CID_LE_SIGNALING
+            || cid >= 0x0040
+            || l2cap.fixed_channels.contains_key(&cid)
+        {
+            return Err(Box::new(L2capError::new("fixed channel not available")));
+        }
+        l2cap.fixed_channels.insert(
+            cid,
+            FixedChannelQueue {
+                pdus: VecDeque::new(),
+                waker: None,
+            },
+        );
+        let hci = l2cap.hci.clone();
+        drop(l2cap);
+        Ok(FixedChannel {
+            l2cap: this,
+            hci,
+            cid,
+        })
+    }
+
+    /// Request new connection parameters from the central of an LE connection. This is used by a
+    /// peripheral that does not use the link layer procedure. It concludes with ``true`` if the
+    /// central has accepted the parameters.
+    pub fn request_connection_parameter_update(
+        this: Arc<DataLock<Self>>,
+        connection: &Connection<T>,
+        parameters: LeConnectionUpdateParameters,
+    ) -> impl Thinkable<Output = Result<bool, BoxError>> {
+        let mut data = Vec::with_capacity(8);
+        put_u16(&mut data, parameters.interval_min);
+        put_u16(&mut data, parameters.interval_max);
+        put_u16(&mut data, parameters.latency);
+        put_u16(&mut data, parameters.supervision_timeout);
+        Self::send_request(
+            this,
+            connection,
+            SignalingCode::ConnectionParameterUpdateRequest,
+            data,
+        )
+        .map(|result| {
+            let response = result?;
+            match response.code {
+                SignalingCode::ConnectionParameterUpdateResponse if response.is_complete() => {
+                    Ok(get_u16(&response.data, 0) == CONNECTION_PARAMETERS_ACCEPTED)
+                }
+                _ => Err(Box::new(L2capError::new("unexpected response")) as BoxError),
+            }
+        })
+    }
+
+    /// Request information from the peer of a BR/EDR connection. This concludes with the
+    /// information data if the peer supports the information type requested.
+    pub fn request_information(
+        this: Arc<DataLock<Self>>,
+        connection: &Connection<T>,
+        info_type: u16,
+    ) -> impl Thinkable<Output = Result<Vec<u8>, BoxError>> {
+        let mut data = Vec::with_capacity(2);
+        put_u16(&mut data, info_type);
+        Self::send_request(this, connection, SignalingCode::InformationRequest, data).map(
+            |result| {
+                let response = result?;
+                if response.code == SignalingCode::InformationResponse
+                    && response.data.len() >= 4
+                    && get_u16(&response.data, 2) == INFO_RESULT_SUCCESS
+                {
+                    Ok(response.data[4..].to_vec())
+                } else {
+                    Err(Box::new(L2capError::new("information not supported")) as BoxError)
+                }
+            },
+        )
+    }
+
+    /// Send a signaling request to the peer. This concludes with the response or the Command
+    /// Reject received for it.
+    pub(crate) fn send_request(
+        this: Arc<DataLock<Self>>,
+        connection: &Connection<T>,
+        code: SignalingCode,
+        data: Vec<u8>,
+    ) -> SignalingRequestThinkable<T> {
+        let info = connection.info();
+        let mut l2cap = this.lock();
+        let identifier = l2cap.next_identifier();
+        l2cap.pending.insert(
+            (info.handle, identifier),
+            PendingRequest {
+                waker: None,
+                response: None,
+                closed: false,
+            },
+        );
+        drop(l2cap);
+        let request = SignalingPacket::new(code, identifier, data);
+        let send = connection.send_acl(basic_frame(
+            signaling_cid(info.transport),
+            &request.to_bytes(),
+        ));
+        SignalingRequestThinkable {
+            l2cap: this,
+            handle: info.handle,
+            identifier,
+            send: Some(send),
+        }
+    }
+
+    /// Close a dynamic channel. The channel is removed immediately and the Thinkable returned
+    /// concludes once the peer has confirmed the disconnection.
+    pub(crate) fn disconnect_channel(
+        this: Arc<DataLock<Self>>,
+        connection: &Connection<T>,
+        local_cid: u16,
+    ) -> DisconnectChannelThinkable<T> {
+        let handle = connection.handle();
+        let mut l2cap = this.lock();
+        let remote_cid = l2cap
+            .remove_credit_channel(handle, local_cid)
+            .or_else(|| l2cap.remove_channel(handle, local_cid));
+        drop(l2cap);
+        let request = remote_cid.map(|remote_cid| {
+            let mut data = Vec::with_capacity(4);
+            put_u16(&mut data, remote_cid);
+            put_u16(&mut data, local_cid);
+            Self::send_request(this, connection, SignalingCode::DisconnectionRequest, data)
+        });
+        DisconnectChannelThinkable { request }
+    }
+
+    /// Allocate a free dynamic channel id up to the given last channel id on the connection with
+    /// the given handle
+    fn allocate_cid(&self, handle: u16, last: u16) -> Option<u16> {
+        (DYNAMIC_CID_START..=last).find(|cid| {
+            !self.credit_channels.contains_key(&(handle, *cid))
+                && !self.channels.contains_key(&(handle, *cid))
+        })
+    }
+
+    /// The peer requests to close a channel, returns the response to be send
+    fn disconnection_requested(
+        &mut self,
+        handle: u16,
+        request: &SignalingPacket,
+    ) -> SignalingPacket {
+        let local_cid = get_u16(&request.data, 0);
+        let remote_cid = get_u16(&request.data, 2);
+        let known = match self.credit_channels.get(&(handle, local_cid)) {
+            Some(state) => state.remote_cid() == remote_cid,
+            None => match self.channels.get(&(handle, local_cid)) {
+                Some(state) => state.remote_cid() == remote_cid,
+                None => false,
+            },
+        };
+        if !known {
+            let mut reason_data = Vec::with_capacity(4);
+            put_u16(&mut reason_data, local_cid);
+            put_u16(&mut reason_data, remote_cid);
+            return SignalingPacket::command_reject(
+                request.identifier,
+                REJECT_INVALID_CID,
+                &reason_data,
+            );
+        }
+
+        info!("L2CAP channel {:#X} closed by peer", local_cid);
+        self.remove_credit_channel(handle, local_cid);
+        self.remove_channel(handle, local_cid);
+        SignalingPacket::new(
+            SignalingCode::DisconnectionResponse,
+            request.identifier,
+            request.data.clone(),
+        )
+    }
+
+    /// Get the next identifier for a signaling request, the identifier 0 is never used
+    fn next_identifier(&mut self) -> u8 {
+        let identifier = self.next_identifier;
+        self.next_identifier = match self.next_identifier.wrapping_add(1) {
+            0 => 1,
+            next => next,
+        };
+        identifier
+    }
+
+    /// The fixed channels supported as bit mask where each bit represents the channel id
+    fn fixed_channel_mask(&self) -> u64 {
+        self.fixed_channels
+            .keys()
+            .fold(1 << CID_SIGNALING, |mask, cid| mask | 1 << *cid)
+    }
+
+    /// The extended features supported
+    fn extended_features(&self) -> u32 {
+        FEATURE_FIXED_CHANNELS | FEATURE_ENHANCED_RETRANSMISSION | FEATURE_STREAMING | FEATURE_FCS
+    }
+
+    /// Pass a PDU received on a fixed channel to the higher layer
+    fn received_fixed(&mut self, handle: u16, cid: u16, payload: &[u8]) -> bool {
+        match self.fixed_channels.get_mut(&cid) {
+            Some(channel) => {
+                if channel.pdus.len() >= MAX_PENDING_PDUS {
+                    warn!("L2CAP channel {:#X} queue full, drop oldest PDU", cid);
+                    channel.pdus.pop_front();
+                }
+                channel.pdus.push_back((handle, payload.to_vec()));
+                if let Some(waker) = channel.waker.take() {
+                    waker.wake();
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// A response to a signaling request has been received
+    fn received_response(&mut self, handle: u16, response: SignalingPacket) {
+        match self.pending.get_mut(&(handle, response.identifier)) {
+            Some(pending) => {
+                pending.response.replace(response);
+                if let Some(waker) = pending.waker.take() {
+                    waker.wake();
+                }
+            }
+            // the disconnection of a channel that failed to be configured is not awaited
+            None if response.code == SignalingCode::DisconnectionResponse => (),
+            None => warn!("unexpected L2CAP signaling response {:?}", response.code),
+        }
+    }
+
+    /// The connection has been closed, close its channels and wake all signaling requests waiting
+    /// for a response
+    fn closed(&mut self, handle: u16) {
+        self.credit_channels_closed(handle);
+        self.channels_closed(handle);
+        for ((pending_handle, _), pending) in self.pending.iter_mut() {
+            if *pending_handle == handle {
+                pending.closed = true;
+                if let Some(waker) = pending.waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+}
+
+/// The signaling channel used on the given transport
+fn signaling_cid(transport: ConnectionTransport) -> u16 {
+    match transport {
+        ConnectionTransport::BrEdr => CID_SIGNALING,
+        ConnectionTransport::Le => CID_LE_SIGNALING,
+    }
+}
+
+/// Send a signaling command to the peer of the connection from its own Thinkable
+fn send_signaling<T>(connection: &Connection<T>, packet: SignalingPacket)
+where
+    T: HcTransportLayer + 'static,
+{
+    send_signaling_all(connection, vec![packet]);
+}
+
+/// Send signaling commands to the peer of the connection. On BR/EDR connections as many commands
+/// as fit the signaling MTU are combined into one PDU.
+fn send_signaling_all<T>(connection: &Connection<T>, packets: Vec<SignalingPacket>)
+where
+    T: HcTransportLayer + 'static,
+{
+    let transport = connection.info().transport;
+    let mut pdu = Vec::new();
+    for packet in packets {
+        let bytes = packet.to_bytes();
+        if !pdu.is_empty()
+            && (transport == ConnectionTransport::Le
+                || pdu.len() + bytes.len() > SIGNALING_MTU_BREDR as usize)
+        {
+            send_signaling_pdu(connection, core::mem::replace(&mut pdu, Vec::new()));
+        }
+        pdu.extend_from_slice(&bytes);
+    }
+    if !pdu.is_empty() {
+        send_signaling_pdu(connection, pdu);
+    }
+}
+
+fn send_signaling_pdu<T>(connection: &Connection<T>, pdu: Vec<u8>)
+where
+    T: HcTransportLayer + 'static,
+{
+    let transport = connection.info().transport;
+    let handle = connection.handle();
+    spawn(
+        connection
+            .send_acl(basic_frame(signaling_cid(transport), &pdu))
+            .map(move |result| {
+                if result.is_err() {
+                    warn!("sending L2CAP signaling to {} failed", handle);
+                }
+            }),
+    );
+}
+
+/// A fixed channel registered by a higher layer. The channel exists on every connection.
+pub struct FixedChannel<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    l2cap: Arc<DataLock<L2cap<T>>>,
+    hci: Arc<DataLock<Hci<T>>>,
+    cid: u16,
+}
+
+impl<T> FixedChannel<T>
+where
+    T: HcTransportLayer,
+{
+    pub fn cid(&self) -> u16 {
+        self.cid
+    }
+
+    /// Send a PDU on this channel to the peer of the connection with the given handle
+    pub fn send(&self, handle: u16, payload: &[u8]) -> SendAclThinkable<T> {
+        SendAclThinkable::new(self.hci.clone(), handle, basic_frame(self.cid, payload))
+    }
+
+    /// Queue a PDU on this channel to the peer of the connection with the given handle without
+    /// waiting for it to be send. PDU's queued are send in order.
+    pub fn queue(&self, handle: u16, payload: &[u8]) {
+        self.hci
+            .lock()
+            .queue_acl_pdu(handle, &basic_frame(self.cid, payload));
+    }
+
+    pub(crate) fn hci(&self) -> Arc<DataLock<Hci<T>>> {
+        self.hci.clone()
+    }
+
+    /// The connection with the given handle if it is established
+    pub fn connection(&self, handle: u16) -> Option<Connection<T>> {
+        Hci::connection(self.hci.clone(), handle)
+    }
+
+    /// Returns a ``Thinkable`` that concludes with the next PDU received on this channel from
+    /// any connection together with the connection handle
+    pub fn recv(&self) -> RecvFixedChannelThinkable<T> {
+        RecvFixedChannelThinkable {
+            l2cap: self.l2cap.clone(),
+            cid: self.cid,
+        }
+    }
+}
+
+/// This ``Thinkable`` concludes with the next PDU received on a fixed channel
+pub struct RecvFixedChannelThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    l2cap: Arc<DataLock<L2cap<T>>>,
+    cid: u16,
+}
+
+impl<T> Thinkable for RecvFixedChannelThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = (u16, Vec<u8>);
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        let mut l2cap = self.l2cap.lock();
+        // the channel has been registered when the FixedChannel was created, if its queue is gone
+        // it is restored to not lose the waker
+        let channel = l2cap
+            .fixed_channels
+            .entry(self.cid)
+            .or_insert_with(|| FixedChannelQueue {
+                pdus: VecDeque::new(),
+                waker: None,
+            });
+        match channel.pdus.pop_front() {
+            Some(pdu) => Conclusion::Ready(pdu),
+            None => {
+                channel.waker.replace(cx.waker().clone());
+                Conclusion::Pending
+            }
+        }
+    }
+}
+
+/// This ``Thinkable`` sends a signaling request and concludes with its response
+pub struct SignalingRequestThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    l2cap: Arc<DataLock<L2cap<T>>>,
+    handle: u16,
+    identifier: u8,
+    send: Option<SendAclThinkable<T>>,
+}
+
+impl<T> Thinkable for SignalingRequestThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = Result<SignalingPacket, BoxError>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        // none of the fields is structurally pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        let key = (this.handle, this.identifier);
+        if let Some(ref mut send) = this.send {
+            match Pin::new(send).think(cx) {
+                Conclusion::Pending => return Conclusion::Pending,
+                Conclusion::Ready(Err(e)) => {
+                    this.l2cap.lock().pending.remove(&key);
+                    return Conclusion::Ready(Err(e));
+                }
+                Conclusion::Ready(Ok(_)) => {
+                    this.send.take();
+                }
+            }
+        }
+
+        let mut l2cap = this.l2cap.lock();
+        let pending = match l2cap.pending.get_mut(&key) {
+            Some(pending) => pending,
+            None => return Conclusion::Ready(Err(Box::new(L2capError::new("no request pending")))),
+        };
+        if let Some(response) = pending.response.take() {
+            l2cap.pending.remove(&key);
+            return Conclusion::Ready(Ok(response));
+        }
+        if pending.closed {
+            l2cap.pending.remove(&key);
+            return Conclusion::Ready(Err(Box::new(L2capError::new("connection closed"))));
+        }
+        pending.waker.replace(cx.waker().clone());
+        Conclusion::Pending
+    }
+}
+
+/// This ``Thinkable`` concludes once the peer has confirmed the disconnection of a channel
+pub struct DisconnectChannelThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    /// the disconnection request, ``None`` if the channel has already been closed
+    request: Option<SignalingRequestThinkable<T>>,
+}
+
+impl<T> Thinkable for DisconnectChannelThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = Result<(), BoxError>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        // none of the fields is structurally pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        match this.request {
+            // a Command Reject is fine as well as the peer does not know the channel any longer
+            Some(ref mut request) => match Pin::new(request).think(cx) {
+                Conclusion::Pending => Conclusion::Pending,
+                Conclusion::Ready(result) => Conclusion::Ready(result.map(|_| ())),
+            },
+            None => Conclusion::Ready(Ok(())),
+        }
+    }
+}
+
+/// This ``Thinkable`` dispatches the PDU's received on a connection until it has been closed
+pub struct ServeConnectionThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    l2cap: Arc<DataLock<L2cap<T>>>,
+    connection: Connection<T>,
+    recv: RecvAclThinkable<T>,
+}
+
+impl<T> ServeConnectionThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    fn new(l2cap: Arc<DataLock<L2cap<T>>>, connection: Connection<T>) -> Self {
+        let recv = connection.recv_acl();
+        Self {
+            l2cap,
+            connection,
+            recv,
+        }
+    }
+
+    /// Dispatch a PDU received by its channel id
+    fn dispatch(&self, pdu: Vec<u8>) {
+        if pdu.len() < L2CAP_HEADER_SIZE {
+            warn!("L2CAP PDU too short");
+            return;
+        }
+        let cid = get_u16(&pdu, 2);
+        let payload = &pdu[L2CAP_HEADER_SIZE..];
+        let info = self.connection.info();
+        if cid == signaling_cid(info.transport) {
+            self.dispatch_signaling(payload);
+        } else if cid >= DYNAMIC_CID_START && info.transport == ConnectionTransport::BrEdr {
+            let mut l2cap = self.l2cap.lock();
+            if !l2cap.channels.contains_key(&(info.handle, cid)) {
+                warn!("L2CAP PDU for unknown channel {:#X} dropped", cid);
+                return;
+            }
+            let timer = l2cap.received_frame(info.handle, cid, &pdu);
+            drop(l2cap);
+            if let Some(timer) = timer {
+                start_ertm_timer(self.l2cap.clone(), info.handle, cid, timer);
+            }
+        } else if cid >= DYNAMIC_CID_START {
+            let mut l2cap = self.l2cap.lock();
+            if !l2cap.credit_channels.contains_key(&(info.handle, cid)) {
+                warn!("L2CAP PDU for unknown channel {:#X} dropped", cid);
+                return;
+            }
+            let packet = l2cap
+                .received_k_frame(info.handle, cid, payload)
+                .or_else(|| l2cap.grant_credits(info.handle, cid));
+            drop(l2cap);
+            if let Some(packet) = packet {
+                send_signaling(&self.connection, packet);
+            }
+        } else if !self.l2cap.lock().received_fixed(info.handle, cid, payload) {
+            warn!("L2CAP PDU for unknown channel {:#X} dropped", cid);
+        }
+    }
+
+    /// Process the signaling commands received and send the responses to the peer
+    fn dispatch_signaling(&self, payload: &[u8]) {
+        let info = self.connection.info();
+        let mut packets = SignalingPacket::parse_all(payload);
+        let mtu = match info.transport {
+            ConnectionTransport::BrEdr => SIGNALING_MTU_BREDR,
+            ConnectionTransport::Le => {
+                // the LE signaling channel carries only one command per PDU
+                packets.truncate(1);
+                SIGNALING_MTU_LE
+            }
+        };
+        if payload.len() > mtu as usize {
+            // reject the first command as the whole PDU exceeds the signaling MTU
+            if let Some(packet) = packets.first() {
+                let mut reason_data = Vec::with_capacity(2);
+                put_u16(&mut reason_data, mtu);
+                send_signaling(
+                    &self.connection,
+                    SignalingPacket::command_reject(
+                        packet.identifier,
+                        REJECT_MTU_EXCEEDED,
+                        &reason_data,
+                    ),
+                );
+            }
+            return;
+        }
+
+        let mut responses = Vec::new();
+        for packet in packets {
+            let mut l2cap = self.l2cap.lock();
+            match (packet.code, info.transport) {
+                // requests too short for their fields are not acted upon, the requesters of
+                // responses check them on their own
+                _ if !packet.is_complete() && !packet.is_response() => {
+                    warn!("L2CAP signaling request {:?} too short", packet.code);
+                    responses.push(SignalingPacket::command_reject(
+                        packet.identifier,
+                        REJECT_NOT_UNDERSTOOD,
+                        &[],
+                    ));
+                }
+                // the responses of the BR/EDR channel setup drive the configuration
+                (SignalingCode::ConnectionResponse, ConnectionTransport::BrEdr)
+                    if packet.is_complete() =>
+                {
+                    responses.extend(l2cap.connection_responded(info.handle, &packet))
+                }
+                (SignalingCode::ConfigureResponse, ConnectionTransport::BrEdr)
+                    if packet.is_complete() =>
+                {
+                    responses.extend(l2cap.configure_responded(info.handle, &packet))
+                }
+                _ if packet.is_response() => l2cap.received_response(info.handle, packet),
+                _ => {
+                    drop(l2cap);
+                    responses.extend(self.process_request(packet));
+                }
+            }
+        }
+        send_signaling_all(&self.connection, responses);
+    }
+
+    /// Process a signaling request and return the commands to be send to the peer
+    fn process_request(&self, request: SignalingPacket) -> Vec<SignalingPacket> {
+        let info = self.connection.info();
+        match (request.code, info.transport) {
+            (SignalingCode::EchoRequest, ConnectionTransport::BrEdr) => vec![SignalingPacket::new(
+                SignalingCode::EchoResponse,
+                request.identifier,
+                request.data,
+            )],
+            (SignalingCode::InformationRequest, ConnectionTransport::BrEdr) => {
+                vec![self.information_response(request)]
+            }
+            (SignalingCode::ConnectionRequest, ConnectionTransport::BrEdr) => self
+                .l2cap
+                .lock()
+                .connection_requested(info.handle, &request),
+            (SignalingCode::ConfigureRequest, ConnectionTransport::BrEdr) => {
+                vec![self.l2cap.lock().configure_requested(info.handle, &request)]
+            }
+            (SignalingCode::LeCreditBasedConnectionRequest, ConnectionTransport::Le) => vec![self
+                .l2cap
+                .lock()
+                .le_connection_requested(info.handle, &request)],
+            (SignalingCode::FlowControlCredit, ConnectionTransport::Le) => self
+                .l2cap
+                .lock()
+                .credits_received(info.handle, &request.data)
+                .into_iter()
+                .collect(),
+            (SignalingCode::DisconnectionRequest, _) => vec![self
+                .l2cap
+                .lock()
+                .disconnection_requested(info.handle, &request)],
+            (SignalingCode::ConnectionParameterUpdateRequest, ConnectionTransport::Le)
+                if info.role == HciConnectionRole::Master =>
+            {
+                vec![self.connection_parameter_update_response(request)]
+            }
+            _ => {
+                info!("L2CAP signaling request {:?} not supported", request.code);
+                vec![SignalingPacket::command_reject(
+                    request.identifier,
+                    REJECT_NOT_UNDERSTOOD,
+                    &[],
+                )]
+            }
+        }
+    }
+
+    fn information_response(&self, request: SignalingPacket) -> SignalingPacket {
+        let info_type = get_u16(&request.data, 0);
+        let mut data = Vec::new();
+        put_u16(&mut data, info_type);
+        match info_type {
+            INFO_TYPE_EXTENDED_FEATURES => {
+                let features = self.l2cap.lock().extended_features();
+                put_u16(&mut data, INFO_RESULT_SUCCESS);
+                data.extend_from_slice(&features.to_le_bytes());
+            }
+            INFO_TYPE_FIXED_CHANNELS => {
+                let channels = self.l2cap.lock().fixed_channel_mask();
+                put_u16(&mut data, INFO_RESULT_SUCCESS);
+                data.extend_from_slice(&channels.to_le_bytes());
+            }
+            _ => put_u16(&mut data, INFO_RESULT_NOT_SUPPORTED),
+        }
+        SignalingPacket::new(SignalingCode::InformationResponse, request.identifier, data)
+    }
+
+    /// The peripheral requests new connection parameters. If they are accepted the connection
+    /// is updated by this device as the central.
+    fn connection_parameter_update_response(&self, request: SignalingPacket) -> SignalingPacket {
+        let parameters = LeConnectionUpdateParameters::new(
+            get_u16(&request.data, 0),
+            get_u16(&request.data, 2),
+            get_u16(&request.data, 4),
+            get_u16(&request.data, 6),
+        );
+        let handle = self.connection.handle();
+        let hci = self.l2cap.lock().hci.clone();
+        let accept = parameters.interval_min <= parameters.interval_max
+            && hci.lock().accept_connection_parameters(handle, &parameters);
+
+        let mut data = Vec::with_capacity(2);
+        if accept {
+            put_u16(&mut data, CONNECTION_PARAMETERS_ACCEPTED);
+            // the connection registry picks up the new parameters from the update complete event
+            spawn(
+                Hci::send_command(hci, HciCommandLeConnectionUpdate::new(handle, &parameters)).map(
+                    move |result| {
+                        if result.is_err() {
+                            warn!("updating the connection parameters of {} failed", handle);
+                        }
+                    },
+                ),
+            );
+        } else {
+            put_u16(&mut data, CONNECTION_PARAMETERS_REJECTED);
+        }
+        SignalingPacket::new(
+            SignalingCode::ConnectionParameterUpdateResponse,
+            request.identifier,
+            data,
+        )
+    }
+}
+
+impl<T> Thinkable for ServeConnectionThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = ();
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        // none of the fields is structurally pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        loop {
+            match Pin::new(&mut this.recv).think(cx) {
+                Conclusion::Pending => return Conclusion::Pending,
+                Conclusion::Ready(Some(pdu)) => this.dispatch(pdu),
+                Conclusion::Ready(None) => {
+                    info!("L2CAP connection {} closed", this.connection.handle());
+                    this.l2cap.lock().closed(this.connection.handle());
+                    return Conclusion::Ready(());
+                }
+            }
+        }
+    }
+}
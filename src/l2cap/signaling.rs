@@ -0,0 +1,207 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # L2CAP Signaling
+//!
+//! The signaling channel carries the commands that control the L2CAP channels of a connection.
+//! On BR/EDR connections one signaling PDU may contain several commands, on LE connections each
+//! PDU contains exactly one command.
+//!
+
+use crate::alloc::vec::Vec;
+
+/// Size of the header of each signaling command
+pub const SIGNALING_HEADER_SIZE: usize = 4;
+/// The MTU of the BR/EDR signaling channel
+pub const SIGNALING_MTU_BREDR: u16 = 48;
+/// The MTU of the LE signaling channel
+pub const SIGNALING_MTU_LE: u16 = 23;
+
+/// Command Reject reason: the command is not understood
+pub const REJECT_NOT_UNDERSTOOD: u16 = 0x0000;
+/// Command Reject reason: the signaling MTU has been exceeded
+pub const REJECT_MTU_EXCEEDED: u16 = 0x0001;
+/// Command Reject reason: the command refers to an invalid channel
+pub const REJECT_INVALID_CID: u16 = 0x0002;
+
+/// Information type requesting the connectionless MTU
+pub const INFO_TYPE_CONNECTIONLESS_MTU: u16 = 0x0001;
+/// Information type requesting the extended features
+pub const INFO_TYPE_EXTENDED_FEATURES: u16 = 0x0002;
+/// Information type requesting the fixed channels supported
+pub const INFO_TYPE_FIXED_CHANNELS: u16 = 0x0003;
+/// Information response result: the information is given
+pub const INFO_RESULT_SUCCESS: u16 = 0x0000;
+/// Information response result: the information type is not supported
+pub const INFO_RESULT_NOT_SUPPORTED: u16 = 0x0001;
+
+/// Extended feature bit signaling the support of enhanced retransmission mode
+pub const FEATURE_ENHANCED_RETRANSMISSION: u32 = 1 << 3;
+/// Extended feature bit signaling the support of streaming mode
+pub const FEATURE_STREAMING: u32 = 1 << 4;
+/// Extended feature bit signaling the support of the FCS option
+pub const FEATURE_FCS: u32 = 1 << 5;
+/// Extended feature bit signaling the support of fixed channels
+pub const FEATURE_FIXED_CHANNELS: u32 = 1 << 7;
+
+/// Connection Parameter Update response result: the parameters are accepted
+pub const CONNECTION_PARAMETERS_ACCEPTED: u16 = 0x0000;
+/// Connection Parameter Update response result: the parameters are rejected
+pub const CONNECTION_PARAMETERS_REJECTED: u16 = 0x0001;
+
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SignalingCode {
+    CommandReject = 0x01,
+    ConnectionRequest = 0x02,
+    ConnectionResponse = 0x03,
+    ConfigureRequest = 0x04,
+    ConfigureResponse = 0x05,
+    DisconnectionRequest = 0x06,
+    DisconnectionResponse = 0x07,
+    EchoRequest = 0x08,
+    EchoResponse = 0x09,
+    InformationRequest = 0x0A,
+    InformationResponse = 0x0B,
+    ConnectionParameterUpdateRequest = 0x12,
+    ConnectionParameterUpdateResponse = 0x13,
+    LeCreditBasedConnectionRequest = 0x14,
+    LeCreditBasedConnectionResponse = 0x15,
+    FlowControlCredit = 0x16,
+    Unknown,
+}
+
+impl From<u8> for SignalingCode {
+    fn from(orig: u8) -> Self {
+        match orig {
+            0x01 => SignalingCode::CommandReject,
+            0x02 => SignalingCode::ConnectionRequest,
+            0x03 => SignalingCode::ConnectionResponse,
+            0x04 => SignalingCode::ConfigureRequest,
+            0x05 => SignalingCode::ConfigureResponse,
+            0x06 => SignalingCode::DisconnectionRequest,
+            0x07 => SignalingCode::DisconnectionResponse,
+            0x08 => SignalingCode::EchoRequest,
+            0x09 => SignalingCode::EchoResponse,
+            0x0A => SignalingCode::InformationRequest,
+            0x0B => SignalingCode::InformationResponse,
+            0x12 => SignalingCode::ConnectionParameterUpdateRequest,
+            0x13 => SignalingCode::ConnectionParameterUpdateResponse,
+            0x14 => SignalingCode::LeCreditBasedConnectionRequest,
+            0x15 => SignalingCode::LeCreditBasedConnectionResponse,
+            0x16 => SignalingCode::FlowControlCredit,
+            _ => SignalingCode::Unknown,
+        }
+    }
+}
+
+/// One signaling command
+#[derive(Debug, Clone)]
+pub struct SignalingPacket {
+    pub code: SignalingCode,
+    /// identifier matching a response to its request, never 0
+    pub identifier: u8,
+    pub data: Vec<u8>,
+}
+
+impl SignalingPacket {
+    pub fn new(code: SignalingCode, identifier: u8, data: Vec<u8>) -> Self {
+        Self {
+            code,
+            identifier,
+            data,
+        }
+    }
+
+    /// Create the Command Reject response to a request with the given identifier
+    pub fn command_reject(identifier: u8, reason: u16, reason_data: &[u8]) -> Self {
+        let mut data = Vec::with_capacity(2 + reason_data.len());
+        put_u16(&mut data, reason);
+        data.extend_from_slice(reason_data);
+        Self::new(SignalingCode::CommandReject, identifier, data)
+    }
+
+    /// Parse all signaling commands contained in the payload of a signaling PDU. Parsing stops at
+    /// the first command that is incomplete.
+    pub fn parse_all(payload: &[u8]) -> Vec<SignalingPacket> {
+        let mut packets = Vec::new();
+        let mut offset = 0;
+        while payload.len() >= offset + SIGNALING_HEADER_SIZE {
+            let length = get_u16(payload, offset + 2) as usize;
+            let start = offset + SIGNALING_HEADER_SIZE;
+            if payload.len() < start + length {
+                break;
+            }
+            packets.push(SignalingPacket::new(
+                payload[offset].into(),
+                payload[offset + 1],
+                payload[start..start + length].to_vec(),
+            ));
+            offset = start + length;
+        }
+        packets
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(SIGNALING_HEADER_SIZE + self.data.len());
+        bytes.push(self.code as u8);
+        bytes.push(self.identifier);
+        put_u16(&mut bytes, self.data.len() as u16);
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+
+    /// Whether this command is a response to a request send by this device
+    pub fn is_response(&self) -> bool {
+        match self.code {
+            SignalingCode::CommandReject
+            | SignalingCode::ConnectionResponse
+            | SignalingCode::ConfigureResponse
+            | SignalingCode::DisconnectionResponse
+            | SignalingCode::EchoResponse
+            | SignalingCode::InformationResponse
+            | SignalingCode::ConnectionParameterUpdateResponse
+            | SignalingCode::LeCreditBasedConnectionResponse => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the data holds all fixed size fields of the command
+    pub fn is_complete(&self) -> bool {
+        let length = match self.code {
+            SignalingCode::CommandReject
+            | SignalingCode::InformationRequest
+            | SignalingCode::ConnectionParameterUpdateResponse => 2,
+            SignalingCode::ConnectionRequest
+            | SignalingCode::ConfigureRequest
+            | SignalingCode::DisconnectionRequest
+            | SignalingCode::DisconnectionResponse
+            | SignalingCode::InformationResponse
+            | SignalingCode::FlowControlCredit => 4,
+            SignalingCode::ConfigureResponse => 6,
+            SignalingCode::ConnectionResponse => 8,
+            SignalingCode::ConnectionParameterUpdateRequest => 8,
+            SignalingCode::LeCreditBasedConnectionRequest
+            | SignalingCode::LeCreditBasedConnectionResponse => 10,
+            SignalingCode::EchoRequest | SignalingCode::EchoResponse | SignalingCode::Unknown => 0,
+        };
+        self.data.len() >= length
+    }
+}
+
+/// Read a little endian u16 from the data at the given offset. Missing data reads as 0.
+pub(crate) fn get_u16(data: &[u8], offset: usize) -> u16 {
+    let low = data.get(offset).copied().unwrap_or(0) as u16;
+    let high = data.get(offset + 1).copied().unwrap_or(0) as u16;
+    low | high << 8
+}
+
+/// Append a little endian u16 to the data
+pub(crate) fn put_u16(data: &mut Vec<u8>, value: u16) {
+    data.push(value as u8);
+    data.push((value >> 8) as u8);
+}
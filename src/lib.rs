@@ -23,8 +23,15 @@ pub use ruspiro_uart as uart;
 
 //pub type SharedTransport = Arc<ruspiro_singleton::Singleton<ruspiro_uart::Uart0>>;
 
+pub mod agent;
+pub mod att;
+pub mod bond;
+pub mod gatt;
 pub mod hci;
 mod hctl;
+pub mod l2cap;
+pub mod smp;
+pub mod uuid;
 
 //mod hci;
 //pub use hci::*;
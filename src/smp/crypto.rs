@@ -0,0 +1,666 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # SMP Cryptographic Functions
+//!
+//! The confirm value function c1 and the key generation function s1 of legacy pairing are built
+//! on the security function e, which is the AES-128 block cipher of the BT host. The functions
+//! f4, f5, f6 and g2 of LE Secure Connections are built on AES-CMAC. They chain many blocks, so
+//! they are calculated on this side with the software implementation of AES-128 found here. All
+//! values are little endian byte arrays as they are transferred in the SMP PDU's. The random
+//! address hash function ah of resolvable private addresses is calculated on this side as well,
+//! as resolving the addresses of many peers takes many encryptions.
+//!
+
+use super::pdu::IoCapability;
+use crate::alloc::boxed::Box;
+use crate::alloc::sync::Arc;
+use crate::alloc::vec;
+use crate::alloc::vec::Vec;
+use crate::brain::*;
+use crate::error::BoxError;
+use crate::hci::{Hci, BD_ADDRESS_SIZE};
+use crate::hctl::HcTransportLayer;
+use crate::lock::*;
+use crate::pin::Pin;
+
+/// Boxed ``Thinkable`` concluding with a 128 bit value calculated by the BT host
+pub(crate) type KeyThinkable = Pin<Box<dyn Thinkable<Output = Result<[u8; 16], BoxError>> + Send>>;
+
+/// The bitwise exclusive or of two 128 bit values
+pub fn xor(a: &[u8; 16], b: &[u8; 16]) -> [u8; 16] {
+    let mut result = [0; 16];
+    for (index, byte) in result.iter_mut().enumerate() {
+        *byte = a[index] ^ b[index];
+    }
+    result
+}
+
+/// The value p1 of the confirm value function c1 built from the Pairing Request and Pairing
+/// Response PDU's and the address types of the initiator and responder
+pub fn c1_p1(preq: &[u8; 7], pres: &[u8; 7], iat: u8, rat: u8) -> [u8; 16] {
+    let mut p1 = [0; 16];
+    p1[0] = iat;
+    p1[1] = rat;
+    p1[2..9].copy_from_slice(preq);
+    p1[9..16].copy_from_slice(pres);
+    p1
+}
+
+/// The value p2 of the confirm value function c1 built from the addresses of the initiator and
+/// responder
+pub fn c1_p2(ia: &[u8; BD_ADDRESS_SIZE], ra: &[u8; BD_ADDRESS_SIZE]) -> [u8; 16] {
+    let mut p2 = [0; 16];
+    p2[..6].copy_from_slice(ra);
+    p2[6..12].copy_from_slice(ia);
+    p2
+}
+
+/// The plaintext of the key generation function s1, the least significant halves of both
+/// random values
+pub fn s1_r(r1: &[u8; 16], r2: &[u8; 16]) -> [u8; 16] {
+    let mut r = [0; 16];
+    r[..8].copy_from_slice(&r2[..8]);
+    r[8..].copy_from_slice(&r1[..8]);
+    r
+}
+
+/// Calculate the confirm value c1(k, r, preq, pres, iat, rat, ia, ra) of legacy pairing
+pub fn c1<T>(
+    hci: Arc<DataLock<Hci<T>>>,
+    k: [u8; 16],
+    r: [u8; 16],
+    p1: [u8; 16],
+    p2: [u8; 16],
+) -> impl Thinkable<Output = Result<[u8; 16], BoxError>>
+where
+    T: HcTransportLayer + 'static,
+{
+    let step: KeyThinkable = Box::pin(Hci::encrypt(hci.clone(), k, xor(&r, &p1)));
+    ConfirmThinkable {
+        hci,
+        k,
+        p2,
+        step,
+        second: false,
+    }
+}
+
+/// Calculate the short term key s1(k, r1, r2) of legacy pairing
+pub fn s1<T>(
+    hci: Arc<DataLock<Hci<T>>>,
+    k: [u8; 16],
+    r1: [u8; 16],
+    r2: [u8; 16],
+) -> impl Thinkable<Output = Result<[u8; 16], BoxError>>
+where
+    T: HcTransportLayer + 'static,
+{
+    Hci::encrypt(hci, k, s1_r(&r1, &r2))
+}
+
+/// Get the given number of random bytes from the random number generator of the BT host
+pub fn random<T>(
+    hci: Arc<DataLock<Hci<T>>>,
+    length: usize,
+) -> impl Thinkable<Output = Result<Vec<u8>, BoxError>>
+where
+    T: HcTransportLayer + 'static,
+{
+    RandomThinkable {
+        hci,
+        length,
+        bytes: Vec::with_capacity(length + 8),
+        step: None,
+    }
+}
+
+/// This ``Thinkable`` runs the two encryptions of the confirm value function
+struct ConfirmThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    hci: Arc<DataLock<Hci<T>>>,
+    k: [u8; 16],
+    p2: [u8; 16],
+    step: KeyThinkable,
+    /// the second encryption is running
+    second: bool,
+}
+
+impl<T> Thinkable for ConfirmThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = Result<[u8; 16], BoxError>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        // the encryption Thinkable is pinned on its own
+        let this = unsafe { self.get_unchecked_mut() };
+        loop {
+            let encrypted = match this.step.as_mut().think(cx) {
+                Conclusion::Pending => return Conclusion::Pending,
+                Conclusion::Ready(Err(e)) => return Conclusion::Ready(Err(e)),
+                Conclusion::Ready(Ok(encrypted)) => encrypted,
+            };
+            if this.second {
+                return Conclusion::Ready(Ok(encrypted));
+            }
+            this.second = true;
+            this.step = Box::pin(Hci::encrypt(
+                this.hci.clone(),
+                this.k,
+                xor(&encrypted, &this.p2),
+            ));
+        }
+    }
+}
+
+/// This ``Thinkable`` requests random numbers from the BT host until there are enough bytes
+struct RandomThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    hci: Arc<DataLock<Hci<T>>>,
+    length: usize,
+    bytes: Vec<u8>,
+    step: Option<Pin<Box<dyn Thinkable<Output = Result<[u8; 8], BoxError>> + Send>>>,
+}
+
+impl<T> Thinkable for RandomThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = Result<Vec<u8>, BoxError>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        // the random number Thinkable is pinned on its own
+        let this = unsafe { self.get_unchecked_mut() };
+        loop {
+            if this.bytes.len() >= this.length {
+                let mut bytes = core::mem::replace(&mut this.bytes, Vec::new());
+                bytes.truncate(this.length);
+                return Conclusion::Ready(Ok(bytes));
+            }
+            let hci = this.hci.clone();
+            let step = this.step.get_or_insert_with(|| Box::pin(Hci::rand(hci)));
+            match step.as_mut().think(cx) {
+                Conclusion::Pending => return Conclusion::Pending,
+                Conclusion::Ready(Err(e)) => return Conclusion::Ready(Err(e)),
+                Conclusion::Ready(Ok(random)) => {
+                    this.step = None;
+                    this.bytes.extend_from_slice(&random);
+                }
+            }
+        }
+    }
+}
+
+/// The AES S-box
+const SBOX: [u8; 256] = [
+    0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76,
+    0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0,
+    0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15,
+    0x04, 0xc7, 0x23, 0xc3, 0x18, 0x96, 0x05, 0x9a, 0x07, 0x12, 0x80, 0xe2, 0xeb, 0x27, 0xb2, 0x75,
+    0x09, 0x83, 0x2c, 0x1a, 0x1b, 0x6e, 0x5a, 0xa0, 0x52, 0x3b, 0xd6, 0xb3, 0x29, 0xe3, 0x2f, 0x84,
+    0x53, 0xd1, 0x00, 0xed, 0x20, 0xfc, 0xb1, 0x5b, 0x6a, 0xcb, 0xbe, 0x39, 0x4a, 0x4c, 0x58, 0xcf,
+    0xd0, 0xef, 0xaa, 0xfb, 0x43, 0x4d, 0x33, 0x85, 0x45, 0xf9, 0x02, 0x7f, 0x50, 0x3c, 0x9f, 0xa8,
+    0x51, 0xa3, 0x40, 0x8f, 0x92, 0x9d, 0x38, 0xf5, 0xbc, 0xb6, 0xda, 0x21, 0x10, 0xff, 0xf3, 0xd2,
+    0xcd, 0x0c, 0x13, 0xec, 0x5f, 0x97, 0x44, 0x17, 0xc4, 0xa7, 0x7e, 0x3d, 0x64, 0x5d, 0x19, 0x73,
+    0x60, 0x81, 0x4f, 0xdc, 0x22, 0x2a, 0x90, 0x88, 0x46, 0xee, 0xb8, 0x14, 0xde, 0x5e, 0x0b, 0xdb,
+    0xe0, 0x32, 0x3a, 0x0a, 0x49, 0x06, 0x24, 0x5c, 0xc2, 0xd3, 0xac, 0x62, 0x91, 0x95, 0xe4, 0x79,
+    0xe7, 0xc8, 0x37, 0x6d, 0x8d, 0xd5, 0x4e, 0xa9, 0x6c, 0x56, 0xf4, 0xea, 0x65, 0x7a, 0xae, 0x08,
+    0xba, 0x78, 0x25, 0x2e, 0x1c, 0xa6, 0xb4, 0xc6, 0xe8, 0xdd, 0x74, 0x1f, 0x4b, 0xbd, 0x8b, 0x8a,
+    0x70, 0x3e, 0xb5, 0x66, 0x48, 0x03, 0xf6, 0x0e, 0x61, 0x35, 0x57, 0xb9, 0x86, 0xc1, 0x1d, 0x9e,
+    0xe1, 0xf8, 0x98, 0x11, 0x69, 0xd9, 0x8e, 0x94, 0x9b, 0x1e, 0x87, 0xe9, 0xce, 0x55, 0x28, 0xdf,
+    0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16,
+];
+
+/// The salt of the key derivation function f5
+const F5_SALT: [u8; 16] = [
+    0x6c, 0x88, 0x83, 0x91, 0xaa, 0xf5, 0xa5, 0x38, 0x60, 0x37, 0x0b, 0xdb, 0x5a, 0x60, 0x83, 0xbe,
+];
+
+/// The key ID "btle" of the key derivation function f5
+const F5_KEY_ID: [u8; 4] = [0x62, 0x74, 0x6c, 0x65];
+
+/// Multiplication by x in GF(2^8)
+fn xtime(value: u8) -> u8 {
+    (value << 1) ^ if value & 0x80 != 0 { 0x1b } else { 0x00 }
+}
+
+/// Encrypt one block with AES-128. Unlike the other functions key, plaintext and the encrypted
+/// block are in the byte order of the AES specification, the most significant byte first.
+pub fn aes128(key: &[u8; 16], plaintext: &[u8; 16]) -> [u8; 16] {
+    // expand the key into the 11 round keys
+    let mut round_keys = [[0u8; 16]; 11];
+    round_keys[0] = *key;
+    let mut rcon = 0x01;
+    for round in 1..11 {
+        let previous = round_keys[round - 1];
+        let mut word = [
+            SBOX[previous[13] as usize] ^ rcon,
+            SBOX[previous[14] as usize],
+            SBOX[previous[15] as usize],
+            SBOX[previous[12] as usize],
+        ];
+        for column in 0..4 {
+            for row in 0..4 {
+                word[row] ^= previous[column * 4 + row];
+                round_keys[round][column * 4 + row] = word[row];
+            }
+        }
+        rcon = xtime(rcon);
+    }
+
+    let mut state = xor(plaintext, &round_keys[0]);
+    for (round, round_key) in round_keys.iter().enumerate().skip(1) {
+        // substitute the bytes and shift the rows, the state is stored column by column
+        let mut shifted = [0u8; 16];
+        for column in 0..4 {
+            for row in 0..4 {
+                shifted[column * 4 + row] = SBOX[state[((column + row) % 4) * 4 + row] as usize];
+            }
+        }
+        // mix the columns, except in the final round
+        if round < 10 {
+            for column in shifted.chunks_mut(4) {
+                let all = column[0] ^ column[1] ^ column[2] ^ column[3];
+                let first = column[0];
+                column[0] ^= all ^ xtime(column[0] ^ column[1]);
+                column[1] ^= all ^ xtime(column[1] ^ column[2]);
+                column[2] ^= all ^ xtime(column[2] ^ column[3]);
+                column[3] ^= all ^ xtime(column[3] ^ first);
+            }
+        }
+        state = xor(&shifted, round_key);
+    }
+    state
+}
+
+/// Shift a 128 bit value left by one bit and reduce it by the polynomial of the CMAC subkeys
+fn cmac_subkey(value: &[u8; 16]) -> [u8; 16] {
+    let mut subkey = [0; 16];
+    for index in 0..16 {
+        let carry = value.get(index + 1).map_or(0, |next| next >> 7);
+        subkey[index] = value[index] << 1 | carry;
+    }
+    if value[0] & 0x80 != 0 {
+        subkey[15] ^= 0x87;
+    }
+    subkey
+}
+
+/// Calculate the AES-CMAC of the message with the given key. Key, message and the MAC are in the
+/// byte order of the AES specification, the most significant byte first.
+pub fn aes_cmac(key: &[u8; 16], message: &[u8]) -> [u8; 16] {
+    let k1 = cmac_subkey(&aes128(key, &[0; 16]));
+    let k2 = cmac_subkey(&k1);
+    let blocks = if message.is_empty() {
+        1
+    } else {
+        (message.len() + 15) / 16
+    };
+    let mut mac = [0; 16];
+    for index in 0..blocks {
+        let mut block = [0; 16];
+        let data = &message[index * 16..message.len().min(index * 16 + 16)];
+        block[..data.len()].copy_from_slice(data);
+        if index == blocks - 1 {
+            // the last block is padded if it is incomplete
+            if data.len() == 16 {
+                block = xor(&block, &k1);
+            } else {
+                block[data.len()] = 0x80;
+                block = xor(&block, &k2);
+            }
+        }
+        mac = aes128(key, &xor(&mac, &block));
+    }
+    mac
+}
+
+/// Reverse little endian bytes to the most significant byte first and the other way round
+fn reversed<A: AsRef<[u8]>>(value: A) -> Vec<u8> {
+    value.as_ref().iter().rev().copied().collect()
+}
+
+fn reversed_key(value: &[u8; 16]) -> [u8; 16] {
+    let mut key = *value;
+    key.reverse();
+    key
+}
+
+/// The address of a device as used by f5 and f6, the address type as most significant byte
+fn address_bytes(address_type: u8, address: &[u8; BD_ADDRESS_SIZE]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(1 + BD_ADDRESS_SIZE);
+    bytes.push(address_type);
+    bytes.extend(address.iter().rev());
+    bytes
+}
+
+/// The confirm value function f4(U, V, X, Z) of LE Secure Connections. U and V are the X
+/// coordinates of the public keys.
+pub fn f4(u: &[u8; 32], v: &[u8; 32], x: &[u8; 16], z: u8) -> [u8; 16] {
+    let mut message = reversed(u);
+    message.extend(reversed(v));
+    message.push(z);
+    reversed_key(&aes_cmac(&reversed_key(x), &message))
+}
+
+/// The key generation function f5(W, N1, N2, A1, A2) of LE Secure Connections. This returns the
+/// MacKey and the long term key. The addresses are given with their address type.
+pub fn f5(
+    w: &[u8; 32],
+    n1: &[u8; 16],
+    n2: &[u8; 16],
+    a1: (u8, &[u8; BD_ADDRESS_SIZE]),
+    a2: (u8, &[u8; BD_ADDRESS_SIZE]),
+) -> ([u8; 16], [u8; 16]) {
+    let t = aes_cmac(&F5_SALT, &reversed(w));
+    let mut message = vec![0x00];
+    message.extend_from_slice(&F5_KEY_ID);
+    message.extend(reversed(n1));
+    message.extend(reversed(n2));
+    message.extend(address_bytes(a1.0, a1.1));
+    message.extend(address_bytes(a2.0, a2.1));
+    message.extend_from_slice(&[0x01, 0x00]);
+    let mac_key = aes_cmac(&t, &message);
+    message[0] = 0x01;
+    let ltk = aes_cmac(&t, &message);
+    (reversed_key(&mac_key), reversed_key(&ltk))
+}
+
+/// The check value function f6(W, N1, N2, R, IOcap, A1, A2) of LE Secure Connections. IOcap are
+/// the authentication requirements, the OOB data flag and the IO capability of the device.
+pub fn f6(
+    w: &[u8; 16],
+    n1: &[u8; 16],
+    n2: &[u8; 16],
+    r: &[u8; 16],
+    io_cap: (u8, bool, IoCapability),
+    a1: (u8, &[u8; BD_ADDRESS_SIZE]),
+    a2: (u8, &[u8; BD_ADDRESS_SIZE]),
+) -> [u8; 16] {
+    let mut message = reversed(n1);
+    message.extend(reversed(n2));
+    message.extend(reversed(r));
+    message.extend_from_slice(&[io_cap.0, io_cap.1 as u8, io_cap.2 as u8]);
+    message.extend(address_bytes(a1.0, a1.1));
+    message.extend(address_bytes(a2.0, a2.1));
+    reversed_key(&aes_cmac(&reversed_key(w), &message))
+}
+
+/// The random address hash function ah(k, r) of resolvable private addresses. k is the identity
+/// resolving key and r the 24 bit random part of the address.
+pub fn ah(k: &[u8; 16], r: &[u8; 3]) -> [u8; 3] {
+    let mut r_padded = [0; 16];
+    r_padded[..3].copy_from_slice(r);
+    let hash = reversed_key(&aes128(&reversed_key(k), &reversed_key(&r_padded)));
+    [hash[0], hash[1], hash[2]]
+}
+
+/// The numeric comparison value function g2(U, V, X, Y) of LE Secure Connections. The six
+/// digit value displayed to the user is the result modulo 1 000 000.
+pub fn g2(u: &[u8; 32], v: &[u8; 32], x: &[u8; 16], y: &[u8; 16]) -> u32 {
+    let mut message = reversed(u);
+    message.extend(reversed(v));
+    message.extend(reversed(y));
+    let mac = aes_cmac(&reversed_key(x), &message);
+    u32::from_be_bytes([mac[12], mac[13], mac[14], mac[15]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The value given with the most significant byte first, as in the specification, in the
+    /// little endian byte order used by the functions
+    fn le(value: [u8; 16]) -> [u8; 16] {
+        reversed_key(&value)
+    }
+
+    fn le_32(value: [u8; 32]) -> [u8; 32] {
+        let mut value = value;
+        value.reverse();
+        value
+    }
+
+    /// The X coordinates of the public keys and the nonces of the sample data of f4 and g2
+    const U: [u8; 32] = [
+        0x20, 0xb0, 0x03, 0xd2, 0xf2, 0x97, 0xbe, 0x2c, 0x5e, 0x2c, 0x83, 0xa7, 0xe9, 0xf9, 0xa5,
+        0xb9, 0xef, 0xf4, 0x91, 0x11, 0xac, 0xf4, 0xfd, 0xdb, 0xcc, 0x03, 0x01, 0x48, 0x0e, 0x35,
+        0x9d, 0xe6,
+    ];
+    const V: [u8; 32] = [
+        0x55, 0x18, 0x8b, 0x3d, 0x32, 0xf6, 0xbb, 0x9a, 0x90, 0x0a, 0xfc, 0xfb, 0xee, 0xd4, 0xe7,
+        0x2a, 0x59, 0xcb, 0x9a, 0xc2, 0xf1, 0x9d, 0x7c, 0xfb, 0x6b, 0x4f, 0xdd, 0x49, 0xf4, 0x7f,
+        0xc5, 0xfd,
+    ];
+    const N1: [u8; 16] = [
+        0xd5, 0xcb, 0x84, 0x54, 0xd1, 0x77, 0x73, 0x3e, 0xff, 0xff, 0xb2, 0xec, 0x71, 0x2b, 0xae,
+        0xab,
+    ];
+    const N2: [u8; 16] = [
+        0xa6, 0xe8, 0xe7, 0xcc, 0x25, 0xa7, 0x5f, 0x6e, 0x21, 0x65, 0x83, 0xf7, 0xff, 0x3d, 0xc4,
+        0xcf,
+    ];
+    /// The addresses of the sample data of f5 and f6, least significant byte first
+    const A1: [u8; BD_ADDRESS_SIZE] = [0xce, 0xbf, 0x37, 0x37, 0x12, 0x56];
+    const A2: [u8; BD_ADDRESS_SIZE] = [0xc1, 0xcf, 0x2d, 0x70, 0x13, 0xa7];
+    const MAC_KEY: [u8; 16] = [
+        0x29, 0x65, 0xf1, 0x76, 0xa1, 0x08, 0x4a, 0x02, 0xfd, 0x3f, 0x6a, 0x20, 0xce, 0x63, 0x6e,
+        0x20,
+    ];
+
+    /// The security function e as calculated by the BT host with the LE Encrypt command
+    fn e(k: &[u8; 16], plaintext: &[u8; 16]) -> [u8; 16] {
+        reversed_key(&aes128(&reversed_key(k), &reversed_key(plaintext)))
+    }
+
+    #[test]
+    fn aes128_fips_197() {
+        let key = [
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d,
+            0x0e, 0x0f,
+        ];
+        let plaintext = [
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd,
+            0xee, 0xff,
+        ];
+        let ciphertext = [
+            0x69, 0xc4, 0xe0, 0xd8, 0x6a, 0x7b, 0x04, 0x30, 0xd8, 0xcd, 0xb7, 0x80, 0x70, 0xb4,
+            0xc5, 0x5a,
+        ];
+        assert_eq!(aes128(&key, &plaintext), ciphertext);
+    }
+
+    #[test]
+    fn c1_sample_data() {
+        // Pairing Request, Pairing Response and the addresses as transferred, least significant
+        // byte first
+        let preq = [0x01, 0x01, 0x00, 0x00, 0x10, 0x07, 0x07];
+        let pres = [0x02, 0x03, 0x00, 0x00, 0x08, 0x00, 0x05];
+        let ia = [0xa6, 0xa5, 0xa4, 0xa3, 0xa2, 0xa1];
+        let ra = [0xb6, 0xb5, 0xb4, 0xb3, 0xb2, 0xb1];
+        let k = [0; 16];
+        let r = le([
+            0x57, 0x83, 0xd5, 0x21, 0x56, 0xad, 0x6f, 0x0e, 0x63, 0x88, 0x27, 0x4e, 0xc6, 0x70,
+            0x2e, 0xe0,
+        ]);
+
+        let p1 = c1_p1(&preq, &pres, 0x01, 0x00);
+        assert_eq!(
+            p1,
+            le([
+                0x05, 0x00, 0x08, 0x00, 0x00, 0x03, 0x02, 0x07, 0x07, 0x10, 0x00, 0x00, 0x01, 0x01,
+                0x00, 0x01,
+            ])
+        );
+        let p2 = c1_p2(&ia, &ra);
+        assert_eq!(
+            p2,
+            le([
+                0x00, 0x00, 0x00, 0x00, 0xa1, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xb1, 0xb2, 0xb3, 0xb4,
+                0xb5, 0xb6,
+            ])
+        );
+        let confirm = e(&k, &xor(&e(&k, &xor(&r, &p1)), &p2));
+        assert_eq!(
+            confirm,
+            le([
+                0x1e, 0x1e, 0x3f, 0xef, 0x87, 0x89, 0x88, 0xea, 0xd2, 0xa7, 0x4d, 0xc5, 0xbe, 0xf1,
+                0x3b, 0x86,
+            ])
+        );
+    }
+
+    #[test]
+    fn s1_sample_data() {
+        let k = [0; 16];
+        let r1 = le([
+            0x00, 0x0f, 0x0e, 0x0d, 0x0c, 0x0b, 0x0a, 0x09, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66,
+            0x77, 0x88,
+        ]);
+        let r2 = le([
+            0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee,
+            0xff, 0x00,
+        ]);
+
+        let r = s1_r(&r1, &r2);
+        assert_eq!(
+            r,
+            le([
+                0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee,
+                0xff, 0x00,
+            ])
+        );
+        assert_eq!(
+            e(&k, &r),
+            le([
+                0x9a, 0x1f, 0xe1, 0xf0, 0xe8, 0xb0, 0xf4, 0x9b, 0x5b, 0x42, 0x16, 0xae, 0x79, 0x6d,
+                0xa0, 0x62,
+            ])
+        );
+    }
+
+    /// The examples of RFC 4493
+    #[test]
+    fn aes_cmac_rfc_4493() {
+        let key = [
+            0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf,
+            0x4f, 0x3c,
+        ];
+        let message = [
+            0x6b, 0xc1, 0xbe, 0xe2, 0x2e, 0x40, 0x9f, 0x96, 0xe9, 0x3d, 0x7e, 0x11, 0x73, 0x93,
+            0x17, 0x2a, 0xae, 0x2d, 0x8a, 0x57, 0x1e, 0x03, 0xac, 0x9c, 0x9e, 0xb7, 0x6f, 0xac,
+            0x45, 0xaf, 0x8e, 0x51, 0x30, 0xc8, 0x1c, 0x46, 0xa3, 0x5c, 0xe4, 0x11, 0xe5, 0xfb,
+            0xc1, 0x19, 0x1a, 0x0a, 0x52, 0xef, 0xf6, 0x9f, 0x24, 0x45, 0xdf, 0x4f, 0x9b, 0x17,
+            0xad, 0x2b, 0x41, 0x7b, 0xe6, 0x6c, 0x37, 0x10,
+        ];
+        assert_eq!(
+            aes_cmac(&key, &[]),
+            [
+                0xbb, 0x1d, 0x69, 0x29, 0xe9, 0x59, 0x37, 0x28, 0x7f, 0xa3, 0x7d, 0x12, 0x9b, 0x75,
+                0x67, 0x46,
+            ]
+        );
+        assert_eq!(
+            aes_cmac(&key, &message[..16]),
+            [
+                0x07, 0x0a, 0x16, 0xb4, 0x6b, 0x4d, 0x41, 0x44, 0xf7, 0x9b, 0xdd, 0x9d, 0xd0, 0x4a,
+                0x28, 0x7c,
+            ]
+        );
+        assert_eq!(
+            aes_cmac(&key, &message[..40]),
+            [
+                0xdf, 0xa6, 0x67, 0x47, 0xde, 0x9a, 0xe6, 0x30, 0x30, 0xca, 0x32, 0x61, 0x14, 0x97,
+                0xc8, 0x27,
+            ]
+        );
+        assert_eq!(
+            aes_cmac(&key, &message),
+            [
+                0x51, 0xf0, 0xbe, 0xbf, 0x7e, 0x3b, 0x9d, 0x92, 0xfc, 0x49, 0x74, 0x17, 0x79, 0x36,
+                0x3c, 0xfe,
+            ]
+        );
+    }
+
+    #[test]
+    fn f4_sample_data() {
+        let confirm = f4(&le_32(U), &le_32(V), &le(N1), 0x00);
+        assert_eq!(
+            confirm,
+            le([
+                0xf2, 0xc9, 0x16, 0xf1, 0x07, 0xa9, 0xbd, 0x1c, 0xf1, 0xed, 0xa1, 0xbe, 0xa9, 0x74,
+                0x87, 0x2d,
+            ])
+        );
+    }
+
+    #[test]
+    fn f5_sample_data() {
+        let w = le_32([
+            0xec, 0x02, 0x34, 0xa3, 0x57, 0xc8, 0xad, 0x05, 0x34, 0x10, 0x10, 0xa6, 0x0a, 0x39,
+            0x7d, 0x9b, 0x99, 0x79, 0x6b, 0x13, 0xb4, 0xf8, 0x66, 0xf1, 0x86, 0x8d, 0x34, 0xf3,
+            0x73, 0xbf, 0xa6, 0x98,
+        ]);
+        let (mac_key, ltk) = f5(&w, &le(N1), &le(N2), (0x00, &A1), (0x00, &A2));
+        assert_eq!(mac_key, le(MAC_KEY));
+        assert_eq!(
+            ltk,
+            le([
+                0x69, 0x86, 0x79, 0x11, 0x69, 0xd7, 0xcd, 0x23, 0x98, 0x05, 0x22, 0xb5, 0x94, 0x75,
+                0x0a, 0x38,
+            ])
+        );
+    }
+
+    #[test]
+    fn f6_sample_data() {
+        let r = le([
+            0x12, 0xa3, 0x34, 0x3b, 0xb4, 0x53, 0xbb, 0x54, 0x08, 0xda, 0x42, 0xd2, 0x0c, 0x2d,
+            0x0f, 0xc8,
+        ]);
+        // IOcap 0x010102: bonding requested, OOB data present and keyboard only
+        let io_cap = (0x01, true, IoCapability::KeyboardOnly);
+        let check = f6(
+            &le(MAC_KEY),
+            &le(N1),
+            &le(N2),
+            &r,
+            io_cap,
+            (0x00, &A1),
+            (0x00, &A2),
+        );
+        assert_eq!(
+            check,
+            le([
+                0xe3, 0xc4, 0x73, 0x98, 0x9c, 0xd0, 0xe8, 0xc5, 0xd2, 0x6c, 0x0b, 0x09, 0xda, 0x95,
+                0x8f, 0x61,
+            ])
+        );
+    }
+
+    #[test]
+    fn g2_sample_data() {
+        let value = g2(&le_32(U), &le_32(V), &le(N1), &le(N2));
+        assert_eq!(value, 0x2f9e_d5ba);
+        assert_eq!(value % 1_000_000, 938_554);
+    }
+
+    #[test]
+    fn ah_sample_data() {
+        let irk = le([
+            0xec, 0x02, 0x34, 0xa3, 0x57, 0xc8, 0xad, 0x05, 0x34, 0x10, 0x10, 0xa6, 0x0a, 0x39,
+            0x7d, 0x9b,
+        ]);
+        assert_eq!(ah(&irk, &[0x94, 0x81, 0x70]), [0xaa, 0xfb, 0x0d]);
+    }
+}
@@ -0,0 +1,66 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # SMP Errors
+//!
+//! The reasons a pairing fails with. They are passed to the peer with the Pairing Failed PDU.
+//!
+
+use crate::error::Error;
+
+/// The user input of the passkey failed or has been cancelled
+pub const SMP_ERROR_PASSKEY_ENTRY_FAILED: u8 = 0x01;
+/// The out of band data is not available
+pub const SMP_ERROR_OOB_NOT_AVAILABLE: u8 = 0x02;
+/// The authentication requirements can not be met due to the IO capabilities
+pub const SMP_ERROR_AUTHENTICATION_REQUIREMENTS: u8 = 0x03;
+/// The confirm value does not match the calculated compare value
+pub const SMP_ERROR_CONFIRM_VALUE_FAILED: u8 = 0x04;
+/// Pairing is not supported by the device
+pub const SMP_ERROR_PAIRING_NOT_SUPPORTED: u8 = 0x05;
+/// The resultant encryption key size is not long enough
+pub const SMP_ERROR_ENCRYPTION_KEY_SIZE: u8 = 0x06;
+/// The SMP command received is not supported
+pub const SMP_ERROR_COMMAND_NOT_SUPPORTED: u8 = 0x07;
+/// Pairing failed for a reason not covered by the other codes
+pub const SMP_ERROR_UNSPECIFIED_REASON: u8 = 0x08;
+/// Pairing has been attempted too often in a short time
+pub const SMP_ERROR_REPEATED_ATTEMPTS: u8 = 0x09;
+/// A command has an invalid length or a parameter is out of range
+pub const SMP_ERROR_INVALID_PARAMETERS: u8 = 0x0A;
+/// The DHKey Check value does not match the calculated one
+pub const SMP_ERROR_DHKEY_CHECK_FAILED: u8 = 0x0B;
+/// The confirm values of the numeric comparison do not match
+pub const SMP_ERROR_NUMERIC_COMPARISON_FAILED: u8 = 0x0C;
+
+/// Error raised if a pairing fails or an SMP operation can not be processed
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub struct SmpError {
+    /// the ``SMP_ERROR_*`` reason of the failure
+    pub code: u8,
+    reason: &'static str,
+}
+
+impl SmpError {
+    pub fn new(code: u8, reason: &'static str) -> Self {
+        Self { code, reason }
+    }
+}
+
+impl Error for SmpError {}
+
+impl core::fmt::Display for SmpError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "SMP error {:#04X}: {}", self.code, self.reason)
+    }
+}
+
+impl core::fmt::Debug for SmpError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        <SmpError as core::fmt::Display>::fmt(self, f)
+    }
+}
@@ -0,0 +1,82 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # Pairing Keys
+//!
+//! The keys distributed while pairing. A bond keeps the keys exchanged with one peer, those
+//! distributed by this device as well as those distributed by the peer.
+//!
+
+use crate::hci::BD_ADDRESS_SIZE;
+
+/// A long term key used to encrypt LE connections
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct LongTermKey {
+    pub ltk: [u8; 16],
+    /// the encrypted diversifier identifying the key, 0 for secure connections
+    pub ediv: u16,
+    /// the random number identifying the key, 0 for secure connections
+    pub random_number: [u8; 8],
+    /// the number of bytes of the key used
+    pub key_size: u8,
+    /// the key has been created with protection against man-in-the-middle attacks
+    pub authenticated: bool,
+    /// the key has been created with LE Secure Connections pairing
+    pub secure_connections: bool,
+}
+
+/// The identity of a device that uses resolvable private addresses
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct IdentityKey {
+    /// the identity resolving key
+    pub irk: [u8; 16],
+    /// 0x00 public, 0x01 static random
+    pub address_type: u8,
+    pub address: [u8; BD_ADDRESS_SIZE],
+}
+
+/// The keys exchanged with one peer
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Bond {
+    /// 0x00 public, 0x01 random, the address of the peer while pairing
+    pub peer_address_type: u8,
+    pub peer_address: [u8; BD_ADDRESS_SIZE],
+    /// the long term key the peer uses to encrypt the connection if this device is the
+    /// peripheral
+    pub local_ltk: Option<LongTermKey>,
+    /// the long term key this device uses to encrypt the connection if the peer is the
+    /// peripheral
+    pub peer_ltk: Option<LongTermKey>,
+    pub peer_identity: Option<IdentityKey>,
+    /// the connection signature resolving key of this device
+    pub local_csrk: Option<[u8; 16]>,
+    /// the connection signature resolving key of the peer
+    pub peer_csrk: Option<[u8; 16]>,
+}
+
+impl Bond {
+    pub fn new(peer_address_type: u8, peer_address: [u8; BD_ADDRESS_SIZE]) -> Self {
+        Self {
+            peer_address_type,
+            peer_address,
+            local_ltk: None,
+            peer_ltk: None,
+            peer_identity: None,
+            local_csrk: None,
+            peer_csrk: None,
+        }
+    }
+
+    /// Whether the bond has been created with the given peer. Peers that distributed their
+    /// identity are known by their identity address.
+    pub fn is_peer(&self, address: &[u8; BD_ADDRESS_SIZE]) -> bool {
+        self.peer_address == *address
+            || self
+                .peer_identity
+                .map_or(false, |identity| identity.address == *address)
+    }
+}
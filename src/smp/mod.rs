@@ -0,0 +1,774 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # Security Manager Protocol
+//!
+//! The security manager pairs with the peers of LE connections on the fixed L2CAP channel
+//! [CID_SMP]. Pairing creates a key the connection is encrypted with and distributes the keys used
+//! to encrypt later connections, to resolve private addresses and to sign data. LE Secure
+//! Connections pairing is used if the peer supports it, legacy pairing otherwise.
+//! The central initiates pairing with [Smp::pair], the pairing requested by a central is answered
+//! by the Thinkable returned from [Smp::serve], which also answers the requests of the BT host for
+//! the long term key of a connection. The user interaction of each pairing is passed to the
+//! [crate::agent::PairingAgent] set with [Smp::set_pairing_agent], without an agent it is
+//! reported as [SmpEvent] just like the outcome of each pairing. The bonds are kept in the
+//! [crate::bond::BondStore] set with [Smp::set_bond_store].
+//!
+
+pub mod crypto;
+pub mod errors;
+pub use errors::*;
+pub mod keys;
+pub use keys::*;
+pub mod pdu;
+pub use pdu::*;
+mod pairing;
+pub use pairing::*;
+
+use crate::agent::{AgentReply, PairingPeer, SharedPairingAgent};
+use crate::alloc::boxed::Box;
+use crate::alloc::collections::{BTreeMap, VecDeque};
+use crate::alloc::sync::Arc;
+use crate::alloc::vec::Vec;
+use crate::bond::{self, BondRecord, MemoryBondStore, SharedBondStore};
+use crate::brain::{waker::*, *};
+use crate::error::BoxError;
+use crate::hci::acl::SendAclThinkable;
+use crate::hci::connection::{
+    ConnectionTransport, EnableEncryptionThinkable, HciConnectionRole,
+    NextLongTermKeyRequestThinkable, CONNECTION_HANDLE_MASK,
+};
+use crate::hci::events::HciEventLeLongTermKeyRequest;
+use crate::hci::{Hci, BD_ADDRESS_SIZE};
+use crate::hctl::HcTransportLayer;
+use crate::l2cap::{FixedChannel, L2cap, RecvFixedChannelThinkable, CID_SMP};
+use crate::lock::*;
+use crate::pin::Pin;
+use crate::{info, warn};
+
+/// Time in milliseconds a pairing waits for the next SMP PDU before it fails
+pub const SMP_TIMEOUT: u64 = 30_000;
+/// The minimum size of an encryption key in bytes
+pub const SMP_MIN_KEY_SIZE: u8 = 7;
+/// The maximum size of an encryption key in bytes
+pub const SMP_MAX_KEY_SIZE: u8 = 16;
+/// Maximum number of events kept until they are taken. If there are more the oldest ones are
+/// dropped.
+const MAX_PENDING_EVENTS: usize = 16;
+
+/// The security properties of this device used for pairing
+#[derive(Debug, Copy, Clone)]
+pub struct SmpConfiguration {
+    /// the IO capability used without a pairing agent, otherwise the agent declares it
+    pub io_capability: IoCapability,
+    /// keys are distributed and stored to encrypt later connections
+    pub bonding: bool,
+    /// protection against man-in-the-middle attacks is required
+    pub mitm: bool,
+    /// the maximum encryption key size in bytes
+    pub max_key_size: u8,
+    /// the ``KEY_DISTRIBUTION_*`` keys this device distributes
+    pub local_keys: u8,
+    /// the ``KEY_DISTRIBUTION_*`` keys requested from the peer
+    pub peer_keys: u8,
+    /// 0x00 public, 0x01 static random, the type of the address this device connects with
+    pub identity_address_type: u8,
+    /// the address this device connects with, distributed as its identity
+    pub identity_address: [u8; BD_ADDRESS_SIZE],
+    /// the identity resolving key, this device distributes its identity only if it has one
+    pub identity_resolving_key: Option<[u8; 16]>,
+    /// LE Secure Connections pairing is supported
+    pub secure_connections: bool,
+    /// pairing fails if the peer does not support LE Secure Connections
+    pub secure_connections_only: bool,
+}
+
+impl SmpConfiguration {
+    pub fn new(io_capability: IoCapability, identity_address: [u8; BD_ADDRESS_SIZE]) -> Self {
+        Self {
+            io_capability,
+            bonding: true,
+            mitm: false,
+            max_key_size: SMP_MAX_KEY_SIZE,
+            local_keys: KEY_DISTRIBUTION_ENCRYPTION
+                | KEY_DISTRIBUTION_IDENTITY
+                | KEY_DISTRIBUTION_SIGNING,
+            peer_keys: KEY_DISTRIBUTION_ENCRYPTION
+                | KEY_DISTRIBUTION_IDENTITY
+                | KEY_DISTRIBUTION_SIGNING,
+            identity_address_type: 0x00,
+            identity_address,
+            identity_resolving_key: None,
+            secure_connections: true,
+            secure_connections_only: false,
+        }
+    }
+
+    /// The ``AUTH_REQ_*`` bits of this device
+    fn auth_req(&self) -> u8 {
+        let mut auth_req = 0;
+        if self.bonding {
+            auth_req |= AUTH_REQ_BONDING;
+        }
+        if self.mitm {
+            auth_req |= AUTH_REQ_MITM;
+        }
+        if self.secure_connections {
+            auth_req |= AUTH_REQ_SECURE_CONNECTIONS;
+        }
+        auth_req
+    }
+
+    /// The keys this device is able to distribute
+    fn distributable_keys(&self) -> u8 {
+        let mut keys = self.local_keys & (KEY_DISTRIBUTION_ENCRYPTION | KEY_DISTRIBUTION_SIGNING);
+        if self.identity_resolving_key.is_some() {
+            keys |= self.local_keys & KEY_DISTRIBUTION_IDENTITY;
+        }
+        keys
+    }
+}
+
+/// The events of the security manager the application is interested in
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SmpEvent {
+    /// the passkey need to be displayed to the user, who enters it on the peer
+    PasskeyDisplay { handle: u16, passkey: u32 },
+    /// the user need to enter the passkey displayed by the peer with [Smp::enter_passkey]
+    PasskeyRequest { handle: u16 },
+    /// the value need to be displayed to the user, who confirms it matches the one displayed by
+    /// the peer with [Smp::confirm_value]
+    NumericComparison { handle: u16, value: u32 },
+    /// the connection has been paired and is encrypted
+    PairingComplete {
+        handle: u16,
+        /// the keys are protected against man-in-the-middle attacks
+        authenticated: bool,
+        /// the keys distributed are stored
+        bonded: bool,
+    },
+    /// the pairing failed with the ``SMP_ERROR_*`` reason
+    PairingFailed { handle: u16, reason: u8 },
+}
+
+/// The data exchanged out of band to authenticate LE Secure Connections pairing
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct OobData {
+    /// 0x00 public, 0x01 random
+    pub address_type: u8,
+    pub address: [u8; BD_ADDRESS_SIZE],
+    pub random: [u8; 16],
+    /// the confirm value f4(PKx, PKx, random, 0) committing to the public key of the device
+    pub confirm: [u8; 16],
+}
+
+/// The state the security manager keeps for each connection
+struct SmpConnection {
+    /// the peer of the connection, to detect a handle being reused for a new connection
+    peer_address: [u8; BD_ADDRESS_SIZE],
+    /// a pairing is running on the connection
+    pairing: bool,
+    /// the PDU's received for the pairing not yet processed
+    pdus: VecDeque<SmpPdu>,
+    /// the reason the peer has failed the pairing with
+    failed: Option<u8>,
+    /// the passkey entered by the user
+    passkey: Option<u32>,
+    /// the user confirmed the numeric comparison value
+    confirmed: bool,
+    /// the user cancelled the passkey entry or rejected the numeric comparison value
+    rejected: bool,
+    /// the short term key the BT host requests to encrypt the connection while pairing
+    stk: Option<[u8; 16]>,
+    /// increased with each PDU send or received while pairing, to relate the timeout to it
+    activity: u32,
+    /// the pairing timed out, no further SMP PDU's are processed on this connection
+    timed_out: bool,
+    waker: Option<Waker>,
+}
+
+impl SmpConnection {
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// The security manager pairing with the peers of LE connections
+pub struct Smp<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    channel: FixedChannel<T>,
+    hci: Arc<DataLock<Hci<T>>>,
+    configuration: SmpConfiguration,
+    connections: BTreeMap<u16, SmpConnection>,
+    store: SharedBondStore,
+    agent: Option<SharedPairingAgent>,
+    events: VecDeque<SmpEvent>,
+    event_waker: Option<Waker>,
+    /// the P-256 public key of this device, read from the BT host once it is needed
+    public_key: Option<[u8; 64]>,
+    /// the out of band data of this device passed to the peers
+    local_oob: Option<OobData>,
+    /// the out of band data received from the peers
+    peer_oob: Vec<OobData>,
+}
+
+impl<T> Smp<T>
+where
+    T: HcTransportLayer,
+{
+    /// Create the security manager using the SMP fixed channel of L2CAP
+    pub fn new(
+        l2cap: Arc<DataLock<L2cap<T>>>,
+        configuration: SmpConfiguration,
+    ) -> Result<Arc<DataLock<Self>>, BoxError> {
+        let channel = L2cap::register_fixed_channel(l2cap, CID_SMP)?;
+        let hci = channel.hci();
+        Ok(Arc::new(DataLock::new(Self {
+            channel,
+            hci,
+            configuration,
+            connections: BTreeMap::new(),
+            store: bond::shared(MemoryBondStore::new()),
+            agent: None,
+            events: VecDeque::new(),
+            event_waker: None,
+            public_key: None,
+            local_oob: None,
+            peer_oob: Vec::new(),
+        })))
+    }
+
+    /// This returns the ``Thinkable`` receiving the SMP PDU's of the peers and the long term key
+    /// requests of the BT host. This should be spawned to the Brain once the security manager is
+    /// created.
+    #[must_use]
+    pub fn serve(this: Arc<DataLock<Self>>) -> ServeSmpThinkable<T> {
+        let smp = this.read();
+        let recv = smp.channel.recv();
+        let requests = Hci::next_long_term_key_request(smp.hci.clone());
+        drop(smp);
+        ServeSmpThinkable {
+            smp: this,
+            recv,
+            requests,
+        }
+    }
+
+    /// Pair with the peer of the LE connection with the given handle in the central role. This
+    /// concludes once the connection is encrypted and the keys have been distributed.
+    pub fn pair(this: Arc<DataLock<Self>>, handle: u16) -> PairingThinkable<T> {
+        PairingThinkable::initiator(this, handle)
+    }
+
+    /// Request the central of the LE connection with the given handle to pair or to encrypt the
+    /// connection with the keys of an existing bond
+    pub fn request_security(this: Arc<DataLock<Self>>, handle: u16) -> SendAclThinkable<T> {
+        let smp = this.read();
+        let auth_req = smp.configuration.auth_req();
+        smp.channel
+            .send(handle, &SmpPdu::SecurityRequest { auth_req }.to_bytes())
+    }
+
+    /// Encrypt the LE connection with the given handle in the central role with the long term key
+    /// of the bond with its peer
+    pub fn encrypt(
+        this: Arc<DataLock<Self>>,
+        handle: u16,
+    ) -> Result<EnableEncryptionThinkable<T>, BoxError> {
+        let smp = this.read();
+        let connection = match smp.channel.connection(handle) {
+            Some(connection) => connection,
+            None => {
+                return Err(Box::new(SmpError::new(
+                    SMP_ERROR_UNSPECIFIED_REASON,
+                    "connection closed",
+                )))
+            }
+        };
+        if connection.role() != HciConnectionRole::Master {
+            return Err(Box::new(SmpError::new(
+                SMP_ERROR_COMMAND_NOT_SUPPORTED,
+                "only the central encrypts a connection",
+            )));
+        }
+        match smp
+            .bond(&connection.peer_address())
+            .and_then(|bond| bond.peer_ltk)
+        {
+            Some(key) => Ok(connection.enable_encryption(key.random_number, key.ediv, key.ltk)),
+            None => Err(Box::new(SmpError::new(
+                SMP_ERROR_UNSPECIFIED_REASON,
+                "no long term key for the peer",
+            ))),
+        }
+    }
+
+    /// Set the agent interacting with the user while pairing. Passing ``None`` reports the
+    /// interaction as [SmpEvent] and authorizes any pairing requested by a peer.
+    pub fn set_pairing_agent(this: Arc<DataLock<Self>>, agent: Option<SharedPairingAgent>) {
+        this.lock().agent = agent;
+    }
+
+    /// Pass the passkey entered by the user after a [SmpEvent::PasskeyRequest] for the
+    /// connection with the given handle. Passing ``None`` cancels the pairing.
+    pub fn enter_passkey(this: Arc<DataLock<Self>>, handle: u16, passkey: Option<u32>) {
+        this.lock().passkey_entered(handle, passkey);
+    }
+
+    /// Pass whether the user confirmed the value of a [SmpEvent::NumericComparison] for the
+    /// connection with the given handle to match the one displayed by the peer
+    pub fn confirm_value(this: Arc<DataLock<Self>>, handle: u16, confirmed: bool) {
+        this.lock().decided(handle, confirmed);
+    }
+
+    /// Pass whether the user authorized the pairing requested by the peer of the connection with
+    /// the given handle after the pairing agent deferred the decision
+    pub fn authorize(this: Arc<DataLock<Self>>, handle: u16, authorized: bool) {
+        this.lock().decided(handle, authorized);
+    }
+
+    /// Create the out of band data of this device to be passed to the peers. This reads a new
+    /// P-256 key pair from the BT host, so out of band data created before becomes invalid.
+    pub fn local_oob_data(
+        this: Arc<DataLock<Self>>,
+    ) -> impl Thinkable<Output = Result<OobData, BoxError>> {
+        let hci = this.read().hci.clone();
+        Hci::read_local_p256_public_key(hci.clone())
+            .then(move |public_key| crypto::random(hci, 16).map(move |random| (public_key, random)))
+            .map(move |(public_key, random)| {
+                let public_key = public_key?;
+                let random = random?;
+                let mut x = [0; 32];
+                x.copy_from_slice(&public_key[..32]);
+                let mut smp = this.lock();
+                let mut data = OobData {
+                    address_type: smp.configuration.identity_address_type,
+                    address: smp.configuration.identity_address,
+                    random: [0; 16],
+                    confirm: [0; 16],
+                };
+                data.random.copy_from_slice(&random[..16]);
+                data.confirm = crypto::f4(&x, &x, &data.random, 0);
+                smp.public_key.replace(public_key);
+                smp.local_oob.replace(data);
+                Ok(data)
+            })
+    }
+
+    /// Pass the out of band data received from a peer, it is used when pairing with the peer
+    /// with LE Secure Connections
+    pub fn set_peer_oob_data(this: Arc<DataLock<Self>>, data: OobData) {
+        let mut smp = this.lock();
+        smp.peer_oob.retain(|known| known.address != data.address);
+        smp.peer_oob.push(data);
+    }
+
+    /// Returns a ``Thinkable`` that concludes with the next event of the security manager
+    pub fn next_event(this: Arc<DataLock<Self>>) -> NextSmpEventThinkable<T> {
+        NextSmpEventThinkable { smp: this }
+    }
+
+    /// Set the store the bonds are kept in. Without a store set the bonds are kept in memory
+    /// only.
+    pub fn set_bond_store(this: Arc<DataLock<Self>>, store: SharedBondStore) {
+        this.lock().store = store;
+    }
+
+    /// The keys of all bonds created
+    pub fn bonds(this: Arc<DataLock<Self>>) -> Vec<Bond> {
+        let store = this.read().store.clone();
+        let records = store.read().records();
+        records.into_iter().filter_map(|record| record.le).collect()
+    }
+
+    /// Forget the bond with the peer with the given address. All keys and configurations
+    /// stored for the peer are removed.
+    pub fn remove_bond(this: Arc<DataLock<Self>>, address: [u8; BD_ADDRESS_SIZE]) {
+        let store = this.read().store.clone();
+        store.lock().remove(&address);
+    }
+
+    fn bond(&self, address: &[u8; BD_ADDRESS_SIZE]) -> Option<Bond> {
+        self.store.read().resolve(address).and_then(|record| record.le)
+    }
+
+    /// The IO capability declared by the pairing agent or the configured one
+    fn io_capability(&self) -> IoCapability {
+        match self.agent {
+            Some(ref agent) => agent.read().io_capability(),
+            None => self.configuration.io_capability,
+        }
+    }
+
+    fn peer(&self, handle: u16) -> PairingPeer {
+        PairingPeer {
+            transport: ConnectionTransport::Le,
+            handle: Some(handle),
+            address: self
+                .connections
+                .get(&handle)
+                .map_or([0; BD_ADDRESS_SIZE], |connection| connection.peer_address),
+        }
+    }
+
+    /// Let the user display the passkey of the pairing on the connection with the given handle
+    fn display_passkey(&mut self, handle: u16, passkey: u32) {
+        let peer = self.peer(handle);
+        match self.agent {
+            Some(ref agent) => agent.lock().display_passkey(peer, passkey),
+            None => self.post(SmpEvent::PasskeyDisplay { handle, passkey }),
+        }
+    }
+
+    /// Let the user enter the passkey of the pairing on the connection with the given handle
+    fn request_passkey(&mut self, handle: u16) {
+        let peer = self.peer(handle);
+        let reply = match self.agent {
+            Some(ref agent) => agent.lock().request_passkey(peer),
+            None => {
+                self.post(SmpEvent::PasskeyRequest { handle });
+                AgentReply::Later
+            }
+        };
+        if let AgentReply::Now(passkey) = reply {
+            self.passkey_entered(handle, passkey);
+        }
+    }
+
+    /// Let the user confirm the numeric comparison value of the pairing on the connection with
+    /// the given handle
+    fn request_confirmation(&mut self, handle: u16, value: u32) {
+        let peer = self.peer(handle);
+        let reply = match self.agent {
+            Some(ref agent) => agent.lock().confirm_value(peer, value),
+            None => {
+                self.post(SmpEvent::NumericComparison { handle, value });
+                AgentReply::Later
+            }
+        };
+        if let AgentReply::Now(confirmed) = reply {
+            self.decided(handle, confirmed);
+        }
+    }
+
+    /// Let the user authorize the pairing requested by the peer of the connection with the
+    /// given handle
+    fn request_authorization(&mut self, handle: u16) {
+        let peer = self.peer(handle);
+        let reply = match self.agent {
+            Some(ref agent) => agent.lock().authorize(peer),
+            None => AgentReply::Now(true),
+        };
+        if let AgentReply::Now(authorized) = reply {
+            self.decided(handle, authorized);
+        }
+    }
+
+    fn passkey_entered(&mut self, handle: u16, passkey: Option<u32>) {
+        if let Some(connection) = self.connections.get_mut(&handle) {
+            if connection.pairing {
+                match passkey {
+                    Some(passkey) => {
+                        connection.passkey.replace(passkey % 1_000_000);
+                    }
+                    None => connection.rejected = true,
+                }
+                connection.wake();
+            }
+        }
+    }
+
+    fn decided(&mut self, handle: u16, confirmed: bool) {
+        if let Some(connection) = self.connections.get_mut(&handle) {
+            if connection.pairing {
+                if confirmed {
+                    connection.confirmed = true;
+                } else {
+                    connection.rejected = true;
+                }
+                connection.wake();
+            }
+        }
+    }
+
+    fn peer_oob_data(&self, address: &[u8; BD_ADDRESS_SIZE]) -> Option<OobData> {
+        self.peer_oob
+            .iter()
+            .find(|data| data.address == *address)
+            .copied()
+    }
+
+    /// Store the bond in the record of the peer. Peers that distributed their identity are
+    /// stored with their identity address.
+    fn store_bond(&mut self, bond: Bond) {
+        let mut store = self.store.lock();
+        let identity = bond.peer_identity;
+        let mut record = store
+            .resolve(&bond.peer_address)
+            .or_else(|| identity.and_then(|identity| store.load(&identity.address)))
+            .unwrap_or_else(|| BondRecord::new(bond.peer_address_type, bond.peer_address));
+        store.remove(&record.address);
+        if let Some(identity) = identity {
+            record.address_type = identity.address_type;
+            record.address = identity.address;
+        }
+        record.le = Some(bond);
+        store.store(record);
+    }
+
+    fn post(&mut self, event: SmpEvent) {
+        if self.events.len() >= MAX_PENDING_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+        if let Some(waker) = self.event_waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Ensure the state of the connection with the given handle and peer is known
+    fn refresh_connection(
+        &mut self,
+        handle: u16,
+        peer_address: [u8; BD_ADDRESS_SIZE],
+    ) -> &mut SmpConnection {
+        let known = self
+            .connections
+            .get(&handle)
+            .map_or(false, |connection| connection.peer_address == peer_address);
+        if !known {
+            self.connections.insert(
+                handle,
+                SmpConnection {
+                    peer_address,
+                    pairing: false,
+                    pdus: VecDeque::new(),
+                    failed: None,
+                    passkey: None,
+                    confirmed: false,
+                    rejected: false,
+                    stk: None,
+                    activity: 0,
+                    timed_out: false,
+                    waker: None,
+                },
+            );
+        }
+        self.connections.get_mut(&handle).unwrap()
+    }
+
+    /// Send a PDU of the pairing running on the connection with the given handle. The pairing
+    /// fails if the next PDU is not send or received in time.
+    fn send(this: &Arc<DataLock<Self>>, smp: &mut Self, handle: u16, pdu: &SmpPdu) {
+        smp.channel.queue(handle, &pdu.to_bytes());
+        Smp::restart_timer(this, smp, handle);
+    }
+
+    fn restart_timer(this: &Arc<DataLock<Self>>, smp: &mut Self, handle: u16) {
+        let activity = match smp.connections.get_mut(&handle) {
+            Some(connection) => {
+                connection.activity = connection.activity.wrapping_add(1);
+                connection.activity
+            }
+            None => return,
+        };
+        let smp = this.clone();
+        spawn(wait(Mseconds(SMP_TIMEOUT), ()).map(move |_| smp.lock().timeout(handle, activity)));
+    }
+
+    /// Nothing has been send or received in time. SMP can not be used on the connection any
+    /// longer.
+    fn timeout(&mut self, handle: u16, activity: u32) {
+        if let Some(connection) = self.connections.get_mut(&handle) {
+            if connection.pairing && connection.activity == activity {
+                warn!("pairing on connection {} timed out", handle);
+                connection.timed_out = true;
+                connection.wake();
+            }
+        }
+    }
+
+    /// Process a PDU received from the peer of the connection with the given handle
+    fn received(this: &Arc<DataLock<Self>>, handle: u16, data: &[u8]) {
+        let mut smp = this.lock();
+        let pdu = match SmpPdu::parse(data) {
+            Ok(pdu) => pdu,
+            Err(reason) => {
+                warn!("invalid SMP PDU received on connection {}", handle);
+                smp.channel
+                    .queue(handle, &SmpPdu::PairingFailed { reason }.to_bytes());
+                return;
+            }
+        };
+        let connection = match smp.channel.connection(handle) {
+            Some(connection) => connection,
+            None => return,
+        };
+        let info = connection.info();
+        if info.transport != ConnectionTransport::Le {
+            return;
+        }
+        let state = smp.refresh_connection(handle, info.peer_address);
+        if state.timed_out {
+            return;
+        }
+        if state.pairing {
+            match pdu {
+                SmpPdu::PairingFailed { reason } => {
+                    state.failed.replace(reason);
+                }
+                // the keypresses of the peer are not reported to the user
+                SmpPdu::KeypressNotification { .. } => return,
+                pdu => state.pdus.push_back(pdu),
+            }
+            state.wake();
+            Smp::restart_timer(this, &mut smp, handle);
+            return;
+        }
+        match pdu {
+            SmpPdu::PairingRequest(features) if info.role == HciConnectionRole::Slave => {
+                info!("pairing requested on connection {}", handle);
+                state.pairing = true;
+                drop(smp);
+                spawn(PairingThinkable::responder(this.clone(), handle, features).map(|_| ()));
+            }
+            SmpPdu::SecurityRequest { auth_req } if info.role == HciConnectionRole::Master => {
+                // a bond satisfying the requirements of the peer is used to encrypt the
+                // connection, otherwise the connection is paired
+                let key = smp
+                    .bond(&info.peer_address)
+                    .and_then(|bond| bond.peer_ltk)
+                    .filter(|key| key.authenticated || auth_req & AUTH_REQ_MITM == 0);
+                drop(smp);
+                match key {
+                    Some(key) => spawn(
+                        connection
+                            .enable_encryption(key.random_number, key.ediv, key.ltk)
+                            .map(move |result| {
+                                if result.is_err() {
+                                    warn!("encrypting connection {} failed", handle);
+                                }
+                            }),
+                    ),
+                    None => spawn(PairingThinkable::initiator(this.clone(), handle).map(|_| ())),
+                }
+            }
+            SmpPdu::PairingRequest(_) => smp.channel.queue(
+                handle,
+                &SmpPdu::PairingFailed {
+                    reason: SMP_ERROR_COMMAND_NOT_SUPPORTED,
+                }
+                .to_bytes(),
+            ),
+            pdu => warn!(
+                "unexpected SMP PDU {:?} on connection {}",
+                pdu.code(),
+                handle
+            ),
+        }
+    }
+
+    /// The BT host requests the long term key of a connection in the peripheral role. While
+    /// pairing this is the short term key, otherwise the key distributed to the peer.
+    fn key_requested(this: &Arc<DataLock<Self>>, request: HciEventLeLongTermKeyRequest) {
+        let handle = request.handle & CONNECTION_HANDLE_MASK;
+        let ediv = request.ediv;
+        let random_number = request.random_number;
+        let smp = this.read();
+        let stk = smp
+            .connections
+            .get(&handle)
+            .filter(|connection| connection.pairing)
+            .and_then(|connection| connection.stk)
+            .filter(|_| ediv == 0 && random_number == [0; 8]);
+        let ltk = stk.or_else(|| {
+            let peer_address = smp.channel.connection(handle)?.peer_address();
+            smp.bond(&peer_address)
+                .and_then(|bond| bond.local_ltk)
+                .filter(|key| key.ediv == ediv && key.random_number == random_number)
+                .map(|key| key.ltk)
+        });
+        if ltk.is_none() {
+            info!("no long term key for connection {}", handle);
+        }
+        let hci = smp.hci.clone();
+        drop(smp);
+        spawn(
+            Hci::reply_long_term_key_request(hci, handle, ltk).map(move |result| {
+                if result.is_err() {
+                    warn!("replying long term key request of {} failed", handle);
+                }
+            }),
+        );
+    }
+}
+
+/// This ``Thinkable`` receives the SMP PDU's of the peers and the long term key requests of the
+/// BT host
+pub struct ServeSmpThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    smp: Arc<DataLock<Smp<T>>>,
+    recv: RecvFixedChannelThinkable<T>,
+    requests: NextLongTermKeyRequestThinkable<T>,
+}
+
+impl<T> Thinkable for ServeSmpThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = ();
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        let this = self.get_mut();
+        loop {
+            let mut pending = true;
+            if let Conclusion::Ready((handle, pdu)) = Pin::new(&mut this.recv).think(cx) {
+                Smp::received(&this.smp, handle, &pdu);
+                pending = false;
+            }
+            if let Conclusion::Ready(request) = Pin::new(&mut this.requests).think(cx) {
+                Smp::key_requested(&this.smp, request);
+                pending = false;
+            }
+            if pending {
+                return Conclusion::Pending;
+            }
+        }
+    }
+}
+
+/// This ``Thinkable`` concludes with the next event of the security manager
+pub struct NextSmpEventThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    smp: Arc<DataLock<Smp<T>>>,
+}
+
+impl<T> Thinkable for NextSmpEventThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = SmpEvent;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        let mut smp = self.smp.lock();
+        match smp.events.pop_front() {
+            Some(event) => Conclusion::Ready(event),
+            None => {
+                smp.event_waker.replace(cx.waker().clone());
+                Conclusion::Pending
+            }
+        }
+    }
+}
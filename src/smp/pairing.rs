@@ -0,0 +1,1303 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # Pairing
+//!
+//! The pairing procedure of one connection. Both devices exchange their pairing features and
+//! choose the association model from their IO capabilities.
+//!
+//! With legacy pairing they then prove the knowledge of the temporary key with the confirm and
+//! random values and encrypt the connection with the short term key derived from it.
+//!
+//! With LE Secure Connections they exchange their P-256 public keys and calculate the shared
+//! Diffie-Hellman key. The association model authenticates the public keys, the passkey is
+//! confirmed bit by bit in 20 rounds. Both devices then prove the knowledge of the
+//! Diffie-Hellman key with the DHKey check values and encrypt the connection with the long term
+//! key derived from it.
+//!
+//! Finally the keys for later connections are distributed, the responder distributes its keys
+//! first.
+//!
+
+use super::crypto::{self, c1_p1, c1_p2, f4, f5, f6, g2};
+use super::*;
+use crate::hci::commands::PeerAddressType;
+
+/// Boxed ``Thinkable`` of one calculation step of the pairing
+type StepThinkable = Pin<Box<dyn Thinkable<Output = Result<Vec<u8>, BoxError>> + Send>>;
+
+/// The number of rounds the passkey is confirmed in with LE Secure Connections, one per bit
+const PASSKEY_ROUNDS: u8 = 20;
+
+/// The association model used to authenticate the pairing
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PairingMethod {
+    /// no authentication, the temporary key is 0
+    JustWorks,
+    /// the initiator displays the passkey the user enters on the responder
+    PasskeyInitiatorDisplays,
+    /// the responder displays the passkey the user enters on the initiator
+    PasskeyResponderDisplays,
+    /// the user enters the same passkey on both devices
+    PasskeyBothInput,
+    /// both devices display a value the user confirms to be the same, LE Secure Connections only
+    NumericComparison,
+    /// the data exchanged out of band authenticates the public keys, LE Secure Connections only
+    OutOfBand,
+}
+
+impl PairingMethod {
+    /// The association model for the features of both devices
+    pub fn choose(request: &PairingFeatures, response: &PairingFeatures) -> Self {
+        use IoCapability::*;
+        let secure_connections = request.auth_req & response.auth_req & AUTH_REQ_SECURE_CONNECTIONS
+            == AUTH_REQ_SECURE_CONNECTIONS;
+        if secure_connections && (request.oob_data || response.oob_data) {
+            return PairingMethod::OutOfBand;
+        }
+        if (request.auth_req | response.auth_req) & AUTH_REQ_MITM == 0 {
+            return PairingMethod::JustWorks;
+        }
+        match (request.io_capability, response.io_capability) {
+            (NoInputNoOutput, _) | (_, NoInputNoOutput) => PairingMethod::JustWorks,
+            (DisplayYesNo, DisplayYesNo)
+            | (DisplayYesNo, KeyboardDisplay)
+            | (KeyboardDisplay, DisplayYesNo)
+            | (KeyboardDisplay, KeyboardDisplay)
+                if secure_connections =>
+            {
+                PairingMethod::NumericComparison
+            }
+            (KeyboardOnly, KeyboardOnly) => PairingMethod::PasskeyBothInput,
+            (KeyboardOnly, _)
+            | (KeyboardDisplay, DisplayOnly)
+            | (KeyboardDisplay, DisplayYesNo) => PairingMethod::PasskeyResponderDisplays,
+            (_, KeyboardOnly) | (_, KeyboardDisplay) => PairingMethod::PasskeyInitiatorDisplays,
+            _ => PairingMethod::JustWorks,
+        }
+    }
+
+    /// The pairing is protected against man-in-the-middle attacks
+    pub fn is_authenticated(&self) -> bool {
+        *self != PairingMethod::JustWorks
+    }
+
+    fn is_passkey(&self) -> bool {
+        match self {
+            PairingMethod::PasskeyInitiatorDisplays
+            | PairingMethod::PasskeyResponderDisplays
+            | PairingMethod::PasskeyBothInput => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum PairingState {
+    Start,
+    /// the initiator waits for the Pairing Response
+    AwaitResponse,
+    /// the local P-256 public key is read
+    PublicKey,
+    AwaitPublicKey,
+    /// the Diffie-Hellman key is calculated
+    DhKey,
+    /// the passkey to display is generated
+    Passkey,
+    /// the user enters the passkey
+    PasskeyEntry,
+    LocalRandom,
+    LocalConfirm,
+    AwaitConfirm,
+    AwaitRandom,
+    /// the confirm value of the peer is verified
+    CheckConfirm,
+    /// the user authorizes the pairing requested by the peer
+    Authorize,
+    /// the user confirms the numeric comparison value
+    UserConfirm,
+    AwaitDhKeyCheck,
+    ShortTermKey,
+    Encrypting,
+    /// the keys to distribute are generated
+    LocalKeys,
+    ReceiveKeys,
+    Done,
+}
+
+/// This ``Thinkable`` runs the pairing of one LE connection and concludes once the connection is
+/// encrypted and the keys have been distributed
+pub struct PairingThinkable<T>
+where
+    T: HcTransportLayer + 'static,
+{
+    smp: Arc<DataLock<Smp<T>>>,
+    hci: Arc<DataLock<Hci<T>>>,
+    handle: u16,
+    initiator: bool,
+    /// the Pairing Request received if this device is the responder
+    request: Option<PairingFeatures>,
+    response: Option<PairingFeatures>,
+    state: PairingState,
+    step: Option<StepThinkable>,
+    encryption: Option<EnableEncryptionThinkable<T>>,
+    /// the address type and address of the initiator and the responder
+    initiator_address: (u8, [u8; BD_ADDRESS_SIZE]),
+    responder_address: (u8, [u8; BD_ADDRESS_SIZE]),
+    secure_connections: bool,
+    method: PairingMethod,
+    key_size: u8,
+    bonding: bool,
+    /// the keys this device and the peer distribute
+    local_keys: u8,
+    peer_keys: u8,
+    /// the key distribution PDU's still expected from the peer in order
+    expected: VecDeque<SmpCode>,
+    /// the key distribution PDU's to send to the peer
+    distribution: Vec<SmpPdu>,
+    /// the temporary key of legacy pairing, the passkey is kept here as well
+    tk: [u8; 16],
+    local_random: [u8; 16],
+    local_confirm: [u8; 16],
+    peer_confirm: [u8; 16],
+    peer_random: [u8; 16],
+    local_public_key: [u8; 64],
+    peer_public_key: [u8; 64],
+    dhkey: [u8; 32],
+    /// the bit of the passkey confirmed in the current round
+    round: u8,
+    /// the random values of the initiator and responder exchanged out of band
+    oob_random: ([u8; 16], [u8; 16]),
+    /// the long term key of LE Secure Connections
+    ltk: [u8; 16],
+    /// the DHKey check value of this device and the one expected from the peer
+    local_check: [u8; 16],
+    peer_check: [u8; 16],
+    /// the key received that waits for the PDU identifying it
+    peer_key: [u8; 16],
+    bond: Bond,
+    /// the ``SMP_ERROR_*`` reason the pairing failed with
+    reason: u8,
+    /// the peer is told about the failure with a Pairing Failed PDU
+    notify: bool,
+    /// this Thinkable owns the pairing state of the connection
+    running: bool,
+}
+
+impl<T> PairingThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    pub(crate) fn initiator(smp: Arc<DataLock<Smp<T>>>, handle: u16) -> Self {
+        PairingThinkable::new(smp, handle, None)
+    }
+
+    pub(crate) fn responder(
+        smp: Arc<DataLock<Smp<T>>>,
+        handle: u16,
+        request: PairingFeatures,
+    ) -> Self {
+        PairingThinkable::new(smp, handle, Some(request))
+    }
+
+    fn new(smp: Arc<DataLock<Smp<T>>>, handle: u16, request: Option<PairingFeatures>) -> Self {
+        let hci = smp.read().hci.clone();
+        Self {
+            smp,
+            hci,
+            handle,
+            initiator: request.is_none(),
+            request,
+            response: None,
+            state: PairingState::Start,
+            step: None,
+            encryption: None,
+            initiator_address: (0, [0; BD_ADDRESS_SIZE]),
+            responder_address: (0, [0; BD_ADDRESS_SIZE]),
+            secure_connections: false,
+            method: PairingMethod::JustWorks,
+            key_size: SMP_MAX_KEY_SIZE,
+            bonding: false,
+            local_keys: 0,
+            peer_keys: 0,
+            expected: VecDeque::new(),
+            distribution: Vec::new(),
+            tk: [0; 16],
+            local_random: [0; 16],
+            local_confirm: [0; 16],
+            peer_confirm: [0; 16],
+            peer_random: [0; 16],
+            local_public_key: [0; 64],
+            peer_public_key: [0; 64],
+            dhkey: [0; 32],
+            round: 0,
+            oob_random: ([0; 16], [0; 16]),
+            ltk: [0; 16],
+            local_check: [0; 16],
+            peer_check: [0; 16],
+            peer_key: [0; 16],
+            bond: Bond::new(0, [0; BD_ADDRESS_SIZE]),
+            reason: SMP_ERROR_UNSPECIFIED_REASON,
+            notify: true,
+            running: request.is_some(),
+        }
+    }
+
+    /// Create the error for a failure detected by this device
+    fn fail(&mut self, code: u8, reason: &'static str) -> BoxError {
+        self.reason = code;
+        self.notify = true;
+        Box::new(SmpError::new(code, reason))
+    }
+
+    /// Create the error for a failure the peer already knows about
+    fn abort(&mut self, code: u8, reason: &'static str) -> BoxError {
+        self.reason = code;
+        self.notify = false;
+        Box::new(SmpError::new(code, reason))
+    }
+
+    fn send(&self, pdu: SmpPdu) {
+        let mut smp = self.smp.lock();
+        Smp::send(&self.smp, &mut smp, self.handle, &pdu);
+    }
+
+    /// The random values of the initiator and the responder
+    fn randoms(&self) -> ([u8; 16], [u8; 16]) {
+        if self.initiator {
+            (self.local_random, self.peer_random)
+        } else {
+            (self.peer_random, self.local_random)
+        }
+    }
+
+    /// The X coordinates of the public keys of this device and the peer
+    fn public_keys(&self) -> ([u8; 32], [u8; 32]) {
+        let mut local = [0; 32];
+        local.copy_from_slice(&self.local_public_key[..32]);
+        let mut peer = [0; 32];
+        peer.copy_from_slice(&self.peer_public_key[..32]);
+        (local, peer)
+    }
+
+    /// The pairing features of this device and the peer
+    fn features(&self) -> (PairingFeatures, PairingFeatures) {
+        let request = self.request.unwrap();
+        let response = self.response.unwrap();
+        if self.initiator {
+            (request, response)
+        } else {
+            (response, request)
+        }
+    }
+
+    fn start(&mut self) -> Result<(), BoxError> {
+        let mut smp = self.smp.lock();
+        let connection = match smp.channel.connection(self.handle) {
+            Some(connection) => connection,
+            None => {
+                drop(smp);
+                return Err(self.abort(SMP_ERROR_UNSPECIFIED_REASON, "connection closed"));
+            }
+        };
+        let info = connection.info();
+        let role = if self.initiator {
+            HciConnectionRole::Master
+        } else {
+            HciConnectionRole::Slave
+        };
+        if info.transport != ConnectionTransport::Le || info.role != role {
+            drop(smp);
+            return Err(self.fail(
+                SMP_ERROR_PAIRING_NOT_SUPPORTED,
+                "pairing requires an LE connection in the proper role",
+            ));
+        }
+        let state = smp.refresh_connection(self.handle, info.peer_address);
+        if self.initiator {
+            if state.pairing || state.timed_out {
+                drop(smp);
+                return Err(self.abort(
+                    SMP_ERROR_UNSPECIFIED_REASON,
+                    "pairing already running or timed out",
+                ));
+            }
+            state.pairing = true;
+            self.running = true;
+        }
+
+        let peer_address_type = match info.peer_address_type {
+            PeerAddressType::Public | PeerAddressType::PublicIdentity => 0x00,
+            _ => 0x01,
+        };
+        let configuration = smp.configuration;
+        let local_address = (
+            configuration.identity_address_type,
+            configuration.identity_address,
+        );
+        let peer_address = (peer_address_type, info.peer_address);
+        self.bond = Bond::new(peer_address_type, info.peer_address);
+        let local_features = PairingFeatures {
+            io_capability: smp.io_capability(),
+            // the peer is told whether its out of band data is available
+            oob_data: configuration.secure_connections
+                && smp.peer_oob_data(&info.peer_address).is_some(),
+            auth_req: configuration.auth_req(),
+            max_key_size: configuration.max_key_size,
+            initiator_keys: configuration.peer_keys,
+            responder_keys: configuration.distributable_keys(),
+        };
+
+        if self.initiator {
+            self.initiator_address = local_address;
+            self.responder_address = peer_address;
+            let request = PairingFeatures {
+                initiator_keys: configuration.distributable_keys(),
+                responder_keys: configuration.peer_keys,
+                ..local_features
+            };
+            self.request = Some(request);
+            Smp::send(
+                &self.smp,
+                &mut smp,
+                self.handle,
+                &SmpPdu::PairingRequest(request),
+            );
+            self.state = PairingState::AwaitResponse;
+            return Ok(());
+        }
+
+        self.initiator_address = peer_address;
+        self.responder_address = local_address;
+        let request = self.request.unwrap();
+        // keys are only distributed if both devices bond
+        let bonding =
+            configuration.bonding && request.auth_req & AUTH_REQ_BONDING == AUTH_REQ_BONDING;
+        let response = if bonding {
+            PairingFeatures {
+                initiator_keys: request.initiator_keys & local_features.initiator_keys,
+                responder_keys: request.responder_keys & local_features.responder_keys,
+                ..local_features
+            }
+        } else {
+            PairingFeatures {
+                initiator_keys: 0,
+                responder_keys: 0,
+                ..local_features
+            }
+        };
+        drop(smp);
+        self.negotiate(response)?;
+        // without authentication the user need to authorize the pairing before it is accepted
+        if self.method == PairingMethod::JustWorks {
+            self.smp.lock().request_authorization(self.handle);
+            self.state = PairingState::Authorize;
+        } else {
+            self.send(SmpPdu::PairingResponse(response));
+        }
+        Ok(())
+    }
+
+    /// Agree on the key size, the keys distributed and the association model
+    fn negotiate(&mut self, response: PairingFeatures) -> Result<(), BoxError> {
+        let request = self.request.unwrap();
+        self.response = Some(response);
+        let configuration = self.smp.read().configuration;
+        self.key_size = request.max_key_size.min(response.max_key_size);
+        if self.key_size < SMP_MIN_KEY_SIZE || self.key_size > SMP_MAX_KEY_SIZE {
+            return Err(self.fail(SMP_ERROR_ENCRYPTION_KEY_SIZE, "unsupported key size"));
+        }
+        self.secure_connections =
+            request.auth_req & response.auth_req & AUTH_REQ_SECURE_CONNECTIONS
+                == AUTH_REQ_SECURE_CONNECTIONS;
+        if configuration.secure_connections_only && !self.secure_connections {
+            return Err(self.fail(
+                SMP_ERROR_AUTHENTICATION_REQUIREMENTS,
+                "peer does not support LE Secure Connections",
+            ));
+        }
+        self.bonding = request.auth_req & response.auth_req & AUTH_REQ_BONDING == AUTH_REQ_BONDING;
+        let (local_keys, peer_keys) = if self.initiator {
+            (response.initiator_keys, response.responder_keys)
+        } else {
+            (response.responder_keys, response.initiator_keys)
+        };
+        // the long term key of LE Secure Connections is derived and not distributed, the link
+        // key is not derived
+        let mut ignored = KEY_DISTRIBUTION_LINK;
+        if self.secure_connections {
+            ignored |= KEY_DISTRIBUTION_ENCRYPTION;
+        }
+        self.local_keys = local_keys & !ignored;
+        self.peer_keys = peer_keys & !ignored;
+        self.expected.clear();
+        if self.peer_keys & KEY_DISTRIBUTION_ENCRYPTION != 0 {
+            self.expected.push_back(SmpCode::EncryptionInformation);
+            self.expected.push_back(SmpCode::CentralIdentification);
+        }
+        if self.peer_keys & KEY_DISTRIBUTION_IDENTITY != 0 {
+            self.expected.push_back(SmpCode::IdentityInformation);
+            self.expected.push_back(SmpCode::IdentityAddressInformation);
+        }
+        if self.peer_keys & KEY_DISTRIBUTION_SIGNING != 0 {
+            self.expected.push_back(SmpCode::SigningInformation);
+        }
+        self.method = PairingMethod::choose(&request, &response);
+        if configuration.mitm && !self.method.is_authenticated() {
+            return Err(self.fail(
+                SMP_ERROR_AUTHENTICATION_REQUIREMENTS,
+                "protection against man-in-the-middle attacks not possible",
+            ));
+        }
+        self.state = if self.secure_connections {
+            PairingState::PublicKey
+        } else {
+            PairingState::Passkey
+        };
+        Ok(())
+    }
+
+    /// Check whether the pairing has been ended by the peer or by a timeout. If not the waker is
+    /// registered to be woken once this happens or a PDU is received.
+    fn interrupted(&mut self, cx: &mut Context<'_>) -> Result<(), BoxError> {
+        let mut smp = self.smp.lock();
+        let cause = match smp.connections.get_mut(&self.handle) {
+            None => Some((SMP_ERROR_UNSPECIFIED_REASON, "connection closed")),
+            Some(connection) if connection.timed_out => {
+                Some((SMP_ERROR_UNSPECIFIED_REASON, "pairing timed out"))
+            }
+            Some(connection) => match connection.failed {
+                Some(reason) => Some((reason, "pairing failed by peer")),
+                None => {
+                    connection.waker.replace(cx.waker().clone());
+                    None
+                }
+            },
+        };
+        drop(smp);
+        match cause {
+            Some((code, reason)) => Err(self.abort(code, reason)),
+            None => Ok(()),
+        }
+    }
+
+    /// Take the next PDU of the pairing received from the peer
+    fn next_pdu(&mut self, cx: &mut Context<'_>) -> Conclusion<Result<SmpPdu, BoxError>> {
+        if let Err(e) = self.interrupted(cx) {
+            return Conclusion::Ready(Err(e));
+        }
+        let pdu = self
+            .smp
+            .lock()
+            .connections
+            .get_mut(&self.handle)
+            .and_then(|connection| connection.pdus.pop_front());
+        match pdu {
+            Some(pdu) => Conclusion::Ready(Ok(pdu)),
+            None => Conclusion::Pending,
+        }
+    }
+
+    /// Process the next PDU received from the peer
+    fn received(&mut self, pdu: SmpPdu) -> Result<(), BoxError> {
+        match (self.state, pdu) {
+            (PairingState::AwaitResponse, SmpPdu::PairingResponse(response)) => {
+                let request = self.request.unwrap();
+                if response.initiator_keys & !request.initiator_keys != 0
+                    || response.responder_keys & !request.responder_keys != 0
+                {
+                    return Err(self.fail(
+                        SMP_ERROR_INVALID_PARAMETERS,
+                        "keys not requested to be distributed",
+                    ));
+                }
+                self.negotiate(response)?;
+            }
+            (PairingState::AwaitPublicKey, SmpPdu::PairingPublicKey { x, y }) => {
+                self.peer_public_key[..32].copy_from_slice(&x);
+                self.peer_public_key[32..].copy_from_slice(&y);
+                if self.peer_public_key[..] == self.local_public_key[..] {
+                    return Err(self.fail(
+                        SMP_ERROR_INVALID_PARAMETERS,
+                        "public key of the peer is the local one",
+                    ));
+                }
+                if !self.initiator {
+                    self.send_public_key();
+                }
+                self.step = Some(Box::pin(
+                    Hci::generate_dhkey(self.hci.clone(), self.peer_public_key)
+                        .map(|result| result.map(|dhkey| dhkey.to_vec())),
+                ));
+                self.state = PairingState::DhKey;
+            }
+            (PairingState::AwaitConfirm, SmpPdu::PairingConfirm { value }) => {
+                self.peer_confirm = value;
+                if self.initiator {
+                    self.send(SmpPdu::PairingRandom {
+                        value: self.local_random,
+                    });
+                } else {
+                    self.send(SmpPdu::PairingConfirm {
+                        value: self.local_confirm,
+                    });
+                }
+                self.state = PairingState::AwaitRandom;
+            }
+            (PairingState::AwaitRandom, SmpPdu::PairingRandom { value }) => {
+                self.peer_random = value;
+                if self.secure_connections {
+                    self.authenticate()?;
+                } else {
+                    self.step = Some(self.c1(value));
+                    self.state = PairingState::CheckConfirm;
+                }
+            }
+            (PairingState::AwaitDhKeyCheck, SmpPdu::PairingDhKeyCheck { value }) => {
+                if value != self.peer_check {
+                    return Err(self.fail(
+                        SMP_ERROR_DHKEY_CHECK_FAILED,
+                        "DHKey check value does not match",
+                    ));
+                }
+                self.encrypt(self.ltk)?;
+                if !self.initiator {
+                    self.send(SmpPdu::PairingDhKeyCheck {
+                        value: self.local_check,
+                    });
+                }
+            }
+            (PairingState::ReceiveKeys, pdu) => {
+                if self.expected.pop_front() != Some(pdu.code()) {
+                    return Err(
+                        self.fail(SMP_ERROR_UNSPECIFIED_REASON, "unexpected key distribution")
+                    );
+                }
+                let key = self.peer_key;
+                match pdu {
+                    SmpPdu::EncryptionInformation { ltk } => self.peer_key = ltk,
+                    SmpPdu::CentralIdentification {
+                        ediv,
+                        random_number,
+                    } => {
+                        self.bond.peer_ltk = Some(LongTermKey {
+                            ltk: key,
+                            ediv,
+                            random_number,
+                            key_size: self.key_size,
+                            authenticated: self.method.is_authenticated(),
+                            secure_connections: false,
+                        })
+                    }
+                    SmpPdu::IdentityInformation { irk } => self.peer_key = irk,
+                    SmpPdu::IdentityAddressInformation {
+                        address_type,
+                        address,
+                    } => {
+                        self.bond.peer_identity = Some(IdentityKey {
+                            irk: key,
+                            address_type,
+                            address,
+                        })
+                    }
+                    SmpPdu::SigningInformation { csrk } => self.bond.peer_csrk = Some(csrk),
+                    _ => (),
+                }
+                if self.expected.is_empty() {
+                    if self.initiator {
+                        self.distribute();
+                    }
+                    self.state = PairingState::Done;
+                }
+            }
+            _ => {
+                return Err(self.fail(SMP_ERROR_UNSPECIFIED_REASON, "unexpected SMP PDU"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Process the result of the calculation step that has finished
+    fn stepped(&mut self, bytes: Vec<u8>) -> Result<(), BoxError> {
+        let mut value = [0; 16];
+        if bytes.len() == 16 {
+            value.copy_from_slice(&bytes);
+        }
+        match self.state {
+            PairingState::PublicKey => {
+                let mut public_key = [0; 64];
+                public_key.copy_from_slice(&bytes[..64]);
+                self.smp.lock().public_key.replace(public_key);
+                self.public_key_ready(public_key);
+            }
+            PairingState::DhKey => {
+                self.dhkey.copy_from_slice(&bytes[..32]);
+                if self.method == PairingMethod::OutOfBand {
+                    self.out_of_band()?;
+                }
+                self.state = PairingState::Passkey;
+            }
+            PairingState::Passkey => {
+                let mut random = [0; 4];
+                random.copy_from_slice(&bytes[..4]);
+                let passkey = u32::from_le_bytes(random) % 1_000_000;
+                self.tk[..4].copy_from_slice(&passkey.to_le_bytes());
+                self.smp.lock().display_passkey(self.handle, passkey);
+                self.random();
+            }
+            PairingState::LocalRandom => {
+                self.local_random = value;
+                if self.secure_connections {
+                    self.commit();
+                } else {
+                    self.step = Some(self.c1(value));
+                    self.state = PairingState::LocalConfirm;
+                }
+            }
+            PairingState::LocalConfirm => {
+                self.local_confirm = value;
+                if self.initiator {
+                    self.send(SmpPdu::PairingConfirm { value });
+                }
+                self.state = PairingState::AwaitConfirm;
+            }
+            PairingState::CheckConfirm => {
+                if value != self.peer_confirm {
+                    return Err(self.fail(
+                        SMP_ERROR_CONFIRM_VALUE_FAILED,
+                        "confirm value does not match",
+                    ));
+                }
+                let (initiator_random, responder_random) = self.randoms();
+                self.step = Some(Box::pin(
+                    crypto::s1(
+                        self.hci.clone(),
+                        self.tk,
+                        responder_random,
+                        initiator_random,
+                    )
+                    .map(|result| result.map(|value| value.to_vec())),
+                ));
+                self.state = PairingState::ShortTermKey;
+            }
+            PairingState::ShortTermKey => {
+                self.encrypt(mask_key(value, self.key_size))?;
+                // the responder reveals its random value once it is prepared for the encryption
+                if !self.initiator {
+                    self.send(SmpPdu::PairingRandom {
+                        value: self.local_random,
+                    });
+                }
+            }
+            PairingState::LocalKeys => self.keys(&bytes),
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Calculate the confirm value of legacy pairing for the given random value
+    fn c1(&self, random: [u8; 16]) -> StepThinkable {
+        let (request, response) = (self.request.unwrap(), self.response.unwrap());
+        let mut preq = [0; 7];
+        preq.copy_from_slice(&SmpPdu::PairingRequest(request).to_bytes());
+        let mut pres = [0; 7];
+        pres.copy_from_slice(&SmpPdu::PairingResponse(response).to_bytes());
+        let p1 = c1_p1(
+            &preq,
+            &pres,
+            self.initiator_address.0,
+            self.responder_address.0,
+        );
+        let p2 = c1_p2(&self.initiator_address.1, &self.responder_address.1);
+        Box::pin(
+            crypto::c1(self.hci.clone(), self.tk, random, p1, p2)
+                .map(|result| result.map(|value| value.to_vec())),
+        )
+    }
+
+    /// Use the known public key of this device or read it from the BT host
+    fn public_key(&mut self) {
+        let public_key = self.smp.read().public_key;
+        match public_key {
+            Some(public_key) => self.public_key_ready(public_key),
+            None => {
+                self.step = Some(Box::pin(
+                    Hci::read_local_p256_public_key(self.hci.clone())
+                        .map(|result| result.map(|public_key| public_key.to_vec())),
+                ))
+            }
+        }
+    }
+
+    fn public_key_ready(&mut self, public_key: [u8; 64]) {
+        self.local_public_key = public_key;
+        if self.initiator {
+            self.send_public_key();
+        }
+        self.state = PairingState::AwaitPublicKey;
+    }
+
+    fn send_public_key(&self) {
+        let mut x = [0; 32];
+        x.copy_from_slice(&self.local_public_key[..32]);
+        let mut y = [0; 32];
+        y.copy_from_slice(&self.local_public_key[32..]);
+        self.send(SmpPdu::PairingPublicKey { x, y });
+    }
+
+    /// Verify the out of band data received from the peer and keep the random values of both
+    /// devices. The random value of a device is only used if the other one has received it.
+    fn out_of_band(&mut self) -> Result<(), BoxError> {
+        let (local_features, peer_features) = self.features();
+        let (_, peer_x) = self.public_keys();
+        let peer_address = self.bond.peer_address;
+        let smp = self.smp.read();
+        let local_random = match smp.local_oob {
+            Some(data) if peer_features.oob_data => data.random,
+            _ => [0; 16],
+        };
+        let peer_data = smp.peer_oob_data(&peer_address);
+        drop(smp);
+        let peer_random = match peer_data {
+            Some(data) if local_features.oob_data => {
+                if f4(&peer_x, &peer_x, &data.random, 0) != data.confirm {
+                    return Err(self.fail(
+                        SMP_ERROR_CONFIRM_VALUE_FAILED,
+                        "out of band confirm value does not match",
+                    ));
+                }
+                data.random
+            }
+            _ if local_features.oob_data => {
+                return Err(self.fail(SMP_ERROR_OOB_NOT_AVAILABLE, "out of band data missing"))
+            }
+            _ => [0; 16],
+        };
+        self.oob_random = if self.initiator {
+            (local_random, peer_random)
+        } else {
+            (peer_random, local_random)
+        };
+        Ok(())
+    }
+
+    /// The value r of the confirm values of LE Secure Connections, the bit of the passkey
+    /// confirmed in the current round
+    fn confirm_bit(&self) -> u8 {
+        if self.method.is_passkey() {
+            let passkey = u32::from_le_bytes([self.tk[0], self.tk[1], self.tk[2], self.tk[3]]);
+            0x80 | ((passkey >> self.round) & 0x01) as u8
+        } else {
+            0x00
+        }
+    }
+
+    /// The random value of this device is known, so commit to it with the confirm value of LE
+    /// Secure Connections
+    fn commit(&mut self) {
+        let (local_x, peer_x) = self.public_keys();
+        self.local_confirm = f4(&local_x, &peer_x, &self.local_random, self.confirm_bit());
+        self.state = match self.method {
+            PairingMethod::OutOfBand => {
+                if self.initiator {
+                    self.send(SmpPdu::PairingRandom {
+                        value: self.local_random,
+                    });
+                }
+                PairingState::AwaitRandom
+            }
+            // only the responder commits with just works and numeric comparison
+            PairingMethod::JustWorks | PairingMethod::NumericComparison if !self.initiator => {
+                self.send(SmpPdu::PairingConfirm {
+                    value: self.local_confirm,
+                });
+                PairingState::AwaitRandom
+            }
+            _ => {
+                if self.initiator && self.method.is_passkey() {
+                    self.send(SmpPdu::PairingConfirm {
+                        value: self.local_confirm,
+                    });
+                }
+                PairingState::AwaitConfirm
+            }
+        };
+    }
+
+    /// The random value of the peer has been received, so verify it against the confirm value
+    /// of the peer with LE Secure Connections
+    fn authenticate(&mut self) -> Result<(), BoxError> {
+        let committed = match self.method {
+            PairingMethod::OutOfBand => false,
+            PairingMethod::JustWorks | PairingMethod::NumericComparison => self.initiator,
+            _ => true,
+        };
+        if committed {
+            let (local_x, peer_x) = self.public_keys();
+            if f4(&peer_x, &local_x, &self.peer_random, self.confirm_bit()) != self.peer_confirm {
+                return Err(self.fail(
+                    SMP_ERROR_CONFIRM_VALUE_FAILED,
+                    "confirm value does not match",
+                ));
+            }
+        }
+        if !self.initiator {
+            self.send(SmpPdu::PairingRandom {
+                value: self.local_random,
+            });
+        }
+        match self.method {
+            _ if self.method.is_passkey() => {
+                self.round += 1;
+                if self.round < PASSKEY_ROUNDS {
+                    self.random();
+                } else {
+                    self.dhkey_check();
+                }
+            }
+            PairingMethod::NumericComparison => {
+                let (local_x, peer_x) = self.public_keys();
+                let (initiator_x, responder_x) = if self.initiator {
+                    (local_x, peer_x)
+                } else {
+                    (peer_x, local_x)
+                };
+                let (initiator_random, responder_random) = self.randoms();
+                let value = g2(
+                    &initiator_x,
+                    &responder_x,
+                    &initiator_random,
+                    &responder_random,
+                ) % 1_000_000;
+                self.smp.lock().request_confirmation(self.handle, value);
+                self.state = PairingState::UserConfirm;
+            }
+            _ => self.dhkey_check(),
+        }
+        Ok(())
+    }
+
+    /// Derive the long term key from the Diffie-Hellman key and calculate the DHKey check values
+    fn dhkey_check(&mut self) {
+        let (initiator_random, responder_random) = self.randoms();
+        let (initiator_r, responder_r) = match self.method {
+            _ if self.method.is_passkey() => (self.tk, self.tk),
+            PairingMethod::OutOfBand => self.oob_random,
+            _ => ([0; 16], [0; 16]),
+        };
+        let a = (self.initiator_address.0, &self.initiator_address.1);
+        let b = (self.responder_address.0, &self.responder_address.1);
+        let (mac_key, ltk) = f5(&self.dhkey, &initiator_random, &responder_random, a, b);
+        let (request, response) = (self.request.unwrap(), self.response.unwrap());
+        let initiator_check = f6(
+            &mac_key,
+            &initiator_random,
+            &responder_random,
+            &responder_r,
+            (request.auth_req, request.oob_data, request.io_capability),
+            a,
+            b,
+        );
+        let responder_check = f6(
+            &mac_key,
+            &responder_random,
+            &initiator_random,
+            &initiator_r,
+            (response.auth_req, response.oob_data, response.io_capability),
+            b,
+            a,
+        );
+        self.ltk = mask_key(ltk, self.key_size);
+        if self.initiator {
+            self.local_check = initiator_check;
+            self.peer_check = responder_check;
+            self.send(SmpPdu::PairingDhKeyCheck {
+                value: initiator_check,
+            });
+        } else {
+            // the responder sends its check value once it has verified the one of the initiator
+            self.local_check = responder_check;
+            self.peer_check = initiator_check;
+        }
+        self.state = PairingState::AwaitDhKeyCheck;
+    }
+
+    /// Encrypt the connection with the given key. The central starts the encryption, the BT
+    /// host of the peripheral requests the key once the central has started it.
+    fn encrypt(&mut self, key: [u8; 16]) -> Result<(), BoxError> {
+        if self.initiator {
+            let connection = self.smp.read().channel.connection(self.handle);
+            match connection {
+                Some(connection) => {
+                    self.encryption = Some(connection.enable_encryption([0; 8], 0, key))
+                }
+                None => return Err(self.abort(SMP_ERROR_UNSPECIFIED_REASON, "connection closed")),
+            }
+        } else {
+            if let Some(state) = self.smp.lock().connections.get_mut(&self.handle) {
+                state.stk.replace(key);
+            }
+            self.encryption = Some(EnableEncryptionThinkable::awaiting(
+                self.hci.clone(),
+                self.handle,
+            ));
+        }
+        self.state = PairingState::Encrypting;
+        Ok(())
+    }
+
+    /// Start to generate the random value of this device
+    fn random(&mut self) {
+        self.step = Some(Box::pin(crypto::random(self.hci.clone(), 16)));
+        self.state = PairingState::LocalRandom;
+    }
+
+    /// The temporary key is known once the passkey has been generated or entered
+    fn passkey(&mut self) {
+        let display = match self.method {
+            PairingMethod::PasskeyInitiatorDisplays => self.initiator,
+            PairingMethod::PasskeyResponderDisplays => !self.initiator,
+            PairingMethod::PasskeyBothInput => false,
+            _ => {
+                self.random();
+                return;
+            }
+        };
+        if display {
+            self.step = Some(Box::pin(crypto::random(self.hci.clone(), 4)));
+        } else {
+            self.smp.lock().request_passkey(self.handle);
+            self.state = PairingState::PasskeyEntry;
+        }
+    }
+
+    /// Wait for the user to enter the passkey
+    fn passkey_entered(&mut self, cx: &mut Context<'_>) -> Conclusion<Result<(), BoxError>> {
+        if let Err(e) = self.interrupted(cx) {
+            return Conclusion::Ready(Err(e));
+        }
+        let (passkey, rejected) = match self.smp.lock().connections.get_mut(&self.handle) {
+            Some(connection) => (connection.passkey.take(), connection.rejected),
+            None => (None, false),
+        };
+        if rejected {
+            return Conclusion::Ready(Err(
+                self.fail(SMP_ERROR_PASSKEY_ENTRY_FAILED, "passkey entry cancelled")
+            ));
+        }
+        match passkey {
+            Some(passkey) => {
+                self.tk[..4].copy_from_slice(&passkey.to_le_bytes());
+                self.random();
+                Conclusion::Ready(Ok(()))
+            }
+            None => Conclusion::Pending,
+        }
+    }
+
+    /// Wait for the user to either confirm or reject
+    fn decision(&mut self, cx: &mut Context<'_>) -> Conclusion<Result<bool, BoxError>> {
+        if let Err(e) = self.interrupted(cx) {
+            return Conclusion::Ready(Err(e));
+        }
+        let decision = match self.smp.lock().connections.get_mut(&self.handle) {
+            Some(connection) if connection.rejected => Some(false),
+            Some(connection) if connection.confirmed => {
+                connection.confirmed = false;
+                Some(true)
+            }
+            _ => None,
+        };
+        match decision {
+            Some(decision) => Conclusion::Ready(Ok(decision)),
+            None => Conclusion::Pending,
+        }
+    }
+
+    /// The user decided whether the pairing requested by the peer is accepted
+    fn authorized(&mut self, authorized: bool) -> Result<(), BoxError> {
+        if !authorized {
+            return Err(self.fail(SMP_ERROR_PAIRING_NOT_SUPPORTED, "pairing not authorized"));
+        }
+        self.send(SmpPdu::PairingResponse(self.response.unwrap()));
+        self.state = if self.secure_connections {
+            PairingState::PublicKey
+        } else {
+            PairingState::Passkey
+        };
+        Ok(())
+    }
+
+    /// The user decided whether the numeric comparison value matches
+    fn value_confirmed(&mut self, confirmed: bool) -> Result<(), BoxError> {
+        if !confirmed {
+            return Err(self.fail(
+                SMP_ERROR_NUMERIC_COMPARISON_FAILED,
+                "numeric comparison rejected",
+            ));
+        }
+        self.dhkey_check();
+        Ok(())
+    }
+
+    /// The connection is encrypted, so the keys can be distributed
+    fn encrypted(&mut self) {
+        let mut length = 0;
+        if self.local_keys & KEY_DISTRIBUTION_ENCRYPTION != 0 {
+            length += 16 + 2 + 8;
+        }
+        if self.local_keys & KEY_DISTRIBUTION_SIGNING != 0 {
+            length += 16;
+        }
+        self.state = PairingState::LocalKeys;
+        if length > 0 {
+            self.step = Some(Box::pin(crypto::random(self.hci.clone(), length)));
+        } else {
+            self.keys(&[]);
+        }
+    }
+
+    /// Create the keys to distribute from the random bytes given and start the distribution
+    fn keys(&mut self, random: &[u8]) {
+        let mut random = random.iter().copied();
+        let mut key = || {
+            let mut key = [0; 16];
+            for byte in key.iter_mut() {
+                *byte = random.next().unwrap_or(0);
+            }
+            key
+        };
+        let configuration = self.smp.read().configuration;
+        self.distribution.clear();
+        if self.secure_connections {
+            // both devices use the derived key in either role
+            let key = LongTermKey {
+                ltk: self.ltk,
+                ediv: 0,
+                random_number: [0; 8],
+                key_size: self.key_size,
+                authenticated: self.method.is_authenticated(),
+                secure_connections: true,
+            };
+            self.bond.local_ltk = Some(key);
+            self.bond.peer_ltk = Some(key);
+        }
+        if self.local_keys & KEY_DISTRIBUTION_ENCRYPTION != 0 {
+            let ltk = mask_key(key(), self.key_size);
+            let identification = key();
+            let ediv = u16::from_le_bytes([identification[0], identification[1]]);
+            let mut random_number = [0; 8];
+            random_number.copy_from_slice(&identification[2..10]);
+            self.bond.local_ltk = Some(LongTermKey {
+                ltk,
+                ediv,
+                random_number,
+                key_size: self.key_size,
+                authenticated: self.method.is_authenticated(),
+                secure_connections: false,
+            });
+            self.distribution
+                .push(SmpPdu::EncryptionInformation { ltk });
+            self.distribution.push(SmpPdu::CentralIdentification {
+                ediv,
+                random_number,
+            });
+        }
+        if self.local_keys & KEY_DISTRIBUTION_IDENTITY != 0 {
+            if let Some(irk) = configuration.identity_resolving_key {
+                self.distribution.push(SmpPdu::IdentityInformation { irk });
+                self.distribution.push(SmpPdu::IdentityAddressInformation {
+                    address_type: configuration.identity_address_type,
+                    address: configuration.identity_address,
+                });
+            }
+        }
+        if self.local_keys & KEY_DISTRIBUTION_SIGNING != 0 {
+            let csrk = key();
+            self.bond.local_csrk = Some(csrk);
+            self.distribution.push(SmpPdu::SigningInformation { csrk });
+        }
+
+        // the responder distributes its keys first
+        if !self.initiator {
+            self.distribute();
+        }
+        if self.expected.is_empty() {
+            if self.initiator {
+                self.distribute();
+            }
+            self.state = PairingState::Done;
+        } else {
+            self.state = PairingState::ReceiveKeys;
+        }
+    }
+
+    fn distribute(&mut self) {
+        let distribution = core::mem::replace(&mut self.distribution, Vec::new());
+        let mut smp = self.smp.lock();
+        for pdu in distribution.iter() {
+            Smp::send(&self.smp, &mut smp, self.handle, pdu);
+        }
+    }
+
+    /// Conclude the pairing, the bond is stored if the pairing succeeded
+    fn finish(&mut self, result: Result<(), BoxError>) -> Conclusion<Result<(), BoxError>> {
+        self.state = PairingState::Done;
+        self.step = None;
+        self.encryption = None;
+        let mut smp = self.smp.lock();
+        let mut timed_out = false;
+        if !self.running {
+            return Conclusion::Ready(result);
+        }
+        self.running = false;
+        if let Some(connection) = smp.connections.get_mut(&self.handle) {
+            connection.pairing = false;
+            connection.pdus.clear();
+            connection.failed = None;
+            connection.passkey = None;
+            connection.confirmed = false;
+            connection.rejected = false;
+            connection.stk = None;
+            timed_out = connection.timed_out;
+        }
+        match result {
+            Ok(()) => {
+                info!("pairing on connection {} complete", self.handle);
+                if self.bonding {
+                    smp.store_bond(self.bond);
+                }
+                smp.post(SmpEvent::PairingComplete {
+                    handle: self.handle,
+                    authenticated: self.method.is_authenticated(),
+                    bonded: self.bonding,
+                });
+            }
+            Err(_) => {
+                warn!(
+                    "pairing on connection {} failed with reason {}",
+                    self.handle, self.reason
+                );
+                if self.notify && !timed_out {
+                    smp.channel.queue(
+                        self.handle,
+                        &SmpPdu::PairingFailed {
+                            reason: self.reason,
+                        }
+                        .to_bytes(),
+                    );
+                }
+                smp.post(SmpEvent::PairingFailed {
+                    handle: self.handle,
+                    reason: self.reason,
+                });
+            }
+        }
+        Conclusion::Ready(result)
+    }
+}
+
+impl<T> Thinkable for PairingThinkable<T>
+where
+    T: HcTransportLayer,
+{
+    type Output = Result<(), BoxError>;
+
+    fn think(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Conclusion<Self::Output> {
+        // the step and encryption Thinkables are pinned on their own
+        let this = unsafe { self.get_unchecked_mut() };
+        loop {
+            if let Some(step) = this.step.as_mut() {
+                let result = match step.as_mut().think(cx) {
+                    Conclusion::Pending => return Conclusion::Pending,
+                    Conclusion::Ready(result) => result,
+                };
+                this.step = None;
+                if let Err(e) = result.and_then(|bytes| this.stepped(bytes)) {
+                    return this.finish(Err(e));
+                }
+                continue;
+            }
+            let result = match this.state {
+                PairingState::Start => this.start(),
+                PairingState::PublicKey => {
+                    this.public_key();
+                    Ok(())
+                }
+                PairingState::Passkey => {
+                    this.passkey();
+                    Ok(())
+                }
+                PairingState::PasskeyEntry => match this.passkey_entered(cx) {
+                    Conclusion::Pending => return Conclusion::Pending,
+                    Conclusion::Ready(result) => result,
+                },
+                PairingState::Authorize => match this.decision(cx) {
+                    Conclusion::Pending => return Conclusion::Pending,
+                    Conclusion::Ready(decision) => {
+                        decision.and_then(|authorized| this.authorized(authorized))
+                    }
+                },
+                PairingState::UserConfirm => match this.decision(cx) {
+                    Conclusion::Pending => return Conclusion::Pending,
+                    Conclusion::Ready(decision) => {
+                        decision.and_then(|confirmed| this.value_confirmed(confirmed))
+                    }
+                },
+                PairingState::AwaitResponse
+                | PairingState::AwaitPublicKey
+                | PairingState::AwaitConfirm
+                | PairingState::AwaitRandom
+                | PairingState::AwaitDhKeyCheck
+                | PairingState::ReceiveKeys => match this.next_pdu(cx) {
+                    Conclusion::Pending => return Conclusion::Pending,
+                    Conclusion::Ready(pdu) => pdu.and_then(|pdu| this.received(pdu)),
+                },
+                PairingState::Encrypting => {
+                    if let Err(e) = this.interrupted(cx) {
+                        return this.finish(Err(e));
+                    }
+                    let encryption = match this.encryption.as_mut() {
+                        Some(encryption) => encryption,
+                        None => {
+                            return this.finish(Err(Box::new(SmpError::new(
+                                SMP_ERROR_UNSPECIFIED_REASON,
+                                "encryption not started",
+                            ))))
+                        }
+                    };
+                    match Pin::new(encryption).think(cx) {
+                        Conclusion::Pending => return Conclusion::Pending,
+                        Conclusion::Ready(result) => {
+                            this.encryption = None;
+                            result.map(|_| this.encrypted())
+                        }
+                    }
+                }
+                PairingState::Done => return this.finish(Ok(())),
+                // these states wait for their calculation step
+                _ => {
+                    return this.finish(Err(Box::new(SmpError::new(
+                        SMP_ERROR_UNSPECIFIED_REASON,
+                        "calculation step missing",
+                    ))))
+                }
+            };
+            if let Err(e) = result {
+                return this.finish(Err(e));
+            }
+        }
+    }
+}
+
+/// Reduce the key to the given size in bytes, the most significant bytes are set to 0
+fn mask_key(mut key: [u8; 16], size: u8) -> [u8; 16] {
+    for byte in key.iter_mut().skip(size as usize) {
+        *byte = 0;
+    }
+    key
+}
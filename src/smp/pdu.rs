@@ -0,0 +1,344 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # SMP PDU's
+//!
+//! Encoding and decoding of the security manager protocol PDU's. Each PDU starts with the code
+//! of the command followed by its parameters in little endian order.
+//!
+
+use super::errors::*;
+use crate::alloc::vec::Vec;
+use crate::hci::BD_ADDRESS_SIZE;
+use crate::l2cap::signaling::{get_u16, put_u16};
+
+/// Authentication requirement: the keys distributed are stored to re-encrypt later connections
+pub const AUTH_REQ_BONDING: u8 = 0x01;
+/// Authentication requirement: protection against man-in-the-middle attacks is required
+pub const AUTH_REQ_MITM: u8 = 0x04;
+/// Authentication requirement: LE Secure Connections pairing is supported
+pub const AUTH_REQ_SECURE_CONNECTIONS: u8 = 0x08;
+/// Authentication requirement: keypress notifications are supported
+pub const AUTH_REQ_KEYPRESS: u8 = 0x10;
+/// Authentication requirement: the h7 function is supported for cross-transport key derivation
+pub const AUTH_REQ_CT2: u8 = 0x20;
+
+/// Key distribution: the long term key with its EDIV and Rand
+pub const KEY_DISTRIBUTION_ENCRYPTION: u8 = 0x01;
+/// Key distribution: the identity resolving key and the identity address
+pub const KEY_DISTRIBUTION_IDENTITY: u8 = 0x02;
+/// Key distribution: the connection signature resolving key
+pub const KEY_DISTRIBUTION_SIGNING: u8 = 0x04;
+/// Key distribution: the BR/EDR link key is derived from the LE long term key
+pub const KEY_DISTRIBUTION_LINK: u8 = 0x08;
+
+/// The input and output capabilities of a device. Together with the capabilities of the peer
+/// they decide on the association model used for pairing.
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum IoCapability {
+    DisplayOnly = 0x00,
+    DisplayYesNo = 0x01,
+    KeyboardOnly = 0x02,
+    NoInputNoOutput = 0x03,
+    KeyboardDisplay = 0x04,
+}
+
+impl IoCapability {
+    fn parse(orig: u8) -> Option<Self> {
+        match orig {
+            0x00 => Some(IoCapability::DisplayOnly),
+            0x01 => Some(IoCapability::DisplayYesNo),
+            0x02 => Some(IoCapability::KeyboardOnly),
+            0x03 => Some(IoCapability::NoInputNoOutput),
+            0x04 => Some(IoCapability::KeyboardDisplay),
+            _ => None,
+        }
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SmpCode {
+    PairingRequest = 0x01,
+    PairingResponse = 0x02,
+    PairingConfirm = 0x03,
+    PairingRandom = 0x04,
+    PairingFailed = 0x05,
+    EncryptionInformation = 0x06,
+    CentralIdentification = 0x07,
+    IdentityInformation = 0x08,
+    IdentityAddressInformation = 0x09,
+    SigningInformation = 0x0A,
+    SecurityRequest = 0x0B,
+    PairingPublicKey = 0x0C,
+    PairingDhKeyCheck = 0x0D,
+    KeypressNotification = 0x0E,
+    Unknown,
+}
+
+impl From<u8> for SmpCode {
+    fn from(orig: u8) -> Self {
+        match orig {
+            0x01 => SmpCode::PairingRequest,
+            0x02 => SmpCode::PairingResponse,
+            0x03 => SmpCode::PairingConfirm,
+            0x04 => SmpCode::PairingRandom,
+            0x05 => SmpCode::PairingFailed,
+            0x06 => SmpCode::EncryptionInformation,
+            0x07 => SmpCode::CentralIdentification,
+            0x08 => SmpCode::IdentityInformation,
+            0x09 => SmpCode::IdentityAddressInformation,
+            0x0A => SmpCode::SigningInformation,
+            0x0B => SmpCode::SecurityRequest,
+            0x0C => SmpCode::PairingPublicKey,
+            0x0D => SmpCode::PairingDhKeyCheck,
+            0x0E => SmpCode::KeypressNotification,
+            _ => SmpCode::Unknown,
+        }
+    }
+}
+
+/// The pairing features exchanged with the Pairing Request and Pairing Response
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PairingFeatures {
+    pub io_capability: IoCapability,
+    /// out of band data of the peer is available
+    pub oob_data: bool,
+    /// the ``AUTH_REQ_*`` bits
+    pub auth_req: u8,
+    pub max_key_size: u8,
+    /// the ``KEY_DISTRIBUTION_*`` keys the initiator distributes
+    pub initiator_keys: u8,
+    /// the ``KEY_DISTRIBUTION_*`` keys the responder distributes
+    pub responder_keys: u8,
+}
+
+/// One security manager protocol PDU
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SmpPdu {
+    PairingRequest(PairingFeatures),
+    PairingResponse(PairingFeatures),
+    PairingConfirm {
+        value: [u8; 16],
+    },
+    PairingRandom {
+        value: [u8; 16],
+    },
+    PairingFailed {
+        reason: u8,
+    },
+    EncryptionInformation {
+        ltk: [u8; 16],
+    },
+    CentralIdentification {
+        ediv: u16,
+        random_number: [u8; 8],
+    },
+    IdentityInformation {
+        irk: [u8; 16],
+    },
+    IdentityAddressInformation {
+        /// 0x00 public, 0x01 static random
+        address_type: u8,
+        address: [u8; BD_ADDRESS_SIZE],
+    },
+    SigningInformation {
+        csrk: [u8; 16],
+    },
+    SecurityRequest {
+        auth_req: u8,
+    },
+    /// the P-256 public key of LE Secure Connections
+    PairingPublicKey {
+        x: [u8; 32],
+        y: [u8; 32],
+    },
+    PairingDhKeyCheck {
+        value: [u8; 16],
+    },
+    /// the user has entered or erased a digit of the passkey
+    KeypressNotification {
+        notification_type: u8,
+    },
+}
+
+impl SmpPdu {
+    pub fn code(&self) -> SmpCode {
+        match self {
+            SmpPdu::PairingRequest(_) => SmpCode::PairingRequest,
+            SmpPdu::PairingResponse(_) => SmpCode::PairingResponse,
+            SmpPdu::PairingConfirm { .. } => SmpCode::PairingConfirm,
+            SmpPdu::PairingRandom { .. } => SmpCode::PairingRandom,
+            SmpPdu::PairingFailed { .. } => SmpCode::PairingFailed,
+            SmpPdu::EncryptionInformation { .. } => SmpCode::EncryptionInformation,
+            SmpPdu::CentralIdentification { .. } => SmpCode::CentralIdentification,
+            SmpPdu::IdentityInformation { .. } => SmpCode::IdentityInformation,
+            SmpPdu::IdentityAddressInformation { .. } => SmpCode::IdentityAddressInformation,
+            SmpPdu::SigningInformation { .. } => SmpCode::SigningInformation,
+            SmpPdu::SecurityRequest { .. } => SmpCode::SecurityRequest,
+            SmpPdu::PairingPublicKey { .. } => SmpCode::PairingPublicKey,
+            SmpPdu::PairingDhKeyCheck { .. } => SmpCode::PairingDhKeyCheck,
+            SmpPdu::KeypressNotification { .. } => SmpCode::KeypressNotification,
+        }
+    }
+
+    /// Decode the PDU received. If it can not be decoded this returns the ``SMP_ERROR_*``
+    /// reason to answer the PDU with.
+    pub fn parse(pdu: &[u8]) -> Result<Self, u8> {
+        let code = match pdu.first() {
+            Some(code) => SmpCode::from(*code),
+            None => return Err(SMP_ERROR_INVALID_PARAMETERS),
+        };
+        let params = &pdu[1..];
+        // ensure the parameters have exactly the given length
+        let expect = |length: usize| {
+            if params.len() != length {
+                Err(SMP_ERROR_INVALID_PARAMETERS)
+            } else {
+                Ok(())
+            }
+        };
+        let key = || {
+            let mut key = [0; 16];
+            key.copy_from_slice(&params[..16]);
+            key
+        };
+
+        let decoded = match code {
+            SmpCode::PairingRequest | SmpCode::PairingResponse => {
+                expect(6)?;
+                let features = PairingFeatures {
+                    io_capability: IoCapability::parse(params[0])
+                        .ok_or(SMP_ERROR_INVALID_PARAMETERS)?,
+                    oob_data: params[1] == 0x01,
+                    auth_req: params[2],
+                    max_key_size: params[3],
+                    initiator_keys: params[4],
+                    responder_keys: params[5],
+                };
+                if code == SmpCode::PairingRequest {
+                    SmpPdu::PairingRequest(features)
+                } else {
+                    SmpPdu::PairingResponse(features)
+                }
+            }
+            SmpCode::PairingConfirm => {
+                expect(16)?;
+                SmpPdu::PairingConfirm { value: key() }
+            }
+            SmpCode::PairingRandom => {
+                expect(16)?;
+                SmpPdu::PairingRandom { value: key() }
+            }
+            SmpCode::PairingFailed => {
+                expect(1)?;
+                SmpPdu::PairingFailed { reason: params[0] }
+            }
+            SmpCode::EncryptionInformation => {
+                expect(16)?;
+                SmpPdu::EncryptionInformation { ltk: key() }
+            }
+            SmpCode::CentralIdentification => {
+                expect(10)?;
+                let mut random_number = [0; 8];
+                random_number.copy_from_slice(&params[2..10]);
+                SmpPdu::CentralIdentification {
+                    ediv: get_u16(params, 0),
+                    random_number,
+                }
+            }
+            SmpCode::IdentityInformation => {
+                expect(16)?;
+                SmpPdu::IdentityInformation { irk: key() }
+            }
+            SmpCode::IdentityAddressInformation => {
+                expect(1 + BD_ADDRESS_SIZE)?;
+                let mut address = [0; BD_ADDRESS_SIZE];
+                address.copy_from_slice(&params[1..]);
+                SmpPdu::IdentityAddressInformation {
+                    address_type: params[0],
+                    address,
+                }
+            }
+            SmpCode::SigningInformation => {
+                expect(16)?;
+                SmpPdu::SigningInformation { csrk: key() }
+            }
+            SmpCode::SecurityRequest => {
+                expect(1)?;
+                SmpPdu::SecurityRequest {
+                    auth_req: params[0],
+                }
+            }
+            SmpCode::PairingPublicKey => {
+                expect(64)?;
+                let mut x = [0; 32];
+                x.copy_from_slice(&params[..32]);
+                let mut y = [0; 32];
+                y.copy_from_slice(&params[32..]);
+                SmpPdu::PairingPublicKey { x, y }
+            }
+            SmpCode::PairingDhKeyCheck => {
+                expect(16)?;
+                SmpPdu::PairingDhKeyCheck { value: key() }
+            }
+            SmpCode::KeypressNotification => {
+                expect(1)?;
+                SmpPdu::KeypressNotification {
+                    notification_type: params[0],
+                }
+            }
+            SmpCode::Unknown => return Err(SMP_ERROR_COMMAND_NOT_SUPPORTED),
+        };
+        Ok(decoded)
+    }
+
+    /// Encode the PDU to be send
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(65);
+        bytes.push(self.code() as u8);
+        match self {
+            SmpPdu::PairingRequest(features) | SmpPdu::PairingResponse(features) => {
+                bytes.push(features.io_capability as u8);
+                bytes.push(features.oob_data as u8);
+                bytes.push(features.auth_req);
+                bytes.push(features.max_key_size);
+                bytes.push(features.initiator_keys);
+                bytes.push(features.responder_keys);
+            }
+            SmpPdu::PairingConfirm { value: key }
+            | SmpPdu::PairingRandom { value: key }
+            | SmpPdu::EncryptionInformation { ltk: key }
+            | SmpPdu::IdentityInformation { irk: key }
+            | SmpPdu::SigningInformation { csrk: key }
+            | SmpPdu::PairingDhKeyCheck { value: key } => bytes.extend_from_slice(key),
+            SmpPdu::PairingFailed { reason } => bytes.push(*reason),
+            SmpPdu::CentralIdentification {
+                ediv,
+                random_number,
+            } => {
+                put_u16(&mut bytes, *ediv);
+                bytes.extend_from_slice(random_number);
+            }
+            SmpPdu::IdentityAddressInformation {
+                address_type,
+                address,
+            } => {
+                bytes.push(*address_type);
+                bytes.extend_from_slice(address);
+            }
+            SmpPdu::SecurityRequest { auth_req } => bytes.push(*auth_req),
+            SmpPdu::PairingPublicKey { x, y } => {
+                bytes.extend_from_slice(x);
+                bytes.extend_from_slice(y);
+            }
+            SmpPdu::KeypressNotification { notification_type } => bytes.push(*notification_type),
+        }
+        bytes
+    }
+}
@@ -0,0 +1,458 @@
+/***************************************************************************************************
+ * Copyright (c) 2019 by the authors
+ *
+ * Author: André Borrmann
+ * License: Apache License 2.0
+ **************************************************************************************************/
+
+//! # Bluetooth UUID
+//!
+//! UUID's identify services, characteristics, protocols and other entities. Besides the full 128
+//! bit form the assigned numbers use a 16 or 32 bit short form, which is an alias of the 128 bit
+//! UUID built from the Bluetooth base UUID. A [Uuid] always keeps the full form, so UUID's are
+//! equal regardless of the form they are created from.
+//!
+
+use crate::alloc::vec::Vec;
+use crate::error::Error;
+
+/// The Bluetooth base UUID 00000000-0000-1000-8000-00805F9B34FB
+pub const BASE_UUID: u128 = 0x0000_0000_0000_1000_8000_0080_5F9B_34FB;
+/// The bits of a UUID that need to match the base UUID for the UUID to have a short form
+const BASE_UUID_MASK: u128 = (1 << 96) - 1;
+
+/// A Bluetooth UUID
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Uuid(u128);
+
+impl Uuid {
+    /// Create the UUID from its 16 bit short form
+    pub const fn from_u16(uuid: u16) -> Self {
+        Self(BASE_UUID | (uuid as u128) << 96)
+    }
+
+    /// Create the UUID from its 32 bit short form
+    pub const fn from_u32(uuid: u32) -> Self {
+        Self(BASE_UUID | (uuid as u128) << 96)
+    }
+
+    /// Create the UUID from its full 128 bit form
+    pub const fn from_u128(uuid: u128) -> Self {
+        Self(uuid)
+    }
+
+    /// The 16 bit short form if the UUID has one
+    pub fn as_u16(&self) -> Option<u16> {
+        self.as_u32()
+            .filter(|uuid| *uuid <= 0xFFFF)
+            .map(|uuid| uuid as u16)
+    }
+
+    /// The 32 bit short form if the UUID has one
+    pub fn as_u32(&self) -> Option<u32> {
+        if self.0 & BASE_UUID_MASK == BASE_UUID {
+            Some((self.0 >> 96) as u32)
+        } else {
+            None
+        }
+    }
+
+    /// The full 128 bit form
+    pub const fn as_u128(&self) -> u128 {
+        self.0
+    }
+
+    /// The number of bytes of the shortest form of the UUID
+    pub fn size(&self) -> usize {
+        if self.as_u16().is_some() {
+            2
+        } else if self.as_u32().is_some() {
+            4
+        } else {
+            16
+        }
+    }
+
+    /// Decode the UUID from the little endian data of the 16, 32 or 128 bit form
+    pub fn from_le_bytes(data: &[u8]) -> Option<Self> {
+        match data.len() {
+            2 => Some(Self::from_u16(u16::from_le_bytes([data[0], data[1]]))),
+            4 => Some(Self::from_u32(u32::from_le_bytes([
+                data[0], data[1], data[2], data[3],
+            ]))),
+            16 => {
+                let mut uuid = [0; 16];
+                uuid.copy_from_slice(data);
+                Some(Self::from_u128(u128::from_le_bytes(uuid)))
+            }
+            _ => None,
+        }
+    }
+
+    /// The little endian encoding of the shortest form of the UUID
+    pub fn to_le_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.size());
+        match self.size() {
+            2 => data.extend_from_slice(&(self.0 >> 96).to_le_bytes()[..2]),
+            4 => data.extend_from_slice(&(self.0 >> 96).to_le_bytes()[..4]),
+            _ => data.extend_from_slice(&self.0.to_le_bytes()),
+        }
+        data
+    }
+
+    /// The little endian encoding of the full 128 bit form of the UUID
+    pub fn to_le_bytes_full(&self) -> [u8; 16] {
+        self.0.to_le_bytes()
+    }
+
+    /// The name of the UUID if it is one of the common assigned numbers
+    pub fn name(&self) -> Option<&'static str> {
+        ASSIGNED_NUMBERS
+            .iter()
+            .find(|(uuid, _)| uuid == self)
+            .map(|(_, name)| *name)
+    }
+}
+
+impl From<u16> for Uuid {
+    fn from(uuid: u16) -> Self {
+        Self::from_u16(uuid)
+    }
+}
+
+impl From<u32> for Uuid {
+    fn from(uuid: u32) -> Self {
+        Self::from_u32(uuid)
+    }
+}
+
+impl From<u128> for Uuid {
+    fn from(uuid: u128) -> Self {
+        Self::from_u128(uuid)
+    }
+}
+
+impl core::str::FromStr for Uuid {
+    type Err = UuidParseError;
+
+    /// Parse the canonical form ``0000180f-0000-1000-8000-00805f9b34fb`` or the 16 and 32 bit
+    /// short forms given as 4 or 8 hex digits
+    fn from_str(uuid: &str) -> Result<Self, Self::Err> {
+        let uuid = uuid.trim();
+        let dashes_valid = match uuid.len() {
+            4 | 8 => !uuid.contains('-'),
+            36 => uuid
+                .char_indices()
+                .all(|(index, c)| (c == '-') == [8, 13, 18, 23].contains(&index)),
+            _ => false,
+        };
+        if !dashes_valid || !uuid.chars().all(|c| c == '-' || c.is_ascii_hexdigit()) {
+            return Err(UuidParseError);
+        }
+        let mut value: u128 = 0;
+        for digit in uuid.chars().filter_map(|c| c.to_digit(16)) {
+            value = value << 4 | digit as u128;
+        }
+        Ok(match uuid.len() {
+            4 | 8 => Self::from_u32(value as u32),
+            _ => Self::from_u128(value),
+        })
+    }
+}
+
+impl core::fmt::Display for Uuid {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let uuid = self.0;
+        write!(
+            f,
+            "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            (uuid >> 96) as u32,
+            (uuid >> 80) as u16,
+            (uuid >> 64) as u16,
+            (uuid >> 48) as u16,
+            uuid & 0xFFFF_FFFF_FFFF
+        )
+    }
+}
+
+impl core::fmt::Debug for Uuid {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        <Uuid as core::fmt::Display>::fmt(self, f)
+    }
+}
+
+/// Error raised if a string is not a valid UUID
+pub struct UuidParseError;
+
+impl Error for UuidParseError {}
+
+impl core::fmt::Display for UuidParseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "invalid UUID")
+    }
+}
+
+impl core::fmt::Debug for UuidParseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        <UuidParseError as core::fmt::Display>::fmt(self, f)
+    }
+}
+
+pub const PROTOCOL_SDP: Uuid = Uuid::from_u16(0x0001);
+pub const PROTOCOL_RFCOMM: Uuid = Uuid::from_u16(0x0003);
+pub const PROTOCOL_ATT: Uuid = Uuid::from_u16(0x0007);
+pub const PROTOCOL_L2CAP: Uuid = Uuid::from_u16(0x0100);
+
+pub const SERVICE_CLASS_SERIAL_PORT: Uuid = Uuid::from_u16(0x1101);
+pub const SERVICE_CLASS_PUBLIC_BROWSE_ROOT: Uuid = Uuid::from_u16(0x1002);
+
+pub const SERVICE_GENERIC_ACCESS: Uuid = Uuid::from_u16(0x1800);
+pub const SERVICE_GENERIC_ATTRIBUTE: Uuid = Uuid::from_u16(0x1801);
+pub const SERVICE_IMMEDIATE_ALERT: Uuid = Uuid::from_u16(0x1802);
+pub const SERVICE_LINK_LOSS: Uuid = Uuid::from_u16(0x1803);
+pub const SERVICE_TX_POWER: Uuid = Uuid::from_u16(0x1804);
+pub const SERVICE_CURRENT_TIME: Uuid = Uuid::from_u16(0x1805);
+pub const SERVICE_HEALTH_THERMOMETER: Uuid = Uuid::from_u16(0x1809);
+pub const SERVICE_DEVICE_INFORMATION: Uuid = Uuid::from_u16(0x180A);
+pub const SERVICE_HEART_RATE: Uuid = Uuid::from_u16(0x180D);
+pub const SERVICE_BATTERY: Uuid = Uuid::from_u16(0x180F);
+pub const SERVICE_AUTOMATION_IO: Uuid = Uuid::from_u16(0x1815);
+pub const SERVICE_ENVIRONMENTAL_SENSING: Uuid = Uuid::from_u16(0x181A);
+pub const SERVICE_USER_DATA: Uuid = Uuid::from_u16(0x181C);
+
+pub const DECLARATION_PRIMARY_SERVICE: Uuid = Uuid::from_u16(0x2800);
+pub const DECLARATION_SECONDARY_SERVICE: Uuid = Uuid::from_u16(0x2801);
+pub const DECLARATION_INCLUDE: Uuid = Uuid::from_u16(0x2802);
+pub const DECLARATION_CHARACTERISTIC: Uuid = Uuid::from_u16(0x2803);
+
+pub const DESCRIPTOR_CHARACTERISTIC_EXTENDED_PROPERTIES: Uuid = Uuid::from_u16(0x2900);
+pub const DESCRIPTOR_CHARACTERISTIC_USER_DESCRIPTION: Uuid = Uuid::from_u16(0x2901);
+pub const DESCRIPTOR_CLIENT_CHARACTERISTIC_CONFIGURATION: Uuid = Uuid::from_u16(0x2902);
+pub const DESCRIPTOR_SERVER_CHARACTERISTIC_CONFIGURATION: Uuid = Uuid::from_u16(0x2903);
+pub const DESCRIPTOR_CHARACTERISTIC_PRESENTATION_FORMAT: Uuid = Uuid::from_u16(0x2904);
+pub const DESCRIPTOR_ENVIRONMENTAL_SENSING_MEASUREMENT: Uuid = Uuid::from_u16(0x290C);
+
+pub const CHARACTERISTIC_DEVICE_NAME: Uuid = Uuid::from_u16(0x2A00);
+pub const CHARACTERISTIC_APPEARANCE: Uuid = Uuid::from_u16(0x2A01);
+pub const CHARACTERISTIC_PERIPHERAL_PREFERRED_CONNECTION_PARAMETERS: Uuid = Uuid::from_u16(0x2A04);
+pub const CHARACTERISTIC_SERVICE_CHANGED: Uuid = Uuid::from_u16(0x2A05);
+pub const CHARACTERISTIC_ALERT_LEVEL: Uuid = Uuid::from_u16(0x2A06);
+pub const CHARACTERISTIC_TX_POWER_LEVEL: Uuid = Uuid::from_u16(0x2A07);
+pub const CHARACTERISTIC_BATTERY_LEVEL: Uuid = Uuid::from_u16(0x2A19);
+pub const CHARACTERISTIC_TEMPERATURE_MEASUREMENT: Uuid = Uuid::from_u16(0x2A1C);
+pub const CHARACTERISTIC_SYSTEM_ID: Uuid = Uuid::from_u16(0x2A23);
+pub const CHARACTERISTIC_MODEL_NUMBER_STRING: Uuid = Uuid::from_u16(0x2A24);
+pub const CHARACTERISTIC_SERIAL_NUMBER_STRING: Uuid = Uuid::from_u16(0x2A25);
+pub const CHARACTERISTIC_FIRMWARE_REVISION_STRING: Uuid = Uuid::from_u16(0x2A26);
+pub const CHARACTERISTIC_HARDWARE_REVISION_STRING: Uuid = Uuid::from_u16(0x2A27);
+pub const CHARACTERISTIC_SOFTWARE_REVISION_STRING: Uuid = Uuid::from_u16(0x2A28);
+pub const CHARACTERISTIC_MANUFACTURER_NAME_STRING: Uuid = Uuid::from_u16(0x2A29);
+pub const CHARACTERISTIC_CURRENT_TIME: Uuid = Uuid::from_u16(0x2A2B);
+pub const CHARACTERISTIC_HEART_RATE_MEASUREMENT: Uuid = Uuid::from_u16(0x2A37);
+pub const CHARACTERISTIC_PNP_ID: Uuid = Uuid::from_u16(0x2A50);
+pub const CHARACTERISTIC_PRESSURE: Uuid = Uuid::from_u16(0x2A6D);
+pub const CHARACTERISTIC_TEMPERATURE: Uuid = Uuid::from_u16(0x2A6E);
+pub const CHARACTERISTIC_HUMIDITY: Uuid = Uuid::from_u16(0x2A6F);
+pub const CHARACTERISTIC_CENTRAL_ADDRESS_RESOLUTION: Uuid = Uuid::from_u16(0x2AA6);
+pub const CHARACTERISTIC_RESOLVABLE_PRIVATE_ADDRESS_ONLY: Uuid = Uuid::from_u16(0x2AC9);
+pub const CHARACTERISTIC_CLIENT_SUPPORTED_FEATURES: Uuid = Uuid::from_u16(0x2B29);
+pub const CHARACTERISTIC_DATABASE_HASH: Uuid = Uuid::from_u16(0x2B2A);
+
+/// The names of the common assigned numbers
+const ASSIGNED_NUMBERS: &[(Uuid, &str)] = &[
+    (PROTOCOL_SDP, "SDP"),
+    (PROTOCOL_RFCOMM, "RFCOMM"),
+    (PROTOCOL_ATT, "ATT"),
+    (PROTOCOL_L2CAP, "L2CAP"),
+    (SERVICE_CLASS_SERIAL_PORT, "Serial Port"),
+    (SERVICE_CLASS_PUBLIC_BROWSE_ROOT, "Public Browse Root"),
+    (SERVICE_GENERIC_ACCESS, "Generic Access"),
+    (SERVICE_GENERIC_ATTRIBUTE, "Generic Attribute"),
+    (SERVICE_IMMEDIATE_ALERT, "Immediate Alert"),
+    (SERVICE_LINK_LOSS, "Link Loss"),
+    (SERVICE_TX_POWER, "Tx Power"),
+    (SERVICE_CURRENT_TIME, "Current Time"),
+    (SERVICE_HEALTH_THERMOMETER, "Health Thermometer"),
+    (SERVICE_DEVICE_INFORMATION, "Device Information"),
+    (SERVICE_HEART_RATE, "Heart Rate"),
+    (SERVICE_BATTERY, "Battery"),
+    (SERVICE_AUTOMATION_IO, "Automation IO"),
+    (SERVICE_ENVIRONMENTAL_SENSING, "Environmental Sensing"),
+    (SERVICE_USER_DATA, "User Data"),
+    (DECLARATION_PRIMARY_SERVICE, "Primary Service"),
+    (DECLARATION_SECONDARY_SERVICE, "Secondary Service"),
+    (DECLARATION_INCLUDE, "Include"),
+    (DECLARATION_CHARACTERISTIC, "Characteristic"),
+    (
+        DESCRIPTOR_CHARACTERISTIC_EXTENDED_PROPERTIES,
+        "Characteristic Extended Properties",
+    ),
+    (
+        DESCRIPTOR_CHARACTERISTIC_USER_DESCRIPTION,
+        "Characteristic User Description",
+    ),
+    (
+        DESCRIPTOR_CLIENT_CHARACTERISTIC_CONFIGURATION,
+        "Client Characteristic Configuration",
+    ),
+    (
+        DESCRIPTOR_SERVER_CHARACTERISTIC_CONFIGURATION,
+        "Server Characteristic Configuration",
+    ),
+    (
+        DESCRIPTOR_CHARACTERISTIC_PRESENTATION_FORMAT,
+        "Characteristic Presentation Format",
+    ),
+    (
+        DESCRIPTOR_ENVIRONMENTAL_SENSING_MEASUREMENT,
+        "Environmental Sensing Measurement",
+    ),
+    (CHARACTERISTIC_DEVICE_NAME, "Device Name"),
+    (CHARACTERISTIC_APPEARANCE, "Appearance"),
+    (
+        CHARACTERISTIC_PERIPHERAL_PREFERRED_CONNECTION_PARAMETERS,
+        "Peripheral Preferred Connection Parameters",
+    ),
+    (CHARACTERISTIC_SERVICE_CHANGED, "Service Changed"),
+    (CHARACTERISTIC_ALERT_LEVEL, "Alert Level"),
+    (CHARACTERISTIC_TX_POWER_LEVEL, "Tx Power Level"),
+    (CHARACTERISTIC_BATTERY_LEVEL, "Battery Level"),
+    (
+        CHARACTERISTIC_TEMPERATURE_MEASUREMENT,
+        "Temperature Measurement",
+    ),
+    (CHARACTERISTIC_SYSTEM_ID, "System ID"),
+    (CHARACTERISTIC_MODEL_NUMBER_STRING, "Model Number String"),
+    (CHARACTERISTIC_SERIAL_NUMBER_STRING, "Serial Number String"),
+    (
+        CHARACTERISTIC_FIRMWARE_REVISION_STRING,
+        "Firmware Revision String",
+    ),
+    (
+        CHARACTERISTIC_HARDWARE_REVISION_STRING,
+        "Hardware Revision String",
+    ),
+    (
+        CHARACTERISTIC_SOFTWARE_REVISION_STRING,
+        "Software Revision String",
+    ),
+    (
+        CHARACTERISTIC_MANUFACTURER_NAME_STRING,
+        "Manufacturer Name String",
+    ),
+    (CHARACTERISTIC_CURRENT_TIME, "Current Time"),
+    (
+        CHARACTERISTIC_HEART_RATE_MEASUREMENT,
+        "Heart Rate Measurement",
+    ),
+    (CHARACTERISTIC_PNP_ID, "PnP ID"),
+    (CHARACTERISTIC_PRESSURE, "Pressure"),
+    (CHARACTERISTIC_TEMPERATURE, "Temperature"),
+    (CHARACTERISTIC_HUMIDITY, "Humidity"),
+    (
+        CHARACTERISTIC_CENTRAL_ADDRESS_RESOLUTION,
+        "Central Address Resolution",
+    ),
+    (
+        CHARACTERISTIC_RESOLVABLE_PRIVATE_ADDRESS_ONLY,
+        "Resolvable Private Address Only",
+    ),
+    (
+        CHARACTERISTIC_CLIENT_SUPPORTED_FEATURES,
+        "Client Supported Features",
+    ),
+    (CHARACTERISTIC_DATABASE_HASH, "Database Hash"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alloc::string::ToString;
+    use core::str::FromStr;
+
+    #[test]
+    fn parse_and_display_round_trip() {
+        for uuid in [
+            "0000180f-0000-1000-8000-00805f9b34fb",
+            "6e400001-b5a3-f393-e0a9-e50e24dcca9e",
+            "00000000-0000-0000-0000-000000000000",
+        ]
+        .iter()
+        {
+            assert_eq!(Uuid::from_str(uuid).unwrap().to_string(), *uuid);
+        }
+        let uuid = Uuid::from_str("6E400001-B5A3-F393-E0A9-E50E24DCCA9E").unwrap();
+        assert_eq!(
+            uuid,
+            Uuid::from_u128(0x6E40_0001_B5A3_F393_E0A9_E50E_24DC_CA9E)
+        );
+        assert_eq!(uuid.to_string(), "6e400001-b5a3-f393-e0a9-e50e24dcca9e");
+    }
+
+    #[test]
+    fn parse_short_forms() {
+        assert_eq!(Uuid::from_str("180F").unwrap(), SERVICE_BATTERY);
+        assert_eq!(
+            Uuid::from_str(" 2a19 ").unwrap(),
+            CHARACTERISTIC_BATTERY_LEVEL
+        );
+        assert_eq!(
+            Uuid::from_str("0001180f").unwrap(),
+            Uuid::from_u32(0x0001_180F)
+        );
+    }
+
+    #[test]
+    fn reject_malformed_strings() {
+        for uuid in [
+            "",
+            "180",
+            "180g",
+            "18-0f",
+            "0000180f0",
+            "0000180f00001000800000805f9b34fb",
+            "0000180f-0000-1000-8000-00805f9b34f",
+            "0000180f-0000-1000-8000-00805f9b34fbb",
+            "0000180f-00001-000-8000-00805f9b34fb",
+            "0000180f-0000-1000-8000-00805f9b34fx",
+        ]
+        .iter()
+        {
+            assert!(Uuid::from_str(uuid).is_err(), "{} accepted", uuid);
+        }
+    }
+
+    #[test]
+    fn short_forms_expand_to_base_uuid() {
+        assert_eq!(
+            Uuid::from_u16(0x180F).as_u128(),
+            0x0000_180F_0000_1000_8000_0080_5F9B_34FB
+        );
+        assert_eq!(
+            Uuid::from_u32(0x1234_5678).as_u128(),
+            0x1234_5678_0000_1000_8000_0080_5F9B_34FB
+        );
+        assert_eq!(Uuid::from_u16(0x0000).as_u128(), BASE_UUID);
+        assert_eq!(Uuid::from_u32(0x1234_5678).as_u16(), None);
+        assert_eq!(Uuid::from_u32(0x1234_5678).as_u32(), Some(0x1234_5678));
+    }
+
+    #[test]
+    fn short_and_full_forms_are_equal() {
+        let full = Uuid::from_u128(0x0000_2A19_0000_1000_8000_0080_5F9B_34FB);
+        assert_eq!(Uuid::from_u16(0x2A19), full);
+        assert_eq!(Uuid::from_u32(0x2A19), full);
+        assert_eq!(full.as_u16(), Some(0x2A19));
+        assert_eq!(full.size(), 2);
+        assert_eq!(
+            Uuid::from_le_bytes(&full.to_le_bytes_full()),
+            Some(Uuid::from_u16(0x2A19))
+        );
+        assert_eq!(full.to_le_bytes(), [0x19, 0x2A]);
+        // a UUID differing from the base UUID has no short form
+        let custom = Uuid::from_u128(0x0000_2A19_0000_1000_8000_0080_5F9B_34FC);
+        assert_ne!(custom, full);
+        assert_eq!(custom.as_u16(), None);
+        assert_eq!(custom.size(), 16);
+    }
+}